@@ -0,0 +1,69 @@
+-- The scraper's base tables, as far as the API reads them. The scraper owns this schema;
+-- tests create it so scrape_migrations can run against an empty database.
+CREATE TABLE artists (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    image TEXT
+);
+
+CREATE TABLE albums (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    image TEXT,
+    date TEXT,
+    track_count BIGINT,
+    upc TEXT,
+    label TEXT
+);
+
+CREATE TABLE songs (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    image TEXT,
+    duration BIGINT,
+    disc_number BIGINT,
+    track_number BIGINT,
+    isrc TEXT,
+    date TEXT
+);
+
+CREATE TABLE genres (
+    id SERIAL PRIMARY KEY,
+    name TEXT NOT NULL
+);
+
+CREATE TABLE song_artists (
+    song_id TEXT NOT NULL REFERENCES songs(id) ON DELETE CASCADE,
+    artist_id TEXT NOT NULL REFERENCES artists(id) ON DELETE CASCADE,
+    PRIMARY KEY (song_id, artist_id)
+);
+
+CREATE TABLE artist_albums (
+    artist_id TEXT NOT NULL REFERENCES artists(id) ON DELETE CASCADE,
+    album_id TEXT NOT NULL REFERENCES albums(id) ON DELETE CASCADE,
+    PRIMARY KEY (artist_id, album_id)
+);
+
+CREATE TABLE song_albums (
+    song_id TEXT NOT NULL REFERENCES songs(id) ON DELETE CASCADE,
+    album_id TEXT NOT NULL REFERENCES albums(id) ON DELETE CASCADE,
+    PRIMARY KEY (song_id, album_id)
+);
+
+CREATE TABLE song_genres (
+    song_id TEXT NOT NULL REFERENCES songs(id) ON DELETE CASCADE,
+    genre_id INTEGER NOT NULL REFERENCES genres(id) ON DELETE CASCADE,
+    PRIMARY KEY (song_id, genre_id)
+);
+
+CREATE TABLE album_genres (
+    album_id TEXT NOT NULL REFERENCES albums(id) ON DELETE CASCADE,
+    genre_id INTEGER NOT NULL REFERENCES genres(id) ON DELETE CASCADE,
+    PRIMARY KEY (album_id, genre_id)
+);
+
+CREATE TABLE artist_genres (
+    artist_id TEXT NOT NULL REFERENCES artists(id) ON DELETE CASCADE,
+    genre_id INTEGER NOT NULL REFERENCES genres(id) ON DELETE CASCADE,
+    PRIMARY KEY (artist_id, genre_id)
+);
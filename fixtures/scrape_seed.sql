@@ -0,0 +1,121 @@
+-- A small catalog for handler tests, loaded after scrape_migrations. fixtures/search.json
+-- indexes the same entities for the in-memory search backend.
+INSERT INTO genres (id, name) VALUES
+    (1, 'Dance'),
+    (2, 'Electronic'),
+    (3, 'Pop'),
+    (4, 'French House');
+
+INSERT INTO artists (id, name, image, apple_music_id) VALUES
+    ('daftpunk00000001', 'Daft Punk', 'https://img.example/daftpunk.jpg', '5468295'),
+    ('pharrell00000001', 'Pharrell Williams', 'https://img.example/pharrell.jpg', NULL),
+    ('nilerodgers00001', 'Nile Rodgers', NULL, NULL),
+    ('zedd000000000001', 'Zedd', 'https://img.example/zedd.jpg', NULL),
+    ('foxes00000000001', 'Foxes', NULL, NULL),
+    ('variousartists01', 'Various Artists', NULL, NULL);
+
+INSERT INTO artist_genres (artist_id, genre_id) VALUES
+    ('daftpunk00000001', 2),
+    ('daftpunk00000001', 4),
+    ('pharrell00000001', 3),
+    ('zedd000000000001', 2);
+
+INSERT INTO albums (id, name, image, date, track_count, upc, label, apple_music_id) VALUES
+    ('ram0000000000001', 'Random Access Memories', 'https://img.example/ram.jpg',
+     '2013-05-17', 3, '886443919266', 'Columbia', '617154241'),
+    ('discovery0000001', 'Discovery', 'https://img.example/discovery.jpg',
+     '2001-03-12', 1, '724384960650', ' Virgin ', NULL),
+    ('clarity000000001', 'Clarity', 'https://img.example/clarity.jpg',
+     '2012-10', 1, '602537152098', 'Interscope', NULL),
+    ('dancehits0000001', 'Dance Hits 2013', NULL, '2013', 2, NULL, NULL, NULL);
+
+INSERT INTO artist_albums (artist_id, album_id, position) VALUES
+    ('daftpunk00000001', 'ram0000000000001', 1),
+    ('daftpunk00000001', 'discovery0000001', 1),
+    ('zedd000000000001', 'clarity000000001', 1),
+    ('variousartists01', 'dancehits0000001', 1);
+
+INSERT INTO album_genres (album_id, genre_id) VALUES
+    ('ram0000000000001', 1),
+    ('ram0000000000001', 2),
+    ('discovery0000001', 4),
+    ('clarity000000001', 2),
+    ('dancehits0000001', 1);
+
+INSERT INTO songs (id, name, image, duration, disc_number, track_number, isrc, date,
+                   explicit, apple_music_id, deleted_at) VALUES
+    ('getlucky00000001', 'Get Lucky', 'https://img.example/ram.jpg', 369000, 1, 8,
+     'USQX91300108', '2013-04-19', FALSE, '617154366', NULL),
+    ('doinitright00001', 'Doin'' It Right', 'https://img.example/ram.jpg', 251000, 1, 12,
+     'USQX91300112', '2013-05-17', TRUE, NULL, NULL),
+    ('horizon000000001', 'Horizon', 'https://img.example/ram.jpg', 251000, 1, 14,
+     'USQX91300114', '2013-05-17', NULL, NULL, '2020-01-01T00:00:00Z'),
+    ('onemoretime00001', 'One More Time', 'https://img.example/discovery.jpg', 320000, 1, 1,
+     'GB-DUW-00-00053', '2000-11-13', FALSE, NULL, NULL),
+    ('clarity000000002', 'Clarity', 'https://img.example/clarity.jpg', 271000, 1, 4,
+     'USUM71212345', '2012-10-02', NULL, NULL, NULL);
+
+-- Positions give the credited order. Clarity's links have none, like rows the scraper wrote
+-- before it recorded positions; Zedd, the primary artist, is linked first.
+INSERT INTO song_artists (song_id, artist_id, position) VALUES
+    ('getlucky00000001', 'daftpunk00000001', 1),
+    ('getlucky00000001', 'pharrell00000001', 2),
+    ('getlucky00000001', 'nilerodgers00001', 3),
+    ('doinitright00001', 'daftpunk00000001', 1),
+    ('horizon000000001', 'daftpunk00000001', 1),
+    ('onemoretime00001', 'daftpunk00000001', 1);
+INSERT INTO song_artists (song_id, artist_id) VALUES
+    ('clarity000000002', 'zedd000000000001');
+INSERT INTO song_artists (song_id, artist_id) VALUES
+    ('clarity000000002', 'foxes00000000001');
+
+INSERT INTO song_albums (song_id, album_id) VALUES
+    ('getlucky00000001', 'ram0000000000001'),
+    ('getlucky00000001', 'dancehits0000001'),
+    ('doinitright00001', 'ram0000000000001'),
+    ('horizon000000001', 'ram0000000000001'),
+    ('onemoretime00001', 'discovery0000001'),
+    ('onemoretime00001', 'dancehits0000001'),
+    ('clarity000000002', 'clarity000000001');
+
+INSERT INTO song_genres (song_id, genre_id) VALUES
+    ('getlucky00000001', 1),
+    ('getlucky00000001', 2),
+    ('doinitright00001', 2),
+    ('onemoretime00001', 4),
+    ('clarity000000002', 2),
+    ('clarity000000002', 3);
+
+INSERT INTO song_popularity (song_id, plays_30d, score) VALUES
+    ('getlucky00000001', 120000, 11.7),
+    ('onemoretime00001', 45000, 10.7);
+
+-- Pinned timestamps so responses compare equal across runs, and the typed release dates
+-- the backfill would fill in. The trigger would overwrite updated_at, so it sits this out.
+ALTER TABLE artists DISABLE TRIGGER artists_set_updated_at;
+ALTER TABLE albums DISABLE TRIGGER albums_set_updated_at;
+ALTER TABLE songs DISABLE TRIGGER songs_set_updated_at;
+UPDATE artists SET created_at = '2024-01-01T00:00:00Z', updated_at = '2024-02-01T00:00:00Z';
+UPDATE albums SET
+    created_at = '2024-01-01T00:00:00Z',
+    updated_at = '2024-02-01T00:00:00Z',
+    release_date = safe_release_date(date),
+    release_date_precision = CASE
+        WHEN date ~ '^\d{4}-\d{2}-\d{2}$' THEN 'day'
+        WHEN date ~ '^\d{4}-\d{2}$' THEN 'month'
+        ELSE 'year'
+    END,
+    release_date_source = date;
+UPDATE songs SET
+    created_at = '2024-01-01T00:00:00Z',
+    updated_at = '2024-02-01T00:00:00Z',
+    release_date = safe_release_date(date),
+    release_date_precision = CASE
+        WHEN date ~ '^\d{4}-\d{2}-\d{2}$' THEN 'day'
+        WHEN date ~ '^\d{4}-\d{2}$' THEN 'month'
+        ELSE 'year'
+    END,
+    release_date_source = date;
+ALTER TABLE artists ENABLE TRIGGER artists_set_updated_at;
+ALTER TABLE albums ENABLE TRIGGER albums_set_updated_at;
+ALTER TABLE songs ENABLE TRIGGER songs_set_updated_at;
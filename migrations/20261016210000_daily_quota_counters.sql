@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS daily_quota_counters (
+    group_name TEXT NOT NULL,
+    client_key TEXT NOT NULL,
+    hour TIMESTAMPTZ NOT NULL,
+    count INTEGER NOT NULL,
+    PRIMARY KEY (group_name, client_key, hour)
+);
+CREATE INDEX IF NOT EXISTS daily_quota_counters_hour_idx ON daily_quota_counters (hour);
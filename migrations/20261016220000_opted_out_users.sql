@@ -0,0 +1,5 @@
+-- Users who asked never to have telemetry stored again; ingest discards their submissions.
+CREATE TABLE IF NOT EXISTS opted_out_users (
+    user_id UUID PRIMARY KEY,
+    opted_out_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
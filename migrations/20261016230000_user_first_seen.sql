@@ -0,0 +1,14 @@
+-- When each user first submitted, kept at ingest so cumulative user counts don't have to
+-- scan all of telemetry. A submission older than the stored value moves it back.
+CREATE TABLE IF NOT EXISTS user_first_seen (
+    user_id UUID PRIMARY KEY,
+    first_seen TIMESTAMPTZ NOT NULL
+);
+CREATE INDEX IF NOT EXISTS user_first_seen_first_seen_idx ON user_first_seen (first_seen);
+
+INSERT INTO user_first_seen (user_id, first_seen)
+SELECT user_id, MIN(time)
+FROM telemetry
+GROUP BY user_id
+ON CONFLICT (user_id) DO UPDATE
+SET first_seen = LEAST(user_first_seen.first_seen, EXCLUDED.first_seen);
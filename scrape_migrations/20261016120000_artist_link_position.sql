@@ -0,0 +1,18 @@
+-- Credit order of artist links. `position` is the source's own order when it gives one;
+-- `inserted_seq` records insertion order for links without it. Links from before this
+-- migration have neither and fall back to artist id order. `inserted_seq` is added
+-- without a default first, so existing rows stay NULL instead of forcing a table rewrite.
+ALTER TABLE song_artists ADD COLUMN IF NOT EXISTS position INTEGER;
+ALTER TABLE artist_albums ADD COLUMN IF NOT EXISTS position INTEGER;
+
+CREATE SEQUENCE IF NOT EXISTS song_artists_inserted_seq;
+ALTER TABLE song_artists ADD COLUMN IF NOT EXISTS inserted_seq BIGINT;
+ALTER SEQUENCE song_artists_inserted_seq OWNED BY song_artists.inserted_seq;
+ALTER TABLE song_artists
+    ALTER COLUMN inserted_seq SET DEFAULT nextval('song_artists_inserted_seq');
+
+CREATE SEQUENCE IF NOT EXISTS artist_albums_inserted_seq;
+ALTER TABLE artist_albums ADD COLUMN IF NOT EXISTS inserted_seq BIGINT;
+ALTER SEQUENCE artist_albums_inserted_seq OWNED BY artist_albums.inserted_seq;
+ALTER TABLE artist_albums
+    ALTER COLUMN inserted_seq SET DEFAULT nextval('artist_albums_inserted_seq');
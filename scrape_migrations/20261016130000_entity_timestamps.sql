@@ -0,0 +1,29 @@
+-- `created_at` is added without a default so rows from before this migration stay NULL
+-- (creation time unknown) instead of all claiming today; only new rows get `NOW()`.
+-- `updated_at` starts at the migration time, which is a safe Last-Modified for them.
+ALTER TABLE songs ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ;
+ALTER TABLE songs ALTER COLUMN created_at SET DEFAULT NOW();
+ALTER TABLE songs ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+ALTER TABLE albums ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ;
+ALTER TABLE albums ALTER COLUMN created_at SET DEFAULT NOW();
+ALTER TABLE albums ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+ALTER TABLE artists ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ;
+ALTER TABLE artists ALTER COLUMN created_at SET DEFAULT NOW();
+ALTER TABLE artists ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+
+CREATE OR REPLACE FUNCTION set_updated_at() RETURNS TRIGGER AS $$
+BEGIN
+    NEW.updated_at = NOW();
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS songs_set_updated_at ON songs;
+CREATE TRIGGER songs_set_updated_at BEFORE UPDATE ON songs
+    FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+DROP TRIGGER IF EXISTS albums_set_updated_at ON albums;
+CREATE TRIGGER albums_set_updated_at BEFORE UPDATE ON albums
+    FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+DROP TRIGGER IF EXISTS artists_set_updated_at ON artists;
+CREATE TRIGGER artists_set_updated_at BEFORE UPDATE ON artists
+    FOR EACH ROW EXECUTE FUNCTION set_updated_at();
@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS artist_aliases (
+    artist_id TEXT NOT NULL REFERENCES artists(id) ON DELETE CASCADE,
+    alias TEXT NOT NULL,
+    PRIMARY KEY (artist_id, alias)
+);
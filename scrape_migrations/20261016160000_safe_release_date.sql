@@ -0,0 +1,19 @@
+-- Release dates are scraped as text in several shapes (YYYY, YYYY-MM, YYYY-MM-DD) and some
+-- are garbage. Returns NULL instead of raising for anything that isn't a real date.
+CREATE OR REPLACE FUNCTION safe_release_date(raw TEXT) RETURNS DATE AS $$
+BEGIN
+    IF raw ~ '^\d{4}-\d{2}-\d{2}$' THEN
+        RETURN raw::date;
+    ELSIF raw ~ '^\d{4}-\d{2}$' THEN
+        RETURN (raw || '-01')::date;
+    ELSIF raw ~ '^\d{4}$' THEN
+        RETURN (raw || '-01-01')::date;
+    END IF;
+    RETURN NULL;
+EXCEPTION WHEN others THEN
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql IMMUTABLE;
+
+CREATE INDEX IF NOT EXISTS songs_release_date_idx ON songs (safe_release_date(date), name, id);
+CREATE INDEX IF NOT EXISTS albums_release_date_idx ON albums (safe_release_date(date), name, id);
@@ -0,0 +1,7 @@
+-- Filled from aggregated play counts; songs without a row rank as before.
+CREATE TABLE IF NOT EXISTS song_popularity (
+    song_id TEXT PRIMARY KEY REFERENCES songs(id) ON DELETE CASCADE,
+    plays_30d BIGINT NOT NULL DEFAULT 0,
+    score DOUBLE PRECISION NOT NULL DEFAULT 0,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
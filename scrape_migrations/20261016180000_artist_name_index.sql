@@ -0,0 +1,3 @@
+-- The "C" collation makes both prefix LIKE and ORDER BY usable from the same index,
+-- independent of the database's default collation.
+CREATE INDEX IF NOT EXISTS artists_lower_name_idx ON artists ((lower(name) COLLATE "C"), id);
@@ -0,0 +1,16 @@
+-- The same folding as text::normalize, from built-ins only so no extension (and no
+-- superuser) is needed: compatibility-decompose, drop the combining marks, lower-case,
+-- then the letters lower-casing keeps apart. Case folding of non-ASCII letters follows the
+-- database collation. The indexes on it are built concurrently in the migrations after
+-- this one, each on its own since CONCURRENTLY can't run inside a transaction.
+CREATE OR REPLACE FUNCTION normalized_name(name TEXT) RETURNS TEXT AS $$
+    SELECT translate(
+        replace(replace(
+            regexp_replace(
+                lower(normalize(name, NFKD)),
+                '[\u0300-\u036f\u1ab0-\u1aff\u1dc0-\u1dff\u20d0-\u20ff\ufe20-\ufe2f]', '', 'g'
+            ),
+            'ß', 'ss'), 'ẞ', 'ss'),
+        'ıς', 'iσ'
+    )
+$$ LANGUAGE sql IMMUTABLE STRICT PARALLEL SAFE;
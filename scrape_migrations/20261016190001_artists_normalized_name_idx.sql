@@ -0,0 +1,3 @@
+-- no-transaction
+CREATE INDEX CONCURRENTLY IF NOT EXISTS artists_normalized_name_idx
+    ON artists (normalized_name(name), id);
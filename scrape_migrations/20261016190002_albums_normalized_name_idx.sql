@@ -0,0 +1,3 @@
+-- no-transaction
+CREATE INDEX CONCURRENTLY IF NOT EXISTS albums_normalized_name_idx
+    ON albums (normalized_name(name), id);
@@ -0,0 +1,3 @@
+-- no-transaction
+CREATE INDEX CONCURRENTLY IF NOT EXISTS albums_upc_normalized_idx
+    ON albums (REPLACE(upc, '-', '')) WHERE upc IS NOT NULL AND upc <> '';
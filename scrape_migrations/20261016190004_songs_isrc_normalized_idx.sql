@@ -0,0 +1,3 @@
+-- no-transaction
+CREATE INDEX CONCURRENTLY IF NOT EXISTS songs_isrc_normalized_idx
+    ON songs (REPLACE(UPPER(isrc), '-', '')) WHERE isrc IS NOT NULL AND isrc <> '';
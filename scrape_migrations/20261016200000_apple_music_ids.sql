@@ -0,0 +1,11 @@
+-- The scraper fills these; adding them here keeps fresh databases queryable.
+ALTER TABLE songs ADD COLUMN IF NOT EXISTS apple_music_id TEXT;
+ALTER TABLE albums ADD COLUMN IF NOT EXISTS apple_music_id TEXT;
+ALTER TABLE artists ADD COLUMN IF NOT EXISTS apple_music_id TEXT;
+
+CREATE INDEX IF NOT EXISTS songs_apple_music_id_idx
+    ON songs (apple_music_id) WHERE apple_music_id IS NOT NULL;
+CREATE INDEX IF NOT EXISTS albums_apple_music_id_idx
+    ON albums (apple_music_id) WHERE apple_music_id IS NOT NULL;
+CREATE INDEX IF NOT EXISTS artists_apple_music_id_idx
+    ON artists (apple_music_id) WHERE apple_music_id IS NOT NULL;
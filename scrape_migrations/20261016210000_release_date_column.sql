@@ -0,0 +1,44 @@
+-- Typed release dates, so date features stop casting the scraped text. `date` stays the
+-- source of truth and is still what the API returns; the sync backfill keeps these columns
+-- in step with it. `release_date_source` is the raw value they were parsed from, NULL until
+-- the row has been looked at, so changed rows are easy to find.
+ALTER TABLE albums ADD COLUMN IF NOT EXISTS release_date DATE;
+ALTER TABLE albums ADD COLUMN IF NOT EXISTS release_date_precision TEXT
+    CHECK (release_date_precision IN ('year', 'month', 'day'));
+ALTER TABLE albums ADD COLUMN IF NOT EXISTS release_date_source TEXT;
+ALTER TABLE songs ADD COLUMN IF NOT EXISTS release_date DATE;
+ALTER TABLE songs ADD COLUMN IF NOT EXISTS release_date_precision TEXT
+    CHECK (release_date_precision IN ('year', 'month', 'day'));
+ALTER TABLE songs ADD COLUMN IF NOT EXISTS release_date_source TEXT;
+
+-- The shapes safe_release_date understands are filled in right away so browsing keeps
+-- working until the first backfill handles the rest.
+UPDATE albums SET
+    release_date = safe_release_date(date),
+    release_date_precision = CASE
+        WHEN date ~ '^\d{4}-\d{2}-\d{2}$' THEN 'day'
+        WHEN date ~ '^\d{4}-\d{2}$' THEN 'month'
+        ELSE 'year'
+    END,
+    release_date_source = date
+WHERE safe_release_date(date) IS NOT NULL;
+UPDATE songs SET
+    release_date = safe_release_date(date),
+    release_date_precision = CASE
+        WHEN date ~ '^\d{4}-\d{2}-\d{2}$' THEN 'day'
+        WHEN date ~ '^\d{4}-\d{2}$' THEN 'month'
+        ELSE 'year'
+    END,
+    release_date_source = date
+WHERE safe_release_date(date) IS NOT NULL;
+
+DROP INDEX IF EXISTS songs_release_date_idx;
+DROP INDEX IF EXISTS albums_release_date_idx;
+CREATE INDEX IF NOT EXISTS songs_release_date_idx ON songs (release_date, name, id);
+CREATE INDEX IF NOT EXISTS albums_release_date_idx ON albums (release_date, name, id);
+
+-- Rows the backfill still has to parse.
+CREATE INDEX IF NOT EXISTS songs_release_date_pending_idx ON songs (id)
+    WHERE release_date_source IS DISTINCT FROM COALESCE(date, '');
+CREATE INDEX IF NOT EXISTS albums_release_date_pending_idx ON albums (id)
+    WHERE release_date_source IS DISTINCT FROM COALESCE(date, '');
@@ -0,0 +1,14 @@
+-- Relevance evaluation runs from POST /metadata/v1/admin/eval, kept to chart ranking
+-- quality across deploys.
+CREATE TABLE IF NOT EXISTS eval_runs (
+    id BIGSERIAL PRIMARY KEY,
+    ran_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    label TEXT,
+    backend TEXT NOT NULL,
+    pairs INTEGER NOT NULL,
+    evaluated INTEGER NOT NULL,
+    mrr DOUBLE PRECISION NOT NULL,
+    hit_at_5 DOUBLE PRECISION NOT NULL,
+    results JSONB NOT NULL
+);
+CREATE INDEX IF NOT EXISTS eval_runs_ran_at_idx ON eval_runs (ran_at);
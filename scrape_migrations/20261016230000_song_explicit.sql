@@ -0,0 +1,2 @@
+-- The scraper fills this from the source's content rating; NULL means it gave none.
+ALTER TABLE songs ADD COLUMN IF NOT EXISTS explicit BOOLEAN;
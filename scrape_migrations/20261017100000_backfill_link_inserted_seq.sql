@@ -0,0 +1,43 @@
+-- no-transaction
+-- Gives links from before 20261016120000 an `inserted_seq` in their physical order, which
+-- for rows the scraper only ever inserted is the order it wrote them in. The values are
+-- negative, so they sort before every link inserted since. Works through each table a
+-- range of blocks at a time and commits after each, so no single statement rewrites or
+-- locks a whole table. Safe to rerun: a rerun continues after the last value it assigned.
+DO $$
+DECLARE
+    tbl TEXT;
+    blocks BIGINT;
+    first_block BIGINT;
+    next_seq BIGINT;
+    updated BIGINT;
+    step CONSTANT BIGINT := 1000;
+BEGIN
+    FOREACH tbl IN ARRAY ARRAY['song_artists', 'artist_albums'] LOOP
+        blocks := pg_relation_size(tbl::regclass) / current_setting('block_size')::BIGINT;
+        EXECUTE format('SELECT MAX(inserted_seq) FROM %I WHERE inserted_seq < 0', tbl)
+            INTO next_seq;
+        next_seq := COALESCE(next_seq, -4611686018427387904);
+        first_block := 0;
+        WHILE first_block < blocks LOOP
+            EXECUTE format(
+                'UPDATE %1$I t SET inserted_seq = $1 + s.n
+                 FROM (
+                     SELECT ctid, row_number() OVER (ORDER BY ctid) AS n
+                     FROM %1$I
+                     WHERE ctid >= $2 AND ctid < $3 AND inserted_seq IS NULL
+                 ) s
+                 WHERE t.ctid = s.ctid',
+                tbl
+            )
+            USING next_seq,
+                format('(%s,0)', first_block)::tid,
+                format('(%s,0)', first_block + step)::tid;
+            GET DIAGNOSTICS updated = ROW_COUNT;
+            next_seq := next_seq + updated;
+            first_block := first_block + step;
+            COMMIT;
+        END LOOP;
+    END LOOP;
+END
+$$;
@@ -0,0 +1,28 @@
+-- `date` is what the API serves, so the typed release date columns derived from it are
+-- bookkeeping: the backfill filling them in must not bump `updated_at` (and with it
+-- Last-Modified and the next incremental sync) for every song and album. Any other change
+-- still does, as does an update that leaves the row as it was.
+DROP TRIGGER IF EXISTS songs_set_updated_at ON songs;
+CREATE TRIGGER songs_set_updated_at BEFORE UPDATE ON songs
+    FOR EACH ROW
+    WHEN (
+        (OLD.release_date, OLD.release_date_precision, OLD.release_date_source)
+            IS NOT DISTINCT FROM
+            (NEW.release_date, NEW.release_date_precision, NEW.release_date_source)
+        OR to_jsonb(OLD) - ARRAY['release_date', 'release_date_precision', 'release_date_source', 'updated_at']
+            IS DISTINCT FROM
+            to_jsonb(NEW) - ARRAY['release_date', 'release_date_precision', 'release_date_source', 'updated_at']
+    )
+    EXECUTE FUNCTION set_updated_at();
+DROP TRIGGER IF EXISTS albums_set_updated_at ON albums;
+CREATE TRIGGER albums_set_updated_at BEFORE UPDATE ON albums
+    FOR EACH ROW
+    WHEN (
+        (OLD.release_date, OLD.release_date_precision, OLD.release_date_source)
+            IS NOT DISTINCT FROM
+            (NEW.release_date, NEW.release_date_precision, NEW.release_date_source)
+        OR to_jsonb(OLD) - ARRAY['release_date', 'release_date_precision', 'release_date_source', 'updated_at']
+            IS DISTINCT FROM
+            to_jsonb(NEW) - ARRAY['release_date', 'release_date_precision', 'release_date_source', 'updated_at']
+    )
+    EXECUTE FUNCTION set_updated_at();
@@ -0,0 +1,217 @@
+use serde_json::json;
+use std::time::Duration;
+use time::OffsetDateTime;
+
+use crate::db;
+use crate::manticore::SearchClient;
+use crate::models::metadata::is_valid_omid;
+use crate::notifier::Notifier;
+use crate::search::SearchBackend;
+use crate::sync::{self, ITEM_TYPES, SyncRunner};
+
+pub const USAGE: &str = "usage: vleer_api [serve | --check | --migrate-only | sync [--full] [--daemon] | verify | stats | reindex <song|album|artist> <id> | backfill-dates]";
+
+/// Runs an admin subcommand and returns the process exit code.
+pub async fn run(command: &str, args: &[String]) -> i32 {
+    let result = match command {
+        "sync" => sync(args).await,
+        "verify" => verify(args).await,
+        "stats" => stats(args).await,
+        "reindex" => reindex(args).await,
+        "backfill-dates" => backfill_dates(args).await,
+        _ => Err(Failure::Usage(format!("unknown command: {command}"))),
+    };
+    match result {
+        Ok(()) => 0,
+        Err(Failure::Usage(msg)) => {
+            eprintln!("{msg}\n{USAGE}");
+            2
+        }
+        Err(Failure::Failed(msg)) => {
+            eprintln!("{command} failed: {msg}");
+            1
+        }
+    }
+}
+
+enum Failure {
+    Usage(String),
+    Failed(String),
+}
+
+impl<E: std::fmt::Display> From<E> for Failure {
+    fn from(e: E) -> Self {
+        Failure::Failed(e.to_string())
+    }
+}
+
+fn no_args(command: &str, args: &[String]) -> Result<(), Failure> {
+    match args.first() {
+        Some(arg) => Err(Failure::Usage(format!(
+            "unexpected argument for {command}: {arg}"
+        ))),
+        None => Ok(()),
+    }
+}
+
+fn manticore() -> Result<SearchClient, Failure> {
+    match SearchBackend::from_env()? {
+        SearchBackend::Manticore(client) => Ok(client),
+        SearchBackend::Memory(_) => Err(Failure::Failed(
+            "this command requires SEARCH_BACKEND=manticore".to_string(),
+        )),
+    }
+}
+
+async fn sync(args: &[String]) -> Result<(), Failure> {
+    let mut full = false;
+    let mut daemon = false;
+    for arg in args {
+        match arg.as_str() {
+            "--full" => full = true,
+            "--daemon" => daemon = true,
+            other => {
+                return Err(Failure::Usage(format!(
+                    "unexpected argument for sync: {other}"
+                )));
+            }
+        }
+    }
+
+    let interval = Duration::from_secs(
+        std::env::var("SYNC_INTERVAL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|v| *v > 0)
+            .unwrap_or(300),
+    );
+    let pool = db::create_scrape_batch_pool(&db::scrape_database_url()).await?;
+    let runner = SyncRunner::new(pool, manticore()?, !daemon);
+    let notifier = Notifier::from_env()?;
+
+    let mut since = OffsetDateTime::now_utc() - interval;
+    loop {
+        let started = OffsetDateTime::now_utc();
+        let mode = if full { "full" } else { "incremental" };
+        let synced = if full {
+            runner.full().await
+        } else {
+            runner.incremental(since).await
+        };
+        let (sent, result) = match synced {
+            Ok(counts) => {
+                let total: u64 = counts.iter().map(|(_, n)| n).sum();
+                tracing::info!("sync complete, {} documents indexed", total);
+                let duration = (OffsetDateTime::now_utc() - started).as_seconds_f64();
+                let counts: serde_json::Map<String, serde_json::Value> = counts
+                    .into_iter()
+                    .map(|(item_type, n)| (item_type.to_string(), n.into()))
+                    .collect();
+                since = started;
+                full = false;
+                let data = json!({ "mode": mode, "counts": counts, "durationSecs": duration });
+                (notifier.notify("sync.completed", mode, data), Ok(()))
+            }
+            Err(e) => {
+                let data = json!({ "mode": mode, "error": e.to_string() });
+                let sent = notifier.notify("sync.failed", mode, data);
+                if daemon {
+                    tracing::error!("sync failed, retrying next interval: {}", e);
+                }
+                (sent, Err(e))
+            }
+        };
+        if !daemon {
+            // The process exits next, which would cancel the delivery.
+            if let Some(sent) = sent {
+                let _ = sent.await;
+            }
+            return result.map_err(Failure::from);
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+async fn counts() -> Result<Vec<(&'static str, i64, i64)>, Failure> {
+    let pool = db::create_scrape_batch_pool(&db::scrape_database_url()).await?;
+    let indexed = SearchBackend::from_env()?.count_by_type().await?;
+    let mut rows = Vec::new();
+    for item_type in ITEM_TYPES {
+        let stored = db::metadata::row_count(&pool, item_type).await?;
+        rows.push((
+            item_type,
+            stored,
+            indexed.get(item_type).copied().unwrap_or(0),
+        ));
+    }
+    Ok(rows)
+}
+
+fn print_counts(rows: &[(&str, i64, i64)]) {
+    println!("{:<8} {:>12} {:>12}", "type", "postgres", "index");
+    for (item_type, stored, indexed) in rows {
+        println!("{item_type:<8} {stored:>12} {indexed:>12}");
+    }
+}
+
+async fn stats(args: &[String]) -> Result<(), Failure> {
+    no_args("stats", args)?;
+    print_counts(&counts().await?);
+    Ok(())
+}
+
+async fn verify(args: &[String]) -> Result<(), Failure> {
+    no_args("verify", args)?;
+    let rows = counts().await?;
+    print_counts(&rows);
+    let mismatched: Vec<&str> = rows
+        .iter()
+        .filter(|(_, stored, indexed)| stored != indexed)
+        .map(|(item_type, _, _)| *item_type)
+        .collect();
+    if mismatched.is_empty() {
+        Ok(())
+    } else {
+        Err(Failure::Failed(format!(
+            "index out of sync for: {}",
+            mismatched.join(", ")
+        )))
+    }
+}
+
+async fn reindex(args: &[String]) -> Result<(), Failure> {
+    let [item_type, id] = args else {
+        return Err(Failure::Usage("reindex expects <type> <id>".to_string()));
+    };
+    if !ITEM_TYPES.contains(&item_type.as_str()) {
+        return Err(Failure::Usage(format!("invalid type: {item_type}")));
+    }
+    let id = id.trim().to_lowercase();
+    if !is_valid_omid(&id) {
+        return Err(Failure::Usage(format!("invalid id: {id}")));
+    }
+
+    let pool = db::create_scrape_batch_pool(&db::scrape_database_url()).await?;
+    let runner = SyncRunner::new(pool, manticore()?, false);
+    if runner.reindex(item_type, &id).await? {
+        println!("reindexed {item_type} {id}");
+        Ok(())
+    } else {
+        Err(Failure::Failed(format!("{item_type} {id} not found")))
+    }
+}
+
+/// Every sync backfills release dates first; this runs just that step, e.g. right after the
+/// migration that adds the typed columns.
+async fn backfill_dates(args: &[String]) -> Result<(), Failure> {
+    no_args("backfill-dates", args)?;
+    let pool = db::create_scrape_batch_pool(&db::scrape_database_url()).await?;
+    println!("{:<8} {:>12} {:>12}", "type", "parsed", "unparseable");
+    for (item_type, counts) in sync::backfill_release_dates(&pool).await? {
+        println!(
+            "{item_type:<8} {:>12} {:>12}",
+            counts.parsed, counts.unparseable
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,463 @@
+use metrics_exporter_prometheus::PrometheusHandle;
+use serde::Serialize;
+use serde_json::json;
+use sqlx::PgPool;
+use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+
+use crate::db;
+use crate::notifier::Notifier;
+use crate::search::SearchBackend;
+use crate::sync::ITEM_TYPES;
+
+/// Samples older than this are dropped; it bounds how far back windowed signals can look.
+const HISTORY: Duration = Duration::from_secs(2 * 60 * 60);
+const DEFAULT_FOR_EVALUATIONS: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    fn holds(self, value: f64, threshold: f64) -> bool {
+        match self {
+            Op::Lt => value < threshold,
+            Op::Le => value <= threshold,
+            Op::Gt => value > threshold,
+            Op::Ge => value >= threshold,
+        }
+    }
+}
+
+/// `<signal> <op> <threshold> [for <n>]`, e.g. `search_error_ratio_5m > 0.05 for 5`. The
+/// rule fires once the condition held for `n` consecutive evaluations (default 3) and
+/// resolves once it has not held for another `n`, so a flapping signal doesn't page twice.
+///
+/// Signals computed here rather than read straight off `/metrics`:
+///
+/// - `ingest_rate_1h`: telemetry submissions in the last hour
+/// - `search_error_ratio_5m`: failed index searches over all index searches in the last
+///   5 minutes; no value while there were none
+/// - `index_drift_count`: summed difference between searchable rows and indexed documents
+///   per type. Counts every table exactly, so only computed when a rule uses it.
+///
+/// Any other name is read from the metrics exposition, summed over all label sets.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    expr: String,
+    signal: String,
+    op: Op,
+    threshold: f64,
+    for_evaluations: u32,
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split_whitespace().collect();
+        let (signal, op, threshold, for_evaluations) = match parts.as_slice() {
+            [signal, op, threshold] => (*signal, *op, *threshold, None),
+            [signal, op, threshold, "for", n] => (*signal, *op, *threshold, Some(*n)),
+            _ => {
+                return Err(format!(
+                    "{s:?} is not `<signal> <op> <threshold> [for <n>]`"
+                ));
+            }
+        };
+        if signal.is_empty()
+            || signal.starts_with(|c: char| c.is_ascii_digit())
+            || !signal
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+        {
+            return Err(format!("invalid signal name {signal:?}"));
+        }
+        let op = match op {
+            "<" => Op::Lt,
+            "<=" => Op::Le,
+            ">" => Op::Gt,
+            ">=" => Op::Ge,
+            _ => return Err(format!("invalid operator {op:?}, expected <, <=, > or >=")),
+        };
+        let threshold = threshold
+            .parse::<f64>()
+            .ok()
+            .filter(|t| t.is_finite())
+            .ok_or_else(|| format!("invalid threshold {threshold:?}"))?;
+        let for_evaluations = match for_evaluations {
+            Some(n) => n
+                .parse::<u32>()
+                .ok()
+                .filter(|n| *n > 0)
+                .ok_or_else(|| format!("invalid evaluation count {n:?}"))?,
+            None => DEFAULT_FOR_EVALUATIONS,
+        };
+        Ok(Rule {
+            expr: parts.join(" "),
+            signal: signal.to_string(),
+            op,
+            threshold,
+            for_evaluations,
+        })
+    }
+}
+
+/// Parses `ALERT_RULES`, a `;`-separated list of rules.
+pub fn rules_from_env() -> Result<Vec<Rule>, String> {
+    std::env::var("ALERT_RULES")
+        .unwrap_or_default()
+        .split(';')
+        .map(str::trim)
+        .filter(|r| !r.is_empty())
+        .map(|r| {
+            r.parse()
+                .map_err(|e| format!("invalid ALERT_RULES entry: {e}"))
+        })
+        .collect()
+}
+
+pub fn interval() -> Duration {
+    Duration::from_secs(
+        std::env::var("ALERT_INTERVAL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|v| *v > 0)
+            .unwrap_or(60),
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertState {
+    Inactive,
+    /// The condition holds but not yet for enough consecutive evaluations.
+    Pending,
+    Firing,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Transition {
+    Fired,
+    Resolved,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlertStatus {
+    pub rule: String,
+    pub state: AlertState,
+    /// `None` when the signal had no value, which never counts as a breach.
+    pub value: Option<f64>,
+    pub consecutive: u32,
+    /// Consecutive evaluations without a breach while firing.
+    pub consecutive_clear: u32,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub firing_since: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub last_evaluated: Option<OffsetDateTime>,
+}
+
+impl AlertStatus {
+    fn new(rule: &Rule) -> Self {
+        Self {
+            rule: rule.expr.clone(),
+            state: AlertState::Inactive,
+            value: None,
+            consecutive: 0,
+            consecutive_clear: 0,
+            firing_since: None,
+            last_evaluated: None,
+        }
+    }
+
+    /// Records one evaluation. Firing needs `for_evaluations` breaches in a row, and
+    /// resolving as many evaluations in a row without one.
+    fn step(&mut self, rule: &Rule, value: Option<f64>, now: OffsetDateTime) -> Option<Transition> {
+        self.value = value;
+        self.last_evaluated = Some(now);
+        let breaching = value.is_some_and(|v| rule.op.holds(v, rule.threshold));
+        if !breaching {
+            self.consecutive = 0;
+            if self.state != AlertState::Firing {
+                self.state = AlertState::Inactive;
+                return None;
+            }
+            self.consecutive_clear = self.consecutive_clear.saturating_add(1);
+            if self.consecutive_clear < rule.for_evaluations {
+                return None;
+            }
+            self.consecutive_clear = 0;
+            self.firing_since = None;
+            self.state = AlertState::Inactive;
+            return Some(Transition::Resolved);
+        }
+
+        self.consecutive_clear = 0;
+        self.consecutive = self.consecutive.saturating_add(1);
+        match self.state {
+            AlertState::Firing => None,
+            _ if self.consecutive >= rule.for_evaluations => {
+                self.state = AlertState::Firing;
+                self.firing_since = Some(now);
+                Some(Transition::Fired)
+            }
+            _ => {
+                self.state = AlertState::Pending;
+                None
+            }
+        }
+    }
+}
+
+/// Where signals come from.
+pub struct Sources {
+    pub metrics: PrometheusHandle,
+    pub scrape_pool: Option<PgPool>,
+    pub search: Arc<SearchBackend>,
+}
+
+#[derive(Clone)]
+pub struct Alerts(Arc<Inner>);
+
+struct Inner {
+    rules: Vec<Rule>,
+    statuses: Mutex<Vec<AlertStatus>>,
+    /// Metric sums per evaluation, oldest first, for windowed signals.
+    history: Mutex<VecDeque<(Instant, HashMap<String, f64>)>>,
+    sources: Sources,
+    notifier: Notifier,
+}
+
+impl Alerts {
+    pub fn new(rules: Vec<Rule>, sources: Sources, notifier: Notifier) -> Self {
+        let statuses = rules.iter().map(AlertStatus::new).collect();
+        Self(Arc::new(Inner {
+            rules,
+            statuses: Mutex::new(statuses),
+            history: Mutex::new(VecDeque::new()),
+            sources,
+            notifier,
+        }))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.rules.is_empty()
+    }
+
+    pub fn statuses(&self) -> Vec<AlertStatus> {
+        self.0
+            .statuses
+            .lock()
+            .expect("alert status lock poisoned")
+            .clone()
+    }
+
+    /// Evaluates every rule once and sends `alert.firing` / `alert.resolved` webhooks for
+    /// the rules that changed state.
+    pub async fn evaluate(self) -> anyhow::Result<()> {
+        let inner = &self.0;
+        let now = Instant::now();
+        let sample = parse_exposition(&inner.sources.metrics.render());
+        let (ingest, error_ratio) = {
+            let mut history = inner.history.lock().expect("alert history lock poisoned");
+            history.push_back((now, sample.clone()));
+            while history
+                .front()
+                .is_some_and(|(at, _)| now.duration_since(*at) > HISTORY)
+            {
+                history.pop_front();
+            }
+            let window = Duration::from_secs(5 * 60);
+            let requests = increase(&history, "search_requests_total", window);
+            let errors = increase(&history, "search_errors_total", window);
+            (
+                sample.get("telemetry_submissions_last_hour").copied(),
+                (requests > 0.0).then(|| errors / requests),
+            )
+        };
+
+        let drift = if inner.rules.iter().any(|r| r.signal == "index_drift_count") {
+            match self.index_drift().await {
+                Ok(drift) => drift,
+                Err(e) => {
+                    tracing::warn!("index drift signal failed: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let evaluated_at = OffsetDateTime::now_utc();
+        let mut statuses = inner.statuses.lock().expect("alert status lock poisoned");
+        for (rule, status) in inner.rules.iter().zip(statuses.iter_mut()) {
+            let value = match rule.signal.as_str() {
+                "ingest_rate_1h" => ingest,
+                "search_error_ratio_5m" => error_ratio,
+                "index_drift_count" => drift,
+                name => sample.get(name).copied(),
+            };
+            let event = match status.step(rule, value, evaluated_at) {
+                Some(Transition::Fired) => "alert.firing",
+                Some(Transition::Resolved) => "alert.resolved",
+                None => continue,
+            };
+            tracing::warn!(rule = %rule.expr, value = ?value, "{}", event);
+            let data = json!({ "rule": rule.expr, "value": value });
+            inner.notifier.notify(event, &rule.expr, data);
+        }
+        Ok(())
+    }
+
+    async fn index_drift(&self) -> anyhow::Result<Option<f64>> {
+        let Some(pool) = &self.0.sources.scrape_pool else {
+            return Ok(None);
+        };
+        let indexed = self.0.sources.search.count_by_type().await?;
+        let mut drift = 0;
+        for item_type in ITEM_TYPES {
+            let rows = db::metadata::indexable_count(pool, item_type).await?;
+            let docs = indexed.get(item_type).copied().unwrap_or(0);
+            drift += (rows - docs).abs();
+        }
+        Ok(Some(drift as f64))
+    }
+}
+
+/// Sums every sample in a Prometheus text exposition by metric name, across label sets.
+fn parse_exposition(text: &str) -> HashMap<String, f64> {
+    let mut sums = HashMap::new();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let name_end = line.find(['{', ' ']).unwrap_or(line.len());
+        let Some(value) = line
+            .rsplit_once(' ')
+            .and_then(|(_, v)| v.parse::<f64>().ok())
+            .filter(|v| v.is_finite())
+        else {
+            continue;
+        };
+        *sums.entry(line[..name_end].to_string()).or_insert(0.0) += value;
+    }
+    sums
+}
+
+/// How much a counter grew over `window`, measured from the newest sample at least that old
+/// (or the oldest one, early after startup).
+fn increase(
+    history: &VecDeque<(Instant, HashMap<String, f64>)>,
+    name: &str,
+    window: Duration,
+) -> f64 {
+    let Some((now, latest)) = history.back() else {
+        return 0.0;
+    };
+    let baseline = history
+        .iter()
+        .rev()
+        .find(|(at, _)| now.duration_since(*at) >= window)
+        .or(history.front())
+        .map(|(_, sample)| sample.get(name).copied().unwrap_or(0.0))
+        .unwrap_or(0.0);
+    (latest.get(name).copied().unwrap_or(0.0) - baseline).max(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(status: &mut AlertStatus, rule: &Rule, values: &[Option<f64>]) -> Vec<AlertState> {
+        values
+            .iter()
+            .map(|&v| {
+                status.step(rule, v, OffsetDateTime::UNIX_EPOCH);
+                status.state
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pending_then_firing_then_resolved() {
+        let rule: Rule = "errors > 5 for 2".parse().unwrap();
+        let mut status = AlertStatus::new(&rule);
+        let now = OffsetDateTime::UNIX_EPOCH;
+
+        assert_eq!(status.step(&rule, Some(10.0), now), None);
+        assert_eq!(status.state, AlertState::Pending);
+        assert_eq!(status.step(&rule, Some(10.0), now), Some(Transition::Fired));
+        assert_eq!(status.state, AlertState::Firing);
+        assert_eq!(status.firing_since, Some(now));
+        assert_eq!(status.step(&rule, Some(10.0), now), None);
+
+        assert_eq!(status.step(&rule, Some(1.0), now), None);
+        assert_eq!(status.state, AlertState::Firing);
+        assert_eq!(
+            status.step(&rule, Some(1.0), now),
+            Some(Transition::Resolved)
+        );
+        assert_eq!(status.state, AlertState::Inactive);
+        assert_eq!(status.firing_since, None);
+    }
+
+    #[test]
+    fn a_single_clear_evaluation_does_not_resolve() {
+        let rule: Rule = "errors > 5 for 3".parse().unwrap();
+        let mut status = AlertStatus::new(&rule);
+        let states = run(
+            &mut status,
+            &rule,
+            &[
+                Some(9.0),
+                Some(9.0),
+                Some(9.0),
+                // Flapping: clear, breach, clear, clear, clear.
+                Some(0.0),
+                Some(9.0),
+                Some(0.0),
+                None,
+                Some(0.0),
+            ],
+        );
+        use AlertState::*;
+        assert_eq!(
+            states,
+            [
+                Pending, Pending, Firing, Firing, Firing, Firing, Firing, Inactive
+            ]
+        );
+    }
+
+    #[test]
+    fn pending_drops_back_on_a_clear_evaluation() {
+        let rule: Rule = "errors > 5 for 3".parse().unwrap();
+        let mut status = AlertStatus::new(&rule);
+        let states = run(
+            &mut status,
+            &rule,
+            &[Some(9.0), Some(9.0), Some(0.0), Some(9.0)],
+        );
+        use AlertState::*;
+        assert_eq!(states, [Pending, Pending, Inactive, Pending]);
+    }
+
+    #[test]
+    fn parses_rules() {
+        let rule: Rule = "search_error_ratio_5m  >  0.05".parse().unwrap();
+        assert_eq!(rule.expr, "search_error_ratio_5m > 0.05");
+        assert_eq!(rule.op, Op::Gt);
+        assert_eq!(rule.for_evaluations, DEFAULT_FOR_EVALUATIONS);
+        assert!("x > 1 for 0".parse::<Rule>().is_err());
+        assert!("x => 1".parse::<Rule>().is_err());
+        assert!("1x > 1".parse::<Rule>().is_err());
+    }
+}
@@ -0,0 +1,31 @@
+use axum::{
+    Json, Router,
+    extract::State,
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use serde_json::json;
+
+use crate::alerts::Alerts;
+use crate::auth::{self, ApiKeys};
+use crate::ip_allowlist::{self, IpAllowlist};
+
+pub fn router(alerts: Alerts, api_keys: ApiKeys, allowlist: IpAllowlist) -> Router {
+    Router::new()
+        .route("/admin/alerts", get(alerts_handler))
+        .layer(middleware::from_fn_with_state(
+            (api_keys, "admin"),
+            auth::require_scope,
+        ))
+        .layer(middleware::from_fn_with_state(
+            allowlist,
+            ip_allowlist::require_allowed_ip,
+        ))
+        .with_state(alerts)
+}
+
+async fn alerts_handler(State(alerts): State<Alerts>) -> Response {
+    (StatusCode::OK, Json(json!({ "data": alerts.statuses() }))).into_response()
+}
@@ -0,0 +1,22 @@
+use axum::{Json, Router, http::StatusCode, response::IntoResponse, routing::get};
+use serde_json::json;
+
+use crate::client_requirements;
+
+pub fn router() -> Router {
+    Router::new().route("/requirements", get(requirements_handler))
+}
+
+/// What the desktop app checks at startup: below `min_version` it should stop using the API
+/// and ask the user to update.
+async fn requirements_handler() -> impl IntoResponse {
+    let requirements = client_requirements::current();
+    (
+        StatusCode::OK,
+        Json(json!({
+            "min_version": requirements.min_version,
+            "latest_version": requirements.latest_version,
+            "deprecations": requirements.deprecations,
+        })),
+    )
+}
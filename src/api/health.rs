@@ -0,0 +1,402 @@
+use axum::{
+    Json, Router,
+    extract::State,
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use serde::Serialize;
+use serde_json::{Value, json};
+use sqlx::PgPool;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+
+use crate::auth::{self, ApiKeys};
+use crate::db::DbPools;
+use crate::ip_allowlist::{self, IpAllowlist};
+use crate::search::SearchBackend;
+
+/// An acquire slower than this means requests are queueing for connections.
+const ACQUIRE_BUDGET: Duration = Duration::from_millis(250);
+const SCHEMA_CHECK_BUDGET: Duration = Duration::from_secs(1);
+pub const HISTORY_INTERVAL: Duration = Duration::from_secs(60);
+/// 24 hours of snapshots at [`HISTORY_INTERVAL`].
+const HISTORY_CAPACITY: usize = 24 * 60;
+/// Each probe has its own budget; this bounds a whole snapshot in case one ignores it.
+const SNAPSHOT_BUDGET: Duration = Duration::from_secs(10);
+
+/// Connection pools worth watching, by the name used in the response and metric labels.
+#[derive(Clone)]
+pub struct Pools(Vec<(&'static str, PgPool)>);
+
+impl Pools {
+    pub fn new(db: &DbPools, scrape_pool: Option<PgPool>) -> Self {
+        let mut pools = vec![("telemetry", db.primary.clone())];
+        pools.extend(db.replica.clone().map(|pool| ("telemetry_replica", pool)));
+        pools.extend(scrape_pool.map(|pool| ("scrape", pool)));
+        Self(pools)
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolHealth {
+    pub status: &'static str,
+    pub size: u32,
+    pub idle: usize,
+    pub max_connections: u32,
+    /// `None` when no connection became free within the probe budget.
+    pub acquire_wait_ms: Option<u64>,
+}
+
+/// Cache entries filled by the startup warm-up.
+#[derive(Clone, Default)]
+pub struct WarmedEntries(Arc<AtomicUsize>);
+
+impl WarmedEntries {
+    pub fn set(&self, count: usize) {
+        self.0.store(count, Ordering::Relaxed);
+    }
+}
+
+/// The last check of every component, taken by the `db_pool_health` job. `/health` is
+/// public and unthrottled, so it serves this rather than probing the pools per request.
+#[derive(Clone, Default)]
+pub struct LatestHealth(Arc<RwLock<Option<Checked>>>);
+
+struct Checked {
+    at: OffsetDateTime,
+    database: serde_json::Map<String, Value>,
+    search_index: Value,
+}
+
+/// Component statuses taken every [`HISTORY_INTERVAL`] over the last 24 hours, oldest
+/// first, so flapping can be dated after the fact.
+#[derive(Clone, Default)]
+pub struct HealthHistory(Arc<Mutex<VecDeque<Snapshot>>>);
+
+struct Snapshot {
+    at: OffsetDateTime,
+    statuses: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+struct SeriesPoint {
+    #[serde(with = "time::serde::rfc3339")]
+    time: OffsetDateTime,
+    status: String,
+}
+
+impl HealthHistory {
+    fn push(&self, snapshot: Snapshot) {
+        let mut snapshots = self.0.lock().expect("health history lock poisoned");
+        if snapshots.len() >= HISTORY_CAPACITY {
+            snapshots.pop_front();
+        }
+        snapshots.push_back(snapshot);
+    }
+}
+
+pub fn router(latest: LatestHealth, warmed: WarmedEntries) -> Router {
+    Router::new()
+        .route("/health", get(health_handler))
+        .with_state((latest, warmed))
+}
+
+pub fn history_router(history: HealthHistory, api_keys: ApiKeys, allowlist: IpAllowlist) -> Router {
+    Router::new()
+        .route("/admin/health/history", get(history_handler))
+        .layer(middleware::from_fn_with_state(
+            (api_keys, "admin"),
+            auth::require_scope,
+        ))
+        .layer(middleware::from_fn_with_state(
+            allowlist,
+            ip_allowlist::require_allowed_ip,
+        ))
+        .with_state(history)
+}
+
+fn degraded_after() -> Duration {
+    Duration::from_millis(
+        std::env::var("HEALTH_ACQUIRE_DEGRADED_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|v| *v > 0)
+            .unwrap_or(100),
+    )
+}
+
+/// Times a connection acquire. A probe that runs out of budget is dropped before it gets a
+/// connection, so it never holds one that a request is waiting for.
+pub async fn probe(name: &'static str, pool: &PgPool) -> PoolHealth {
+    let started = Instant::now();
+    let wait = match tokio::time::timeout(ACQUIRE_BUDGET, pool.acquire()).await {
+        Ok(Ok(conn)) => {
+            drop(conn);
+            Some(started.elapsed())
+        }
+        Ok(Err(e)) => {
+            tracing::warn!("{} pool health probe failed: {}", name, e);
+            None
+        }
+        Err(_) => None,
+    };
+
+    let size = pool.size();
+    let idle = pool.num_idle();
+    metrics::gauge!("db_pool_connections", "pool" => name).set(size as f64);
+    metrics::gauge!("db_pool_idle_connections", "pool" => name).set(idle as f64);
+    metrics::gauge!("db_pool_acquire_wait_seconds", "pool" => name)
+        .set(wait.unwrap_or(ACQUIRE_BUDGET).as_secs_f64());
+
+    PoolHealth {
+        status: match wait {
+            Some(wait) if wait < degraded_after() => "ok",
+            _ => "degraded",
+        },
+        size,
+        idle,
+        max_connections: pool.options().get_max_connections(),
+        acquire_wait_ms: wait.map(|w| w.as_millis() as u64),
+    }
+}
+
+/// Checks every component for `/health`, refreshing the pool gauges on /metrics as it goes.
+pub async fn sample(
+    latest: LatestHealth,
+    pools: Pools,
+    search: Arc<SearchBackend>,
+) -> anyhow::Result<()> {
+    let (database, search_index) = check_components(&pools, &search).await;
+    *latest.0.write().expect("latest health lock poisoned") = Some(Checked {
+        at: OffsetDateTime::now_utc(),
+        database,
+        search_index,
+    });
+    Ok(())
+}
+
+/// The search index's schema version against the binary's. An outdated index still serves
+/// queries, but documents from before the change lack newer fields.
+async fn search_index_health(search: &SearchBackend) -> Value {
+    match tokio::time::timeout(SCHEMA_CHECK_BUDGET, search.schema_status()).await {
+        Ok(Ok(schema)) => {
+            let status = if schema.is_current() {
+                "ok"
+            } else {
+                "outdated"
+            };
+            let mut health = json!(schema);
+            health["status"] = json!(status);
+            health
+        }
+        Ok(Err(e)) => {
+            tracing::warn!("search index schema check failed: {}", e);
+            json!({ "status": "unavailable" })
+        }
+        Err(_) => json!({ "status": "unavailable" }),
+    }
+}
+
+/// Probes every pool and the search index: the pools keyed by name, then the index.
+async fn check_components(
+    pools: &Pools,
+    search: &SearchBackend,
+) -> (serde_json::Map<String, Value>, Value) {
+    let mut database = serde_json::Map::new();
+    for (name, pool) in &pools.0 {
+        database.insert(name.to_string(), json!(probe(name, pool).await));
+    }
+    (database, search_index_health(search).await)
+}
+
+/// `unknown` until the first check has run.
+async fn health_handler(
+    State((latest, warmed)): State<(LatestHealth, WarmedEntries)>,
+) -> (StatusCode, Json<Value>) {
+    let latest = latest.0.read().expect("latest health lock poisoned");
+    let warmed = warmed.0.load(Ordering::Relaxed);
+    let Some(checked) = latest.as_ref() else {
+        return (
+            StatusCode::OK,
+            Json(json!({
+                "status": "unknown",
+                "components": {},
+                "checkedAt": null,
+                "warmedEntries": warmed,
+            })),
+        );
+    };
+    let degraded = checked.database.values().any(|h| h["status"] != "ok")
+        || checked.search_index["status"] != "ok";
+    let status = if degraded { "degraded" } else { "ok" };
+    (
+        StatusCode::OK,
+        Json(json!({
+            "status": status,
+            "components": { "database": checked.database, "searchIndex": checked.search_index },
+            "checkedAt": checked.at.format(&Rfc3339).ok(),
+            "warmedEntries": warmed,
+        })),
+    )
+}
+
+/// Takes one snapshot for the history. A snapshot that runs out of budget records every
+/// component as unavailable rather than holding up the next run.
+pub async fn record_history(
+    history: HealthHistory,
+    pools: Pools,
+    search: Arc<SearchBackend>,
+) -> anyhow::Result<()> {
+    let at = OffsetDateTime::now_utc();
+    let names = pools
+        .0
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .chain(["searchIndex".to_string()]);
+    let statuses =
+        match tokio::time::timeout(SNAPSHOT_BUDGET, check_components(&pools, &search)).await {
+            Ok((database, search_index)) => database
+                .iter()
+                .map(|(name, health)| (name.clone(), health["status"].clone()))
+                .chain([("searchIndex".to_string(), search_index["status"].clone())])
+                .map(|(name, status)| (name, status.as_str().unwrap_or("unavailable").to_string()))
+                .collect(),
+            Err(_) => {
+                tracing::warn!("health snapshot timed out");
+                names
+                    .map(|name| (name, "unavailable".to_string()))
+                    .collect()
+            }
+        };
+    history.push(Snapshot { at, statuses });
+    Ok(())
+}
+
+/// Per component, its status series and the share of snapshots it was `ok`, as a
+/// percentage.
+async fn history_handler(State(history): State<HealthHistory>) -> Response {
+    let snapshots = history.0.lock().expect("health history lock poisoned");
+    let mut series: BTreeMap<&str, Vec<SeriesPoint>> = BTreeMap::new();
+    for snapshot in snapshots.iter() {
+        for (name, status) in &snapshot.statuses {
+            series.entry(name).or_default().push(SeriesPoint {
+                time: snapshot.at,
+                status: status.clone(),
+            });
+        }
+    }
+    let components: serde_json::Map<String, Value> = series
+        .into_iter()
+        .map(|(name, points)| {
+            let ok = points.iter().filter(|p| p.status == "ok").count();
+            let uptime = 100.0 * ok as f64 / points.len() as f64;
+            (
+                name.to_string(),
+                json!({ "uptimePercent": uptime, "series": points }),
+            )
+        })
+        .collect();
+    (
+        StatusCode::OK,
+        Json(json!({
+            "data": components,
+            "length": snapshots.len(),
+            "capacity": HISTORY_CAPACITY,
+            "intervalSecs": HISTORY_INTERVAL.as_secs(),
+        })),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::postgres::PgPoolOptions;
+
+    use crate::test_support::{self, get, send};
+
+    /// Runs one `db_pool_health` check over `pools` and fetches /health.
+    async fn checked_health(pools: Pools) -> Value {
+        let latest = LatestHealth::default();
+        sample(latest.clone(), pools, test_support::memory_search())
+            .await
+            .expect("sample");
+        let (status, _, body) =
+            send(router(latest, WarmedEntries::default()), get("/health")).await;
+        assert_eq!(status, StatusCode::OK);
+        body
+    }
+
+    #[tokio::test]
+    async fn serves_unknown_until_the_first_check() {
+        let app = router(LatestHealth::default(), WarmedEntries::default());
+        let (status, _, body) = send(app, get("/health")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "unknown");
+        assert_eq!(body["checkedAt"], Value::Null);
+    }
+
+    #[tokio::test]
+    async fn unreachable_database_reports_degraded() {
+        let pool = test_support::unreachable_pool();
+        let body = checked_health(Pools::new(&DbPools::new(pool, None), None)).await;
+        assert_eq!(body["status"], "degraded");
+        assert!(body["checkedAt"].is_string());
+        assert_eq!(
+            body["components"]["database"]["telemetry"]["status"],
+            "degraded"
+        );
+        assert_eq!(
+            body["components"]["database"]["telemetry"]["acquireWaitMs"],
+            Value::Null
+        );
+        assert_eq!(body["components"]["searchIndex"]["status"], "ok");
+    }
+
+    #[tokio::test]
+    async fn healthy_pools_report_ok() {
+        let Some(pool) = test_support::scrape_db().await else {
+            return;
+        };
+        let body = checked_health(Pools::new(&DbPools::new(pool.clone(), None), Some(pool))).await;
+        assert_eq!(body["status"], "ok", "{body}");
+        assert_eq!(body["components"]["database"]["scrape"]["status"], "ok");
+    }
+
+    #[tokio::test]
+    async fn exhausted_pool_reports_degraded() {
+        let Some(scrape) = test_support::scrape_db().await else {
+            return;
+        };
+        let pool = PgPoolOptions::new()
+            .max_connections(1)
+            .connect_with((*scrape.connect_options()).clone())
+            .await
+            .expect("connect");
+        let held = pool.acquire().await.expect("the only connection");
+
+        let body = checked_health(Pools::new(&DbPools::new(pool.clone(), None), None)).await;
+        let telemetry = &body["components"]["database"]["telemetry"];
+        assert_eq!(body["status"], "degraded", "{body}");
+        assert_eq!(telemetry["status"], "degraded");
+        assert_eq!(telemetry["acquireWaitMs"], Value::Null);
+        assert_eq!(telemetry["size"], 1);
+        assert_eq!(telemetry["idle"], 0);
+        assert_eq!(telemetry["maxConnections"], 1);
+
+        // The timed-out probe didn't take the connection once it came free.
+        drop(held);
+        let body = checked_health(Pools::new(&DbPools::new(pool, None), None)).await;
+        assert_eq!(
+            body["components"]["database"]["telemetry"]["status"], "ok",
+            "{body}"
+        );
+    }
+}
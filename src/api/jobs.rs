@@ -0,0 +1,47 @@
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use serde_json::json;
+
+use crate::api::error_response;
+use crate::auth::{self, ApiKeys};
+use crate::ip_allowlist::{self, IpAllowlist};
+use crate::scheduler::{Scheduler, Trigger};
+
+pub fn router(scheduler: Scheduler, api_keys: ApiKeys, allowlist: IpAllowlist) -> Router {
+    Router::new()
+        .route("/admin/jobs", get(jobs_handler))
+        .route("/admin/jobs/{name}/run", post(run_job_handler))
+        .layer(middleware::from_fn_with_state(
+            (api_keys, "admin"),
+            auth::require_scope,
+        ))
+        .layer(middleware::from_fn_with_state(
+            allowlist,
+            ip_allowlist::require_allowed_ip,
+        ))
+        .with_state(scheduler)
+}
+
+async fn jobs_handler(State(scheduler): State<Scheduler>) -> Response {
+    (
+        StatusCode::OK,
+        Json(json!({ "data": scheduler.statuses() })),
+    )
+        .into_response()
+}
+
+async fn run_job_handler(State(scheduler): State<Scheduler>, Path(name): Path<String>) -> Response {
+    match scheduler.trigger(&name) {
+        Trigger::Queued => (StatusCode::ACCEPTED, Json(json!({ "queued": name }))).into_response(),
+        Trigger::AlreadyRunning => {
+            error_response(StatusCode::CONFLICT, "Job is already running").into_response()
+        }
+        Trigger::UnknownJob => error_response(StatusCode::NOT_FOUND, "Unknown job").into_response(),
+    }
+}
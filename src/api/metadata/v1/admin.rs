@@ -0,0 +1,834 @@
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
+};
+use futures::{StreamExt, stream};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use validator::Validate;
+
+use crate::api::metadata::v1::cache::{CacheStats, HitCounts};
+use crate::api::metadata::v1::metadata::SearchState;
+use crate::api::validation::ValidatedJson;
+use crate::api::{db_error_status, error_response, search_error_status};
+use crate::auth::{self, ApiKeys};
+use crate::db;
+use crate::db::eval::EvalRun;
+use crate::ip_allowlist::{self, IpAllowlist};
+use crate::models::metadata::{ItemType, OmId, is_valid_omid};
+use crate::search::SearchQuery;
+use crate::synonyms;
+
+const DEFAULT_REPORT_LIMIT: i64 = 100;
+const MAX_REPORT_LIMIT: i64 = 1000;
+const MAX_MERGE_IDS: u64 = 20;
+/// Most missing documents one drift request will queue for reindexing.
+const MAX_REPAIR_IDS: usize = 200;
+/// The quality report scans whole tables, so it is rebuilt at most this often.
+const QUALITY_TTL: Duration = Duration::from_secs(10 * 60);
+const QUALITY_SAMPLES: i64 = 10;
+const MAX_EVAL_PAIRS: u64 = 200;
+/// Results looked at per query; an expected id ranked lower counts as not found.
+const EVAL_DEPTH: i32 = 50;
+const EVAL_CONCURRENCY: usize = 8;
+/// Queries still running when this runs out are reported as timed out.
+const EVAL_BUDGET: Duration = Duration::from_secs(30);
+
+/// The last data quality report and when it was built. The lock is held while rebuilding,
+/// so concurrent requests wait for one set of scans instead of starting their own.
+#[derive(Clone, Default)]
+pub struct QualityCache {
+    report: Arc<tokio::sync::Mutex<Option<(Instant, Value)>>>,
+    counts: Arc<HitCounts>,
+}
+
+impl QualityCache {
+    pub async fn stats(&self) -> CacheStats {
+        let report = self.report.lock().await;
+        let bytes = report.as_ref().map_or(0, |(_, r)| r.to_string().len());
+        self.counts.stats(report.is_some() as usize, bytes)
+    }
+
+    /// Drops the report. Returns how many entries there were.
+    pub async fn clear(&self) -> usize {
+        self.report.lock().await.take().is_some() as usize
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DuplicatesQuery {
+    #[serde(rename = "type")]
+    pub item_type: Option<String>,
+    pub after: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DriftQuery {
+    #[serde(rename = "type")]
+    pub item_type: Option<String>,
+    pub after_id: Option<String>,
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub repair: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReleaseDateQuery {
+    #[serde(rename = "type")]
+    pub item_type: Option<String>,
+    pub after_id: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct EvalRequest {
+    #[validate(length(min = 1, max = "MAX_EVAL_PAIRS"))]
+    pub pairs: Vec<EvalPair>,
+    /// Stores the run in `eval_runs`.
+    #[serde(default)]
+    pub persist: bool,
+    /// Free-form tag for persisted runs, e.g. the deployed version.
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EvalPair {
+    pub query: String,
+    /// Defaults to `song`.
+    #[serde(rename = "type")]
+    pub item_type: Option<String>,
+    pub expected_id: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EvalResult {
+    query: String,
+    #[serde(rename = "type")]
+    item_type: &'static str,
+    expected_id: String,
+    /// 1-based position of the expected id, `None` when it wasn't in the top results.
+    rank: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'static str>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct MergeArtistsRequest {
+    pub keep_id: String,
+    #[validate(length(min = 1, max = "MAX_MERGE_IDS"))]
+    pub merge_ids: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MismatchQuery {
+    pub threshold: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+pub fn router(api_keys: ApiKeys, allowlist: IpAllowlist) -> Router<SearchState> {
+    Router::new()
+        .route("/admin/synonyms/reload", post(reload_synonyms_handler))
+        .route(
+            "/admin/reports/track_counts",
+            get(track_count_report_handler),
+        )
+        .route(
+            "/admin/reports/release_dates",
+            get(release_date_report_handler),
+        )
+        .route("/admin/quality", get(quality_handler))
+        .route("/admin/duplicates", get(duplicates_handler))
+        .route("/admin/drift", get(drift_handler))
+        .route("/admin/artists/merge", post(merge_artists_handler))
+        .route("/admin/eval", post(eval_handler))
+        .route("/admin/cache/stats", get(cache_stats_handler))
+        .route("/admin/cache", delete(flush_cache_handler))
+        .route("/admin/cache/entity/{id}", delete(evict_entity_handler))
+        .layer(middleware::from_fn_with_state(
+            (api_keys, "admin"),
+            auth::require_scope,
+        ))
+        .layer(middleware::from_fn_with_state(
+            allowlist,
+            ip_allowlist::require_allowed_ip,
+        ))
+}
+
+/// Synonyms are expanded at query time, so a reload takes effect without reindexing.
+async fn reload_synonyms_handler() -> Response {
+    match synonyms::load() {
+        Ok(groups) => (StatusCode::OK, Json(json!({ "groups": groups }))).into_response(),
+        Err(e) => {
+            tracing::error!("synonym reload error: {}", e);
+            error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to reload synonyms",
+            )
+            .into_response()
+        }
+    }
+}
+
+async fn track_count_report_handler(
+    State(state): State<SearchState>,
+    Query(params): Query<MismatchQuery>,
+) -> Response {
+    let threshold = params.threshold.unwrap_or(0).max(0);
+    let limit = params.limit.unwrap_or(DEFAULT_REPORT_LIMIT);
+    if !(1..=MAX_REPORT_LIMIT).contains(&limit) {
+        return error_response(StatusCode::BAD_REQUEST, "limit must be between 1 and 1000")
+            .into_response();
+    }
+    match db::metadata::track_count_mismatches(&state.scrape_pool, threshold, limit).await {
+        Ok(albums) => (StatusCode::OK, Json(json!({ "data": albums }))).into_response(),
+        Err(e) => {
+            tracing::error!("track count report error: {}", e);
+            error_response(db_error_status(&e), "Failed to build report").into_response()
+        }
+    }
+}
+
+/// Counts of catalog problems with a few sample ids each, for the scraper team. Cached for
+/// ten minutes; `generatedAt` tells how fresh the numbers are.
+async fn quality_handler(State(state): State<SearchState>) -> Response {
+    let cache = &state.quality_cache;
+    let mut cached = cache.report.lock().await;
+    let fresh = cached.as_ref().filter(|(at, _)| at.elapsed() < QUALITY_TTL);
+    cache.counts.record(fresh.is_some());
+    if let Some((_, report)) = fresh {
+        return (StatusCode::OK, Json(report.clone())).into_response();
+    }
+
+    let mut data = serde_json::Map::new();
+    for (name, select_ids) in db::metadata::QUALITY_CHECKS {
+        match db::metadata::quality_count(&state.scrape_pool, select_ids, QUALITY_SAMPLES).await {
+            Ok(count) => {
+                data.insert(name.to_string(), json!(count));
+            }
+            Err(e) => {
+                tracing::error!(check = name, "quality report error: {}", e);
+                return error_response(db_error_status(&e), "Failed to build report")
+                    .into_response();
+            }
+        }
+    }
+    let report = json!({
+        "data": data,
+        "generatedAt": OffsetDateTime::now_utc().format(&Rfc3339).ok(),
+    });
+    *cached = Some((Instant::now(), report.clone()));
+    (StatusCode::OK, Json(report)).into_response()
+}
+
+/// Raw release dates the sync backfill couldn't parse, so scraper output can be fixed at the
+/// source. Pages by id; `next` is the `after_id` of the following page.
+async fn release_date_report_handler(
+    State(state): State<SearchState>,
+    Query(params): Query<ReleaseDateQuery>,
+) -> Response {
+    let item_type = match params.item_type.as_deref().map(str::parse::<ItemType>) {
+        Some(Ok(ItemType::Artist)) => {
+            return error_response(StatusCode::BAD_REQUEST, "type must be song or album")
+                .into_response();
+        }
+        Some(Ok(item_type)) => item_type,
+        Some(Err(e)) => return e.into_response(),
+        None => return error_response(StatusCode::BAD_REQUEST, "type is required").into_response(),
+    };
+    let limit = params.limit.unwrap_or(DEFAULT_REPORT_LIMIT);
+    if !(1..=MAX_REPORT_LIMIT).contains(&limit) {
+        return error_response(StatusCode::BAD_REQUEST, "limit must be between 1 and 1000")
+            .into_response();
+    }
+    let after_id = params.after_id.as_deref().filter(|a| !a.is_empty());
+    match db::metadata::unparseable_release_date_rows(
+        &state.scrape_pool,
+        item_type.as_str(),
+        after_id,
+        limit,
+    )
+    .await
+    {
+        Ok(rows) => {
+            let next = (rows.len() as i64 == limit)
+                .then(|| rows.last().map(|r| r.id.clone()))
+                .flatten();
+            (StatusCode::OK, Json(json!({ "data": rows, "next": next }))).into_response()
+        }
+        Err(e) => {
+            tracing::error!("release date report error: {}", e);
+            error_response(db_error_status(&e), "Failed to build report").into_response()
+        }
+    }
+}
+
+/// Candidate duplicate clusters for manual review; `next` is the key to pass as `after`.
+async fn duplicates_handler(
+    State(state): State<SearchState>,
+    Query(params): Query<DuplicatesQuery>,
+) -> Response {
+    let item_type = match params.item_type.as_deref().map(str::parse::<ItemType>) {
+        Some(Ok(item_type)) => item_type,
+        Some(Err(e)) => return e.into_response(),
+        None => return error_response(StatusCode::BAD_REQUEST, "type is required").into_response(),
+    };
+    let limit = params.limit.unwrap_or(DEFAULT_REPORT_LIMIT);
+    if !(1..=MAX_REPORT_LIMIT).contains(&limit) {
+        return error_response(StatusCode::BAD_REQUEST, "limit must be between 1 and 1000")
+            .into_response();
+    }
+    let after = params.after.as_deref().filter(|a| !a.is_empty());
+    let prefixes = db::metadata::duplicate_key_prefixes(item_type.as_str());
+    if after.is_some_and(|a| !prefixes.iter().any(|p| a.starts_with(p))) {
+        return error_response(StatusCode::BAD_REQUEST, "Invalid after").into_response();
+    }
+    match db::metadata::duplicate_clusters(&state.scrape_pool, item_type.as_str(), after, limit)
+        .await
+    {
+        Ok(clusters) => {
+            let next = (clusters.len() as i64 == limit)
+                .then(|| clusters.last().map(|c| c.key.clone()))
+                .flatten();
+            (
+                StatusCode::OK,
+                Json(json!({ "data": clusters, "next": next })),
+            )
+                .into_response()
+        }
+        Err(e) => {
+            tracing::error!("duplicates report error: {}", e);
+            error_response(db_error_status(&e), "Failed to build report").into_response()
+        }
+    }
+}
+
+/// Rows that exist in Postgres but not in the search index, one page of ids at a time so a
+/// cron can sweep the catalog with `after_id = next`. With `repair=true` the missing rows
+/// are touched so the next incremental sync reindexes them.
+async fn drift_handler(
+    State(state): State<SearchState>,
+    Query(params): Query<DriftQuery>,
+) -> Response {
+    let item_type = match params.item_type.as_deref().map(str::parse::<ItemType>) {
+        Some(Ok(item_type)) => item_type,
+        Some(Err(e)) => return e.into_response(),
+        None => return error_response(StatusCode::BAD_REQUEST, "type is required").into_response(),
+    };
+    let limit = params.limit.unwrap_or(MAX_REPORT_LIMIT);
+    if !(1..=MAX_REPORT_LIMIT).contains(&limit) {
+        return error_response(StatusCode::BAD_REQUEST, "limit must be between 1 and 1000")
+            .into_response();
+    }
+    let after_id = params.after_id.as_deref().filter(|a| !a.is_empty());
+
+    let ids =
+        match db::metadata::indexable_ids(&state.scrape_pool, item_type.as_str(), after_id, limit)
+            .await
+        {
+            Ok(ids) => ids,
+            Err(e) => {
+                tracing::error!("drift report error: {}", e);
+                return error_response(db_error_status(&e), "Failed to build report")
+                    .into_response();
+            }
+        };
+    let indexed = match state.client.existing_ids(item_type, &ids).await {
+        Ok(indexed) => indexed,
+        Err(e) => {
+            tracing::error!("drift index lookup error: {}", e);
+            return error_response(search_error_status(&e), "Search backend unavailable")
+                .into_response();
+        }
+    };
+    let missing: Vec<String> = ids
+        .iter()
+        .filter(|id| !indexed.contains(*id))
+        .cloned()
+        .collect();
+
+    let repaired = if params.repair && !missing.is_empty() {
+        let batch = &missing[..missing.len().min(MAX_REPAIR_IDS)];
+        match db::metadata::touch(&state.scrape_pool, item_type.as_str(), batch).await {
+            Ok(touched) => {
+                tracing::info!(target: "audit", item_type = %item_type, touched, "drift repair queued");
+                touched
+            }
+            Err(e) => {
+                tracing::error!("drift repair error: {}", e);
+                return error_response(db_error_status(&e), "Failed to queue repair")
+                    .into_response();
+            }
+        }
+    } else {
+        0
+    };
+
+    let next = (ids.len() as i64 == limit)
+        .then(|| ids.last().cloned())
+        .flatten();
+    (
+        StatusCode::OK,
+        Json(json!({
+            "data": {
+                "checked": ids.len(),
+                "missingCount": missing.len(),
+                "missing": missing,
+                "repaired": repaired,
+            },
+            "next": next,
+        })),
+    )
+        .into_response()
+}
+
+async fn merge_artists_handler(
+    State(state): State<SearchState>,
+    headers: HeaderMap,
+    ValidatedJson(body): ValidatedJson<MergeArtistsRequest>,
+) -> Response {
+    let keep_id = body.keep_id.trim().to_lowercase();
+    let mut merge_ids: Vec<String> = body
+        .merge_ids
+        .iter()
+        .map(|id| id.trim().to_lowercase())
+        .collect();
+    merge_ids.sort();
+    merge_ids.dedup();
+    if !is_valid_omid(&keep_id) || !merge_ids.iter().all(|id| is_valid_omid(id)) {
+        return error_response(StatusCode::BAD_REQUEST, "Invalid artist id").into_response();
+    }
+    if merge_ids.contains(&keep_id) {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            "Cannot merge an artist into itself",
+        )
+        .into_response();
+    }
+
+    let summary = match db::metadata::merge_artists(&state.scrape_pool, &keep_id, &merge_ids).await
+    {
+        Ok(Some(summary)) => summary,
+        Ok(None) => {
+            return error_response(StatusCode::NOT_FOUND, "Artist not found").into_response();
+        }
+        Err(e) => {
+            tracing::error!("artist merge error: {}", e);
+            return error_response(db_error_status(&e), "Merge failed").into_response();
+        }
+    };
+
+    let key_id = state
+        .api_keys
+        .authenticate(&headers)
+        .map(|k| k.id.clone())
+        .unwrap_or_default();
+    tracing::info!(
+        target: "audit",
+        key_id,
+        keep_id,
+        merge_ids = ?merge_ids,
+        summary = ?summary,
+        "artists merged"
+    );
+
+    // The kept artist and the touched songs carry a new updated_at, so the next
+    // incremental sync reindexes them; only the deleted documents need removing here.
+    if let Err(e) = state.client.delete_documents(&merge_ids).await {
+        tracing::warn!("failed to remove merged artists from the index: {}", e);
+    }
+    // Cached songs embed artist names, so any selection may be stale, and the merged
+    // artists no longer count towards their letters.
+    state.discover_cache.clear();
+    state.letter_counts.clear().await;
+    for id in &merge_ids {
+        state.artwork_cache.evict(&format!("omm:artist:{id}")).await;
+    }
+
+    (StatusCode::OK, Json(json!({ "data": summary }))).into_response()
+}
+
+/// Runs each `(query, expected_id)` pair through the live backend as a plain name search
+/// and reports where the expected id ranked, plus MRR and hit@5 over the pairs that ran.
+async fn eval_handler(
+    State(state): State<SearchState>,
+    ValidatedJson(body): ValidatedJson<EvalRequest>,
+) -> Response {
+    let mut pairs = Vec::with_capacity(body.pairs.len());
+    for pair in body.pairs {
+        let item_type = match pair
+            .item_type
+            .as_deref()
+            .unwrap_or("song")
+            .parse::<ItemType>()
+        {
+            Ok(item_type) => item_type,
+            Err(e) => return e.into_response(),
+        };
+        let query = pair.query.trim().to_string();
+        if query.is_empty() || query.len() > 256 {
+            return error_response(
+                StatusCode::BAD_REQUEST,
+                "every query must be 1 to 256 characters",
+            )
+            .into_response();
+        }
+        pairs.push((query, item_type, pair.expected_id.trim().to_lowercase()));
+    }
+
+    let deadline = tokio::time::Instant::now() + EVAL_BUDGET;
+    let results: Vec<EvalResult> = stream::iter(pairs)
+        .map(|(query, item_type, expected_id)| {
+            let client = state.client.clone();
+            async move {
+                let search_query = SearchQuery {
+                    name: Some(&query),
+                    ..Default::default()
+                };
+                let search = client.search(item_type, &search_query, EVAL_DEPTH, 0);
+                let (rank, error) = match tokio::time::timeout_at(deadline, search).await {
+                    Ok(Ok(hits)) => (
+                        hits.iter()
+                            .position(|(id, _, _, _)| *id == expected_id)
+                            .map(|i| i + 1),
+                        None,
+                    ),
+                    Ok(Err(e)) => {
+                        tracing::warn!("eval query failed: {}", e);
+                        (None, Some("search_failed"))
+                    }
+                    Err(_) => (None, Some("timed_out")),
+                };
+                EvalResult {
+                    query,
+                    item_type: item_type.as_str(),
+                    expected_id,
+                    rank,
+                    error,
+                }
+            }
+        })
+        .buffered(EVAL_CONCURRENCY)
+        .collect()
+        .await;
+
+    let (evaluated, mrr, hit_at_5) = summarize(&results);
+    let results = json!(results);
+    let run_id = if body.persist {
+        let run = EvalRun {
+            label: body.label.as_deref(),
+            backend: state.client.name(),
+            pairs: results.as_array().map_or(0, Vec::len) as i32,
+            evaluated: evaluated as i32,
+            mrr,
+            hit_at_5,
+            results: &results,
+        };
+        match db::eval::insert_run(&state.scrape_pool, &run).await {
+            Ok(id) => Some(id),
+            Err(e) => {
+                tracing::error!("eval persist error: {}", e);
+                return error_response(db_error_status(&e), "Failed to store run").into_response();
+            }
+        }
+    } else {
+        None
+    };
+
+    (
+        StatusCode::OK,
+        Json(json!({
+            "data": results,
+            "evaluated": evaluated,
+            "mrr": mrr,
+            "hitAt5": hit_at_5,
+            "runId": run_id,
+        })),
+    )
+        .into_response()
+}
+
+/// Pairs that ran, with their MRR and hit@5. Errored pairs say nothing about ranking, so
+/// they count towards neither.
+fn summarize(results: &[EvalResult]) -> (usize, f64, f64) {
+    let evaluated: Vec<&EvalResult> = results.iter().filter(|r| r.error.is_none()).collect();
+    if evaluated.is_empty() {
+        return (0, 0.0, 0.0);
+    }
+    let n = evaluated.len() as f64;
+    let reciprocal: f64 = evaluated
+        .iter()
+        .filter_map(|r| r.rank)
+        .map(|rank| 1.0 / rank as f64)
+        .sum();
+    let hits = evaluated
+        .iter()
+        .filter(|r| r.rank.is_some_and(|rank| rank <= 5))
+        .count();
+    (evaluated.len(), reciprocal / n, hits as f64 / n)
+}
+
+async fn cache_stats_handler(State(state): State<SearchState>) -> Response {
+    (
+        StatusCode::OK,
+        Json(json!({
+            "data": {
+                "discover": state.discover_cache.stats(),
+                "quality": state.quality_cache.stats().await,
+                "letterCounts": state.letter_counts.stats().await,
+                "artwork": state.artwork_cache.stats().await,
+            }
+        })),
+    )
+        .into_response()
+}
+
+async fn flush_cache_handler(State(state): State<SearchState>) -> Response {
+    let evicted = state.discover_cache.clear()
+        + state.quality_cache.clear().await
+        + state.letter_counts.clear().await
+        + state.artwork_cache.clear().await;
+    tracing::info!(target: "audit", evicted, "caches flushed");
+    (StatusCode::OK, Json(json!({ "evicted": evicted }))).into_response()
+}
+
+/// Drops what mentions one entity. The quality report and letter totals aren't per entity,
+/// so they're left to expire or to a full flush.
+async fn evict_entity_handler(State(state): State<SearchState>, omid: OmId) -> Response {
+    let omid = omid.to_string();
+    let evicted = state.discover_cache.evict(&omid) + state.artwork_cache.evict(&omid).await;
+    (StatusCode::OK, Json(json!({ "evicted": evicted }))).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::extract::Request;
+
+    use super::*;
+    use crate::test_support::{self, as_admin, get, post_json, request, send};
+
+    fn delete(uri: &str) -> Request {
+        as_admin(request(
+            Request::delete(uri)
+                .body(Body::empty())
+                .expect("valid request"),
+        ))
+    }
+
+    #[tokio::test]
+    async fn eval_ranks_pairs_against_the_backend_and_persists_runs() {
+        let Some(pool) = test_support::scrape_db().await else {
+            return;
+        };
+        let app = test_support::app(
+            Some(test_support::admin_search_state(pool.clone())),
+            test_support::unreachable_pool(),
+        );
+        // On the fixture, "i" ranks One More Time, Clarity, then Doin' It Right.
+        let pairs = json!([
+            { "query": "get lucky", "expected_id": "getlucky00000001" },
+            { "query": "i", "expected_id": "doinitright00001" },
+            { "query": "clarity", "expected_id": "getlucky00000001" },
+        ]);
+
+        let (status, _, body) = send(
+            app.clone(),
+            as_admin(post_json(
+                "/metadata/v1/admin/eval",
+                &json!({ "pairs": pairs }),
+            )),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let ranks: Vec<&Value> = body["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| &r["rank"])
+            .collect();
+        assert_eq!(ranks, [&json!(1), &json!(3), &Value::Null]);
+        assert_eq!(body["evaluated"], 3);
+        assert!((body["mrr"].as_f64().unwrap() - 4.0 / 9.0).abs() < 1e-9);
+        assert!((body["hitAt5"].as_f64().unwrap() - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(body["runId"], Value::Null);
+        let stored: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM eval_runs")
+            .fetch_one(&pool)
+            .await
+            .expect("count runs");
+        assert_eq!(stored, 0);
+
+        let (status, _, persisted) = send(
+            app.clone(),
+            as_admin(post_json(
+                "/metadata/v1/admin/eval",
+                &json!({ "pairs": pairs, "persist": true, "label": "v1.2.3" }),
+            )),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let run_id = persisted["runId"].as_i64().expect("run id");
+        let (label, backend, count, evaluated, mrr, results): (
+            Option<String>,
+            String,
+            i32,
+            i32,
+            f64,
+            sqlx::types::Json<Value>,
+        ) = sqlx::query_as(
+            "SELECT label, backend, pairs, evaluated, mrr, results FROM eval_runs WHERE id = $1",
+        )
+        .bind(run_id)
+        .fetch_one(&pool)
+        .await
+        .expect("stored run");
+        assert_eq!(label.as_deref(), Some("v1.2.3"));
+        assert_eq!((backend.as_str(), count, evaluated), ("memory", 3, 3));
+        assert!((mrr - 4.0 / 9.0).abs() < 1e-9);
+        assert_eq!(results.0, persisted["data"]);
+    }
+
+    #[test]
+    fn errored_pairs_count_towards_neither_mrr_nor_hit_at_5() {
+        let result = |rank, error| EvalResult {
+            query: "q".to_string(),
+            item_type: "song",
+            expected_id: "getlucky00000001".to_string(),
+            rank,
+            error,
+        };
+        let results = [
+            result(Some(2), None),
+            result(None, None),
+            result(None, Some("search_failed")),
+            result(None, Some("timed_out")),
+        ];
+        assert_eq!(summarize(&results), (2, 0.25, 0.5));
+        assert_eq!(summarize(&results[2..]), (0, 0.0, 0.0));
+    }
+
+    #[tokio::test]
+    async fn merging_artists_repoints_links_and_recounts_letters() {
+        let Some(pool) = test_support::scrape_db().await else {
+            return;
+        };
+        let app = test_support::app(
+            Some(test_support::admin_search_state(pool.clone())),
+            test_support::unreachable_pool(),
+        );
+        let merge = |body: Value| as_admin(post_json("/metadata/v1/admin/artists/merge", &body));
+
+        let (status, _, before) =
+            send(app.clone(), get("/metadata/v1/artists?starts_with=z")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(before["letters"]["Z"], 1);
+
+        let (status, _, _) = send(
+            app.clone(),
+            merge(json!({ "keep_id": "daftpunk00000001", "merge_ids": ["DaftPunk00000001"] })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _, _) = send(
+            app.clone(),
+            merge(json!({ "keep_id": "daftpunk00000001", "merge_ids": ["unknown000000001"] })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // Zedd's genre is one Daft Punk already has; the song and album links move over.
+        let (status, _, merged) = send(
+            app.clone(),
+            merge(json!({ "keep_id": "daftpunk00000001", "merge_ids": ["zedd000000000001"] })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            merged["data"],
+            json!({
+                "repointedSongLinks": 1,
+                "repointedAlbumLinks": 1,
+                "repointedGenreLinks": 0,
+                "repointedAliases": 0,
+                "droppedLinks": 1,
+                "deletedArtists": 1,
+                "touchedSongs": 1,
+            })
+        );
+
+        let links: Vec<(String, String)> = sqlx::query_as(
+            "SELECT 'song', song_id FROM song_artists WHERE artist_id = 'daftpunk00000001'
+             UNION ALL
+             SELECT 'album', album_id FROM artist_albums WHERE artist_id = 'daftpunk00000001'
+             ORDER BY 1, 2",
+        )
+        .fetch_all(&pool)
+        .await
+        .expect("read links");
+        assert!(links.contains(&("song".into(), "clarity000000002".into())));
+        assert!(links.contains(&("album".into(), "clarity000000001".into())));
+        let alias: Option<String> = sqlx::query_scalar(
+            "SELECT alias FROM artist_aliases WHERE artist_id = 'daftpunk00000001'",
+        )
+        .fetch_optional(&pool)
+        .await
+        .expect("read alias");
+        assert_eq!(alias.as_deref(), Some("Zedd"));
+
+        let (_, _, after) = send(app.clone(), get("/metadata/v1/artists?starts_with=z")).await;
+        assert_eq!(after["letters"]["Z"], Value::Null);
+        assert_eq!(after["data"], json!([]));
+    }
+
+    #[tokio::test]
+    async fn flushing_the_caches_makes_the_next_read_fresh() {
+        let Some(pool) = test_support::scrape_db().await else {
+            return;
+        };
+        let app = test_support::app(
+            Some(test_support::admin_search_state(pool.clone())),
+            test_support::unreachable_pool(),
+        );
+        let letters = |app: Router| async move {
+            let (status, _, body) = send(app, get("/metadata/v1/artists?starts_with=d")).await;
+            assert_eq!(status, StatusCode::OK);
+            body["letters"]["D"].clone()
+        };
+
+        assert_eq!(letters(app.clone()).await, 1);
+        sqlx::query("INSERT INTO artists (id, name) VALUES ('dido000000000001', 'Dido')")
+            .execute(&pool)
+            .await
+            .expect("insert artist");
+        assert_eq!(letters(app.clone()).await, 1, "served from the cache");
+
+        let (status, _, stats) =
+            send(app.clone(), as_admin(get("/metadata/v1/admin/cache/stats"))).await;
+        assert_eq!(status, StatusCode::OK);
+        let counts = &stats["data"]["letterCounts"];
+        assert_eq!(
+            (&counts["entries"], &counts["hits"], &counts["misses"]),
+            (&json!(1), &json!(1), &json!(1))
+        );
+        for cache in ["discover", "quality", "artwork"] {
+            assert_eq!(stats["data"][cache]["entries"], 0, "{cache}");
+        }
+
+        let (status, _, _) = send(app.clone(), get("/metadata/v1/admin/cache/stats")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, _, flushed) = send(app.clone(), delete("/metadata/v1/admin/cache")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(flushed["evicted"], 1);
+        assert_eq!(letters(app.clone()).await, 2);
+    }
+}
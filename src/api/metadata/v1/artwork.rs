@@ -0,0 +1,335 @@
+use axum::{
+    Router,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use image::{
+    DynamicImage, ImageFormat, ImageReader, Limits, RgbImage, codecs::jpeg::JpegEncoder,
+    imageops::FilterType,
+};
+use reqwest::Client;
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::collections::{HashMap, VecDeque};
+use std::io::Cursor;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, Semaphore};
+
+use crate::api::metadata::v1::cache::{CacheStats, HitCounts};
+use crate::api::{db_error_status, error_response};
+use crate::db;
+use crate::models::metadata::OmId;
+
+const SIZES: [u32; 4] = [64, 128, 256, 512];
+const MAX_SOURCE_BYTES: usize = 8 * 1024 * 1024;
+const CACHE_ENTRIES: usize = 2048;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+/// Larger sources are rejected before decoding; catalog artwork tops out at 3000px.
+const MAX_SOURCE_DIMENSION: u32 = 4096;
+/// Decoder allocation cap, so a small compressed file can't expand into gigabytes.
+const MAX_DECODE_ALLOC: u64 = 128 * 1024 * 1024;
+/// Decodes running at once; each can hold up to [`MAX_DECODE_ALLOC`].
+const MAX_CONCURRENT_DECODES: usize = 4;
+/// Not `immutable`: the URL names the entity, not the image, so artwork changes in place.
+const CACHE_CONTROL: &str = "public, max-age=86400";
+const PLACEHOLDER_CACHE_CONTROL: &str = "public, max-age=300";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Encoding {
+    Jpeg,
+    WebP,
+}
+
+impl Encoding {
+    fn negotiate(headers: &HeaderMap) -> Self {
+        let accepts_webp = headers
+            .get(header::ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.contains("image/webp"));
+        if accepts_webp {
+            Encoding::WebP
+        } else {
+            Encoding::Jpeg
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Encoding::Jpeg => "image/jpeg",
+            Encoding::WebP => "image/webp",
+        }
+    }
+}
+
+type CacheKey = (String, u32, Encoding);
+
+/// Resized images, shared with the admin cache endpoints.
+#[derive(Clone, Default)]
+pub struct ArtworkCache {
+    lru: Arc<Mutex<Lru>>,
+    counts: Arc<HitCounts>,
+}
+
+impl ArtworkCache {
+    async fn get(&self, key: &CacheKey) -> Option<Arc<Vec<u8>>> {
+        let hit = self.lru.lock().await.get(key);
+        self.counts.record(hit.is_some());
+        hit
+    }
+
+    async fn insert(&self, key: CacheKey, value: Arc<Vec<u8>>) {
+        self.lru.lock().await.insert(key, value);
+    }
+
+    pub async fn stats(&self) -> CacheStats {
+        let lru = self.lru.lock().await;
+        let bytes = lru.entries.values().map(|v| v.len()).sum();
+        self.counts.stats(lru.entries.len(), bytes)
+    }
+
+    /// Drops every image. Returns how many there were.
+    pub async fn clear(&self) -> usize {
+        let mut lru = self.lru.lock().await;
+        lru.order.clear();
+        std::mem::take(&mut lru.entries).len()
+    }
+
+    /// Drops every size and encoding of one entity's artwork. Returns how many were dropped.
+    pub async fn evict(&self, omid: &str) -> usize {
+        let mut lru = self.lru.lock().await;
+        let before = lru.entries.len();
+        lru.entries.retain(|(id, _, _), _| id != omid);
+        lru.order.retain(|(id, _, _)| id != omid);
+        before - lru.entries.len()
+    }
+}
+
+#[derive(Default)]
+struct Lru {
+    entries: HashMap<CacheKey, Arc<Vec<u8>>>,
+    order: VecDeque<CacheKey>,
+}
+
+impl Lru {
+    fn get(&mut self, key: &CacheKey) -> Option<Arc<Vec<u8>>> {
+        let hit = self.entries.get(key).cloned()?;
+        if let Some(pos) = self.order.iter().position(|k| k == key) {
+            let key = self.order.remove(pos).expect("position is in bounds");
+            self.order.push_back(key);
+        }
+        Some(hit)
+    }
+
+    fn insert(&mut self, key: CacheKey, value: Arc<Vec<u8>>) {
+        if self.entries.insert(key.clone(), value).is_none() {
+            self.order.push_back(key);
+        }
+        while self.order.len() > CACHE_ENTRIES {
+            if let Some(evicted) = self.order.pop_front() {
+                self.entries.remove(&evicted);
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+struct ArtworkState {
+    client: Client,
+    scrape_pool: PgPool,
+    cache: ArtworkCache,
+    decodes: Arc<Semaphore>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ArtworkQuery {
+    pub size: Option<u32>,
+}
+
+pub fn router<S>(scrape_pool: PgPool, cache: ArtworkCache) -> Router<S> {
+    let client = Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .connect_timeout(Duration::from_secs(2))
+        // Image URLs are stored absolute; a redirect could point the fetch at an internal
+        // address.
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .expect("Failed to build artwork http client");
+    let state = ArtworkState {
+        client,
+        scrape_pool,
+        cache,
+        decodes: Arc::new(Semaphore::new(MAX_CONCURRENT_DECODES)),
+    };
+    Router::new()
+        .route("/artwork/{id}", get(artwork_handler))
+        .with_state(state)
+}
+
+async fn artwork_handler(
+    State(state): State<ArtworkState>,
+    omid: OmId,
+    Query(params): Query<ArtworkQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let size = params.size.unwrap_or(256);
+    if !SIZES.contains(&size) {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            "size must be one of 64, 128, 256, 512",
+        )
+        .into_response();
+    }
+    let encoding = Encoding::negotiate(&headers);
+    let key = (omid.to_string(), size, encoding);
+
+    if let Some(bytes) = state.cache.get(&key).await {
+        return image_response(encoding, CACHE_CONTROL, bytes.to_vec());
+    }
+
+    let url = match db::metadata::image_url(&state.scrape_pool, &omid.item_type, &omid.id).await {
+        Ok(Some(url)) => url,
+        Ok(None) => return placeholder(size, encoding).await,
+        Err(e) => {
+            tracing::error!("artwork lookup error: {}", e);
+            return error_response(db_error_status(&e), "Artwork lookup failed").into_response();
+        }
+    };
+
+    let source = match fetch_source(&state.client, &url).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::warn!("artwork: fetch failed for {}: {}", omid, e);
+            return placeholder(size, encoding).await;
+        }
+    };
+
+    let permit = state
+        .decodes
+        .clone()
+        .acquire_owned()
+        .await
+        .expect("decode semaphore is never closed");
+    let resized = tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        let img = decode(&source)?;
+        encode(&img.resize(size, size, FilterType::Lanczos3), encoding)
+    })
+    .await;
+
+    match resized {
+        Ok(Ok(bytes)) => {
+            let bytes = Arc::new(bytes);
+            state.cache.insert(key, bytes.clone()).await;
+            image_response(encoding, CACHE_CONTROL, bytes.to_vec())
+        }
+        Ok(Err(e)) => {
+            tracing::warn!("artwork: failed to process image for {}: {}", omid, e);
+            placeholder(size, encoding).await
+        }
+        Err(e) => {
+            tracing::error!("artwork: resize task failed: {}", e);
+            placeholder(size, encoding).await
+        }
+    }
+}
+
+async fn fetch_source(client: &Client, url: &str) -> anyhow::Result<Vec<u8>> {
+    if !url.starts_with("https://") && !url.starts_with("http://") {
+        anyhow::bail!("unsupported artwork url scheme");
+    }
+    let mut resp = client.get(url).send().await?.error_for_status()?;
+    if resp
+        .content_length()
+        .is_some_and(|len| len as usize > MAX_SOURCE_BYTES)
+    {
+        anyhow::bail!("artwork exceeds size cap");
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = resp.chunk().await? {
+        if body.len() + chunk.len() > MAX_SOURCE_BYTES {
+            anyhow::bail!("artwork exceeds size cap");
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+/// Decodes a fetched source within [`MAX_SOURCE_DIMENSION`] and [`MAX_DECODE_ALLOC`].
+fn decode(source: &[u8]) -> image::ImageResult<DynamicImage> {
+    let mut reader = ImageReader::new(Cursor::new(source)).with_guessed_format()?;
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_SOURCE_DIMENSION);
+    limits.max_image_height = Some(MAX_SOURCE_DIMENSION);
+    limits.max_alloc = Some(MAX_DECODE_ALLOC);
+    reader.limits(limits);
+    reader.decode()
+}
+
+fn encode(img: &DynamicImage, encoding: Encoding) -> image::ImageResult<Vec<u8>> {
+    let mut buf = Vec::new();
+    match encoding {
+        Encoding::Jpeg => {
+            JpegEncoder::new_with_quality(&mut buf, 85).encode_image(&img.to_rgb8())?;
+        }
+        Encoding::WebP => {
+            DynamicImage::ImageRgb8(img.to_rgb8())
+                .write_to(&mut Cursor::new(&mut buf), ImageFormat::WebP)?;
+        }
+    }
+    Ok(buf)
+}
+
+async fn placeholder(size: u32, encoding: Encoding) -> Response {
+    let encoded = tokio::task::spawn_blocking(move || {
+        let img =
+            DynamicImage::ImageRgb8(RgbImage::from_pixel(size, size, image::Rgb([40, 40, 40])));
+        encode(&img, encoding)
+    })
+    .await;
+    match encoded {
+        Ok(Ok(bytes)) => image_response(encoding, PLACEHOLDER_CACHE_CONTROL, bytes),
+        _ => error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to render artwork",
+        )
+        .into_response(),
+    }
+}
+
+fn image_response(encoding: Encoding, cache_control: &'static str, bytes: Vec<u8>) -> Response {
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, encoding.content_type()),
+            (header::CACHE_CONTROL, cache_control),
+            (header::VARY, "Accept"),
+        ],
+        bytes,
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut buf = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::new(width, height))
+            .write_to(&mut Cursor::new(&mut buf), ImageFormat::Png)
+            .unwrap();
+        buf
+    }
+
+    #[test]
+    fn decode_rejects_oversized_sources() {
+        assert!(decode(&png(512, 512)).is_ok());
+        assert!(matches!(
+            decode(&png(MAX_SOURCE_DIMENSION + 1, 1)),
+            Err(image::ImageError::Limits(_))
+        ));
+    }
+}
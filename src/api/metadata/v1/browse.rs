@@ -0,0 +1,349 @@
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use time::{Date, Month};
+
+use crate::api::metadata::v1::cache::{CacheStats, HitCounts};
+use crate::api::metadata::v1::metadata::{SearchState, hydrate_all};
+use crate::api::{db_error_status, error_response};
+use crate::db;
+use crate::models::metadata::{ItemType, is_valid_omid};
+
+const DEFAULT_BROWSE_LIMIT: i64 = 50;
+const MAX_BROWSE_LIMIT: i64 = 100;
+const LETTER_COUNTS_TTL: Duration = Duration::from_secs(10 * 60);
+
+type LetterCounts = BTreeMap<String, i64>;
+
+/// Per-letter artist totals and when they were counted. Like the quality report, the lock
+/// is held while recounting so concurrent pages share one scan.
+#[derive(Clone, Default)]
+pub struct LetterCountsCache {
+    counts: Arc<tokio::sync::Mutex<Option<(Instant, LetterCounts)>>>,
+    hits: Arc<HitCounts>,
+}
+
+impl LetterCountsCache {
+    async fn get(&self, pool: &sqlx::PgPool) -> Result<LetterCounts, sqlx::Error> {
+        let mut cached = self.counts.lock().await;
+        let fresh = cached
+            .as_ref()
+            .filter(|(at, _)| at.elapsed() < LETTER_COUNTS_TTL);
+        self.hits.record(fresh.is_some());
+        if let Some((_, counts)) = fresh {
+            return Ok(counts.clone());
+        }
+        let counts = db::metadata::artist_letter_counts(pool).await?;
+        *cached = Some((Instant::now(), counts.clone()));
+        Ok(counts)
+    }
+
+    pub async fn stats(&self) -> CacheStats {
+        let cached = self.counts.lock().await;
+        let bytes = cached.as_ref().map_or(0, |(_, counts)| {
+            counts.keys().map(|k| k.len() + size_of::<i64>()).sum()
+        });
+        self.hits.stats(cached.is_some() as usize, bytes)
+    }
+
+    /// Drops the totals, e.g. after artists were merged. Returns how many entries there were.
+    pub async fn clear(&self) -> usize {
+        self.counts.lock().await.take().is_some() as usize
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BrowseQuery {
+    #[serde(rename = "type")]
+    pub item_type: Option<String>,
+    pub year: Option<i32>,
+    pub date_from: Option<String>,
+    pub date_to: Option<String>,
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+    /// `false` skips counting the rows with unparseable dates; `skipped` is then omitted.
+    pub count: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ArtistIndexQuery {
+    pub starts_with: Option<String>,
+    pub after: Option<String>,
+    pub limit: Option<i64>,
+    /// `false` skips the per-letter totals.
+    pub count: Option<bool>,
+}
+
+pub fn router() -> Router<SearchState> {
+    Router::new()
+        .route("/browse", get(browse_handler))
+        .route("/artists", get(artist_index_handler))
+}
+
+/// Parses a strict `YYYY-MM-DD` calendar date.
+pub fn parse_date(raw: &str) -> Option<Date> {
+    let mut parts = raw.trim().splitn(3, '-');
+    let year = parts.next().filter(|p| p.len() == 4)?.parse().ok()?;
+    let month = parts.next().filter(|p| p.len() == 2)?.parse::<u8>().ok()?;
+    let day = parts.next().filter(|p| p.len() == 2)?.parse().ok()?;
+    Date::from_calendar_date(year, Month::try_from(month).ok()?, day).ok()
+}
+
+fn date_range(params: &BrowseQuery) -> Result<(Date, Date), &'static str> {
+    if let Some(year) = params.year {
+        if params.date_from.is_some() || params.date_to.is_some() {
+            return Err("Use either year or date_from/date_to");
+        }
+        let from = Date::from_calendar_date(year, Month::January, 1);
+        let to = Date::from_calendar_date(year, Month::December, 31);
+        return match (from, to) {
+            (Ok(from), Ok(to)) if year > 0 => Ok((from, to)),
+            _ => Err("Invalid year"),
+        };
+    }
+    let (Some(from), Some(to)) = (&params.date_from, &params.date_to) else {
+        return Err("Provide year or both date_from and date_to");
+    };
+    let from = parse_date(from).ok_or("Invalid date_from, expected YYYY-MM-DD")?;
+    let to = parse_date(to).ok_or("Invalid date_to, expected YYYY-MM-DD")?;
+    if from > to {
+        return Err("date_from must not be after date_to");
+    }
+    Ok((from, to))
+}
+
+async fn browse_handler(
+    State(state): State<SearchState>,
+    Query(params): Query<BrowseQuery>,
+) -> Response {
+    let item_type = match params.item_type.as_deref().map(str::parse) {
+        None => ItemType::Song,
+        Some(Ok(ItemType::Artist)) => {
+            return error_response(StatusCode::BAD_REQUEST, "type must be song or album")
+                .into_response();
+        }
+        Some(Ok(item_type)) => item_type,
+        Some(Err(e)) => return e.into_response(),
+    };
+    let item_type = item_type.as_str();
+    let (from, to) = match date_range(&params) {
+        Ok(range) => range,
+        Err(msg) => return error_response(StatusCode::BAD_REQUEST, msg).into_response(),
+    };
+    let limit = params.limit.unwrap_or(DEFAULT_BROWSE_LIMIT);
+    if !(1..=MAX_BROWSE_LIMIT).contains(&limit) {
+        return error_response(StatusCode::BAD_REQUEST, "limit must be between 1 and 100")
+            .into_response();
+    }
+    let cursor = params.cursor.as_deref().map(|c| c.trim().to_lowercase());
+    if cursor.as_deref().is_some_and(|c| !is_valid_omid(c)) {
+        return error_response(StatusCode::BAD_REQUEST, "Invalid cursor").into_response();
+    }
+
+    let pool = &state.scrape_pool;
+    // One extra row tells whether there is a next page without a second query.
+    let ids =
+        db::metadata::ids_by_release_date(pool, item_type, from, to, cursor.as_deref(), limit + 1);
+    let skipped = async {
+        if params.count == Some(false) {
+            return Ok(None);
+        }
+        db::metadata::unparseable_release_dates(pool, item_type, from.year(), to.year())
+            .await
+            .map(Some)
+    };
+    let (mut ids, skipped) = match tokio::try_join!(ids, skipped) {
+        Ok(result) => result,
+        Err(e) => {
+            tracing::error!("browse error: {}", e);
+            return error_response(db_error_status(&e), "Browse failed").into_response();
+        }
+    };
+
+    let has_more = ids.len() as i64 > limit;
+    ids.truncate(limit as usize);
+    let next = has_more.then(|| ids.last().cloned()).flatten();
+    let data = match hydrate_all(&state, item_type, ids, &HashSet::new()).await {
+        Ok(data) => data,
+        Err(e) => {
+            tracing::error!("browse error: {}", e);
+            return error_response(db_error_status(&e), "Browse failed").into_response();
+        }
+    };
+
+    let mut body = json!({ "data": data, "next": next });
+    if let Some(skipped) = skipped {
+        body["skipped"] = json!(skipped);
+    }
+    (StatusCode::OK, Json(body)).into_response()
+}
+
+/// An artist index cursor: the hex-encoded lower-cased name and the id of the last artist
+/// on the page, joined by a dot.
+fn artist_cursor(name: &str, id: &str) -> String {
+    format!("{}.{id}", hex::encode(name))
+}
+
+fn parse_artist_cursor(raw: &str) -> Option<(String, String)> {
+    let (name, id) = raw.trim().rsplit_once('.')?;
+    let id = id.to_lowercase();
+    let name = String::from_utf8(hex::decode(name).ok()?).ok()?;
+    is_valid_omid(&id).then_some((name, id))
+}
+
+/// A–Z artist listing straight from Postgres. `starts_with` is a letter or `#` for names
+/// that don't start with one; pages are keyed on the last artist's name and id. Letter
+/// totals are cached for ten minutes.
+async fn artist_index_handler(
+    State(state): State<SearchState>,
+    Query(params): Query<ArtistIndexQuery>,
+) -> Response {
+    let letter = match params.starts_with.as_deref().map(str::trim) {
+        Some("#") => '#',
+        Some(raw) if raw.len() == 1 && raw.as_bytes()[0].is_ascii_alphabetic() => {
+            raw.as_bytes()[0].to_ascii_lowercase() as char
+        }
+        _ => {
+            return error_response(
+                StatusCode::BAD_REQUEST,
+                "starts_with must be a single letter or #",
+            )
+            .into_response();
+        }
+    };
+    let limit = params.limit.unwrap_or(DEFAULT_BROWSE_LIMIT);
+    if !(1..=MAX_BROWSE_LIMIT).contains(&limit) {
+        return error_response(StatusCode::BAD_REQUEST, "limit must be between 1 and 100")
+            .into_response();
+    }
+    let after = match params.after.as_deref().map(parse_artist_cursor) {
+        None => None,
+        Some(Some(after)) => Some(after),
+        Some(None) => {
+            return error_response(StatusCode::BAD_REQUEST, "Invalid after").into_response();
+        }
+    };
+
+    let pool = &state.scrape_pool;
+    let rows = db::metadata::artist_ids_by_letter(
+        pool,
+        letter,
+        after
+            .as_ref()
+            .map(|(name, id)| (name.as_str(), id.as_str())),
+        limit + 1,
+    );
+    let letters = async {
+        if params.count == Some(false) {
+            return Ok(None);
+        }
+        state.letter_counts.get(pool).await.map(Some)
+    };
+    let (mut rows, letters) = match tokio::try_join!(rows, letters) {
+        Ok(result) => result,
+        Err(e) => {
+            tracing::error!("artist index error: {}", e);
+            return error_response(db_error_status(&e), "Browse failed").into_response();
+        }
+    };
+
+    let has_more = rows.len() as i64 > limit;
+    rows.truncate(limit as usize);
+    let next = has_more
+        .then(|| rows.last().map(|(id, name)| artist_cursor(name, id)))
+        .flatten();
+    let ids = rows.into_iter().map(|(id, _)| id).collect();
+    let data = match hydrate_all(&state, "artist", ids, &HashSet::new()).await {
+        Ok(data) => data,
+        Err(e) => {
+            tracing::error!("artist index error: {}", e);
+            return error_response(db_error_status(&e), "Browse failed").into_response();
+        }
+    };
+
+    let mut body = json!({ "data": data, "next": next });
+    if let Some(letters) = letters {
+        body["letters"] = json!(letters);
+    }
+    (StatusCode::OK, Json(body)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+
+    use crate::test_support::{self, get, send};
+
+    fn ids(body: &serde_json::Value) -> Vec<&str> {
+        body["data"]
+            .as_array()
+            .expect("data array")
+            .iter()
+            .map(|a| a["id"].as_str().unwrap_or_default())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn artist_index_pages_past_removed_artists() {
+        let Some(pool) = test_support::scrape_db().await else {
+            return;
+        };
+        sqlx::raw_sql(
+            "INSERT INTO artists (id, name) VALUES
+                 ('dido000000000001', 'Dido'),
+                 ('disclosure000001', 'Disclosure'),
+                 ('dj00000000000001', 'DJ Snake')",
+        )
+        .execute(&pool)
+        .await
+        .expect("insert artists");
+        let app = test_support::app(
+            Some(test_support::search_state(pool.clone())),
+            test_support::unreachable_pool(),
+        );
+
+        let (status, _, first) = send(
+            app.clone(),
+            get("/metadata/v1/artists?starts_with=D&limit=2"),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            ids(&first),
+            ["omm:artist:daftpunk00000001", "omm:artist:dido000000000001"]
+        );
+        assert_eq!(first["letters"]["D"], 4);
+        let next = first["next"].as_str().expect("next cursor").to_string();
+
+        // The cursor carries the name, so the page continues after its artist is gone.
+        sqlx::query("DELETE FROM artists WHERE id = 'dido000000000001'")
+            .execute(&pool)
+            .await
+            .expect("delete artist");
+        let uri = format!("/metadata/v1/artists?starts_with=d&limit=2&after={next}");
+        let (status, _, second) = send(app.clone(), get(&uri)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            ids(&second),
+            ["omm:artist:disclosure000001", "omm:artist:dj00000000000001"]
+        );
+        assert_eq!(second["next"], serde_json::Value::Null);
+        // Letter totals come from the cache until it expires.
+        assert_eq!(second["letters"]["D"], 4);
+
+        for after in ["dido000000000001", "zz.dido000000000001", "6469646f.short"] {
+            let uri = format!("/metadata/v1/artists?starts_with=d&after={after}");
+            let (status, _, _) = send(app.clone(), get(&uri)).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{after}");
+        }
+    }
+}
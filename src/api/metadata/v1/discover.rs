@@ -0,0 +1,185 @@
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use time::{Date, OffsetDateTime};
+
+use crate::api::metadata::v1::browse::parse_date;
+use crate::api::metadata::v1::cache::{CacheStats, HitCounts};
+use crate::api::metadata::v1::metadata::{SearchState, hydrate_all};
+use crate::api::{db_error_status, error_response};
+use crate::db;
+use crate::models::metadata::ItemType;
+
+const DEFAULT_DISCOVER_LIMIT: usize = 20;
+const MAX_DISCOVER_LIMIT: usize = 100;
+const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+const CACHE_ENTRIES: usize = 64;
+
+struct CachedSelection {
+    at: Instant,
+    data: Arc<Vec<Value>>,
+    /// Serialized size, as a rough memory estimate.
+    bytes: usize,
+}
+
+#[derive(Default)]
+struct CacheInner {
+    entries: Mutex<HashMap<(String, String), CachedSelection>>,
+    counts: HitCounts,
+}
+
+/// Hydrated selections per (seed, type), always `MAX_DISCOVER_LIMIT` long so any smaller
+/// `limit` is a prefix of the cached list.
+#[derive(Clone, Default)]
+pub struct DiscoverCache(Arc<CacheInner>);
+
+impl DiscoverCache {
+    fn entries(&self) -> MutexGuard<'_, HashMap<(String, String), CachedSelection>> {
+        self.0.entries.lock().expect("discover cache lock poisoned")
+    }
+
+    fn get(&self, key: &(String, String)) -> Option<Arc<Vec<Value>>> {
+        let hit = self
+            .entries()
+            .get(key)
+            .filter(|c| c.at.elapsed() < CACHE_TTL)
+            .map(|c| c.data.clone());
+        self.0.counts.record(hit.is_some());
+        hit
+    }
+
+    fn insert(&self, key: (String, String), data: Arc<Vec<Value>>) {
+        let bytes = data.iter().map(|v| v.to_string().len()).sum();
+        let mut entries = self.entries();
+        entries.retain(|_, c| c.at.elapsed() < CACHE_TTL);
+        if entries.len() >= CACHE_ENTRIES {
+            entries.clear();
+        }
+        entries.insert(
+            key,
+            CachedSelection {
+                at: Instant::now(),
+                data,
+                bytes,
+            },
+        );
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let entries = self.entries();
+        self.0
+            .counts
+            .stats(entries.len(), entries.values().map(|c| c.bytes).sum())
+    }
+
+    /// Drops every selection. Returns how many there were.
+    pub fn clear(&self) -> usize {
+        let mut entries = self.entries();
+        let count = entries.len();
+        entries.clear();
+        count
+    }
+
+    /// Drops the selections that contain the resource `omid`. Returns how many.
+    pub fn evict(&self, omid: &str) -> usize {
+        let mut entries = self.entries();
+        let before = entries.len();
+        entries.retain(|_, c| !c.data.iter().any(|v| v["id"] == omid));
+        before - entries.len()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DiscoverQuery {
+    #[serde(rename = "type")]
+    pub item_type: Option<String>,
+    pub seed: Option<String>,
+    pub limit: Option<usize>,
+}
+
+pub fn router() -> Router<SearchState> {
+    Router::new().route("/discover", get(discover_handler))
+}
+
+/// The cached selection for `seed`, hydrating and caching it on a miss.
+pub async fn selection(
+    state: &SearchState,
+    item_type: &str,
+    seed: Date,
+) -> Result<Arc<Vec<Value>>, Arc<sqlx::Error>> {
+    let key = (seed.to_string(), item_type.to_string());
+    if let Some(data) = state.discover_cache.get(&key) {
+        return Ok(data);
+    }
+    let hash_seed = format!("{}:{}", key.0, key.1);
+    let ids = db::metadata::discover_ids(
+        &state.scrape_pool,
+        item_type,
+        &hash_seed,
+        MAX_DISCOVER_LIMIT as i64,
+    )
+    .await
+    .map_err(Arc::new)?;
+    let data = Arc::new(hydrate_all(state, item_type, ids, &HashSet::new()).await?);
+    state.discover_cache.insert(key, data.clone());
+    Ok(data)
+}
+
+async fn discover_handler(
+    State(state): State<SearchState>,
+    Query(params): Query<DiscoverQuery>,
+) -> Response {
+    let item_type = match params.item_type.as_deref().map(str::parse) {
+        None => ItemType::Song,
+        Some(Ok(ItemType::Artist)) => {
+            return error_response(StatusCode::BAD_REQUEST, "type must be song or album")
+                .into_response();
+        }
+        Some(Ok(item_type)) => item_type,
+        Some(Err(e)) => return e.into_response(),
+    };
+    let item_type = item_type.as_str();
+    let seed = match params.seed.as_deref() {
+        Some(raw) => match parse_date(raw) {
+            Some(date) => date,
+            None => {
+                return error_response(
+                    StatusCode::BAD_REQUEST,
+                    "Invalid seed, expected YYYY-MM-DD",
+                )
+                .into_response();
+            }
+        },
+        None => OffsetDateTime::now_utc().date(),
+    };
+    let limit = params.limit.unwrap_or(DEFAULT_DISCOVER_LIMIT);
+    if !(1..=MAX_DISCOVER_LIMIT).contains(&limit) {
+        return error_response(StatusCode::BAD_REQUEST, "limit must be between 1 and 100")
+            .into_response();
+    }
+
+    let data = match selection(&state, item_type, seed).await {
+        Ok(data) => data,
+        Err(e) => {
+            tracing::error!("discover error: {}", e);
+            return error_response(db_error_status(&e), "Discover failed").into_response();
+        }
+    };
+
+    let page: Vec<&Value> = data.iter().take(limit).collect();
+    (
+        StatusCode::OK,
+        [(header::CACHE_CONTROL, "public, max-age=3600")],
+        Json(json!({ "data": page, "seed": seed.to_string() })),
+    )
+        .into_response()
+}
@@ -0,0 +1,172 @@
+use axum::{
+    Router,
+    body::{Body, Bytes},
+    extract::{Path, Query, State},
+    http::{StatusCode, header},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use futures::{SinkExt, TryStreamExt, channel::mpsc};
+use serde::Deserialize;
+
+use crate::api::metadata::v1::metadata::SearchState;
+use crate::api::{db_error_status, error_response};
+use crate::auth::{self, ApiKeys};
+use crate::daily_quota::daily_quota;
+use crate::db;
+use crate::ip_allowlist::{self, IpAllowlist};
+use crate::models::metadata::{ItemType, is_valid_omid};
+use crate::rate_limit::rate_limit;
+
+const DEFAULT_EXPORT_LIMIT: i64 = 100;
+const MAX_EXPORT_LIMIT: i64 = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    pub after_id: Option<String>,
+    pub limit: Option<i64>,
+}
+
+pub fn router(api_keys: ApiKeys, allowlist: IpAllowlist) -> Router<SearchState> {
+    Router::new()
+        .route("/export/{type}", get(export_handler))
+        .layer(middleware::from_fn_with_state(
+            (api_keys.clone(), "export"),
+            auth::require_scope,
+        ))
+        .layer(middleware::from_fn_with_state(
+            allowlist,
+            ip_allowlist::require_allowed_ip,
+        ))
+        .layer(daily_quota("export", 5_000, &api_keys))
+        .layer(rate_limit("export", 2, 1000, &api_keys))
+}
+
+async fn export_handler(
+    State(state): State<SearchState>,
+    Path(item_type): Path<String>,
+    Query(params): Query<ExportQuery>,
+) -> Response {
+    let item_type = match item_type.parse::<ItemType>() {
+        Ok(item_type) => item_type.as_str(),
+        Err(e) => return e.into_response(),
+    };
+
+    let limit = params.limit.unwrap_or(DEFAULT_EXPORT_LIMIT);
+    if !(1..=MAX_EXPORT_LIMIT).contains(&limit) {
+        return error_response(StatusCode::BAD_REQUEST, "limit must be between 1 and 1000")
+            .into_response();
+    }
+
+    let after_id = params
+        .after_id
+        .as_deref()
+        .map(|s| s.trim().to_lowercase())
+        .unwrap_or_default();
+    if !after_id.is_empty() && !is_valid_omid(&after_id) {
+        return error_response(StatusCode::BAD_REQUEST, "Invalid after_id").into_response();
+    }
+
+    let page_end = match db::metadata::export_page_end(
+        &state.scrape_pool,
+        item_type,
+        &after_id,
+        limit,
+    )
+    .await
+    {
+        Ok(end) => end,
+        Err(e) => {
+            tracing::error!("export error: {}", e);
+            return error_response(db_error_status(&e), "Export failed").into_response();
+        }
+    };
+
+    let Some((last_id, count)) = page_end else {
+        return (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, "application/x-ndjson")],
+            "",
+        )
+            .into_response();
+    };
+
+    let (mut tx, rx) = mpsc::channel::<Result<Bytes, std::io::Error>>(64);
+    let pool = state.scrape_pool.clone();
+    let cursor = last_id.clone();
+    tokio::spawn(async move {
+        let Some(mut rows) = db::metadata::export_rows(&pool, item_type, &after_id, &cursor) else {
+            return;
+        };
+        loop {
+            let line = match rows.try_next().await {
+                Ok(Some(doc)) => {
+                    let mut line = doc.to_string();
+                    line.push('\n');
+                    Ok(Bytes::from(line))
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    tracing::error!("export stream error: {}", e);
+                    Err(std::io::Error::other(e))
+                }
+            };
+            let failed = line.is_err();
+            if tx.send(line).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    let mut response = (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(rx),
+    )
+        .into_response();
+    if count == limit
+        && let Ok(value) = last_id.parse()
+    {
+        response.headers_mut().insert("x-next-after", value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{StatusCode, header};
+
+    use crate::api::metadata::v1::metadata::SearchState;
+    use crate::auth::ApiKeys;
+    use crate::test_support::{self, get, send};
+
+    #[tokio::test]
+    async fn unavailable_songs_are_not_exported() {
+        let Some(pool) = test_support::scrape_db().await else {
+            return;
+        };
+        let keys = ApiKeys::from_entries("mirror:export-token:export");
+        let state = SearchState::new(test_support::memory_search(), pool, keys);
+        let app = test_support::app(Some(state), test_support::unreachable_pool());
+        let mut req = get("/metadata/v1/export/song?limit=1000");
+        req.headers_mut().insert(
+            header::AUTHORIZATION,
+            "Bearer export-token".parse().unwrap(),
+        );
+
+        let (status, _, body) = send(app, req).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let ids: Vec<String> = body
+            .as_str()
+            .expect("ndjson")
+            .lines()
+            .map(|line| {
+                let doc: serde_json::Value = serde_json::from_str(line).expect("json line");
+                doc["id"].as_str().expect("id").to_string()
+            })
+            .collect();
+        assert!(ids.contains(&"getlucky00000001".to_string()), "{ids:?}");
+        assert!(!ids.contains(&"horizon000000001".to_string()), "{ids:?}");
+    }
+}
@@ -1,37 +1,173 @@
 use axum::{
     Json, Router,
-    extract::{Path, Query, State},
-    http::StatusCode,
-    response::IntoResponse,
+    extract::{Path, Query, Request, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
 };
-use serde::Deserialize;
+use futures::future::{BoxFuture, Shared};
+use futures::{FutureExt, StreamExt, TryStreamExt, stream};
+use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
 use sqlx::PgPool;
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
 
+use crate::api::metadata::v1::admin::QualityCache;
+use crate::api::metadata::v1::artwork::ArtworkCache;
+use crate::api::metadata::v1::browse::LetterCountsCache;
+use crate::api::metadata::v1::discover::DiscoverCache;
 use crate::api::metadata::v1::resource::{
-    parse_includes, render_album, render_artist, render_song,
+    EXTERNAL_IDS, parse_includes, put_external_ids, render_album, render_artist, render_song,
+    supported_includes,
 };
+use crate::api::validation::{KnownParams, StrictQuery};
+use crate::api::{db_error_status, error_response, search_error_status};
+use crate::auth::ApiKeys;
 use crate::db;
-use crate::manticore::SearchClient;
+use crate::models::metadata::{
+    ExplicitFilter, ItemType, OmId, is_valid_apple_music_id, normalize_isrc, normalize_upc,
+};
+use crate::rate_limit::rate_limit_if;
+use crate::search::{SearchBackend, SearchQuery};
+use crate::text;
 
 #[derive(Clone)]
 pub struct SearchState {
-    pub client: Arc<SearchClient>,
+    pub client: Arc<SearchBackend>,
     pub scrape_pool: PgPool,
+    pub api_keys: ApiKeys,
+    pub in_flight: InFlight,
+    pub discover_cache: DiscoverCache,
+    pub quality_cache: QualityCache,
+    pub letter_counts: LetterCountsCache,
+    pub artwork_cache: ArtworkCache,
+    /// Time allowed for index queries and hydration within one request.
+    pub budget: Duration,
+}
+
+impl SearchState {
+    pub fn new(client: Arc<SearchBackend>, scrape_pool: PgPool, api_keys: ApiKeys) -> Self {
+        Self {
+            client,
+            scrape_pool,
+            api_keys,
+            in_flight: Default::default(),
+            discover_cache: Default::default(),
+            quality_cache: Default::default(),
+            letter_counts: Default::default(),
+            artwork_cache: Default::default(),
+            budget: Duration::from_millis(
+                std::env::var("SEARCH_BUDGET_MS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .filter(|v| *v > 0)
+                    .unwrap_or(2000),
+            ),
+        }
+    }
+}
+
+type SharedFetch = Shared<BoxFuture<'static, Result<Option<Fetched>, Arc<sqlx::Error>>>>;
+
+/// Entity lookups currently hitting Postgres, keyed by type, id and includes, so concurrent
+/// requests for the same resource share one query.
+#[derive(Clone, Default)]
+pub struct InFlight(Arc<Mutex<HashMap<String, SharedFetch>>>);
+
+impl InFlight {
+    /// Runs the lookup `load` starts unless one under `key` is already in flight, in which
+    /// case its result is shared. Also tells whether it joined one.
+    async fn run<F>(
+        &self,
+        key: String,
+        load: F,
+    ) -> (Result<Option<Fetched>, Arc<sqlx::Error>>, bool)
+    where
+        F: FnOnce() -> BoxFuture<'static, Result<Option<Fetched>, Arc<sqlx::Error>>>,
+    {
+        let (shared, coalesced) = {
+            let mut in_flight = self.0.lock().expect("in-flight lock poisoned");
+            if let Some(shared) = in_flight.get(&key) {
+                (shared.clone(), true)
+            } else {
+                let entries = self.0.clone();
+                let done_key = key.clone();
+                let lookup = load();
+                let shared = async move {
+                    let result = lookup.await;
+                    entries
+                        .lock()
+                        .expect("in-flight lock poisoned")
+                        .remove(&done_key);
+                    result
+                }
+                .boxed()
+                .shared();
+                in_flight.insert(key.clone(), shared.clone());
+                (shared, false)
+            }
+        };
+        let mut waiter = Waiter {
+            in_flight: self,
+            key,
+            shared,
+        };
+        ((&mut waiter.shared).await, coalesced)
+    }
+}
+
+/// One request's handle on an in-flight lookup. A request can be dropped mid-lookup (client
+/// disconnect, timeout, partial results); when the last one goes, the entry is removed so
+/// the unfinished query is dropped with it instead of holding its pool connection.
+struct Waiter<'a> {
+    in_flight: &'a InFlight,
+    key: String,
+    shared: SharedFetch,
+}
+
+impl Drop for Waiter<'_> {
+    fn drop(&mut self) {
+        // `None` once this handle saw the result; the lookup removed its own entry then.
+        let Some(handles) = self.shared.strong_count() else {
+            return;
+        };
+        let mut in_flight = self.in_flight.0.lock().expect("in-flight lock poisoned");
+        // Only the map's handle and this one left: nobody else is waiting.
+        if handles <= 2
+            && in_flight
+                .get(&self.key)
+                .is_some_and(|s| s.ptr_eq(&self.shared))
+        {
+            in_flight.remove(&self.key);
+        }
+    }
 }
 
 const MAX_LOOKUP_VALUES: usize = 100;
 const MATCH_CANDIDATES: i32 = 50;
+const HYDRATE_CONCURRENCY: usize = 4;
+const NAME_SEPARATOR: char = '\u{1f}';
+const MAX_EMBEDDED_TRACKS: i64 = 200;
+const TOP_SONGS: i64 = 10;
+const DEFAULT_LABEL_LIMIT: i64 = 20;
+const MAX_LABEL_LIMIT: i64 = 100;
+/// Collection lookups can mix types and fan out widely, so they don't embed tracklists.
+const COLLECTION_INCLUDES: &[&str] = &["albums", "artists", EXTERNAL_IDS];
 
 fn best_jw(candidate_joined: &str, query: &str) -> f64 {
-    let q = query.to_lowercase();
-    let c = candidate_joined.to_lowercase();
-    if c.contains(q.as_str()) {
-        return 1.0;
-    }
-    c.split_whitespace()
-        .map(|part| strsim::jaro_winkler(part.trim_matches(','), q.as_str()))
+    let q = text::normalize(query);
+    candidate_joined
+        .split(NAME_SEPARATOR)
+        .map(|name| {
+            let c = text::normalize(name.trim());
+            if c.contains(q.as_str()) {
+                1.0
+            } else {
+                strsim::jaro_winkler(&c, q.as_str())
+            }
+        })
         .fold(0.0_f64, f64::max)
 }
 
@@ -43,7 +179,7 @@ fn score_candidate(
     qa: Option<&str>,
     qal: Option<&str>,
 ) -> f64 {
-    let mut score = strsim::jaro_winkler(&cn.to_lowercase(), &qn.to_lowercase()) * 0.6;
+    let mut score = strsim::jaro_winkler(&text::normalize(cn), &text::normalize(qn)) * 0.6;
     if let Some(a) = qa {
         score += best_jw(ca, a) * 0.3;
     }
@@ -56,6 +192,9 @@ fn score_candidate(
 #[derive(Debug, Deserialize)]
 pub struct IncludeQuery {
     pub include: Option<String>,
+    pub include_unavailable: Option<bool>,
+    /// Narrows embedded `tracks` and `top_songs`.
+    pub explicit: Option<ExplicitFilter>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -64,6 +203,7 @@ pub struct LookupQuery {
     pub isrc: Option<String>,
     pub upc: Option<String>,
     pub include: Option<String>,
+    pub include_unavailable: Option<bool>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -71,22 +211,80 @@ pub struct MatchQuery {
     pub name: Option<String>,
     pub album: Option<String>,
     pub artist: Option<String>,
+    pub genre: Option<String>,
+    pub label: Option<String>,
+    /// Only applies to songs.
+    pub explicit: Option<ExplicitFilter>,
     pub include: Option<String>,
+    pub suggest: Option<bool>,
+    /// Adds `timings` to the response. Allowed without a key, but limited separately.
+    pub debug: Option<bool>,
+}
+
+impl KnownParams for MatchQuery {
+    const PARAMS: &'static [&'static str] = &[
+        "name", "album", "artist", "genre", "label", "explicit", "include", "suggest", "debug",
+    ];
+}
+
+#[derive(Debug, Deserialize)]
+struct DebugQuery {
+    debug: Option<bool>,
+}
+
+fn is_debug_request(req: &Request) -> bool {
+    Query::<DebugQuery>::try_from_uri(req.uri()).is_ok_and(|q| q.debug == Some(true))
 }
 
-pub fn router() -> Router<SearchState> {
+/// Where a `debug=true` match spent its time, so slow reports can be told apart from slow
+/// networks.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestTimings {
+    pub backend: &'static str,
+    pub index_ms: f64,
+    pub hydrate_ms: f64,
+    pub serialize_ms: f64,
+    /// Hydration joined a lookup of the same resource that was already in flight.
+    pub coalesced: bool,
+}
+
+fn elapsed_ms(start: Instant) -> f64 {
+    start.elapsed().as_secs_f64() * 1000.0
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LabelsQuery {
+    /// Case-insensitive name prefix, for autocomplete.
+    pub q: Option<String>,
+    pub limit: Option<i64>,
+}
+
+pub fn router(api_keys: &ApiKeys) -> Router<SearchState> {
     Router::new()
         .route("/", axum::routing::get(stats_handler))
+        .route("/genres", axum::routing::get(genres_handler))
+        .route("/labels", axum::routing::get(labels_handler))
         .route("/lookup", axum::routing::get(lookup_collection_handler))
         .route("/lookup/{id}", axum::routing::get(lookup_single_handler))
-        .route("/match/{type}", axum::routing::get(match_handler))
-}
-
-fn error_response(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
-    (
-        status,
-        Json(json!({ "error": { "status": status.as_u16(), "message": message } })),
-    )
+        .route(
+            "/lookup/{id}/albums",
+            axum::routing::get(song_albums_handler),
+        )
+        .route(
+            "/match/{type}",
+            axum::routing::get(match_handler).layer(rate_limit_if(
+                "match_debug",
+                10,
+                60_000,
+                api_keys,
+                is_debug_request,
+            )),
+        )
+        .route(
+            "/apple/{type}/{apple_id}",
+            axum::routing::get(apple_lookup_handler),
+        )
 }
 
 fn split_values(raw: &str) -> Vec<String> {
@@ -96,24 +294,15 @@ fn split_values(raw: &str) -> Vec<String> {
         .collect()
 }
 
-fn parse_id(raw: &str) -> Option<(String, String)> {
-    let parts: Vec<&str> = raw.trim().splitn(3, ':').collect();
-    if parts.len() != 3 || parts[0] != "omm" {
-        return None;
-    }
-    let item_type = parts[1];
-    let id = parts[2];
-    if !matches!(item_type, "song" | "album" | "artist") || !is_valid_omid(id) {
-        return None;
-    }
-    Some((item_type.to_string(), id.to_string()))
-}
-
-fn is_valid_omid(id: &str) -> bool {
-    id.len() == 16
-        && id
-            .chars()
-            .all(|c| c.is_ascii_digit() || c.is_ascii_lowercase())
+/// Normalizes every value, or returns the first one that is malformed.
+fn normalize_all(
+    values: &[String],
+    normalize: fn(&str) -> Option<String>,
+) -> Result<Vec<String>, &str> {
+    values
+        .iter()
+        .map(|v| normalize(v).ok_or(v.as_str()))
+        .collect()
 }
 
 async fn stats_handler(State(state): State<SearchState>) -> impl IntoResponse {
@@ -126,34 +315,234 @@ async fn stats_handler(State(state): State<SearchState>) -> impl IntoResponse {
         ),
         Err(e) => {
             tracing::error!("stats error: {}", e);
-            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Failed to load stats")
+            error_response(db_error_status(&e), "Failed to load stats")
+        }
+    }
+}
+
+async fn genres_handler(State(state): State<SearchState>) -> impl IntoResponse {
+    match db::metadata::genre_counts(&state.scrape_pool).await {
+        Ok(genres) => (StatusCode::OK, Json(json!({ "data": genres }))),
+        Err(e) => {
+            tracing::error!("genres error: {}", e);
+            error_response(db_error_status(&e), "Failed to load genres")
+        }
+    }
+}
+
+async fn labels_handler(
+    State(state): State<SearchState>,
+    Query(params): Query<LabelsQuery>,
+) -> impl IntoResponse {
+    let prefix = params.q.as_deref().map(str::trim).filter(|q| !q.is_empty());
+    if prefix.is_some_and(|q| q.len() > 256) {
+        return error_response(StatusCode::BAD_REQUEST, "q too long");
+    }
+    let limit = params.limit.unwrap_or(DEFAULT_LABEL_LIMIT);
+    if !(1..=MAX_LABEL_LIMIT).contains(&limit) {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            &format!("limit must be between 1 and {MAX_LABEL_LIMIT}"),
+        );
+    }
+    let prefix = prefix.map(str::to_lowercase);
+    match db::metadata::label_counts(&state.scrape_pool, prefix.as_deref(), limit).await {
+        Ok(labels) => (StatusCode::OK, Json(json!({ "data": labels }))),
+        Err(e) => {
+            tracing::error!("labels error: {}", e);
+            error_response(db_error_status(&e), "Failed to load labels")
         }
     }
 }
 
-async fn fetch_resource(
+#[derive(Clone)]
+pub struct Fetched {
+    pub resource: Value,
+    pub updated_at: Option<OffsetDateTime>,
+    pub unavailable: bool,
+}
+
+/// Loads a resource, joining an identical lookup that is already in flight if there is one.
+pub async fn fetch_resource(
+    state: &SearchState,
+    item_type: &str,
+    id: &str,
+    include: &HashSet<String>,
+) -> Result<Option<Fetched>, Arc<sqlx::Error>> {
+    fetch_resource_coalesced(state, item_type, id, include)
+        .await
+        .0
+}
+
+/// [`fetch_resource`], also telling whether the lookup joined one already in flight.
+async fn fetch_resource_coalesced(
+    state: &SearchState,
+    item_type: &str,
+    id: &str,
+    include: &HashSet<String>,
+) -> (Result<Option<Fetched>, Arc<sqlx::Error>>, bool) {
+    let mut includes: Vec<&str> = include.iter().map(String::as_str).collect();
+    includes.sort_unstable();
+    let key = format!("{item_type}:{id}:{}", includes.join(","));
+
+    let (result, coalesced) = state
+        .in_flight
+        .run(key, || {
+            let (state, item_type, id, include) = (
+                state.clone(),
+                item_type.to_string(),
+                id.to_string(),
+                include.clone(),
+            );
+            async move {
+                load_resource(&state, &item_type, &id, &include)
+                    .await
+                    .map_err(Arc::new)
+            }
+            .boxed()
+        })
+        .await;
+    if coalesced {
+        metrics::counter!("lookup_coalesced_total", "type" => item_type.to_string()).increment(1);
+    }
+    (result, coalesced)
+}
+
+/// Hydrates ids of one type in order, a few at a time, skipping ids that no longer exist
+/// and unavailable entities.
+pub async fn hydrate_all(
+    state: &SearchState,
+    item_type: &str,
+    ids: Vec<String>,
+    include: &HashSet<String>,
+) -> Result<Vec<Value>, Arc<sqlx::Error>> {
+    let fetched: Vec<Option<Fetched>> = stream::iter(ids)
+        .map(|id| async move { fetch_resource(state, item_type, &id, include).await })
+        .buffered(HYDRATE_CONCURRENCY)
+        .try_collect()
+        .await?;
+    Ok(fetched
+        .into_iter()
+        .flatten()
+        .filter(|f| !f.unavailable)
+        .map(|f| f.resource)
+        .collect())
+}
+
+#[tracing::instrument(name = "db.hydrate", skip_all, fields(item_type, id))]
+async fn load_resource(
     state: &SearchState,
     item_type: &str,
     id: &str,
-    include: &std::collections::HashSet<String>,
-) -> Result<Option<Value>, sqlx::Error> {
+    include: &HashSet<String>,
+) -> Result<Option<Fetched>, sqlx::Error> {
     Ok(match item_type {
         "song" => db::metadata::get_song_by_id(&state.scrape_pool, id)
             .await?
-            .map(|s| render_song(&s, include)),
-        "album" => db::metadata::get_album_by_id(&state.scrape_pool, id)
-            .await?
-            .map(|a| render_album(&a, include)),
-        "artist" => db::metadata::get_artist_by_id(&state.scrape_pool, id)
-            .await?
-            .map(|a| render_artist(&a)),
+            .map(|s| {
+                let mut resource = render_song(&s, include);
+                if include.contains(EXTERNAL_IDS) {
+                    put_external_ids(&mut resource, s.apple_music_id.as_deref());
+                }
+                Fetched {
+                    resource,
+                    updated_at: s.updated_at,
+                    unavailable: s.deleted_at.is_some(),
+                }
+            }),
+        "album" => match db::metadata::get_album_by_id(&state.scrape_pool, id).await? {
+            Some(a) => {
+                let mut resource = render_album(&a, include);
+                if include.contains(EXTERNAL_IDS) {
+                    put_external_ids(&mut resource, a.apple_music_id.as_deref());
+                }
+                if include.contains("tracks") {
+                    embed_tracks(&state.scrape_pool, &mut resource, &a.id).await?;
+                }
+                Some(Fetched {
+                    resource,
+                    updated_at: a.updated_at,
+                    unavailable: false,
+                })
+            }
+            None => None,
+        },
+        "artist" => match db::metadata::get_artist_by_id(&state.scrape_pool, id).await? {
+            Some(a) => {
+                let mut resource = render_artist(&a);
+                if include.contains(EXTERNAL_IDS) {
+                    put_external_ids(&mut resource, a.apple_music_id.as_deref());
+                }
+                if include.contains("top_songs") {
+                    let ids =
+                        db::metadata::artist_top_song_ids(&state.scrape_pool, &a.id, TOP_SONGS)
+                            .await?;
+                    let songs = render_songs(&state.scrape_pool, ids).await?;
+                    resource["relationships"]["top_songs"] = json!({ "data": songs });
+                }
+                Some(Fetched {
+                    resource,
+                    updated_at: a.updated_at,
+                    unavailable: false,
+                })
+            }
+            None => None,
+        },
         _ => None,
     })
 }
 
+/// Adds the album's available songs in tracklist order under `relationships.tracks`.
+async fn embed_tracks(
+    pool: &PgPool,
+    resource: &mut Value,
+    album_id: &str,
+) -> Result<(), sqlx::Error> {
+    let mut ids = db::metadata::album_track_ids(pool, album_id, MAX_EMBEDDED_TRACKS + 1).await?;
+    let truncated = ids.len() as i64 > MAX_EMBEDDED_TRACKS;
+    ids.truncate(MAX_EMBEDDED_TRACKS as usize);
+    let tracks = render_songs(pool, ids).await?;
+    resource["relationships"]["tracks"] = json!({ "data": tracks, "truncated": truncated });
+    Ok(())
+}
+
+async fn render_songs(pool: &PgPool, ids: Vec<String>) -> Result<Vec<Value>, sqlx::Error> {
+    let songs: Vec<_> = stream::iter(ids)
+        .map(|id| async move { db::metadata::get_song_by_id(pool, &id).await })
+        .buffered(HYDRATE_CONCURRENCY)
+        .try_collect()
+        .await?;
+    let no_nested = HashSet::new();
+    Ok(songs
+        .into_iter()
+        .flatten()
+        .map(|s| render_song(&s, &no_nested))
+        .collect())
+}
+
+/// Unavailable entities are only revealed to admin keys that explicitly ask for them.
+fn reveal_unavailable(state: &SearchState, headers: &HeaderMap, requested: Option<bool>) -> bool {
+    requested == Some(true)
+        && state
+            .api_keys
+            .authenticate(headers)
+            .is_some_and(|key| key.has_scope("admin"))
+}
+
+fn not_modified_since(headers: &HeaderMap, updated_at: OffsetDateTime) -> bool {
+    headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| httpdate::parse_http_date(v).ok())
+        .is_some_and(|since| {
+            updated_at.unix_timestamp() <= OffsetDateTime::from(since).unix_timestamp()
+        })
+}
+
 async fn lookup_collection_handler(
     State(state): State<SearchState>,
     Query(params): Query<LookupQuery>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
     let ids = params.ids.as_deref().filter(|s| !s.is_empty());
     let isrc = params.isrc.as_deref().filter(|s| !s.is_empty());
@@ -179,7 +568,12 @@ async fn lookup_collection_handler(
         .into_response();
     }
 
-    let include = parse_includes(&params.include);
+    let deadline = tokio::time::Instant::now() + state.budget;
+    let include = match parse_includes(&params.include, COLLECTION_INCLUDES) {
+        Ok(include) => include,
+        Err(msg) => return error_response(StatusCode::BAD_REQUEST, &msg).into_response(),
+    };
+    let reveal = reveal_unavailable(&state, &headers, params.include_unavailable);
 
     let resolved: Vec<(String, String)> = if let Some(ids) = ids {
         let raw_ids = split_values(ids);
@@ -187,19 +581,32 @@ async fn lookup_collection_handler(
             return error_response(StatusCode::BAD_REQUEST, "Maximum 100 lookup values allowed")
                 .into_response();
         }
-        raw_ids.iter().filter_map(|raw| parse_id(raw)).collect()
+        raw_ids
+            .iter()
+            .filter_map(|raw| raw.parse::<OmId>().ok())
+            .map(|omid| (omid.item_type, omid.id))
+            .collect()
     } else if let Some(isrc) = isrc {
         let values = split_values(isrc);
         if values.len() > MAX_LOOKUP_VALUES {
             return error_response(StatusCode::BAD_REQUEST, "Maximum 100 lookup values allowed")
                 .into_response();
         }
+        let values = match normalize_all(&values, normalize_isrc) {
+            Ok(values) => values,
+            Err(bad) => {
+                return error_response(
+                    StatusCode::BAD_REQUEST,
+                    &format!("Invalid ISRC {bad:?}, expected CC-XXX-YY-NNNNN"),
+                )
+                .into_response();
+            }
+        };
         match db::metadata::song_ids_by_isrc(&state.scrape_pool, &values).await {
             Ok(ids) => ids.into_iter().map(|id| ("song".to_string(), id)).collect(),
             Err(e) => {
                 tracing::error!("lookup error: {}", e);
-                return error_response(StatusCode::INTERNAL_SERVER_ERROR, "Lookup failed")
-                    .into_response();
+                return error_response(db_error_status(&e), "Lookup failed").into_response();
             }
         }
     } else {
@@ -208,6 +615,16 @@ async fn lookup_collection_handler(
             return error_response(StatusCode::BAD_REQUEST, "Maximum 100 lookup values allowed")
                 .into_response();
         }
+        let values = match normalize_all(&values, normalize_upc) {
+            Ok(values) => values,
+            Err(bad) => {
+                return error_response(
+                    StatusCode::BAD_REQUEST,
+                    &format!("Invalid UPC {bad:?}, expected 12 to 14 digits"),
+                )
+                .into_response();
+            }
+        };
         match db::metadata::album_ids_by_upc(&state.scrape_pool, &values).await {
             Ok(ids) => ids
                 .into_iter()
@@ -215,46 +632,203 @@ async fn lookup_collection_handler(
                 .collect(),
             Err(e) => {
                 tracing::error!("lookup error: {}", e);
-                return error_response(StatusCode::INTERNAL_SERVER_ERROR, "Lookup failed")
-                    .into_response();
+                return error_response(db_error_status(&e), "Lookup failed").into_response();
             }
         }
     };
 
-    let mut data: Vec<Value> = Vec::new();
-    for (item_type, id) in resolved {
-        match fetch_resource(&state, &item_type, &id, &include).await {
-            Ok(Some(resource)) => data.push(resource),
-            Ok(None) => {}
-            Err(e) => {
+    // Each resource is an independent query; run a few at a time so one lookup can't
+    // monopolize the pool. Whatever is hydrated when the budget runs out is returned as a
+    // partial result.
+    let total = resolved.len();
+    let (state_ref, include) = (&state, &include);
+    let mut hydrated =
+        stream::iter(resolved)
+            .map(|(item_type, id)| async move {
+                fetch_resource(state_ref, &item_type, &id, include).await
+            })
+            .buffered(HYDRATE_CONCURRENCY);
+    let mut data: Vec<Value> = Vec::with_capacity(total);
+    let mut partial = false;
+    loop {
+        match tokio::time::timeout_at(deadline, hydrated.next()).await {
+            Ok(Some(Ok(Some(f)))) => {
+                if reveal || !f.unavailable {
+                    data.push(f.resource);
+                }
+            }
+            Ok(Some(Ok(None))) => {}
+            Ok(Some(Err(e))) => {
                 tracing::error!("lookup error: {}", e);
-                return error_response(StatusCode::INTERNAL_SERVER_ERROR, "Lookup failed")
-                    .into_response();
+                return error_response(db_error_status(&e), "Lookup failed").into_response();
+            }
+            Ok(None) => break,
+            Err(_) => {
+                partial = true;
+                break;
             }
         }
     }
 
+    if partial {
+        metrics::counter!("lookup_partial_responses_total").increment(1);
+        return (
+            StatusCode::OK,
+            Json(json!({ "data": data, "partial": true, "total": total })),
+        )
+            .into_response();
+    }
     (StatusCode::OK, Json(json!({ "data": data }))).into_response()
 }
 
 async fn lookup_single_handler(
     State(state): State<SearchState>,
-    Path(raw_id): Path<String>,
+    omid: OmId,
     Query(params): Query<IncludeQuery>,
-) -> impl IntoResponse {
-    let Some((item_type, id)) = parse_id(&raw_id) else {
-        return error_response(StatusCode::BAD_REQUEST, "Invalid id. Expected omm:TYPE:ID")
-            .into_response();
+    headers: HeaderMap,
+) -> Response {
+    single_resource(&state, &omid, &params, &headers).await
+}
+
+/// Resolves an Apple Music catalog id to our entity and responds like `/lookup/{id}`.
+async fn apple_lookup_handler(
+    State(state): State<SearchState>,
+    Path((item_type, apple_id)): Path<(String, String)>,
+    Query(params): Query<IncludeQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let item_type: ItemType = match item_type.parse() {
+        Ok(item_type) => item_type,
+        Err(e) => return e.into_response(),
     };
+    if !is_valid_apple_music_id(&apple_id) {
+        return error_response(StatusCode::BAD_REQUEST, "Invalid Apple Music id").into_response();
+    }
+    match db::metadata::id_by_apple_music_id(&state.scrape_pool, item_type.as_str(), &apple_id)
+        .await
+    {
+        Ok(Some(id)) => {
+            let omid = OmId {
+                item_type: item_type.as_str().to_string(),
+                id,
+            };
+            single_resource(&state, &omid, &params, &headers).await
+        }
+        Ok(None) => error_response(StatusCode::NOT_FOUND, "Resource not found").into_response(),
+        Err(e) => {
+            tracing::error!("apple music lookup error: {}", e);
+            error_response(db_error_status(&e), "Lookup failed").into_response()
+        }
+    }
+}
 
-    let include = parse_includes(&params.include);
+/// Single-entity responses carry external ids without asking for them.
+async fn single_resource(
+    state: &SearchState,
+    omid: &OmId,
+    params: &IncludeQuery,
+    headers: &HeaderMap,
+) -> Response {
+    let mut include = match parse_includes(&params.include, supported_includes(&omid.item_type)) {
+        Ok(include) => include,
+        Err(msg) => return error_response(StatusCode::BAD_REQUEST, &msg).into_response(),
+    };
+    // Embedded resources change without touching the parent's `updated_at`, so only a bare
+    // resource can be revalidated against it.
+    let embeds = include.iter().any(|i| i != EXTERNAL_IDS);
+    include.insert(EXTERNAL_IDS.to_string());
+    let reveal = reveal_unavailable(state, headers, params.include_unavailable);
 
-    match fetch_resource(&state, &item_type, &id, &include).await {
-        Ok(Some(resource)) => (StatusCode::OK, Json(json!({ "data": resource }))).into_response(),
-        Ok(None) => error_response(StatusCode::NOT_FOUND, "Resource not found").into_response(),
+    let mut fetched = match fetch_resource(state, &omid.item_type, &omid.id, &include).await {
+        Ok(Some(f)) if f.unavailable && !reveal => {
+            return error_response(StatusCode::GONE, "Resource is no longer available")
+                .into_response();
+        }
+        Ok(Some(f)) => f,
+        Ok(None) => {
+            return error_response(StatusCode::NOT_FOUND, "Resource not found").into_response();
+        }
         Err(e) => {
             tracing::error!("lookup error: {}", e);
-            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Lookup failed").into_response()
+            return error_response(db_error_status(&e), "Lookup failed").into_response();
+        }
+    };
+    filter_embedded_songs(&mut fetched.resource, params.explicit.unwrap_or_default());
+
+    match fetched {
+        Fetched {
+            resource,
+            updated_at: Some(updated_at),
+            ..
+        } if !embeds => {
+            let last_modified = httpdate::fmt_http_date(updated_at.into());
+            if not_modified_since(headers, updated_at) {
+                return (
+                    StatusCode::NOT_MODIFIED,
+                    [(header::LAST_MODIFIED, last_modified)],
+                )
+                    .into_response();
+            }
+            (
+                StatusCode::OK,
+                [(header::LAST_MODIFIED, last_modified)],
+                Json(json!({ "data": resource })),
+            )
+                .into_response()
+        }
+        Fetched { resource, .. } => {
+            (StatusCode::OK, Json(json!({ "data": resource }))).into_response()
+        }
+    }
+}
+
+/// Drops embedded `tracks` / `top_songs` entries the filter rejects. Lookups are shared
+/// between requests, so this runs on the response rather than in the query.
+fn filter_embedded_songs(resource: &mut Value, explicit: ExplicitFilter) {
+    if explicit == ExplicitFilter::Include {
+        return;
+    }
+    for rel in ["tracks", "top_songs"] {
+        let pointer = format!("/relationships/{rel}/data");
+        if let Some(songs) = resource.pointer_mut(&pointer).and_then(Value::as_array_mut) {
+            songs.retain(|s| explicit.allows(s["attributes"]["explicit"].as_bool()));
+        }
+    }
+}
+
+/// Every release a song appears on, oldest first.
+async fn song_albums_handler(State(state): State<SearchState>, omid: OmId) -> Response {
+    if omid.item_type != "song" {
+        return error_response(StatusCode::BAD_REQUEST, "Only songs have an album list")
+            .into_response();
+    }
+    match fetch_resource(&state, "song", &omid.id, &HashSet::new()).await {
+        Ok(Some(f)) if f.unavailable => {
+            return error_response(StatusCode::GONE, "Resource is no longer available")
+                .into_response();
+        }
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return error_response(StatusCode::NOT_FOUND, "Resource not found").into_response();
+        }
+        Err(e) => {
+            tracing::error!("song albums error: {}", e);
+            return error_response(db_error_status(&e), "Lookup failed").into_response();
+        }
+    }
+
+    let ids = match db::metadata::song_album_ids(&state.scrape_pool, &omid.id).await {
+        Ok(ids) => ids,
+        Err(e) => {
+            tracing::error!("song albums error: {}", e);
+            return error_response(db_error_status(&e), "Lookup failed").into_response();
+        }
+    };
+    match hydrate_all(&state, "album", ids, &HashSet::new()).await {
+        Ok(data) => (StatusCode::OK, Json(json!({ "data": data }))).into_response(),
+        Err(e) => {
+            tracing::error!("song albums error: {}", e);
+            error_response(db_error_status(&e), "Lookup failed").into_response()
         }
     }
 }
@@ -262,11 +836,17 @@ async fn lookup_single_handler(
 async fn match_handler(
     State(state): State<SearchState>,
     Path(item_type): Path<String>,
-    Query(params): Query<MatchQuery>,
+    StrictQuery(params): StrictQuery<MatchQuery>,
 ) -> impl IntoResponse {
-    if !matches!(item_type.as_str(), "song" | "album" | "artist") {
-        return error_response(StatusCode::BAD_REQUEST, "Invalid type").into_response();
-    }
+    let item_type: ItemType = match item_type.parse() {
+        Ok(item_type) => item_type,
+        Err(e) => return e.into_response(),
+    };
+
+    let include = match parse_includes(&params.include, supported_includes(item_type.as_str())) {
+        Ok(include) => include,
+        Err(msg) => return error_response(StatusCode::BAD_REQUEST, &msg).into_response(),
+    };
 
     let name = params.name.as_deref().filter(|s| !s.is_empty());
     let Some(name) = name else {
@@ -282,47 +862,582 @@ async fn match_handler(
         return error_response(StatusCode::BAD_REQUEST, "query parameter too long").into_response();
     }
 
-    let (artist, album) = match item_type.as_str() {
-        "song" => (artist, album),
-        "album" => (artist, None),
-        _ => (None, None),
+    let genres: Vec<String> = params
+        .genre
+        .as_deref()
+        .map(split_values)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|g| g.to_lowercase())
+        .collect();
+    let capabilities = state.client.capabilities();
+    if !genres.is_empty() && !capabilities.supports_genre_filter {
+        return error_response(
+            StatusCode::NOT_IMPLEMENTED,
+            "genre filtering is not supported by this search backend",
+        )
+        .into_response();
+    }
+    if params.suggest == Some(true) && !capabilities.supports_suggest {
+        return error_response(
+            StatusCode::NOT_IMPLEMENTED,
+            "suggestions are not supported by this search backend",
+        )
+        .into_response();
+    }
+    if genres.len() > MAX_LOOKUP_VALUES {
+        return error_response(StatusCode::BAD_REQUEST, "Too many genre values").into_response();
+    }
+
+    let label = params
+        .label
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty());
+    if label.is_some_and(|s| s.len() > 256) {
+        return error_response(StatusCode::BAD_REQUEST, "label too long").into_response();
+    }
+    if label.is_some() && !capabilities.supports_label_filter {
+        return error_response(
+            StatusCode::NOT_IMPLEMENTED,
+            "label filtering is not supported by this search backend",
+        )
+        .into_response();
+    }
+    let label = label.map(str::to_lowercase);
+
+    let explicit = params.explicit.unwrap_or_default();
+    if explicit != ExplicitFilter::Include && !capabilities.supports_explicit_filter {
+        return error_response(
+            StatusCode::NOT_IMPLEMENTED,
+            "explicit filtering is not supported by this search backend",
+        )
+        .into_response();
+    }
+
+    let (artist, album, genres, label, explicit) = match item_type {
+        ItemType::Song => (artist, album, genres, label, explicit),
+        ItemType::Album => (artist, None, genres, label, ExplicitFilter::Include),
+        ItemType::Artist => (None, None, Vec::new(), None, ExplicitFilter::Include),
+    };
+    let query = SearchQuery {
+        name: Some(name),
+        artist,
+        album,
+        genres,
+        label,
+        explicit,
     };
 
-    let candidates = match state
-        .client
-        .search(&item_type, Some(name), artist, album, MATCH_CANDIDATES, 0)
-        .await
-    {
-        Ok(result) => result,
-        Err(e) => {
+    let debug = params.debug == Some(true);
+    let mut timings = RequestTimings {
+        backend: state.client.name(),
+        ..Default::default()
+    };
+    let started = Instant::now();
+    let search = state.client.search(item_type, &query, MATCH_CANDIDATES, 0);
+    let candidates = match tokio::time::timeout(state.budget, search).await {
+        Ok(Ok(result)) => result,
+        Err(_) => {
+            return error_response(StatusCode::GATEWAY_TIMEOUT, "Match timed out").into_response();
+        }
+        Ok(Err(e)) => {
             tracing::error!("match error: {}", e);
-            return error_response(StatusCode::INTERNAL_SERVER_ERROR, "Match failed")
-                .into_response();
+            return error_response(search_error_status(&e), "Match failed").into_response();
         }
     };
 
-    let Some((matched_id, _, _, _)) =
-        candidates
-            .iter()
-            .max_by(|(_, cn1, ca1, cal1), (_, cn2, ca2, cal2)| {
-                score_candidate(cn1, ca1, cal1, name, artist, album)
-                    .total_cmp(&score_candidate(cn2, ca2, cal2, name, artist, album))
-            })
+    timings.index_ms = elapsed_ms(started);
+
+    let Some((matched_id, _, _, _)) = candidates
+        .iter()
+        // max_by keeps the last of equal scores; reversing lets ties go to the
+        // backend's order, which accounts for popularity.
+        .rev()
+        .max_by(|(_, cn1, ca1, cal1), (_, cn2, ca2, cal2)| {
+            score_candidate(cn1, ca1, cal1, name, artist, album)
+                .total_cmp(&score_candidate(cn2, ca2, cal2, name, artist, album))
+        })
     else {
-        return error_response(StatusCode::NOT_FOUND, "No match found").into_response();
+        if params.suggest == Some(false) || !capabilities.supports_suggest {
+            return error_response(StatusCode::NOT_FOUND, "No match found").into_response();
+        }
+        return no_match_with_suggestion(&state, item_type, name).await;
     };
     let matched_id = matched_id.clone();
 
-    let include = parse_includes(&params.include);
-
-    let result = fetch_resource(&state, &item_type, &matched_id, &include).await;
+    let started = Instant::now();
+    let (result, coalesced) =
+        fetch_resource_coalesced(&state, item_type.as_str(), &matched_id, &include).await;
+    timings.hydrate_ms = elapsed_ms(started);
+    timings.coalesced = coalesced;
 
     match result {
-        Ok(Some(resource)) => (StatusCode::OK, Json(json!({ "data": resource }))).into_response(),
-        Ok(None) => error_response(StatusCode::NOT_FOUND, "No match found").into_response(),
+        Ok(Some(f)) if !f.unavailable => {
+            let mut body = json!({ "data": f.resource });
+            if debug {
+                // Encoding happens again on the way out; this measures what that costs.
+                let started = Instant::now();
+                let _ = serde_json::to_vec(&body);
+                timings.serialize_ms = elapsed_ms(started);
+                body["timings"] = json!(timings);
+            }
+            (StatusCode::OK, Json(body)).into_response()
+        }
+        Ok(_) => error_response(StatusCode::NOT_FOUND, "No match found").into_response(),
         Err(e) => {
             tracing::error!("match error: {}", e);
-            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Match failed").into_response()
+            error_response(db_error_status(&e), "Match failed").into_response()
+        }
+    }
+}
+
+/// Answers an empty match with a spelling correction for `name` when the index has one.
+async fn no_match_with_suggestion(
+    state: &SearchState,
+    item_type: ItemType,
+    name: &str,
+) -> Response {
+    let (status, Json(mut body)) = error_response(StatusCode::NOT_FOUND, "No match found");
+    match state.client.suggest(item_type, name).await {
+        Ok(Some(suggestion)) => {
+            body["suggest"] = json!({ "text": suggestion.text, "docs": suggestion.docs });
+        }
+        Ok(None) => {}
+        Err(e) => tracing::warn!("suggest error: {}", e),
+    }
+    (status, Json(body)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Fetched, InFlight, MatchQuery};
+    use axum::http::StatusCode;
+    use futures::FutureExt;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use crate::models::metadata::ExplicitFilter;
+    use crate::test_support::{self, every_param, get, send, strict_query};
+
+    fn id_of(body: &serde_json::Value) -> &str {
+        body["data"]["id"].as_str().unwrap_or_default()
+    }
+
+    async fn counted_lookup(
+        in_flight: &InFlight,
+        key: &str,
+        loads: &Arc<AtomicUsize>,
+    ) -> (serde_json::Value, bool) {
+        let loads = loads.clone();
+        let (result, coalesced) = in_flight
+            .run(key.to_string(), move || {
+                async move {
+                    let n = loads.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    Ok(Some(Fetched {
+                        resource: serde_json::json!(n),
+                        updated_at: None,
+                        unavailable: false,
+                    }))
+                }
+                .boxed()
+            })
+            .await;
+        (result.unwrap().unwrap().resource, coalesced)
+    }
+
+    #[tokio::test]
+    async fn concurrent_lookups_share_one_load() {
+        let in_flight = InFlight::default();
+        let loads = Arc::new(AtomicUsize::new(0));
+
+        let results = futures::future::join_all(
+            (0..10).map(|_| counted_lookup(&in_flight, "song:a:", &loads)),
+        )
+        .await;
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+        assert!(results.iter().all(|(value, _)| *value == 0));
+        assert_eq!(
+            results.iter().filter(|(_, coalesced)| *coalesced).count(),
+            9
+        );
+        assert!(in_flight.0.lock().unwrap().is_empty());
+
+        // Finished lookups aren't cached: the next one loads again.
+        let (value, coalesced) = counted_lookup(&in_flight, "song:a:", &loads).await;
+        assert_eq!((value, coalesced), (serde_json::json!(1), false));
+
+        // Different keys never share.
+        let (a, b) = tokio::join!(
+            counted_lookup(&in_flight, "song:a:", &loads),
+            counted_lookup(&in_flight, "song:a:artists", &loads),
+        );
+        assert!(!a.1 && !b.1);
+        assert_eq!(loads.load(Ordering::SeqCst), 4);
+    }
+
+    /// Sets its flag when dropped, to show a cancelled lookup's future is gone.
+    struct DropFlag(Arc<AtomicUsize>);
+
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
         }
     }
+
+    async fn stalled_lookup(in_flight: &InFlight, dropped: &Arc<AtomicUsize>) {
+        let dropped = dropped.clone();
+        let _ = in_flight
+            .run("song:a:".to_string(), move || {
+                let flag = DropFlag(dropped);
+                async move {
+                    let _flag = flag;
+                    futures::future::pending::<()>().await;
+                    Ok(None)
+                }
+                .boxed()
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn cancelling_every_waiter_drops_the_lookup() {
+        let in_flight = InFlight::default();
+        let dropped = Arc::new(AtomicUsize::new(0));
+
+        // One waiter leaving early doesn't cancel the lookup for the others.
+        let waiters =
+            futures::future::join_all((0..3).map(|_| stalled_lookup(&in_flight, &dropped)));
+        let mut waiters = Box::pin(waiters);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(20), &mut waiters)
+                .await
+                .is_err()
+        );
+        let early = tokio::time::timeout(
+            Duration::from_millis(20),
+            stalled_lookup(&in_flight, &dropped),
+        );
+        assert!(early.await.is_err());
+        assert_eq!(in_flight.0.lock().unwrap().len(), 1);
+        assert_eq!(dropped.load(Ordering::SeqCst), 0);
+
+        drop(waiters);
+        assert!(in_flight.0.lock().unwrap().is_empty());
+        assert_eq!(
+            dropped.load(Ordering::SeqCst),
+            1,
+            "the lookup future was dropped"
+        );
+    }
+
+    #[tokio::test]
+    async fn collection_lookup_returns_a_partial_result_when_the_budget_runs_out() {
+        let (metrics, _recorder) = test_support::local_metrics();
+        let mut state = test_support::search_state(test_support::stalled_pool().await);
+        state.budget = Duration::from_millis(50);
+        let in_flight = state.in_flight.clone();
+        let app = test_support::app(Some(state), test_support::unreachable_pool());
+
+        // The malformed id is skipped, so it isn't part of the total.
+        let ids = "omm:song:getlucky00000001,omm:album:getlucky00000001,bad";
+        let (status, _, body) = send(app, get(&format!("/metadata/v1/lookup?ids={ids}"))).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["partial"], true);
+        assert_eq!(body["total"], 2);
+        assert_eq!(body["data"], serde_json::json!([]));
+        assert_eq!(
+            test_support::metric(&metrics, "lookup_partial_responses_total", &[]),
+            Some(1.0)
+        );
+        // The abandoned lookups don't stay in flight holding connections.
+        assert!(in_flight.0.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_omids() {
+        let app = test_support::app(
+            Some(test_support::search_state(test_support::unreachable_pool())),
+            test_support::unreachable_pool(),
+        );
+        for id in [
+            "getlucky00000001",
+            "omm:video:getlucky00000001",
+            "omm:song:tooshort",
+            "omm:song:getlucky0000000!",
+            "omm:song:getlucky00000001:extra",
+            "spotify:song:getlucky00000001",
+        ] {
+            let (status, _, body) =
+                send(app.clone(), get(&format!("/metadata/v1/lookup/{id}"))).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{id}");
+            assert_eq!(body["error"]["status"], 400, "{id}");
+            assert_eq!(
+                body["error"]["message"], "Invalid id. Expected omm:TYPE:ID",
+                "{id}"
+            );
+        }
+
+        let (status, _, _) = send(app, get("/metadata/v1/lookup/bad/albums")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_match_parameters() {
+        let app = test_support::app(
+            Some(test_support::search_state(test_support::unreachable_pool())),
+            test_support::unreachable_pool(),
+        );
+        for uri in [
+            "/metadata/v1/match/video?name=x",
+            "/metadata/v1/match/song",
+            "/metadata/v1/match/song?name=x&explicit=maybe",
+            "/metadata/v1/match/song?name=x&include=tracks",
+        ] {
+            let (status, _, _) = send(app.clone(), get(uri)).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{uri}");
+        }
+
+        // Unknown parameters are only rejected in strict mode.
+        let mut strict = get("/metadata/v1/match/song?name=x&nmae=y");
+        strict
+            .headers_mut()
+            .insert("x-strict", "true".parse().unwrap());
+        let (status, _, body) = send(app, strict).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["message"], "Unknown query parameters: nmae");
+    }
+
+    #[tokio::test]
+    async fn lookup_by_omid() {
+        let Some(pool) = test_support::scrape_db().await else {
+            return;
+        };
+        let app = test_support::app(
+            Some(test_support::search_state(pool)),
+            test_support::unreachable_pool(),
+        );
+
+        let (status, headers, body) = send(
+            app.clone(),
+            get("/metadata/v1/lookup/OMM:SONG:getlucky00000001"),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(id_of(&body), "omm:song:getlucky00000001");
+        assert_eq!(
+            body["data"]["attributes"]["externalIds"]["appleMusic"],
+            "617154366"
+        );
+        assert_eq!(headers["last-modified"], "Thu, 01 Feb 2024 00:00:00 GMT");
+
+        let (status, headers, body) = send(
+            app.clone(),
+            get("/metadata/v1/lookup/omm:song:getlucky00000001?include=artists"),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body["data"]["relationships"]["artists"]["data"][0]["id"],
+            "omm:artist:daftpunk00000001"
+        );
+        assert!(!headers.contains_key("last-modified"));
+
+        let (status, _, _) = send(
+            app.clone(),
+            get("/metadata/v1/lookup/omm:song:horizon000000001"),
+        )
+        .await;
+        assert_eq!(status, StatusCode::GONE);
+        let (status, _, _) = send(app, get("/metadata/v1/lookup/omm:song:missing000000001")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn unpositioned_artists_keep_insertion_order() {
+        let Some(pool) = test_support::scrape_db().await else {
+            return;
+        };
+        // Rewrites Zedd's link so its physical position now follows Foxes'.
+        sqlx::query(
+            "UPDATE song_artists SET position = NULL
+             WHERE song_id = 'clarity000000002' AND artist_id = 'zedd000000000001'",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let app = test_support::app(
+            Some(test_support::search_state(pool)),
+            test_support::unreachable_pool(),
+        );
+
+        let (status, _, body) =
+            send(app, get("/metadata/v1/lookup/omm:song:clarity000000002")).await;
+        assert_eq!(status, StatusCode::OK);
+        let artists: Vec<&str> = body["data"]["attributes"]["artists"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|a| a["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(artists, ["Zedd", "Foxes"]);
+    }
+
+    #[tokio::test]
+    async fn only_various_artists_albums_are_compilations() {
+        let Some(pool) = test_support::scrape_db().await else {
+            return;
+        };
+        // Sixty credited artists alone don't make Discovery a compilation.
+        sqlx::raw_sql(
+            "INSERT INTO artists (id, name)
+                 SELECT 'soloist' || lpad(n::text, 9, '0'), 'Soloist ' || n
+                 FROM generate_series(1, 60) n;
+             INSERT INTO artist_albums (artist_id, album_id)
+                 SELECT 'soloist' || lpad(n::text, 9, '0'), 'discovery0000001'
+                 FROM generate_series(1, 60) n;",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let app = test_support::app(
+            Some(test_support::search_state(pool)),
+            test_support::unreachable_pool(),
+        );
+
+        let (status, _, body) = send(
+            app.clone(),
+            get("/metadata/v1/lookup/omm:album:dancehits0000001"),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let attrs = &body["data"]["attributes"];
+        assert_eq!(attrs["isCompilation"], true);
+        assert_eq!(attrs["artistName"], "Various Artists");
+
+        let (status, _, body) =
+            send(app, get("/metadata/v1/lookup/omm:album:discovery0000001")).await;
+        assert_eq!(status, StatusCode::OK);
+        let attrs = &body["data"]["attributes"];
+        assert_eq!(attrs["isCompilation"], false);
+        assert_eq!(attrs["artistCount"], 61);
+        assert_eq!(attrs["artists"].as_array().unwrap().len(), 50);
+        assert_eq!(attrs["artists"][0]["name"], "Daft Punk");
+    }
+
+    #[tokio::test]
+    async fn match_applies_each_filter() {
+        let Some(pool) = test_support::scrape_db().await else {
+            return;
+        };
+        let app = test_support::app(
+            Some(test_support::search_state(pool)),
+            test_support::unreachable_pool(),
+        );
+        let cases = [
+            ("song?name=get%20lucky", Some("omm:song:getlucky00000001")),
+            (
+                "song?name=one%20more%20time&artist=daft%20punk",
+                Some("omm:song:onemoretime00001"),
+            ),
+            (
+                "song?name=get%20lucky&album=random%20access%20memories",
+                Some("omm:song:getlucky00000001"),
+            ),
+            (
+                "song?name=get%20lucky&genre=dance",
+                Some("omm:song:getlucky00000001"),
+            ),
+            ("song?name=get%20lucky&genre=rock", None),
+            (
+                "album?name=discovery&label=Virgin",
+                Some("omm:album:discovery0000001"),
+            ),
+            ("album?name=discovery&label=columbia", None),
+            (
+                "song?name=doin%27%20it%20right&explicit=only",
+                Some("omm:song:doinitright00001"),
+            ),
+            ("song?name=doin%27%20it%20right&explicit=exclude", None),
+            ("artist?name=zedd", Some("omm:artist:zedd000000000001")),
+        ];
+        for (query, expected) in cases {
+            let (status, _, body) =
+                send(app.clone(), get(&format!("/metadata/v1/match/{query}"))).await;
+            match expected {
+                Some(id) => {
+                    assert_eq!(status, StatusCode::OK, "{query}: {body}");
+                    assert_eq!(id_of(&body), id, "{query}");
+                }
+                None => assert_eq!(status, StatusCode::NOT_FOUND, "{query}: {body}"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn strict_match_query_accepts_every_known_param() {
+        let query = every_param::<MatchQuery>(&[
+            ("name", "get%20lucky"),
+            ("album", "random%20access%20memories"),
+            ("artist", "daft%20punk"),
+            ("genre", "disco"),
+            ("label", "columbia"),
+            ("explicit", "exclude"),
+            ("include", "album,artists"),
+            ("suggest", "true"),
+            ("debug", "false"),
+        ]);
+        let parsed = strict_query::<MatchQuery>(&query)
+            .await
+            .expect("deserializes");
+        assert_eq!(parsed.name.as_deref(), Some("get lucky"));
+        assert_eq!(parsed.artist.as_deref(), Some("daft punk"));
+        assert_eq!(parsed.explicit, Some(ExplicitFilter::Exclude));
+        assert_eq!(parsed.include.as_deref(), Some("album,artists"));
+        assert_eq!((parsed.suggest, parsed.debug), (Some(true), Some(false)));
+
+        for bad in ["artis=daft%20punk", "name=get%20lucky&limit=5"] {
+            let result = strict_query::<MatchQuery>(bad).await;
+            assert_eq!(result.err(), Some(StatusCode::BAD_REQUEST), "{bad}");
+        }
+    }
+
+    #[tokio::test]
+    async fn unreachable_search_backend_fails_within_the_connect_timeout() {
+        use crate::manticore::SearchClient;
+        use crate::search::SearchBackend;
+        use std::time::Instant;
+
+        let (snapshotter, _recorder) = test_support::local_metrics();
+        let blackhole = test_support::blackhole();
+        let connect_timeout = Duration::from_millis(200);
+        let client = SearchClient::with_timeouts(
+            &format!("http://{}", blackhole.addr),
+            Duration::from_secs(30),
+            connect_timeout,
+            1,
+        )
+        .expect("client builds");
+        let mut state = test_support::search_state(test_support::unreachable_pool());
+        state.client = Arc::new(SearchBackend::Manticore(client));
+        // Longer than any transport timeout, so a 504 can only come from the client.
+        state.budget = Duration::from_secs(30);
+        let app = test_support::app(Some(state), test_support::unreachable_pool());
+
+        let started = Instant::now();
+        let (status, _, body) = send(app, get("/metadata/v1/match/song?name=get%20lucky")).await;
+        let elapsed = started.elapsed();
+
+        assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(body["error"]["message"], "Match failed");
+        // One attempt plus one retry, each cut off by the connect timeout.
+        assert!(elapsed >= connect_timeout * 2, "{elapsed:?}");
+        assert!(elapsed < Duration::from_secs(2), "{elapsed:?}");
+        assert_eq!(
+            test_support::metric(&snapshotter, "search_retries_total", &[]),
+            Some(1.0)
+        );
+    }
 }
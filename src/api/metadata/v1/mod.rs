@@ -1,16 +1,30 @@
+pub mod admin;
+pub mod artwork;
+pub mod browse;
+pub mod cache;
+pub mod discover;
+pub mod export;
 pub mod metadata;
 pub mod resource;
+pub mod warmup;
 
-use crate::{api::metadata::v1::metadata::SearchState, manticore::SearchClient};
-use axum::Router;
-use sqlx::PgPool;
-use std::sync::Arc;
+use crate::{
+    api::{metadata::v1::metadata::SearchState, msgpack},
+    ip_allowlist::IpAllowlist,
+};
+use axum::{Router, middleware};
 
-pub fn router(search_client: Arc<SearchClient>, scrape_pool: PgPool) -> Router {
-    let search_state = SearchState {
-        client: search_client,
-        scrape_pool,
-    };
+pub fn router(search_state: SearchState, allowlist: IpAllowlist) -> Router {
+    let api_keys = search_state.api_keys.clone();
+    let scrape_pool = search_state.scrape_pool.clone();
+    let artwork_cache = search_state.artwork_cache.clone();
 
-    metadata::router().with_state(search_state)
+    metadata::router(&api_keys)
+        .merge(browse::router())
+        .merge(discover::router())
+        .merge(export::router(api_keys.clone(), allowlist.clone()))
+        .merge(admin::router(api_keys, allowlist))
+        .merge(artwork::router(scrape_pool, artwork_cache))
+        .with_state(search_state)
+        .layer(middleware::from_fn(msgpack::negotiate))
 }
@@ -1,18 +1,48 @@
 use std::collections::HashSet;
 
 use serde_json::{Map, Value, json};
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
 
-use crate::models::metadata::{Album, Artist, Song};
+use crate::models::metadata::{Album, Artist, Song, VARIOUS_ARTISTS};
 
-pub fn parse_includes(raw: &Option<String>) -> HashSet<String> {
-    raw.as_ref()
+/// Albums can credit hundreds of artists; only this many are listed inline.
+const MAX_ALBUM_ARTIST_REFS: usize = 50;
+
+/// Include that adds `attributes.externalIds`; on by default for single-entity lookups.
+pub const EXTERNAL_IDS: &str = "external_ids";
+
+/// Relationships each resource type can embed via `include`.
+pub fn supported_includes(item_type: &str) -> &'static [&'static str] {
+    match item_type {
+        "song" => &["albums", "artists", EXTERNAL_IDS],
+        "album" => &["artists", "tracks", EXTERNAL_IDS],
+        "artist" => &["top_songs", EXTERNAL_IDS],
+        _ => &[],
+    }
+}
+
+/// Parses a comma-separated `include` parameter, rejecting anything not in `supported`.
+pub fn parse_includes(raw: &Option<String>, supported: &[&str]) -> Result<HashSet<String>, String> {
+    let include: HashSet<String> = raw
+        .as_ref()
         .map(|v| {
             v.split(',')
                 .map(|x| x.trim().to_string())
                 .filter(|x| !x.is_empty())
                 .collect()
         })
-        .unwrap_or_default()
+        .unwrap_or_default();
+    match include.iter().find(|x| !supported.contains(&x.as_str())) {
+        Some(unknown) if supported.is_empty() => Err(format!(
+            "Unsupported include {unknown:?}, this resource has no includes"
+        )),
+        Some(unknown) => Err(format!(
+            "Unsupported include {unknown:?}, expected one of: {}",
+            supported.join(", ")
+        )),
+        None => Ok(include),
+    }
 }
 
 fn put_str(map: &mut Map<String, Value>, key: &str, val: &str) {
@@ -27,6 +57,12 @@ fn put_int(map: &mut Map<String, Value>, key: &str, val: i64) {
     }
 }
 
+fn put_time(map: &mut Map<String, Value>, key: &str, val: Option<OffsetDateTime>) {
+    if let Some(formatted) = val.and_then(|t| t.format(&Rfc3339).ok()) {
+        map.insert(key.to_string(), json!(formatted));
+    }
+}
+
 fn put_genres(map: &mut Map<String, Value>, genres: &[String]) {
     if !genres.is_empty() {
         map.insert("genres".to_string(), json!(genres));
@@ -37,14 +73,44 @@ fn rel_list(items: Vec<Value>) -> Value {
     json!({ "data": items })
 }
 
-fn artist_names(artists: &[String]) -> String {
-    artists.join(", ")
+fn artist_names(artists: &[Artist]) -> String {
+    artists
+        .iter()
+        .map(|x| x.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn put_artist_refs(map: &mut Map<String, Value>, artists: &[Artist]) {
+    if !artists.is_empty() {
+        let refs: Vec<Value> = artists
+            .iter()
+            .map(|x| json!({ "id": format!("omm:artist:{}", x.id), "name": x.name }))
+            .collect();
+        map.insert("artists".to_string(), json!(refs));
+    }
+}
+
+/// Catalog ids from other services. Always an object, so clients can rely on the key.
+pub fn put_external_ids(resource: &mut Value, apple_music: Option<&str>) {
+    let mut ids = Map::new();
+    if let Some(id) = apple_music.filter(|id| !id.is_empty()) {
+        ids.insert("appleMusic".to_string(), json!(id));
+    }
+    resource["attributes"]["externalIds"] = Value::Object(ids);
 }
 
 pub fn render_artist(a: &Artist) -> Value {
     let mut attrs = Map::new();
     attrs.insert("name".to_string(), json!(a.name));
     put_str(&mut attrs, "artworkUrl", &a.image);
+    if let Some(source) = a.image_source {
+        put_str(&mut attrs, "imageSource", source.as_str());
+    }
+    put_int(&mut attrs, "albumCount", a.album_count);
+    put_int(&mut attrs, "songCount", a.song_count);
+    put_time(&mut attrs, "createdAt", a.created_at);
+    put_time(&mut attrs, "updatedAt", a.updated_at);
     json!({
         "id": format!("omm:artist:{}", a.id),
         "type": "artist",
@@ -53,15 +119,35 @@ pub fn render_artist(a: &Artist) -> Value {
 }
 
 pub fn render_album(a: &Album, include: &HashSet<String>) -> Value {
-    let artist_name = artist_names(&a.artist.iter().map(|x| x.name.clone()).collect::<Vec<_>>());
+    let artists = &a.artist[..a.artist.len().min(MAX_ALBUM_ARTIST_REFS)];
+    let artist_name = if a.is_compilation {
+        VARIOUS_ARTISTS.to_string()
+    } else {
+        artist_names(artists)
+    };
     let mut attrs = Map::new();
     attrs.insert("name".to_string(), json!(a.name));
     attrs.insert("trackCount".to_string(), json!(a.track_count as i64));
+    attrs.insert(
+        "availableTrackCount".to_string(),
+        json!(a.available_track_count as i64),
+    );
+    attrs.insert("complete".to_string(), json!(!a.artist.is_empty()));
+    attrs.insert("isCompilation".to_string(), json!(a.is_compilation));
+    if a.is_compilation || artists.len() < a.artist.len() {
+        attrs.insert("artistCount".to_string(), json!(a.artist.len()));
+    }
     put_str(&mut attrs, "artistName", &artist_name);
+    put_artist_refs(&mut attrs, artists);
     put_str(&mut attrs, "artworkUrl", &a.image);
     put_str(&mut attrs, "upc", &a.upc);
+    if let Some(label) = &a.label {
+        put_str(&mut attrs, "recordLabel", label);
+    }
     put_genres(&mut attrs, &a.genres);
     put_str(&mut attrs, "releaseDate", &a.date);
+    put_time(&mut attrs, "createdAt", a.created_at);
+    put_time(&mut attrs, "updatedAt", a.updated_at);
 
     let mut resource = Map::new();
     resource.insert("id".to_string(), json!(format!("omm:album:{}", a.id)));
@@ -69,7 +155,7 @@ pub fn render_album(a: &Album, include: &HashSet<String>) -> Value {
     resource.insert("attributes".to_string(), Value::Object(attrs));
 
     if include.contains("artists") {
-        let items = a.artist.iter().map(render_artist).collect();
+        let items = artists.iter().map(render_artist).collect();
         let mut rels = Map::new();
         rels.insert("artists".to_string(), rel_list(items));
         resource.insert("relationships".to_string(), Value::Object(rels));
@@ -78,22 +164,43 @@ pub fn render_album(a: &Album, include: &HashSet<String>) -> Value {
 }
 
 pub fn render_song(s: &Song, include: &HashSet<String>) -> Value {
-    let artist_name = artist_names(&s.artist.iter().map(|x| x.name.clone()).collect::<Vec<_>>());
+    let artist_name = artist_names(&s.artist);
     let album_name = s.album.first().map(|x| x.name.clone()).unwrap_or_default();
 
     let mut attrs = Map::new();
     attrs.insert("name".to_string(), json!(s.name));
+    attrs.insert(
+        "complete".to_string(),
+        json!(!s.artist.is_empty() && !s.album.is_empty()),
+    );
     put_str(&mut attrs, "albumName", &album_name);
+    if let Some(id) = &s.primary_album_id {
+        attrs.insert(
+            "primaryAlbumId".to_string(),
+            json!(format!("omm:album:{id}")),
+        );
+    }
     put_str(&mut attrs, "artistName", &artist_name);
+    put_artist_refs(&mut attrs, &s.artist);
     put_str(&mut attrs, "isrc", &s.isrc);
     put_str(&mut attrs, "artworkUrl", &s.image);
     put_int(&mut attrs, "trackNumber", s.track_number as i64);
     put_int(&mut attrs, "discNumber", s.disc_number as i64);
     put_genres(&mut attrs, &s.genres);
     put_str(&mut attrs, "releaseDate", &s.date);
+    put_time(&mut attrs, "createdAt", s.created_at);
+    put_time(&mut attrs, "updatedAt", s.updated_at);
     if s.duration > 0 {
         attrs.insert("durationMs".to_string(), json!(s.duration));
     }
+    if let Some(popularity) = s.popularity {
+        attrs.insert("popularity".to_string(), json!(popularity));
+    }
+    // Always present, so clients can tell an unknown rating (null) from a clean one.
+    attrs.insert("explicit".to_string(), json!(s.explicit));
+    if s.deleted_at.is_some() {
+        attrs.insert("available".to_string(), json!(false));
+    }
 
     let mut resource = Map::new();
     resource.insert("id".to_string(), json!(format!("omm:song:{}", s.id)));
@@ -0,0 +1,91 @@
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+
+use crate::api::metadata::v1::discover;
+use crate::api::metadata::v1::metadata::{SearchState, hydrate_all};
+use crate::models::metadata::ItemType;
+use crate::search::SearchQuery;
+
+/// Hits hydrated per warm-up query, matching the first page a client would show.
+const QUERY_HITS: i32 = 10;
+
+/// Whether to warm up before reporting ready; `WARMUP=off` skips it, e.g. in development.
+pub fn enabled() -> bool {
+    !std::env::var("WARMUP").is_ok_and(|v| {
+        matches!(
+            v.trim().to_ascii_lowercase().as_str(),
+            "off" | "false" | "0"
+        )
+    })
+}
+
+fn budget() -> Duration {
+    Duration::from_secs(
+        std::env::var("WARMUP_BUDGET_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|v| *v > 0)
+            .unwrap_or(10),
+    )
+}
+
+/// Song queries from `WARMUP_QUERIES`, comma-separated.
+fn queries() -> Vec<String> {
+    std::env::var("WARMUP_QUERIES")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|q| !q.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Fills today's discover selections, then runs the configured queries against the index
+/// and hydrates their top hits, until the time budget runs out. Returns how many entries
+/// were warmed.
+pub async fn run(state: &SearchState) -> usize {
+    let deadline = Instant::now() + budget();
+    let mut warmed = 0;
+
+    let today = OffsetDateTime::now_utc().date();
+    for item_type in ["song", "album"] {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match tokio::time::timeout(remaining, discover::selection(state, item_type, today)).await {
+            Ok(Ok(_)) => warmed += 1,
+            Ok(Err(e)) => tracing::warn!("warm-up of {} discover failed: {}", item_type, e),
+            Err(_) => {
+                tracing::warn!("warm-up budget spent after {} entries", warmed);
+                return warmed;
+            }
+        }
+    }
+
+    for name in queries() {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let warm = async {
+            let query = SearchQuery {
+                name: Some(&name),
+                ..Default::default()
+            };
+            let hits = state
+                .client
+                .search(ItemType::Song, &query, QUERY_HITS, 0)
+                .await?;
+            let ids = hits.into_iter().map(|(id, ..)| id).collect();
+            hydrate_all(state, "song", ids, &HashSet::new())
+                .await
+                .map_err(|e| anyhow::anyhow!("{e}"))?;
+            anyhow::Ok(())
+        };
+        match tokio::time::timeout(remaining, warm).await {
+            Ok(Ok(())) => warmed += 1,
+            Ok(Err(e)) => tracing::warn!("warm-up query {:?} failed: {}", name, e),
+            Err(_) => {
+                tracing::warn!("warm-up budget spent after {} entries", warmed);
+                return warmed;
+            }
+        }
+    }
+    warmed
+}
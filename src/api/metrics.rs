@@ -0,0 +1,57 @@
+use axum::{Router, extract::State, middleware, routing::get};
+use metrics_exporter_prometheus::PrometheusHandle;
+use std::time::Duration;
+
+use crate::auth::{self, ApiKeys};
+use crate::db::{self, DbPools};
+use crate::ip_allowlist::{self, IpAllowlist};
+
+pub fn router(handle: PrometheusHandle, api_keys: ApiKeys, allowlist: IpAllowlist) -> Router {
+    Router::new()
+        .route("/metrics", get(metrics_handler))
+        .layer(middleware::from_fn_with_state(
+            (api_keys, "metrics"),
+            auth::require_scope,
+        ))
+        .layer(middleware::from_fn_with_state(
+            allowlist,
+            ip_allowlist::require_allowed_ip,
+        ))
+        .with_state(handle)
+}
+
+/// Refreshes the telemetry KPI gauges; scheduled every `TELEMETRY_KPI_INTERVAL_SECS`
+/// (default 60):
+///
+/// - `telemetry_users_total`: distinct users that ever submitted
+/// - `telemetry_active_users_24h`: distinct users that submitted in the last 24 hours
+/// - `telemetry_submissions_last_hour`: submissions in the last hour
+/// - `telemetry_library_songs_total`: sum of every user's latest `song_count`
+///
+/// Reads from the replica when there is one. A failed refresh leaves the previous values in
+/// place and increments `telemetry_kpi_refresh_errors_total`.
+pub async fn refresh_kpis(pools: DbPools) -> anyhow::Result<()> {
+    let kpis = pools.read(db::telemetry::kpis).await.inspect_err(|_| {
+        metrics::counter!("telemetry_kpi_refresh_errors_total").increment(1);
+    })?;
+    metrics::gauge!("telemetry_users_total").set(kpis.total_users as f64);
+    metrics::gauge!("telemetry_active_users_24h").set(kpis.active_users_24h as f64);
+    metrics::gauge!("telemetry_submissions_last_hour").set(kpis.submissions_1h as f64);
+    metrics::gauge!("telemetry_library_songs_total").set(kpis.library_songs as f64);
+    metrics::gauge!("telemetry_opted_out_users").set(kpis.opted_out_users as f64);
+    Ok(())
+}
+
+pub fn kpi_interval() -> Duration {
+    Duration::from_secs(
+        std::env::var("TELEMETRY_KPI_INTERVAL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|v| *v > 0)
+            .unwrap_or(60),
+    )
+}
+
+async fn metrics_handler(State(handle): State<PrometheusHandle>) -> String {
+    handle.render()
+}
@@ -1,26 +1,291 @@
-use crate::manticore::SearchClient;
-use axum::{Router, body::Body, extract::Request, routing::any};
-use sqlx::PgPool;
-use std::sync::Arc;
+use crate::{
+    api::metadata::v1::metadata::SearchState, auth::ApiKeys, db::DbPools,
+    ip_allowlist::IpAllowlist, load_shed::shed_load,
+};
+use axum::{
+    Json, Router,
+    body::Body,
+    extract::Request,
+    http::{StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::any,
+};
+use serde_json::{Value, json};
 
+pub mod alerts;
+pub mod client;
+pub mod health;
+pub mod jobs;
 pub mod metadata;
+pub mod metrics;
+pub mod msgpack;
+pub mod ready;
+pub mod schema;
 pub mod telemetry;
 pub mod update;
 pub mod validation;
+pub mod version;
 
+/// Every public route, mounted as `/<service>/<version>/...`.
 pub fn app_router(
-    search_client: Arc<SearchClient>,
-    pool: PgPool,
-    scrape_pool: Option<PgPool>,
+    search_state: Option<SearchState>,
+    pools: DbPools,
+    api_keys: ApiKeys,
+    allowlist: IpAllowlist,
+    max_in_flight: usize,
 ) -> Router {
     let mut router = Router::new()
-        .nest("/telemetry", telemetry::router().with_state(pool))
-        .nest("/update", update::router())
+        .nest(
+            "/telemetry/v1",
+            shed_load(
+                telemetry::v1::router(&api_keys, allowlist.clone()).with_state(pools),
+                max_in_flight,
+            ),
+        )
+        .nest("/update/v1", update::v1::router())
+        .nest("/client/v1", client::v1::router())
         .route("/", any(|_: Request<Body>| async { "Healthy" }));
 
-    if let Some(pool) = scrape_pool {
-        router = router.nest("/metadata", metadata::router(search_client, pool));
+    if let Some(search_state) = search_state {
+        router = router.nest(
+            "/metadata/v1",
+            shed_load(metadata::v1::router(search_state, allowlist), max_in_flight),
+        );
     }
 
     router
 }
+
+pub fn error_response(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    if status.is_server_error() && status != StatusCode::SERVICE_UNAVAILABLE {
+        sentry::capture_message(message, sentry::Level::Error);
+    }
+    (
+        status,
+        Json(json!({ "error": { "status": status.as_u16(), "message": message } })),
+    )
+}
+
+/// Gives axum's bodyless 405s the usual JSON error body. The `Allow` header the method
+/// router sets is kept as is.
+pub async fn json_method_not_allowed(req: Request, next: Next) -> Response {
+    let response = next.run(req).await;
+    if response.status() != StatusCode::METHOD_NOT_ALLOWED
+        || response.headers().contains_key(header::CONTENT_TYPE)
+    {
+        return response;
+    }
+    let (mut parts, _) = response.into_parts();
+    parts.headers.remove(header::CONTENT_LENGTH);
+    let (_, body) = error_response(StatusCode::METHOD_NOT_ALLOWED, "Method not allowed");
+    (parts, body).into_response()
+}
+
+pub fn db_error_status(e: &sqlx::Error) -> StatusCode {
+    match e {
+        sqlx::Error::PoolTimedOut => StatusCode::SERVICE_UNAVAILABLE,
+        sqlx::Error::Database(db) if db.code().as_deref() == Some("57014") => {
+            StatusCode::GATEWAY_TIMEOUT
+        }
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+pub fn search_error_status(e: &anyhow::Error) -> StatusCode {
+    if e.is::<crate::search::SearchBackendTimeout>() {
+        StatusCode::GATEWAY_TIMEOUT
+    } else if e.is::<crate::search::SearchBackendError>() {
+        StatusCode::BAD_GATEWAY
+    } else {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::extract::Request;
+    use axum::http::{Method, StatusCode, header};
+    use axum::middleware;
+    use serde_json::json;
+
+    use crate::test_support::{self, get, send};
+
+    /// The public URL table. Each request is one the handler turns away before touching a
+    /// database or upstream, so the status proves the route matched offline. If a
+    /// module move changes one of these, clients break: update the table only on purpose.
+    #[tokio::test]
+    async fn public_paths_are_mounted_where_clients_expect() {
+        // With the outer layers `main` adds, so preflights and 405s look as clients see them.
+        let app = test_support::app(
+            Some(test_support::search_state(test_support::unreachable_pool())),
+            test_support::unreachable_pool(),
+        )
+        .layer(middleware::from_fn(super::json_method_not_allowed))
+        .layer(crate::cors::cors_layer("*").expect("valid origins"));
+        for (method, uri, expected) in [
+            (Method::GET, "/", StatusCode::OK),
+            (
+                Method::GET,
+                "/metadata/v1/lookup/bad",
+                StatusCode::BAD_REQUEST,
+            ),
+            (Method::GET, "/metadata/v1/lookup", StatusCode::BAD_REQUEST),
+            (
+                Method::GET,
+                "/metadata/v1/lookup/bad/albums",
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                Method::GET,
+                "/metadata/v1/match/song",
+                StatusCode::BAD_REQUEST,
+            ),
+            (Method::GET, "/metadata/v1/browse", StatusCode::BAD_REQUEST),
+            (Method::GET, "/metadata/v1/artists", StatusCode::BAD_REQUEST),
+            (
+                Method::GET,
+                "/metadata/v1/artwork/bad",
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                Method::GET,
+                "/metadata/v1/export/song",
+                StatusCode::UNAUTHORIZED,
+            ),
+            (
+                Method::GET,
+                "/metadata/v1/admin/quality",
+                StatusCode::UNAUTHORIZED,
+            ),
+            (Method::POST, "/telemetry/v1", StatusCode::BAD_REQUEST),
+            (
+                Method::POST,
+                "/telemetry/v1/optout",
+                StatusCode::BAD_REQUEST,
+            ),
+            (Method::GET, "/telemetry/v1/status", StatusCode::BAD_REQUEST),
+            (
+                Method::GET,
+                "/telemetry/v1/admin/export",
+                StatusCode::UNAUTHORIZED,
+            ),
+            (
+                Method::POST,
+                "/update/v1/check",
+                StatusCode::METHOD_NOT_ALLOWED,
+            ),
+            (Method::GET, "/client/v1/requirements", StatusCode::OK),
+            (
+                Method::OPTIONS,
+                "/metadata/v1/lookup/omm:song:getlucky00000001",
+                StatusCode::OK,
+            ),
+            (
+                Method::HEAD,
+                "/metadata/v1/lookup/bad",
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                Method::DELETE,
+                "/metadata/v1/lookup/bad",
+                StatusCode::METHOD_NOT_ALLOWED,
+            ),
+            // The layouts public URLs must not drift into.
+            (
+                Method::GET,
+                "/v1/metadata/lookup/bad",
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                Method::GET,
+                "/metadata/v1/v1/lookup/bad",
+                StatusCode::NOT_FOUND,
+            ),
+            (Method::GET, "/metadata/lookup/bad", StatusCode::NOT_FOUND),
+            (Method::POST, "/v1/telemetry/", StatusCode::NOT_FOUND),
+        ] {
+            let mut req = Request::builder().method(method.clone()).uri(uri);
+            if method == Method::OPTIONS {
+                // What a browser sends before a cross-origin GET.
+                req = req
+                    .header(header::ORIGIN, "https://app.vleer.app")
+                    .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET");
+            }
+            let req = req.body(Body::empty()).expect("valid request");
+            let (status, headers, body) = send(app.clone(), test_support::request(req)).await;
+            assert_eq!(status, expected, "{method} {uri}");
+            match method {
+                Method::OPTIONS => assert_eq!(
+                    headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+                    "*",
+                    "{method} {uri}"
+                ),
+                Method::HEAD => assert_eq!(body, serde_json::Value::Null, "{method} {uri}"),
+                Method::DELETE => {
+                    assert_eq!(headers[header::ALLOW], "GET,HEAD", "{method} {uri}");
+                    assert_eq!(body["error"]["status"], 405, "{method} {uri}");
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Response contracts for the desktop client. A failing snapshot means the shape
+    /// changed: review the diff and accept it only if the change is intended.
+    #[tokio::test]
+    async fn metadata_responses_match_snapshots() {
+        let Some(pool) = test_support::scrape_db().await else {
+            return;
+        };
+        let app = test_support::app(
+            Some(test_support::search_state(pool)),
+            test_support::unreachable_pool(),
+        );
+        for (name, uri) in [
+            (
+                "lookup_song",
+                "/metadata/v1/lookup/omm:song:getlucky00000001",
+            ),
+            (
+                "lookup_album_with_tracks",
+                "/metadata/v1/lookup/omm:album:ram0000000000001?include=tracks,artists",
+            ),
+            (
+                "lookup_artist_with_top_songs",
+                "/metadata/v1/lookup/omm:artist:daftpunk00000001?include=top_songs",
+            ),
+            (
+                "lookup_collection",
+                "/metadata/v1/lookup?ids=omm:song:onemoretime00001,omm:album:discovery0000001",
+            ),
+            ("lookup_by_isrc", "/metadata/v1/lookup?isrc=GB-DUW-00-00053"),
+            (
+                "song_albums",
+                "/metadata/v1/lookup/omm:song:getlucky00000001/albums",
+            ),
+            (
+                "match_song",
+                "/metadata/v1/match/song?name=get%20lucky&artist=daft%20punk",
+            ),
+            ("genres", "/metadata/v1/genres"),
+            ("labels", "/metadata/v1/labels"),
+            (
+                "deleted_song",
+                "/metadata/v1/lookup/omm:song:horizon000000001",
+            ),
+            (
+                "missing_song",
+                "/metadata/v1/lookup/omm:song:missing000000001",
+            ),
+            (
+                "invalid_id",
+                "/metadata/v1/lookup/omm:video:getlucky00000001",
+            ),
+        ] {
+            let (status, _, body) = send(app.clone(), get(uri)).await;
+            insta::assert_json_snapshot!(name, json!({ "status": status.as_u16(), "body": body }));
+        }
+    }
+}
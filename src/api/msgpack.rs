@@ -0,0 +1,184 @@
+use axum::{
+    body::{Body, HttpBody, to_bytes},
+    extract::Request,
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::Response,
+};
+use serde_json::Value;
+
+const CONTENT_TYPE: &str = "application/msgpack";
+/// Larger JSON bodies are passed through untouched rather than buffered twice.
+const MAX_TRANSCODE_BYTES: usize = 32 * 1024 * 1024;
+
+fn accepts_msgpack(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|v| {
+            let media = v.split(';').next().unwrap_or("").trim();
+            media.eq_ignore_ascii_case(CONTENT_TYPE)
+                || media.eq_ignore_ascii_case("application/x-msgpack")
+        })
+}
+
+fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"))
+}
+
+/// Re-encodes JSON responses as MessagePack for clients that send
+/// `Accept: application/msgpack`, so handlers keep returning `Json` and never branch.
+/// Everything else, including streamed NDJSON and images, passes through.
+pub async fn negotiate(req: Request, next: Next) -> Response {
+    let wants_msgpack = accepts_msgpack(req.headers());
+    let mut response = next.run(req).await;
+    response
+        .headers_mut()
+        .append(header::VARY, HeaderValue::from_static("accept"));
+    if !wants_msgpack || !is_json(response.headers()) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    // Streamed bodies have no known length; those and oversized ones stay JSON.
+    let len = parts
+        .headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok()?.parse::<u64>().ok())
+        .or_else(|| body.size_hint().exact());
+    if len.is_none_or(|len| len > MAX_TRANSCODE_BYTES as u64) {
+        return Response::from_parts(parts, body);
+    }
+
+    let bytes = match to_bytes(body, MAX_TRANSCODE_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::error!("failed to buffer response for msgpack: {}", e);
+            parts.status = StatusCode::INTERNAL_SERVER_ERROR;
+            parts.headers.remove(header::CONTENT_LENGTH);
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+    let Ok(value) = serde_json::from_slice::<Value>(&bytes) else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+    let encoded = match rmp_serde::to_vec_named(&value) {
+        Ok(encoded) => encoded,
+        Err(e) => {
+            tracing::error!("failed to encode msgpack response: {}", e);
+            return Response::from_parts(parts, Body::from(bytes));
+        }
+    };
+    parts
+        .headers
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(CONTENT_TYPE));
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(encoded))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::extract::Request;
+    use axum::http::{StatusCode, header};
+    use axum::{Json, Router, middleware, routing::get};
+    use serde_json::{Value, json};
+
+    use crate::test_support::{self, send};
+
+    async fn fetch(app: Router, uri: &str, accept: &str) -> (StatusCode, String, Vec<u8>) {
+        let req = Request::get(uri)
+            .header(header::ACCEPT, accept)
+            .body(Body::empty())
+            .expect("valid request");
+        let response = tower::ServiceExt::oneshot(app, test_support::request(req))
+            .await
+            .expect("router is infallible");
+        let content_type = response.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .expect("ascii")
+            .to_string();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("read body");
+        (status, content_type, body.to_vec())
+    }
+
+    /// The same request as JSON and as MessagePack decodes to the same value.
+    async fn assert_same_structure(app: &Router, uri: &str) {
+        let (status, content_type, json) = fetch(app.clone(), uri, "application/json").await;
+        assert!(content_type.starts_with("application/json"), "{uri}");
+        let (packed_status, packed_type, packed) =
+            fetch(app.clone(), uri, "application/msgpack").await;
+        assert_eq!(packed_status, status, "{uri}");
+        assert_eq!(packed_type, super::CONTENT_TYPE, "{uri}");
+        let json: Value = serde_json::from_slice(&json).expect("json body");
+        let packed: Value = rmp_serde::from_slice(&packed).expect("msgpack body");
+        assert_eq!(packed, json, "{uri}");
+    }
+
+    #[tokio::test]
+    async fn error_bodies_round_trip() {
+        let app = test_support::app(
+            Some(test_support::search_state(test_support::unreachable_pool())),
+            test_support::unreachable_pool(),
+        );
+        assert_same_structure(&app, "/metadata/v1/lookup/omm:video:getlucky00000001").await;
+    }
+
+    #[tokio::test]
+    async fn metadata_bodies_round_trip() {
+        let Some(pool) = test_support::scrape_db().await else {
+            return;
+        };
+        let app = test_support::app(
+            Some(test_support::search_state(pool)),
+            test_support::unreachable_pool(),
+        );
+        for uri in [
+            "/metadata/v1/lookup/omm:song:getlucky00000001",
+            "/metadata/v1/lookup/omm:album:ram0000000000001?include=tracks,artists",
+            "/metadata/v1/lookup?ids=omm:song:onemoretime00001,omm:album:discovery0000001",
+            "/metadata/v1/match/song?name=get%20lucky&artist=daft%20punk",
+            "/metadata/v1/genres",
+        ] {
+            assert_same_structure(&app, uri).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn oversized_bodies_pass_through_as_json() {
+        let big = "x".repeat(super::MAX_TRANSCODE_BYTES);
+        let app = Router::new()
+            .route(
+                "/big",
+                get(move || async move { Json(json!({ "data": big })) }),
+            )
+            .layer(middleware::from_fn(super::negotiate));
+        let (status, content_type, body) = fetch(app, "/big", "application/msgpack").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(content_type.starts_with("application/json"));
+        let body: Value = serde_json::from_slice(&body).expect("json body");
+        assert_eq!(
+            body["data"].as_str().map(str::len),
+            Some(super::MAX_TRANSCODE_BYTES)
+        );
+    }
+
+    #[tokio::test]
+    async fn other_accept_headers_get_json() {
+        let app = Router::new()
+            .route("/small", get(|| async { Json(json!({ "ok": true })) }))
+            .layer(middleware::from_fn(super::negotiate));
+        let (status, headers, body) = send(app, test_support::get("/small")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[header::VARY], "accept");
+        assert_eq!(body, json!({ "ok": true }));
+    }
+}
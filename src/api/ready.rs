@@ -0,0 +1,40 @@
+use axum::{
+    Router,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::api::error_response;
+
+/// Whether the process should receive traffic: false until startup dependencies have
+/// answered, and false again once shutdown has begun draining.
+#[derive(Debug, Clone, Default)]
+pub struct Readiness(Arc<AtomicBool>);
+
+impl Readiness {
+    pub fn set(&self, ready: bool) {
+        self.0.store(ready, Ordering::SeqCst);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+pub fn router(readiness: Readiness) -> Router {
+    Router::new()
+        .route("/ready", get(ready_handler))
+        .with_state(readiness)
+}
+
+async fn ready_handler(State(readiness): State<Readiness>) -> Response {
+    if readiness.is_ready() {
+        "Ready".into_response()
+    } else {
+        error_response(StatusCode::SERVICE_UNAVAILABLE, "Not ready").into_response()
+    }
+}
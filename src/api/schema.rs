@@ -0,0 +1,128 @@
+use axum::{
+    Json, Router,
+    extract::State,
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+use crate::api::{db_error_status, error_response};
+use crate::auth::{self, ApiKeys};
+use crate::db::{self, schema};
+use crate::ip_allowlist::{self, IpAllowlist};
+
+#[derive(Clone)]
+struct SchemaState {
+    telemetry: PgPool,
+    scrape: Option<PgPool>,
+}
+
+pub fn router(
+    telemetry: PgPool,
+    scrape: Option<PgPool>,
+    api_keys: ApiKeys,
+    allowlist: IpAllowlist,
+) -> Router {
+    Router::new()
+        .route("/admin/schema", get(schema_handler))
+        .layer(middleware::from_fn_with_state(
+            (api_keys, "admin"),
+            auth::require_scope,
+        ))
+        .layer(middleware::from_fn_with_state(
+            allowlist,
+            ip_allowlist::require_allowed_ip,
+        ))
+        .with_state(SchemaState { telemetry, scrape })
+}
+
+/// For post-deploy checks: whether both databases have every migration this binary embeds,
+/// and whether the scraper-owned tables still have the columns hydration reads.
+async fn schema_handler(State(state): State<SchemaState>) -> Response {
+    match schema_report(&state).await {
+        Ok(report) => (StatusCode::OK, Json(json!({ "data": report }))).into_response(),
+        Err(e) => {
+            tracing::error!("schema report error: {}", e);
+            error_response(db_error_status(&e), "Failed to build report").into_response()
+        }
+    }
+}
+
+async fn schema_report(state: &SchemaState) -> Result<Value, sqlx::Error> {
+    let telemetry = schema::migration_status(&state.telemetry, &db::MIGRATOR).await?;
+    let scrape = match &state.scrape {
+        Some(pool) => {
+            let migrations = schema::migration_status(pool, &db::SCRAPE_MIGRATOR).await?;
+            let columns = schema::scrape_column_drift(pool).await?;
+            json!({ "migrations": migrations, "columns": columns })
+        }
+        None => Value::Null,
+    };
+    Ok(json!({ "telemetry": { "migrations": telemetry }, "scrape": scrape }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, as_admin, get, send};
+
+    #[tokio::test]
+    async fn schema_reports_renamed_columns_and_unapplied_migrations() {
+        let (Some(telemetry), Some(scrape)) = (
+            test_support::telemetry_db().await,
+            test_support::scrape_db().await,
+        ) else {
+            return;
+        };
+        let app = router(
+            telemetry,
+            Some(scrape.clone()),
+            test_support::admin_keys(),
+            IpAllowlist::default(),
+        );
+        let report = || async {
+            let (status, _, body) = send(app.clone(), as_admin(get("/admin/schema"))).await;
+            assert_eq!(status, StatusCode::OK);
+            body["data"].clone()
+        };
+
+        let (status, _, _) = send(app.clone(), get("/admin/schema")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let clean = report().await;
+        assert_eq!(clean["telemetry"]["migrations"]["upToDate"], true);
+        assert_eq!(clean["scrape"]["migrations"]["upToDate"], true);
+        let columns = &clean["scrape"]["columns"];
+        assert_eq!(columns["matches"], true);
+        assert_eq!(columns["missing"], json!([]));
+        assert_eq!(columns["checksum"], columns["expectedChecksum"]);
+
+        let latest = clean["scrape"]["migrations"]["latestEmbedded"].clone();
+        sqlx::raw_sql(sqlx::AssertSqlSafe(format!(
+            "ALTER TABLE songs RENAME COLUMN isrc TO isrc_code;
+             ALTER TABLE artists ADD COLUMN sort_name TEXT;
+             DELETE FROM _sqlx_migrations WHERE version = {latest};"
+        )))
+        .execute(&scrape)
+        .await
+        .expect("drift the scrape schema");
+
+        let drifted = report().await;
+        // Extra columns don't count; only the renamed one is missing.
+        let columns = &drifted["scrape"]["columns"];
+        assert_eq!(columns["matches"], false);
+        assert_eq!(columns["missing"], json!(["songs.isrc"]));
+        assert_ne!(columns["checksum"], columns["expectedChecksum"]);
+        assert_eq!(
+            columns["expectedChecksum"],
+            clean["scrape"]["columns"]["expectedChecksum"]
+        );
+        let migrations = &drifted["scrape"]["migrations"];
+        assert_eq!(migrations["upToDate"], false);
+        assert_eq!(migrations["pending"], json!([latest]));
+        assert_eq!(drifted["telemetry"], clean["telemetry"]);
+    }
+}
@@ -0,0 +1,146 @@
+use axum::{
+    Router,
+    body::{Body, Bytes},
+    extract::{Query, State},
+    http::{StatusCode, header},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use futures::{SinkExt, StreamExt, TryStreamExt, channel::mpsc};
+use serde::Deserialize;
+use serde_json::json;
+use time::format_description::well_known::Rfc3339;
+use time::{Duration, OffsetDateTime};
+
+use crate::api::{db_error_status, error_response};
+use crate::auth::{self, ApiKeys};
+use crate::db::{self, DbPools};
+use crate::ip_allowlist::{self, IpAllowlist};
+use crate::rate_limit::rate_limit;
+use crate::redaction;
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub from: Option<OffsetDateTime>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub to: Option<OffsetDateTime>,
+}
+
+pub fn router(api_keys: &ApiKeys, allowlist: IpAllowlist) -> Router<DbPools> {
+    Router::new()
+        .route("/admin/export", get(export_handler))
+        .layer(middleware::from_fn_with_state(
+            (api_keys.clone(), "admin"),
+            auth::require_scope,
+        ))
+        .layer(middleware::from_fn_with_state(
+            allowlist,
+            ip_allowlist::require_allowed_ip,
+        ))
+        .layer(rate_limit("telemetry_export", 1, 10_000, api_keys))
+}
+
+/// Longest `to - from` one export may cover, from `TELEMETRY_EXPORT_MAX_DAYS` (default 90).
+fn max_range() -> Duration {
+    Duration::days(
+        std::env::var("TELEMETRY_EXPORT_MAX_DAYS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|v| *v > 0)
+            .unwrap_or(90),
+    )
+}
+
+/// Most rows one export may return, from `TELEMETRY_EXPORT_MAX_ROWS` (default 1,000,000).
+fn max_rows() -> i64 {
+    std::env::var("TELEMETRY_EXPORT_MAX_ROWS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(1_000_000)
+}
+
+/// Streams raw submissions in `[from, to)` as NDJSON, oldest first, with user ids replaced by
+/// the same keyed hash the logs use. `x-total-rows` carries the row count up front so clients
+/// can show progress while the chunks arrive.
+async fn export_handler(
+    State(pools): State<DbPools>,
+    Query(params): Query<ExportQuery>,
+) -> Response {
+    let (Some(from), Some(to)) = (params.from, params.to) else {
+        return error_response(StatusCode::BAD_REQUEST, "from and to are required").into_response();
+    };
+    if from >= to {
+        return error_response(StatusCode::BAD_REQUEST, "from must be before to").into_response();
+    }
+    let max_range = max_range();
+    if to - from > max_range {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            &format!("range must not exceed {} days", max_range.whole_days()),
+        )
+        .into_response();
+    }
+
+    // A stream can't switch pools halfway, so exports stay on the replica when there is one.
+    let pool = pools.reader().clone();
+    let max_rows = max_rows();
+    let total = match db::telemetry::count_submissions_capped(&pool, from, to, max_rows + 1).await {
+        Ok(total) => total,
+        Err(e) => {
+            tracing::error!("telemetry export error: {}", e);
+            return error_response(db_error_status(&e), "Export failed").into_response();
+        }
+    };
+    if total > max_rows {
+        return error_response(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            &format!("range holds more than {max_rows} rows, narrow it"),
+        )
+        .into_response();
+    }
+
+    let (mut tx, rx) = mpsc::channel::<Result<Bytes, std::io::Error>>(64);
+    tokio::spawn(async move {
+        // Rows written after the count are cut off so the stream matches x-total-rows.
+        let mut rows = db::telemetry::raw_submissions(&pool, from, to)
+            .take(total as usize)
+            .boxed();
+        loop {
+            let line = match rows.try_next().await {
+                Ok(Some(row)) => {
+                    let mut line = json!({
+                        "user_id": redaction::hashed_user_id(&row.user_id),
+                        "app_version": row.app_version,
+                        "os": row.os,
+                        "song_count": row.song_count,
+                        "time": row.time.format(&Rfc3339).ok(),
+                    })
+                    .to_string();
+                    line.push('\n');
+                    Ok(Bytes::from(line))
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    tracing::error!("telemetry export stream error: {}", e);
+                    Err(std::io::Error::other(e))
+                }
+            };
+            let failed = line.is_err();
+            if tx.send(line).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    let mut response = (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(rx),
+    )
+        .into_response();
+    response.headers_mut().insert("x-total-rows", total.into());
+    response
+}
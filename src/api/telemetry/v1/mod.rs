@@ -1,3 +1,4 @@
+pub mod export;
 pub mod telemetry;
 
 pub use telemetry::router;
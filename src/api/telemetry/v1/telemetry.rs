@@ -1,151 +1,337 @@
 use axum::{
     Json, Router,
-    extract::{Query, State},
+    extract::State,
     http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
     routing::{get, post},
 };
-use sqlx::PgPool;
+use serde_json::{Value, json};
 use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
 use tracing::{debug, error};
 
 use crate::{
-    api::validation::ValidatedJson,
-    db,
-    models::telemetry::{DistributionPoint, StatsQuery, TelemetrySubmission, TimeSeriesPoint},
+    api::{
+        db_error_status, error_response,
+        validation::{StrictQuery, ValidatedJson},
+    },
+    auth::ApiKeys,
+    client_requirements,
+    db::{self, DbPools},
+    ip_allowlist::{self, IpAllowlist},
+    models::telemetry::{
+        DistributionPoint, InvalidTimeZone, OptOutRequest, SeriesFormat, StatsQuery,
+        SubmissionStatusQuery, TelemetrySubmission, TimeSeriesPoint, TimeZone,
+    },
     rate_limit::rate_limit,
+    redaction,
 };
 
-pub fn router() -> Router<PgPool> {
+// Users who haven't reported for this long are treated as churned.
+const CHURN_THRESHOLD: &str = "30 days";
+/// Accepted submissions per user per day; more are answered with 429.
+const DAILY_SUBMISSION_LIMIT: i64 = 10;
+
+pub fn router(api_keys: &ApiKeys, allowlist: IpAllowlist) -> Router<DbPools> {
     let ingest_routes = Router::new()
         .route("/", post(submit_telemetry))
-        .layer(rate_limit(1, 2000));
+        .layer(rate_limit("ingest", 1, 2000, api_keys));
+
+    let optout_routes = Router::new()
+        .route("/optout", post(opt_out))
+        .layer(rate_limit("optout", 5, 60_000, api_keys));
+
+    let status_routes = Router::new()
+        .route("/status", get(submission_status))
+        .layer(rate_limit("telemetry_status", 10, 60_000, api_keys));
 
     let dashboard_routes = Router::new()
         .route("/songs_over_time", get(get_songs_over_time))
         .route("/users_over_time", get(get_users_over_time))
+        .route("/submissions_over_time", get(get_submissions_over_time))
         .route("/distribution/os", get(get_os_distribution))
         .route("/distribution/version", get(get_version_distribution))
-        .layer(rate_limit(20, 1000));
+        .layer(rate_limit("dashboard", 20, 1000, api_keys))
+        .layer(middleware::from_fn_with_state(
+            allowlist.clone(),
+            ip_allowlist::require_allowed_ip,
+        ));
 
-    Router::new().merge(ingest_routes).merge(dashboard_routes)
+    Router::new()
+        .merge(ingest_routes)
+        .merge(optout_routes)
+        .merge(status_routes)
+        .merge(dashboard_routes)
+        .merge(super::export::router(api_keys, allowlist))
 }
 
+/// Submissions from app versions below the soft minimum are still stored; the response
+/// carries `deprecated: true` so the app can nudge the user to update.
 async fn submit_telemetry(
-    State(pool): State<PgPool>,
+    State(pools): State<DbPools>,
     ValidatedJson(payload): ValidatedJson<TelemetrySubmission>,
-) -> StatusCode {
-    match db::telemetry::daily_submission_count(&pool, payload.user_id).await {
-        Ok(count) if count >= 10 => return StatusCode::TOO_MANY_REQUESTS,
+) -> Response {
+    let pool = &pools.primary;
+    match db::telemetry::is_opted_out(pool, payload.user_id).await {
+        // A 200 so stale clients drop the submission instead of retrying it.
+        Ok(true) => {
+            return (StatusCode::OK, Json(json!({ "status": "discarded" }))).into_response();
+        }
+        Err(e) => {
+            error!("opt-out check error: {}", e);
+            return error_response(db_error_status(&e), "Failed to check opt-out").into_response();
+        }
+        _ => {}
+    }
+
+    match db::telemetry::daily_submission_count(pool, payload.user_id).await {
+        Ok(count) if count >= DAILY_SUBMISSION_LIMIT => {
+            return StatusCode::TOO_MANY_REQUESTS.into_response();
+        }
         Err(e) => {
             error!("daily count error: {}", e);
-            return StatusCode::INTERNAL_SERVER_ERROR;
+            return error_response(db_error_status(&e), "Failed to count submissions")
+                .into_response();
         }
         _ => {}
     }
 
-    match db::telemetry::last_submission(&pool, payload.user_id).await {
+    match db::telemetry::last_submission(pool, payload.user_id).await {
         Ok(Some(last)) => {
             if last.os != payload.os.as_str() {
-                return StatusCode::UNPROCESSABLE_ENTITY;
+                return StatusCode::UNPROCESSABLE_ENTITY.into_response();
             }
             if last.song_count > 100 && payload.song_count < last.song_count / 2 {
-                return StatusCode::UNPROCESSABLE_ENTITY;
+                return StatusCode::UNPROCESSABLE_ENTITY.into_response();
             }
         }
         Err(e) => {
             error!("last submission error: {}", e);
-            return StatusCode::INTERNAL_SERVER_ERROR;
+            return error_response(db_error_status(&e), "Failed to read last submission")
+                .into_response();
         }
         _ => {}
     }
 
-    debug!(user_id = %payload.user_id, "receiving telemetry");
+    debug!(user = %redaction::user_id(&payload.user_id), "receiving telemetry");
 
-    match db::telemetry::insert_submission(&pool, &payload).await {
-        Ok(_) => StatusCode::OK,
+    match db::telemetry::insert_submission(pool, &payload, None).await {
+        Ok(_) if client_requirements::is_deprecated(&payload.app_version) => {
+            (StatusCode::OK, Json(json!({ "deprecated": true }))).into_response()
+        }
+        Ok(_) => StatusCode::OK.into_response(),
         Err(e) => {
             error!("telemetry insert error: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
+            error_response(db_error_status(&e), "Failed to store telemetry").into_response()
         }
     }
 }
 
+/// Stops storing telemetry for a user id from now on. No key is needed: the random id
+/// is only known to the user's own client. Repeating it is harmless.
+async fn opt_out(
+    State(pools): State<DbPools>,
+    ValidatedJson(payload): ValidatedJson<OptOutRequest>,
+) -> Response {
+    match db::telemetry::opt_out(&pools.primary, payload.user_id).await {
+        Ok(new) => {
+            if new {
+                tracing::info!(
+                    target: "audit",
+                    user = %redaction::user_id(&payload.user_id),
+                    "telemetry opt-out"
+                );
+            }
+            (StatusCode::OK, Json(json!({ "status": "opted_out" }))).into_response()
+        }
+        Err(e) => {
+            error!("opt-out error: {}", e);
+            error_response(db_error_status(&e), "Failed to opt out").into_response()
+        }
+    }
+}
+
+/// When the client may submit next, so it can show that instead of guessing. Same trust
+/// model as ingest: the random id is the only credential, so the answer is limited to the
+/// user's limiter state.
+async fn submission_status(
+    State(pools): State<DbPools>,
+    StrictQuery(params): StrictQuery<SubmissionStatusQuery>,
+) -> Response {
+    // The primary, so a submission made a moment ago is already counted.
+    let status = match db::telemetry::submission_status(&pools.primary, params.user_id).await {
+        Ok(status) => status,
+        Err(e) => {
+            error!("submission status error: {}", e);
+            return error_response(db_error_status(&e), "Failed to read submission status")
+                .into_response();
+        }
+    };
+    let now = OffsetDateTime::now_utc();
+    let next_allowed_at = if status.submissions_today >= DAILY_SUBMISSION_LIMIT {
+        status.day_ends_at
+    } else {
+        now
+    };
+    (
+        StatusCode::OK,
+        Json(json!({
+            "lastSubmissionAt": status.last_submission_at.and_then(|t| t.format(&Rfc3339).ok()),
+            "dailyLimit": DAILY_SUBMISSION_LIMIT,
+            "remainingToday": (DAILY_SUBMISSION_LIMIT - status.submissions_today).max(0),
+            "nextAllowedAt": next_allowed_at.format(&Rfc3339).ok(),
+        })),
+    )
+        .into_response()
+}
+
 async fn resolve_time_range(
-    pool: &PgPool,
+    pools: &DbPools,
     from: Option<OffsetDateTime>,
     to: Option<OffsetDateTime>,
-) -> Result<(OffsetDateTime, OffsetDateTime), StatusCode> {
+) -> Result<(OffsetDateTime, OffsetDateTime), Response> {
     let end = to.unwrap_or_else(OffsetDateTime::now_utc);
     let start = match from {
         Some(t) => t,
         None => {
-            let min = db::telemetry::earliest_time(pool).await.map_err(|e| {
-                error!("min time query error: {}", e);
-                StatusCode::INTERNAL_SERVER_ERROR
-            })?;
+            let min = pools
+                .read(db::telemetry::earliest_time)
+                .await
+                .map_err(|e| {
+                    error!("min time query error: {}", e);
+                    error_response(db_error_status(&e), "Failed to resolve time range")
+                        .into_response()
+                })?;
             min.unwrap_or(end)
         }
     };
     Ok((start, end))
 }
 
+fn resolve_time_zone(params: &StatsQuery) -> Result<TimeZone, InvalidTimeZone> {
+    match params.tz.as_deref().filter(|tz| !tz.is_empty()) {
+        Some(tz) => tz.parse(),
+        None => Ok(TimeZone::UTC),
+    }
+}
+
 async fn get_songs_over_time(
-    State(pool): State<PgPool>,
-    Query(params): Query<StatsQuery>,
-) -> Result<Json<Vec<TimeSeriesPoint>>, StatusCode> {
-    let (start, end) = resolve_time_range(&pool, params.from, params.to).await?;
+    State(pools): State<DbPools>,
+    StrictQuery(params): StrictQuery<StatsQuery>,
+) -> Result<Json<Value>, Response> {
+    let tz = resolve_time_zone(&params).map_err(IntoResponse::into_response)?;
+    let (start, end) = resolve_time_range(&pools, params.from, params.to).await?;
 
     let interval = format!("{} seconds", calculate_bucket_interval(&start, &end));
 
-    let points = db::telemetry::songs_over_time(&pool, start, end, interval)
+    let include_stale = params.include_stale.unwrap_or(true);
+    let points = pools
+        .read(|pool| {
+            db::telemetry::songs_over_time(
+                pool,
+                start,
+                end,
+                interval.clone(),
+                include_stale,
+                CHURN_THRESHOLD,
+                tz,
+            )
+        })
         .await
         .map_err(|e| {
             error!("songs db error: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
+            error_response(db_error_status(&e), "Failed to load series").into_response()
         })?;
 
-    Ok(Json(points))
+    Ok(series_response("songs", points, &params))
 }
 
 async fn get_users_over_time(
-    State(pool): State<PgPool>,
-    Query(params): Query<StatsQuery>,
-) -> Result<Json<Vec<TimeSeriesPoint>>, StatusCode> {
-    let (start, end) = resolve_time_range(&pool, params.from, params.to).await?;
+    State(pools): State<DbPools>,
+    StrictQuery(params): StrictQuery<StatsQuery>,
+) -> Result<Json<Value>, Response> {
+    let tz = resolve_time_zone(&params).map_err(IntoResponse::into_response)?;
+    let (start, end) = resolve_time_range(&pools, params.from, params.to).await?;
 
     let interval = format!("{} seconds", calculate_bucket_interval(&start, &end));
 
-    let points = db::telemetry::users_over_time(&pool, start, end, interval)
+    let points = pools
+        .read(|pool| db::telemetry::users_over_time(pool, start, end, interval.clone(), tz))
         .await
         .map_err(|e| {
             error!("users db error: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
+            error_response(db_error_status(&e), "Failed to load series").into_response()
+        })?;
+
+    Ok(series_response("users", points, &params))
+}
+
+/// Ingest volume: every stored submission counts, not distinct users.
+async fn get_submissions_over_time(
+    State(pools): State<DbPools>,
+    StrictQuery(params): StrictQuery<StatsQuery>,
+) -> Result<Json<Value>, Response> {
+    let tz = resolve_time_zone(&params).map_err(IntoResponse::into_response)?;
+    let (start, end) = resolve_time_range(&pools, params.from, params.to).await?;
+
+    let interval = format!("{} seconds", calculate_bucket_interval(&start, &end));
+
+    let points = pools
+        .read(|pool| db::telemetry::submissions_over_time(pool, start, end, interval.clone(), tz))
+        .await
+        .map_err(|e| {
+            error!("submissions db error: {}", e);
+            error_response(db_error_status(&e), "Failed to load series").into_response()
         })?;
 
-    Ok(Json(points))
+    Ok(series_response("submissions", points, &params))
+}
+
+fn series_response(target: &str, points: Vec<TimeSeriesPoint>, params: &StatsQuery) -> Json<Value> {
+    match params.format {
+        SeriesFormat::Points if params.epoch => Json(
+            points
+                .iter()
+                .map(|p| json!({ "bucket": p.bucket_millis(), "value": p.value }))
+                .collect(),
+        ),
+        SeriesFormat::Points => Json(json!(points)),
+        SeriesFormat::Grafana => {
+            let datapoints: Vec<Value> = points
+                .iter()
+                .map(|p| json!([p.value, p.bucket_millis()]))
+                .collect();
+            Json(json!([{ "target": target, "datapoints": datapoints }]))
+        }
+    }
 }
 
 async fn get_os_distribution(
-    State(pool): State<PgPool>,
-    Query(_): Query<StatsQuery>,
-) -> Result<Json<Vec<DistributionPoint>>, StatusCode> {
-    let stats = db::telemetry::os_distribution(&pool).await.map_err(|e| {
-        error!("os stats error: {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
+    State(pools): State<DbPools>,
+    StrictQuery(_): StrictQuery<StatsQuery>,
+) -> Result<Json<Vec<DistributionPoint>>, Response> {
+    let stats = pools
+        .read(db::telemetry::os_distribution)
+        .await
+        .map_err(|e| {
+            error!("os stats error: {}", e);
+            error_response(db_error_status(&e), "Failed to load distribution").into_response()
+        })?;
 
     Ok(Json(stats))
 }
 
 async fn get_version_distribution(
-    State(pool): State<PgPool>,
-    Query(_): Query<StatsQuery>,
-) -> Result<Json<Vec<DistributionPoint>>, StatusCode> {
-    let stats = db::telemetry::version_distribution(&pool)
+    State(pools): State<DbPools>,
+    StrictQuery(_): StrictQuery<StatsQuery>,
+) -> Result<Json<Vec<DistributionPoint>>, Response> {
+    let stats = pools
+        .read(db::telemetry::version_distribution)
         .await
         .map_err(|e| {
             error!("version stats error: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
+            error_response(db_error_status(&e), "Failed to load distribution").into_response()
         })?;
 
     Ok(Json(stats))
@@ -191,3 +377,339 @@ fn calculate_bucket_interval(from: &OffsetDateTime, to: &OffsetDateTime) -> i64
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use serde_json::json;
+    use time::{Duration, OffsetDateTime, format_description::well_known::Rfc3339};
+
+    use crate::test_support::{self, get, post_json, send};
+
+    fn at(rfc3339: &str) -> OffsetDateTime {
+        OffsetDateTime::parse(rfc3339, &Rfc3339).expect("valid timestamp")
+    }
+
+    /// Stores a submission as if it had arrived at `time`.
+    async fn report(pool: &sqlx::PgPool, user_id: uuid::Uuid, song_count: i64, time: &str) {
+        sqlx::query(
+            "INSERT INTO telemetry (user_id, app_version, os, song_count, time)
+             VALUES ($1, '1.0.0', 'Linux', $2, $3)",
+        )
+        .bind(user_id)
+        .bind(song_count)
+        .bind(at(time))
+        .execute(pool)
+        .await
+        .expect("insert submission");
+    }
+
+    /// The series value in effect at `time`: points are only emitted when the value changes.
+    fn value_at(points: &serde_json::Value, time: &str) -> f64 {
+        let time = at(time);
+        points
+            .as_array()
+            .expect("points")
+            .iter()
+            .take_while(|p| at(p["bucket"].as_str().expect("bucket")) <= time)
+            .last()
+            .and_then(|p| p["value"].as_f64())
+            .expect("a point at or before the time")
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_submissions_before_touching_the_database() {
+        for body in [
+            json!({ "user_id": "not-a-uuid", "app_version": "1.2.3", "os": "Linux", "song_count": 1 }),
+            json!({ "user_id": uuid::Uuid::new_v4(), "app_version": "latest", "os": "Linux", "song_count": 1 }),
+            json!({ "user_id": uuid::Uuid::new_v4(), "app_version": "1.2.3", "os": "BeOS", "song_count": 1 }),
+            json!({ "user_id": uuid::Uuid::new_v4(), "app_version": "1.2.3", "os": "Linux", "song_count": -1 }),
+        ] {
+            let app = test_support::app(None, test_support::unreachable_pool());
+            let (status, _, _) = send(app, post_json("/telemetry/v1", &body)).await;
+            assert!(status.is_client_error(), "{body}: {status}");
+        }
+    }
+
+    #[tokio::test]
+    async fn database_errors_carry_the_json_error_body() {
+        let app = test_support::app(None, test_support::unreachable_pool());
+        let submission = json!({ "user_id": uuid::Uuid::new_v4(), "app_version": "1.2.3", "os": "Linux", "song_count": 1 });
+        for req in [
+            post_json("/telemetry/v1", &submission),
+            get("/telemetry/v1/songs_over_time"),
+            get("/telemetry/v1/distribution/os"),
+        ] {
+            let uri = req.uri().to_string();
+            let (status, _, body) = send(app.clone(), req).await;
+            assert!(status.is_server_error(), "{uri}: {status}");
+            assert_eq!(body["error"]["status"], status.as_u16(), "{uri}");
+            assert!(body["error"]["message"].is_string(), "{uri}: {body}");
+        }
+    }
+
+    #[tokio::test]
+    async fn submission_shows_up_in_status_and_songs_over_time() {
+        let Some(pool) = test_support::telemetry_db().await else {
+            return;
+        };
+        let app = test_support::app(None, pool);
+        let user_id = uuid::Uuid::new_v4();
+
+        let submission =
+            json!({ "user_id": user_id, "app_version": "9.9.9", "os": "Linux", "song_count": 42 });
+        let (status, _, _) = send(app.clone(), post_json("/telemetry/v1", &submission)).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _, body) = send(
+            app.clone(),
+            get(&format!("/telemetry/v1/status?user_id={user_id}")),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["remainingToday"], 9);
+        assert!(body["lastSubmissionAt"].is_string());
+
+        let now = OffsetDateTime::now_utc();
+        let from = (now - Duration::hours(1)).format(&Rfc3339).unwrap();
+        let to = (now + Duration::hours(1)).format(&Rfc3339).unwrap();
+        let uri = format!(
+            "/telemetry/v1/songs_over_time?from={}&to={}",
+            from.replace('+', "%2B"),
+            to.replace('+', "%2B")
+        );
+        let (status, _, body) = send(app, get(&uri)).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let points = body.as_array().expect("points");
+        assert!(points.iter().any(|p| p["value"] == 42.0), "{body}");
+    }
+
+    #[tokio::test]
+    async fn status_requires_a_valid_user_id() {
+        for uri in [
+            "/telemetry/v1/status",
+            "/telemetry/v1/status?user_id=",
+            "/telemetry/v1/status?user_id=not-a-uuid",
+        ] {
+            let app = test_support::app(None, test_support::unreachable_pool());
+            let (status, _, body) = send(app, get(uri)).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{uri}: {body}");
+        }
+    }
+
+    #[tokio::test]
+    async fn status_reports_only_limiter_state_and_when_the_next_submission_is_allowed() {
+        let Some(pool) = test_support::telemetry_db().await else {
+            return;
+        };
+        let app = test_support::app(None, pool.clone());
+        let user_id = uuid::Uuid::new_v4();
+        let uri = format!("/telemetry/v1/status?user_id={user_id}");
+
+        let (status, _, body) = send(app.clone(), get(&uri)).await;
+        assert_eq!(status, StatusCode::OK);
+        let mut fields: Vec<&str> = body
+            .as_object()
+            .expect("object")
+            .keys()
+            .map(String::as_str)
+            .collect();
+        fields.sort_unstable();
+        assert_eq!(
+            fields,
+            [
+                "dailyLimit",
+                "lastSubmissionAt",
+                "nextAllowedAt",
+                "remainingToday"
+            ],
+            "nothing else about the user's data"
+        );
+        assert_eq!(body["lastSubmissionAt"], serde_json::Value::Null);
+        assert_eq!(body["dailyLimit"], super::DAILY_SUBMISSION_LIMIT);
+        assert_eq!(body["remainingToday"], super::DAILY_SUBMISSION_LIMIT);
+        let next = at(body["nextAllowedAt"].as_str().expect("nextAllowedAt"));
+        assert!(
+            next <= OffsetDateTime::now_utc(),
+            "a new user may submit now"
+        );
+
+        for _ in 0..super::DAILY_SUBMISSION_LIMIT {
+            sqlx::query(
+                "INSERT INTO telemetry (user_id, app_version, os, song_count)
+                 VALUES ($1, '1.0.0', 'Linux', 1)",
+            )
+            .bind(user_id)
+            .execute(&pool)
+            .await
+            .expect("insert submission");
+        }
+        let (status, _, body) = send(app, get(&uri)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["remainingToday"], 0);
+        assert!(body["lastSubmissionAt"].is_string());
+        let next = at(body["nextAllowedAt"].as_str().expect("nextAllowedAt"));
+        let tomorrow = OffsetDateTime::now_utc()
+            .date()
+            .next_day()
+            .unwrap()
+            .midnight()
+            .assume_utc();
+        assert_eq!(next, tomorrow, "the daily limit resets at midnight UTC");
+    }
+
+    #[tokio::test]
+    async fn backfilled_submission_moves_first_seen_and_the_user_series_back() {
+        let Some(pool) = test_support::telemetry_db().await else {
+            return;
+        };
+        let app = test_support::app(None, pool.clone());
+        let user_id = uuid::Uuid::new_v4();
+        let submission =
+            json!({ "user_id": user_id, "app_version": "9.9.9", "os": "Linux", "song_count": 42 });
+        let (status, _, _) = send(app.clone(), post_json("/telemetry/v1", &submission)).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let now = OffsetDateTime::now_utc().replace_nanosecond(0).unwrap();
+        let backfilled = now - Duration::days(10);
+        let uri = format!(
+            "/telemetry/v1/users_over_time?from={}&to={}",
+            (now - Duration::days(30)).format(&Rfc3339).unwrap(),
+            (now + Duration::hours(1)).format(&Rfc3339).unwrap(),
+        );
+        let before = send(app.clone(), get(&uri)).await.2;
+        assert_eq!(
+            value_at(&before, &backfilled.format(&Rfc3339).unwrap()),
+            0.0
+        );
+
+        let payload = serde_json::from_value(submission).unwrap();
+        for recorded_at in [backfilled, now - Duration::days(5)] {
+            crate::db::telemetry::insert_submission(&pool, &payload, Some(recorded_at))
+                .await
+                .expect("backfill");
+        }
+        let first_seen: OffsetDateTime =
+            sqlx::query_scalar("SELECT first_seen FROM user_first_seen WHERE user_id = $1")
+                .bind(user_id)
+                .fetch_one(&pool)
+                .await
+                .expect("first seen");
+        assert_eq!(
+            first_seen, backfilled,
+            "only the oldest submission moves it"
+        );
+
+        let after = send(app.clone(), get(&uri)).await.2;
+        assert_eq!(value_at(&after, &backfilled.format(&Rfc3339).unwrap()), 1.0);
+        let last = after.as_array().and_then(|p| p.last()).expect("points");
+        assert_eq!(last["value"], 1.0, "counted once: {after}");
+        assert_eq!(send(app, get(&uri)).await.2, after, "the series is stable");
+    }
+
+    #[tokio::test]
+    async fn songs_over_time_carries_users_forward_until_they_churn() {
+        let Some(pool) = test_support::telemetry_db().await else {
+            return;
+        };
+        let (week_one_only, steady) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        report(&pool, week_one_only, 100, "2026-01-06T12:00:00Z").await;
+        for day in [5, 12, 19, 26] {
+            report(&pool, steady, 50, &format!("2026-01-{day:02}T12:00:00Z")).await;
+        }
+        for day in [2, 9, 16, 23] {
+            report(&pool, steady, 50, &format!("2026-02-{day:02}T12:00:00Z")).await;
+        }
+        // Only reported before the range: their latest report is what's carried in.
+        let lapsed = uuid::Uuid::new_v4();
+        report(&pool, lapsed, 200, "2025-10-01T12:00:00Z").await;
+        report(&pool, lapsed, 20, "2025-12-20T12:00:00Z").await;
+        let app = test_support::app(None, pool);
+        let range = "from=2026-01-05T00:00:00Z&to=2026-03-02T00:00:00Z";
+
+        let (status, _, default) = send(
+            app.clone(),
+            get(&format!("/telemetry/v1/songs_over_time?{range}")),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{default}");
+        let (status, _, fresh_only) = send(
+            app,
+            get(&format!(
+                "/telemetry/v1/songs_over_time?{range}&include_stale=false"
+            )),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{fresh_only}");
+
+        // Week 1: the lapsed user's December report is still inside the threshold.
+        assert_eq!(value_at(&default, "2026-01-08T12:00:00Z"), 170.0);
+        assert_eq!(value_at(&fresh_only, "2026-01-08T12:00:00Z"), 170.0);
+        // Week 4: the week-1 report is 23 days old, inside the churn threshold either way.
+        assert_eq!(value_at(&default, "2026-01-29T12:00:00Z"), 170.0);
+        assert_eq!(value_at(&fresh_only, "2026-01-29T12:00:00Z"), 150.0);
+        // Week 7: past the threshold, only `include_stale=false` drops them.
+        assert_eq!(value_at(&default, "2026-02-19T12:00:00Z"), 170.0);
+        assert_eq!(value_at(&fresh_only, "2026-02-19T12:00:00Z"), 50.0);
+    }
+
+    #[tokio::test]
+    async fn series_bucket_days_at_local_midnight_across_a_dst_change() {
+        let Some(pool) = test_support::telemetry_db().await else {
+            return;
+        };
+        let user_id = uuid::Uuid::new_v4();
+        // Zurich moves from +01:00 to +02:00 on 2026-03-29, a 23-hour local day.
+        for time in [
+            "2026-03-28T23:30:00Z", // 00:30 on the 29th
+            "2026-03-29T21:30:00Z", // 23:30 on the 29th
+            "2026-03-29T22:30:00Z", // 00:30 on the 30th
+        ] {
+            report(&pool, user_id, 10, time).await;
+        }
+        sqlx::query("INSERT INTO user_first_seen (user_id, first_seen) VALUES ($1, $2)")
+            .bind(user_id)
+            .bind(at("2026-03-28T23:30:00Z"))
+            .execute(&pool)
+            .await
+            .expect("insert first seen");
+        let app = test_support::app(None, pool);
+        // Long enough that the series is bucketed by day.
+        let query = "from=2025-11-01T00:00:00Z&to=2026-04-30T00:00:00Z&tz=Europe/Zurich";
+
+        let (status, _, submissions) = send(
+            app.clone(),
+            get(&format!("/telemetry/v1/submissions_over_time?{query}")),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{submissions}");
+        let count = |bucket: &str| {
+            submissions
+                .as_array()
+                .expect("points")
+                .iter()
+                .find(|p| p["bucket"] == bucket)
+                .and_then(|p| p["value"].as_f64())
+                .unwrap_or_else(|| panic!("no bucket {bucket} in {submissions}"))
+        };
+        assert_eq!(count("2026-03-27T23:00:00Z"), 0.0);
+        assert_eq!(count("2026-03-28T23:00:00Z"), 2.0);
+        assert_eq!(count("2026-03-29T22:00:00Z"), 1.0);
+        assert_eq!(count("2026-03-30T22:00:00Z"), 0.0);
+
+        // Every series buckets the same way.
+        let buckets = |points: &serde_json::Value| -> Vec<String> {
+            points
+                .as_array()
+                .expect("points")
+                .iter()
+                .map(|p| p["bucket"].as_str().expect("bucket").to_string())
+                .collect()
+        };
+        let (status, _, users) =
+            send(app, get(&format!("/telemetry/v1/users_over_time?{query}"))).await;
+        assert_eq!(status, StatusCode::OK, "{users}");
+        assert_eq!(buckets(&users), buckets(&submissions));
+        assert_eq!(value_at(&users, "2026-03-28T23:00:00Z"), 1.0);
+    }
+}
@@ -7,6 +7,8 @@ use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 use tokio::time::timeout;
 
+use crate::api::error_response;
+
 static SEMVER_RE: OnceLock<Regex> = OnceLock::new();
 
 fn is_semver(v: &str) -> bool {
@@ -52,13 +54,6 @@ pub fn router() -> Router {
         .with_state(state)
 }
 
-fn error_response(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
-    (
-        status,
-        Json(json!({ "error": { "status": status.as_u16(), "message": message } })),
-    )
-}
-
 async fn fetch_platforms(client: Client, version: &str) -> Map<String, Value> {
     let handles: Vec<_> = PLATFORMS
         .iter()
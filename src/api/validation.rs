@@ -1,15 +1,73 @@
 use axum::{
     Json,
-    extract::{FromRequest, Request},
-    http::StatusCode,
+    extract::{FromRequest, FromRequestParts, Path, Query, Request},
+    http::{StatusCode, request::Parts},
     response::{IntoResponse, Response},
 };
 use serde::de::DeserializeOwned;
 use validator::Validate;
 
+use crate::api::error_response;
+use crate::models::metadata::{InvalidItemType, InvalidOmId, ItemType, OmId};
+use crate::models::telemetry::InvalidTimeZone;
+
 #[derive(Debug, Clone, Copy, Default)]
 pub struct ValidatedJson<T>(pub T);
 
+/// Query parameter names a query struct understands, for [`StrictQuery`].
+pub trait KnownParams {
+    const PARAMS: &'static [&'static str];
+}
+
+/// `Query<T>` that, in strict mode, rejects parameters `T` doesn't know instead of ignoring
+/// them, so a typo like `artis=` fails loudly. Strict mode is on for every request with
+/// `STRICT_QUERY_PARAMS=true`, otherwise per request with an `X-Strict: true` header.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StrictQuery<T>(pub T);
+
+fn strict_requested(parts: &Parts) -> bool {
+    std::env::var("STRICT_QUERY_PARAMS").is_ok_and(|v| v.eq_ignore_ascii_case("true"))
+        || parts
+            .headers
+            .get("x-strict")
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.trim().eq_ignore_ascii_case("true"))
+}
+
+impl<T, S> FromRequestParts<S> for StrictQuery<T>
+where
+    T: DeserializeOwned + KnownParams,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if strict_requested(parts) {
+            let Query(pairs) =
+                Query::<Vec<(String, String)>>::try_from_uri(&parts.uri).map_err(|e| {
+                    error_response(StatusCode::BAD_REQUEST, &e.body_text()).into_response()
+                })?;
+            let mut unknown: Vec<&str> = Vec::new();
+            for (key, _) in &pairs {
+                if !T::PARAMS.contains(&key.as_str()) && !unknown.contains(&key.as_str()) {
+                    unknown.push(key);
+                }
+            }
+            if !unknown.is_empty() {
+                return Err(error_response(
+                    StatusCode::BAD_REQUEST,
+                    &format!("Unknown query parameters: {}", unknown.join(", ")),
+                )
+                .into_response());
+            }
+        }
+        let Query(value) = Query::<T>::from_request_parts(parts, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        Ok(StrictQuery(value))
+    }
+}
+
 impl<T, S> FromRequest<S> for ValidatedJson<T>
 where
     T: DeserializeOwned + Validate,
@@ -49,3 +107,47 @@ impl IntoResponse for ValidationError {
         (status, message).into_response()
     }
 }
+
+impl<S> FromRequestParts<S> for OmId
+where
+    S: Send + Sync,
+{
+    type Rejection = InvalidOmId;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Path(raw) = Path::<String>::from_request_parts(parts, state)
+            .await
+            .map_err(|_| InvalidOmId)?;
+        raw.parse()
+    }
+}
+
+impl IntoResponse for InvalidOmId {
+    fn into_response(self) -> Response {
+        error_response(StatusCode::BAD_REQUEST, "Invalid id. Expected omm:TYPE:ID").into_response()
+    }
+}
+
+impl IntoResponse for InvalidTimeZone {
+    fn into_response(self) -> Response {
+        error_response(
+            StatusCode::BAD_REQUEST,
+            &format!("Unsupported time zone '{}'", self.0),
+        )
+        .into_response()
+    }
+}
+
+impl IntoResponse for InvalidItemType {
+    fn into_response(self) -> Response {
+        error_response(
+            StatusCode::BAD_REQUEST,
+            &format!(
+                "Invalid type '{}'. Expected one of: {}",
+                self.0,
+                ItemType::VALID
+            ),
+        )
+        .into_response()
+    }
+}
@@ -0,0 +1,48 @@
+use axum::{Json, Router, extract::State, routing::get};
+use serde_json::{Value, json};
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+
+use crate::search::Capabilities;
+
+#[derive(Clone)]
+struct VersionState {
+    search_backend: &'static str,
+    index_name: String,
+    capabilities: Capabilities,
+}
+
+pub fn router(
+    search_backend: &'static str,
+    index_name: String,
+    capabilities: Capabilities,
+) -> Router {
+    Router::new()
+        .route("/version", get(version_handler))
+        .with_state(VersionState {
+            search_backend,
+            index_name,
+            capabilities,
+        })
+}
+
+fn build_time() -> String {
+    env!("VLEER_BUILD_TIME")
+        .parse::<i64>()
+        .ok()
+        .filter(|ts| *ts > 0)
+        .and_then(|ts| OffsetDateTime::from_unix_timestamp(ts).ok())
+        .and_then(|t| t.format(&Rfc3339).ok())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+async fn version_handler(State(state): State<VersionState>) -> Json<Value> {
+    Json(json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_sha": env!("VLEER_GIT_SHA"),
+        "build_time": build_time(),
+        "search_backend": state.search_backend,
+        "index_name": state.index_name,
+        "capabilities": state.capabilities,
+    }))
+}
@@ -0,0 +1,186 @@
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::api::error_response;
+
+#[derive(Debug, Clone)]
+pub struct ApiKey {
+    pub id: String,
+    pub scopes: Vec<String>,
+    pub quota: Option<KeyQuota>,
+    /// Requests per rolling 24h, replacing each route group's default daily cap.
+    pub daily_quota: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct KeyQuota {
+    pub requests: NonZeroU32,
+    pub period: Duration,
+}
+
+impl FromStr for KeyQuota {
+    type Err = String;
+
+    /// Parses `<requests>/<period>`, where period is e.g. `60s`, `5m` or `500ms`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (requests, period) = s
+            .split_once('/')
+            .ok_or_else(|| format!("invalid quota {s:?}, expected <requests>/<period>"))?;
+        let requests = requests
+            .trim()
+            .parse::<NonZeroU32>()
+            .map_err(|_| format!("invalid request count in quota {s:?}"))?;
+        let period = period.trim();
+        let (value, unit_ms) = if let Some(v) = period.strip_suffix("ms") {
+            (v, 1)
+        } else if let Some(v) = period.strip_suffix('s') {
+            (v, 1000)
+        } else if let Some(v) = period.strip_suffix('m') {
+            (v, 60_000)
+        } else {
+            return Err(format!("invalid period in quota {s:?}"));
+        };
+        let value = value
+            .parse::<u64>()
+            .ok()
+            .filter(|v| *v > 0)
+            .ok_or_else(|| format!("invalid period in quota {s:?}"))?;
+        Ok(Self {
+            requests,
+            period: Duration::from_millis(value * unit_ms),
+        })
+    }
+}
+
+impl ApiKey {
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|s| s == scope)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ApiKeys(Arc<HashMap<String, ApiKey>>);
+
+impl ApiKeys {
+    /// Parses `API_KEYS`, a comma-separated list of `id:token:scope+scope` entries, and
+    /// per-key rate limit overrides from `API_KEY_QUOTAS` (`id:100/60s,...`) and daily caps
+    /// from `API_KEY_DAILY_QUOTAS` (`id:1000000,...`).
+    pub fn from_env() -> Self {
+        let mut keys = parse_keys(&std::env::var("API_KEYS").unwrap_or_default());
+
+        let quotas = std::env::var("API_KEY_QUOTAS").unwrap_or_default();
+        for entry in quotas.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let Some((id, quota)) = entry.split_once(':') else {
+                tracing::warn!("ignoring malformed API_KEY_QUOTAS entry");
+                continue;
+            };
+            let quota = match quota.parse::<KeyQuota>() {
+                Ok(quota) => quota,
+                Err(e) => {
+                    tracing::warn!("ignoring API_KEY_QUOTAS entry for {}: {}", id, e);
+                    continue;
+                }
+            };
+            let mut matched = false;
+            for key in keys.values_mut().filter(|k| k.id == id) {
+                key.quota = Some(quota);
+                matched = true;
+            }
+            if !matched {
+                tracing::warn!("API_KEY_QUOTAS references unknown key id {}", id);
+            }
+        }
+
+        let daily = std::env::var("API_KEY_DAILY_QUOTAS").unwrap_or_default();
+        for entry in daily.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let Some((id, cap)) = entry.split_once(':') else {
+                tracing::warn!("ignoring malformed API_KEY_DAILY_QUOTAS entry");
+                continue;
+            };
+            let Ok(cap) = cap.trim().parse::<u64>() else {
+                tracing::warn!(
+                    "ignoring API_KEY_DAILY_QUOTAS entry for {}: invalid cap",
+                    id
+                );
+                continue;
+            };
+            let mut matched = false;
+            for key in keys.values_mut().filter(|k| k.id == id) {
+                key.daily_quota = Some(cap);
+                matched = true;
+            }
+            if !matched {
+                tracing::warn!("API_KEY_DAILY_QUOTAS references unknown key id {}", id);
+            }
+        }
+
+        Self(Arc::new(keys))
+    }
+
+    /// Keys from `API_KEYS`-style entries, without quotas.
+    #[cfg(test)]
+    pub fn from_entries(raw: &str) -> Self {
+        Self(Arc::new(parse_keys(raw)))
+    }
+
+    pub fn authenticate(&self, headers: &HeaderMap) -> Option<&ApiKey> {
+        let token = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))?;
+        self.0.get(token.trim())
+    }
+
+    pub fn quotas(&self) -> impl Iterator<Item = (&str, KeyQuota)> {
+        self.0
+            .values()
+            .filter_map(|k| k.quota.map(|q| (k.id.as_str(), q)))
+    }
+}
+
+fn parse_keys(raw: &str) -> HashMap<String, ApiKey> {
+    let mut keys = HashMap::new();
+    for entry in raw.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let parts: Vec<&str> = entry.splitn(3, ':').collect();
+        if parts.len() != 3 || parts.iter().any(|p| p.is_empty()) {
+            tracing::warn!("ignoring malformed API_KEYS entry");
+            continue;
+        }
+        keys.insert(
+            parts[1].to_string(),
+            ApiKey {
+                id: parts[0].to_string(),
+                scopes: parts[2].split('+').map(str::to_string).collect(),
+                quota: None,
+                daily_quota: None,
+            },
+        );
+    }
+    keys
+}
+
+pub async fn require_scope(
+    State((keys, scope)): State<(ApiKeys, &'static str)>,
+    req: Request,
+    next: Next,
+) -> Response {
+    match keys.authenticate(req.headers()) {
+        Some(key) if key.has_scope(scope) => next.run(req).await,
+        Some(key) => {
+            tracing::warn!(key_id = %key.id, scope, "api key lacks required scope");
+            error_response(StatusCode::FORBIDDEN, "API key lacks required scope").into_response()
+        }
+        None => {
+            error_response(StatusCode::UNAUTHORIZED, "Missing or invalid API key").into_response()
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use sqlx::migrate::Migrator;
+use sqlx::postgres::PgConnectOptions;
+use std::str::FromStr;
+
+use crate::cors;
+use crate::db;
+use crate::search::SearchBackend;
+
+struct CheckResult {
+    name: &'static str,
+    outcome: Result<String, String>,
+}
+
+/// Validates configuration and dependencies without starting the server.
+/// Prints one line per check and returns whether all of them passed.
+pub async fn run() -> bool {
+    let results = [
+        CheckResult {
+            name: "cors",
+            outcome: cors::cors_from_env().map(|_| "origins ok".to_string()),
+        },
+        CheckResult {
+            name: "database",
+            outcome: match std::env::var("DATABASE_URL") {
+                Ok(url) => check_database(&url, &db::MIGRATOR).await,
+                Err(_) => Err("DATABASE_URL is not set".to_string()),
+            },
+        },
+        CheckResult {
+            name: "scrape database",
+            outcome: check_database(&db::scrape_database_url(), &db::SCRAPE_MIGRATOR).await,
+        },
+        CheckResult {
+            name: "search",
+            outcome: check_search().await,
+        },
+    ];
+
+    let mut ok = true;
+    for result in &results {
+        let (status, detail) = match &result.outcome {
+            Ok(detail) => ("PASS", detail),
+            Err(detail) => {
+                ok = false;
+                ("FAIL", detail)
+            }
+        };
+        println!("{:<5} {:<16} {}", status, result.name, detail);
+    }
+    ok
+}
+
+/// Applies pending migrations on both databases and returns whether both succeeded.
+pub async fn migrate_only() -> bool {
+    let mut ok = true;
+    match db::create_pool().await {
+        Ok(pool) => {
+            println!("database migrations applied");
+            pool.close().await;
+        }
+        Err(e) => {
+            eprintln!("database migrations failed: {e}");
+            ok = false;
+        }
+    }
+    match db::migrate_scrape(&db::scrape_database_url()).await {
+        Ok(()) => println!("scrape database migrations applied"),
+        Err(e) => {
+            eprintln!("scrape database migrations failed: {e}");
+            ok = false;
+        }
+    }
+    ok
+}
+
+async fn check_database(url: &str, migrator: &Migrator) -> Result<String, String> {
+    let opts = PgConnectOptions::from_str(url).map_err(|e| format!("invalid url: {e}"))?;
+    match db::pending_migrations(&opts, migrator).await {
+        Ok(0) => Ok("connected, migrations up to date".to_string()),
+        Ok(n) => Ok(format!("connected, {n} pending migration(s)")),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+async fn check_search() -> Result<String, String> {
+    match SearchBackend::from_env().map_err(|e| e.to_string())? {
+        SearchBackend::Memory(client) => Ok(format!("memory, {} documents", client.count())),
+        SearchBackend::Manticore(client) => {
+            client.ping().await.map_err(|e| e.to_string())?;
+            Ok(format!("manticore, index {}", client.index_name()))
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use serde::Deserialize;
+use std::sync::RwLock;
+use std::time::Duration;
+
+/// How often [`load`] re-reads the file, so a bump takes effect without a redeploy.
+pub const RELOAD_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Which desktop app versions are still supported, from `CLIENT_REQUIREMENTS_FILE`, e.g.
+/// `{"min_version": "1.2.0", "soft_min_version": "1.4.0", "latest_version": "1.6.0",
+/// "deprecations": ["..."]}`. Every field is optional; nothing is gated without the file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClientRequirements {
+    /// Older clients should stop using the API and ask the user to update.
+    pub min_version: Option<String>,
+    /// Older clients still work but are told they are deprecated. Falls back to
+    /// `min_version`.
+    pub soft_min_version: Option<String>,
+    pub latest_version: Option<String>,
+    #[serde(default)]
+    pub deprecations: Vec<String>,
+}
+
+static REQUIREMENTS: RwLock<Option<ClientRequirements>> = RwLock::new(None);
+
+/// `major.minor.patch`, the only shape telemetry accepts for `app_version`.
+fn parse_version(v: &str) -> Option<(u64, u64, u64)> {
+    let mut parts = v.trim().split('.').map(|p| p.parse::<u64>().ok());
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(Some(major)), Some(Some(minor)), Some(Some(patch)), None) => {
+            Some((major, minor, patch))
+        }
+        _ => None,
+    }
+}
+
+/// Reads `CLIENT_REQUIREMENTS_FILE` and swaps it in. On error the previous requirements
+/// stay active. Returns whether a file is configured.
+pub fn load() -> Result<bool, String> {
+    let Ok(path) = std::env::var("CLIENT_REQUIREMENTS_FILE") else {
+        return Ok(false);
+    };
+    let raw = std::fs::read_to_string(&path)
+        .map_err(|e| format!("failed to read client requirements file {path}: {e}"))?;
+    let requirements: ClientRequirements = serde_json::from_str(&raw)
+        .map_err(|e| format!("invalid client requirements file {path}: {e}"))?;
+    for version in [
+        &requirements.min_version,
+        &requirements.soft_min_version,
+        &requirements.latest_version,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        if parse_version(version).is_none() {
+            return Err(format!(
+                "invalid version {version:?} in {path}, expected major.minor.patch"
+            ));
+        }
+    }
+    *REQUIREMENTS
+        .write()
+        .expect("client requirements lock poisoned") = Some(requirements);
+    Ok(true)
+}
+
+pub fn current() -> ClientRequirements {
+    REQUIREMENTS
+        .read()
+        .expect("client requirements lock poisoned")
+        .clone()
+        .unwrap_or_default()
+}
+
+/// Whether `app_version` is below the soft minimum. Unparseable versions never are.
+pub fn is_deprecated(app_version: &str) -> bool {
+    let requirements = REQUIREMENTS
+        .read()
+        .expect("client requirements lock poisoned");
+    let Some(threshold) = requirements
+        .as_ref()
+        .and_then(|r| r.soft_min_version.as_ref().or(r.min_version.as_ref()))
+        .and_then(|v| parse_version(v))
+    else {
+        return false;
+    };
+    parse_version(app_version).is_some_and(|v| v < threshold)
+}
+
+/// The reload job; a broken edit is reported and the last good file keeps serving.
+pub async fn reload() -> anyhow::Result<()> {
+    load().map(|_| ()).map_err(anyhow::Error::msg)
+}
@@ -0,0 +1,127 @@
+use axum::http::{HeaderName, HeaderValue, Method, header};
+use reqwest::Url;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+const EXPOSED_HEADERS: [&str; 3] = ["retry-after", "x-ratelimit-after", "x-request-id"];
+
+/// Builds the CORS layer from `CORS_ALLOWED_ORIGINS` (comma-separated, `*` allows any origin).
+/// Falls back to the older `ALLOWED_ORIGINS` variable when unset.
+pub fn cors_from_env() -> Result<CorsLayer, String> {
+    let raw = std::env::var("CORS_ALLOWED_ORIGINS")
+        .or_else(|_| std::env::var("ALLOWED_ORIGINS"))
+        .unwrap_or_default();
+    cors_layer(&raw)
+}
+
+pub fn cors_layer(raw: &str) -> Result<CorsLayer, String> {
+    let mut origins = Vec::new();
+    let mut any = false;
+    for origin in raw.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        if origin == "*" {
+            any = true;
+            continue;
+        }
+        origins.push(parse_origin(origin)?);
+    }
+
+    let allow_origin = if any {
+        AllowOrigin::from(Any)
+    } else {
+        AllowOrigin::list(origins)
+    };
+
+    Ok(CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods([Method::GET, Method::HEAD, Method::POST])
+        .allow_headers([
+            header::CONTENT_TYPE,
+            header::AUTHORIZATION,
+            HeaderName::from_static("x-strict"),
+        ])
+        .expose_headers(EXPOSED_HEADERS.map(HeaderName::from_static)))
+}
+
+fn parse_origin(origin: &str) -> Result<HeaderValue, String> {
+    let url = Url::parse(origin).map_err(|e| format!("invalid CORS origin {origin:?}: {e}"))?;
+    if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none() {
+        return Err(format!(
+            "invalid CORS origin {origin:?}: expected http(s)://host"
+        ));
+    }
+    if url.path() != "/" || url.query().is_some() || url.fragment().is_some() {
+        return Err(format!(
+            "invalid CORS origin {origin:?}: must not include a path"
+        ));
+    }
+    origin
+        .trim_end_matches('/')
+        .parse()
+        .map_err(|_| format!("invalid CORS origin {origin:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, extract::Request, http::StatusCode, routing::get};
+    use tower::ServiceExt;
+
+    async fn preflight(allowed: &str, origin: &str) -> (StatusCode, Option<HeaderValue>) {
+        let app = Router::new()
+            .route("/", get(|| async { "ok" }))
+            .layer(cors_layer(allowed).unwrap());
+        let req = Request::options("/")
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(req).await.unwrap();
+        let allow = response
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .cloned();
+        (response.status(), allow)
+    }
+
+    #[tokio::test]
+    async fn allowed_origin_is_echoed() {
+        let (status, allow) = preflight(
+            "https://vleer.app, https://dash.vleer.app/",
+            "https://dash.vleer.app",
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(allow.unwrap(), "https://dash.vleer.app");
+    }
+
+    #[tokio::test]
+    async fn disallowed_origin_gets_no_allow_header() {
+        for origin in [
+            "https://evil.example",
+            "http://vleer.app",
+            "https://vleer.app.evil",
+        ] {
+            let (_, allow) = preflight("https://vleer.app", origin).await;
+            assert_eq!(allow, None, "{origin}");
+        }
+        let (_, allow) = preflight("", "https://vleer.app").await;
+        assert_eq!(allow, None);
+    }
+
+    #[tokio::test]
+    async fn wildcard_allows_any_origin() {
+        let (_, allow) = preflight("*", "https://anything.example").await;
+        assert_eq!(allow.unwrap(), "*");
+    }
+
+    #[test]
+    fn rejects_origins_with_paths_or_other_schemes() {
+        for raw in [
+            "https://vleer.app/app",
+            "ftp://vleer.app",
+            "vleer.app",
+            "https://",
+        ] {
+            assert!(cors_layer(raw).is_err(), "{raw}");
+        }
+    }
+}
@@ -0,0 +1,421 @@
+use axum::extract::Request;
+use axum::http::{self, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use futures::future::BoxFuture;
+use serde_json::json;
+use sqlx::PgPool;
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv6Addr};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use tower::{Layer, Service};
+
+use crate::api::error_response;
+use crate::auth::ApiKeys;
+use crate::db::quota::{self, HourlyCount};
+use crate::ip_allowlist;
+
+/// Counts are kept per hour; a client's quota covers the last 24 of them.
+const WINDOW_HOURS: i64 = 24;
+const DEFAULT_MAX_KEYS: usize = 100_000;
+
+/// Clients seen while a group already tracks `max_keys` share this counter, like the burst
+/// limiter's overflow bucket.
+const OVERFLOW_KEY: &str = "overflow";
+
+/// Per group, per client key (`key:<id>`, `ip:<addr>` or [`OVERFLOW_KEY`]), requests by hour
+/// since the epoch.
+type Counters = HashMap<String, HashMap<String, Counter>>;
+
+static COUNTERS: OnceLock<Mutex<Counters>> = OnceLock::new();
+
+fn counters() -> &'static Mutex<Counters> {
+    COUNTERS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn current_hour() -> i64 {
+    OffsetDateTime::now_utc().unix_timestamp().div_euclid(3600)
+}
+
+#[derive(Default)]
+struct Counter {
+    /// Requests by hour across every replica, as of the last load or persist, plus this
+    /// replica's requests since.
+    hours: BTreeMap<i64, u32>,
+    /// This replica's requests by hour that haven't been persisted yet.
+    unsaved: BTreeMap<i64, u32>,
+}
+
+impl Counter {
+    fn prune(&mut self, hour: i64) {
+        self.hours = self.hours.split_off(&(hour - WINDOW_HOURS + 1));
+        self.unsaved = self.unsaved.split_off(&(hour - WINDOW_HOURS + 1));
+    }
+
+    /// Takes a stored total, which doesn't include the requests not yet persisted.
+    fn merge(&mut self, hour: i64, saved: i32) {
+        let unsaved = self.unsaved.get(&hour).copied().unwrap_or(0);
+        self.hours
+            .insert(hour, (saved.max(0) as u32).saturating_add(unsaved));
+    }
+
+    fn total(&self) -> u64 {
+        self.hours.values().map(|&c| c as u64).sum()
+    }
+
+    /// The hour at which enough old requests have left the window to allow another one.
+    fn reset_hour(&self, cap: u64) -> i64 {
+        let mut total = self.total();
+        for (&hour, &count) in &self.hours {
+            total -= count as u64;
+            if total < cap {
+                return hour + WINDOW_HOURS;
+            }
+        }
+        current_hour() + 1
+    }
+}
+
+/// Caps each client at a number of requests per rolling 24 hours, on top of the burst limit
+/// from [`crate::rate_limit::rate_limit`]. The cap is `DAILY_QUOTA_<GROUP>` (e.g.
+/// `DAILY_QUOTA_GLOBAL`) or `default_cap`, with `0` turning the layer off; API keys with an
+/// `API_KEY_DAILY_QUOTAS` entry use that instead. Clients are keyed like the burst limiter:
+/// by API key id, otherwise by IP, with IPv6 by /64.
+pub fn daily_quota(group: &'static str, default_cap: u64, api_keys: &ApiKeys) -> DailyQuotaLayer {
+    let env = format!("DAILY_QUOTA_{}", group.to_ascii_uppercase());
+    let cap = std::env::var(&env)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(default_cap);
+    let max_keys = std::env::var("RATE_LIMIT_MAX_KEYS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(DEFAULT_MAX_KEYS);
+
+    DailyQuotaLayer {
+        state: Arc::new(DailyQuotaState {
+            group,
+            cap,
+            max_keys,
+            api_keys: api_keys.clone(),
+        }),
+    }
+}
+
+struct DailyQuotaState {
+    group: &'static str,
+    cap: u64,
+    max_keys: usize,
+    api_keys: ApiKeys,
+}
+
+impl DailyQuotaState {
+    /// Counts the request, or returns when the client may make another one.
+    fn check(&self, req: &Request) -> Result<(), OffsetDateTime> {
+        let (key, cap) = match self.api_keys.authenticate(req.headers()) {
+            Some(key) => (
+                format!("key:{}", key.id),
+                key.daily_quota.unwrap_or(self.cap),
+            ),
+            None => match ip_allowlist::trusted_client_ip(req) {
+                Some(ip) => (format!("ip:{}", client_network(ip)), self.cap),
+                // The burst limiter already rejects requests without a client address.
+                None => return Ok(()),
+            },
+        };
+        if cap == 0 {
+            return Ok(());
+        }
+
+        let hour = current_hour();
+        let mut counters = counters().lock().expect("daily quota lock poisoned");
+        let group = counters.entry(self.group.to_string()).or_default();
+        let key = if !group.contains_key(&key) && group.len() >= self.max_keys {
+            // Counted in a shared bucket, so flooding the store with new addresses can't buy
+            // uncapped requests.
+            metrics::counter!("daily_quota_overflow_total", "group" => self.group).increment(1);
+            OVERFLOW_KEY.to_string()
+        } else {
+            key
+        };
+        let counter = group.entry(key).or_default();
+        counter.prune(hour);
+        if counter.total() >= cap {
+            let reset = OffsetDateTime::from_unix_timestamp(counter.reset_hour(cap) * 3600)
+                .unwrap_or_else(|_| OffsetDateTime::now_utc());
+            return Err(reset);
+        }
+        *counter.hours.entry(hour).or_insert(0) += 1;
+        *counter.unsaved.entry(hour).or_insert(0) += 1;
+        Ok(())
+    }
+
+    fn reject(&self, reset: OffsetDateTime) -> Response {
+        metrics::counter!("daily_quota_rejections_total", "group" => self.group).increment(1);
+
+        let wait = (reset - OffsetDateTime::now_utc()).whole_seconds().max(1) as u64;
+        let mut headers = HeaderMap::new();
+        let wait_secs = HeaderValue::from(wait);
+        headers.insert("x-ratelimit-after", wait_secs.clone());
+        headers.insert(http::header::RETRY_AFTER, wait_secs);
+
+        let (status, mut body) = error_response(
+            StatusCode::TOO_MANY_REQUESTS,
+            "Daily request quota exceeded",
+        );
+        body["error"]["code"] = json!("daily_quota_exceeded");
+        body["error"]["resetAt"] = json!(reset.format(&Rfc3339).ok());
+        (status, headers, body).into_response()
+    }
+}
+
+/// IPv6 clients by their /64, so address rotation within one allocation shares a quota.
+fn client_network(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => {
+            let mut segments = v6.segments();
+            segments[4..].fill(0);
+            IpAddr::V6(Ipv6Addr::from(segments))
+        }
+        v4 => v4,
+    }
+}
+
+/// Interval of the persist job, from `DAILY_QUOTA_PERSIST_MINUTES` (default 5).
+pub fn persist_interval() -> Duration {
+    Duration::from_secs(
+        60 * std::env::var("DAILY_QUOTA_PERSIST_MINUTES")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .filter(|v| *v > 0)
+            .unwrap_or(5),
+    )
+}
+
+/// Restores the counters saved by [`persist`], so a restart doesn't hand every client a
+/// fresh quota. Returns how many clients were restored.
+pub async fn load(pool: &PgPool) -> Result<usize, sqlx::Error> {
+    let hour = current_hour();
+    let rows = quota::load_daily_counts(pool, hour - WINDOW_HOURS).await?;
+    let mut counters = counters().lock().expect("daily quota lock poisoned");
+    let mut restored = 0;
+    for row in rows {
+        counters
+            .entry(row.group_name)
+            .or_default()
+            .entry(row.client_key)
+            .or_insert_with(|| {
+                restored += 1;
+                Counter::default()
+            })
+            .merge(row.hour, row.count);
+    }
+    Ok(restored)
+}
+
+/// Adds the requests counted since the last run to the stored totals, which replicas
+/// sharing the database all add to, and takes back the merged totals of those clients.
+/// Clients only seen by other replicas are picked up at the next [`load`]. Also drops
+/// clients whose window is empty and deletes rows that have left the window.
+pub async fn persist(pool: PgPool) -> anyhow::Result<()> {
+    let hour = current_hour();
+    let mut changed = Vec::new();
+    let mut tracked = 0;
+    {
+        let mut counters = counters().lock().expect("daily quota lock poisoned");
+        for (group, clients) in counters.iter_mut() {
+            clients.retain(|_, counter| {
+                counter.prune(hour);
+                !counter.hours.is_empty()
+            });
+            clients.shrink_to_fit();
+            tracked += clients.len();
+            for (key, counter) in clients.iter_mut() {
+                changed.extend(std::mem::take(&mut counter.unsaved).into_iter().map(
+                    |(h, count)| HourlyCount {
+                        group_name: group.clone(),
+                        client_key: key.clone(),
+                        hour: h,
+                        count: count.min(i32::MAX as u32) as i32,
+                    },
+                ));
+            }
+        }
+    }
+    metrics::gauge!("daily_quota_tracked_keys").set(tracked as f64);
+
+    let saved = quota::save_daily_counts(&pool, &changed).await;
+    take_back(saved, changed)?;
+    quota::delete_daily_counts_before(&pool, hour - WINDOW_HOURS).await?;
+    Ok(())
+}
+
+/// Merges the totals [`persist`] saved, or, if nothing was saved, keeps `changed` for the
+/// next run.
+fn take_back(
+    saved: Result<Vec<HourlyCount>, sqlx::Error>,
+    changed: Vec<HourlyCount>,
+) -> Result<(), sqlx::Error> {
+    let mut counters = counters().lock().expect("daily quota lock poisoned");
+    let saved = match saved {
+        Ok(saved) => saved,
+        Err(e) => {
+            for row in changed {
+                let counter = counters
+                    .entry(row.group_name)
+                    .or_default()
+                    .entry(row.client_key)
+                    .or_default();
+                *counter.unsaved.entry(row.hour).or_insert(0) += row.count as u32;
+            }
+            return Err(e);
+        }
+    };
+    for row in saved {
+        if let Some(counter) = counters
+            .get_mut(&row.group_name)
+            .and_then(|clients| clients.get_mut(&row.client_key))
+        {
+            counter.merge(row.hour, row.count);
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone)]
+pub struct DailyQuotaLayer {
+    state: Arc<DailyQuotaState>,
+}
+
+impl<S> Layer<S> for DailyQuotaLayer {
+    type Service = DailyQuotaService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        DailyQuotaService {
+            inner,
+            state: self.state.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct DailyQuotaService<S> {
+    inner: S,
+    state: Arc<DailyQuotaState>,
+}
+
+impl<S> Service<Request> for DailyQuotaService<S>
+where
+    S: Service<Request, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        match self.state.check(&req) {
+            Ok(()) => Box::pin(self.inner.call(req)),
+            Err(reset) => {
+                let response = self.state.reject(reset);
+                Box::pin(async move { Ok(response) })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::extract::ConnectInfo;
+    use std::net::SocketAddr;
+
+    fn request_from(addr: &str) -> Request {
+        let mut req = Request::new(Body::empty());
+        let addr: SocketAddr = addr.parse().unwrap();
+        req.extensions_mut().insert(ConnectInfo(addr));
+        req
+    }
+
+    fn state(group: &'static str, cap: u64, max_keys: usize) -> DailyQuotaState {
+        DailyQuotaState {
+            group,
+            cap,
+            max_keys,
+            api_keys: ApiKeys::default(),
+        }
+    }
+
+    #[test]
+    fn counts_each_client_up_to_the_cap() {
+        let state = state("test_cap", 2, 10);
+        assert!(state.check(&request_from("192.0.2.1:1000")).is_ok());
+        assert!(state.check(&request_from("192.0.2.1:1001")).is_ok());
+        assert!(state.check(&request_from("192.0.2.1:1002")).is_err());
+        assert!(state.check(&request_from("192.0.2.2:1000")).is_ok());
+    }
+
+    #[test]
+    fn overflow_clients_share_a_counted_bucket() {
+        let state = state("test_overflow", 2, 1);
+        assert!(state.check(&request_from("192.0.2.1:1000")).is_ok());
+        // The store is full; everyone else lands in the shared bucket.
+        assert!(state.check(&request_from("192.0.2.2:1000")).is_ok());
+        assert!(state.check(&request_from("192.0.2.3:1000")).is_ok());
+        assert!(state.check(&request_from("192.0.2.4:1000")).is_err());
+
+        let counters = counters().lock().unwrap();
+        let group = &counters["test_overflow"];
+        assert_eq!(group.len(), 2);
+        assert_eq!(group[OVERFLOW_KEY].total(), 2);
+    }
+
+    #[tokio::test]
+    async fn replicas_add_up_their_counts() {
+        let Some(pool) = crate::test_support::telemetry_db().await else {
+            return;
+        };
+        let hour = current_hour();
+        let state = state("test_replicas", 6, 10);
+        let client = || request_from("192.0.2.1:1000");
+        let stored = || async {
+            sqlx::query_scalar::<_, i32>(
+                "SELECT count FROM daily_quota_counters WHERE group_name = 'test_replicas'",
+            )
+            .fetch_one(&pool)
+            .await
+            .expect("stored count")
+        };
+
+        // Another replica already saved three requests from the same client this hour.
+        sqlx::query(
+            "INSERT INTO daily_quota_counters (group_name, client_key, hour, count)
+             VALUES ('test_replicas', 'ip:192.0.2.1', to_timestamp($1 * 3600), 3)",
+        )
+        .bind(hour)
+        .execute(&pool)
+        .await
+        .expect("insert other replica's count");
+        assert!(state.check(&client()).is_ok());
+        assert!(state.check(&client()).is_ok());
+
+        persist(pool.clone()).await.expect("persist");
+        assert_eq!(stored().await, 5);
+        // Persisting again adds nothing new.
+        persist(pool.clone()).await.expect("persist");
+        assert_eq!(stored().await, 5);
+
+        // The merged total counts against the cap here too.
+        assert!(state.check(&client()).is_ok());
+        assert!(state.check(&client()).is_err());
+    }
+}
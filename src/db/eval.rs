@@ -0,0 +1,34 @@
+use serde_json::Value;
+use sqlx::PgPool;
+
+/// One relevance evaluation, as stored in `eval_runs`.
+pub struct EvalRun<'a> {
+    pub label: Option<&'a str>,
+    pub backend: &'a str,
+    pub pairs: i32,
+    pub evaluated: i32,
+    pub mrr: f64,
+    pub hit_at_5: f64,
+    pub results: &'a Value,
+}
+
+/// Stores a run and returns its id.
+#[tracing::instrument(skip_all)]
+pub async fn insert_run(pool: &PgPool, run: &EvalRun<'_>) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        INSERT INTO eval_runs (label, backend, pairs, evaluated, mrr, hit_at_5, results)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING id
+        "#,
+    )
+    .bind(run.label)
+    .bind(run.backend)
+    .bind(run.pairs)
+    .bind(run.evaluated)
+    .bind(run.mrr)
+    .bind(run.hit_at_5)
+    .bind(sqlx::types::Json(run.results))
+    .fetch_one(pool)
+    .await
+}
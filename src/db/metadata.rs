@@ -1,6 +1,150 @@
+use futures::{Stream, TryStreamExt};
+use sqlx::types::Json;
 use sqlx::{PgPool, Row};
+use time::{Date, OffsetDateTime};
 
-use crate::models::metadata::{Album, Artist, Song};
+use crate::models::metadata::{Album, Artist, ImageSource, Song, is_compilation};
+
+/// Artwork of an artist's most recently released album, for artists without an image.
+/// Meant for `LEFT JOIN LATERAL`, with the artist aliased as `a`.
+const LATEST_ALBUM_IMAGE: &str = "SELECT al.image FROM artist_albums aa
+     JOIN albums al ON al.id = aa.album_id
+     WHERE aa.artist_id = a.id AND COALESCE(al.image, '') <> ''
+     ORDER BY al.release_date DESC NULLS LAST, al.id
+     LIMIT 1";
+
+#[derive(sqlx::FromRow)]
+struct SongRow {
+    id: String,
+    name: String,
+    image: Option<String>,
+    duration: Option<i64>,
+    disc_number: Option<i64>,
+    track_number: Option<i64>,
+    isrc: Option<String>,
+    date: Option<String>,
+    created_at: Option<OffsetDateTime>,
+    updated_at: Option<OffsetDateTime>,
+    deleted_at: Option<OffsetDateTime>,
+    popularity: Option<f64>,
+    explicit: Option<bool>,
+    apple_music_id: Option<String>,
+    artists_json: Option<Json<Vec<Artist>>>,
+    albums_json: Option<Json<Vec<Album>>>,
+    genres: Vec<String>,
+}
+
+impl From<SongRow> for Song {
+    fn from(r: SongRow) -> Self {
+        let album: Vec<Album> = r
+            .albums_json
+            .map(|j| j.0)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|mut al| {
+                al.is_compilation = is_compilation(&al.artist);
+                al
+            })
+            .collect();
+        Song {
+            id: r.id,
+            name: r.name,
+            artist: r.artists_json.map(|j| j.0).unwrap_or_default(),
+            primary_album_id: album.first().map(|al| al.id.clone()),
+            album,
+            genres: r.genres,
+            image: r.image.unwrap_or_default(),
+            disc_number: r.disc_number.unwrap_or(1) as i32,
+            track_number: r.track_number.unwrap_or(1) as i32,
+            duration: r.duration.unwrap_or(0) as i32,
+            isrc: r.isrc.unwrap_or_default(),
+            date: r.date.unwrap_or_default(),
+            created_at: r.created_at,
+            updated_at: r.updated_at,
+            deleted_at: r.deleted_at,
+            popularity: r.popularity,
+            explicit: r.explicit,
+            apple_music_id: r.apple_music_id,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct AlbumRow {
+    id: String,
+    name: String,
+    image: Option<String>,
+    date: Option<String>,
+    track_count: Option<i64>,
+    available_track_count: i64,
+    upc: Option<String>,
+    label: Option<String>,
+    created_at: Option<OffsetDateTime>,
+    updated_at: Option<OffsetDateTime>,
+    apple_music_id: Option<String>,
+    artists_json: Option<Json<Vec<Artist>>>,
+    genres: Vec<String>,
+}
+
+impl From<AlbumRow> for Album {
+    fn from(r: AlbumRow) -> Self {
+        let artist = r.artists_json.map(|j| j.0).unwrap_or_default();
+        Album {
+            id: r.id,
+            name: r.name,
+            is_compilation: is_compilation(&artist),
+            artist,
+            genres: r.genres,
+            image: r.image.unwrap_or_default(),
+            date: r.date.unwrap_or_default(),
+            track_count: r.track_count.unwrap_or(0) as i32,
+            available_track_count: r.available_track_count as i32,
+            upc: r.upc.unwrap_or_default(),
+            label: r.label,
+            created_at: r.created_at,
+            updated_at: r.updated_at,
+            apple_music_id: r.apple_music_id,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct ArtistRow {
+    id: String,
+    name: String,
+    image: Option<String>,
+    fallback_image: Option<String>,
+    genres: Vec<String>,
+    created_at: Option<OffsetDateTime>,
+    updated_at: Option<OffsetDateTime>,
+    album_count: i64,
+    song_count: i64,
+    apple_music_id: Option<String>,
+}
+
+impl From<ArtistRow> for Artist {
+    fn from(r: ArtistRow) -> Self {
+        let own = r.image.filter(|s| !s.is_empty());
+        let fallback = r.fallback_image.filter(|s| !s.is_empty());
+        let (image, image_source) = match (own, fallback) {
+            (Some(image), _) => (image, ImageSource::Artist),
+            (None, Some(image)) => (image, ImageSource::AlbumFallback),
+            (None, None) => (String::new(), ImageSource::None),
+        };
+        Artist {
+            id: r.id,
+            name: r.name,
+            image,
+            genres: r.genres,
+            created_at: r.created_at,
+            updated_at: r.updated_at,
+            album_count: r.album_count,
+            song_count: r.song_count,
+            image_source: Some(image_source),
+            apple_music_id: r.apple_music_id,
+        }
+    }
+}
 
 pub async fn stats(pool: &PgPool) -> Result<(i64, i64, i64), sqlx::Error> {
     let rows = sqlx::query(
@@ -26,15 +170,99 @@ pub async fn stats(pool: &PgPool) -> Result<(i64, i64, i64), sqlx::Error> {
     Ok((songs, albums, artists))
 }
 
+#[derive(Debug, serde::Serialize, sqlx::FromRow)]
+pub struct GenreCount {
+    pub name: String,
+    pub songs: i64,
+    pub albums: i64,
+}
+
+#[derive(Debug, serde::Serialize, sqlx::FromRow)]
+pub struct LabelCount {
+    pub name: String,
+    pub albums: i64,
+}
+
+/// Record labels with their album counts, most used first. Names differing only in case or
+/// surrounding whitespace are merged; `prefix` must already be lowercased.
+pub async fn label_counts(
+    pool: &PgPool,
+    prefix: Option<&str>,
+    limit: i64,
+) -> Result<Vec<LabelCount>, sqlx::Error> {
+    sqlx::query_as::<_, LabelCount>(
+        r#"SELECT MIN(BTRIM(label)) AS name, COUNT(*) AS albums
+           FROM albums
+           WHERE BTRIM(label) <> ''
+             AND ($1::TEXT IS NULL OR starts_with(LOWER(BTRIM(label)), $1))
+           GROUP BY LOWER(BTRIM(label))
+           ORDER BY albums DESC, name
+           LIMIT $2"#,
+    )
+    .bind(prefix)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+/// Genres that appear on at least one available song or album, most used first.
+/// Names differing only in case are merged.
+pub async fn genre_counts(pool: &PgPool) -> Result<Vec<GenreCount>, sqlx::Error> {
+    sqlx::query_as::<_, GenreCount>(
+        r#"WITH song_counts AS (
+                SELECT LOWER(g.name) AS key, COUNT(DISTINCT sg.song_id) AS songs
+                FROM song_genres sg
+                JOIN genres g ON sg.genre_id = g.id
+                JOIN songs s ON sg.song_id = s.id
+                WHERE s.deleted_at IS NULL
+                GROUP BY LOWER(g.name)
+            ),
+            album_counts AS (
+                SELECT LOWER(g.name) AS key, COUNT(DISTINCT alg.album_id) AS albums
+                FROM album_genres alg
+                JOIN genres g ON alg.genre_id = g.id
+                GROUP BY LOWER(g.name)
+            ),
+            names AS (
+                SELECT LOWER(name) AS key, MIN(name) AS name
+                FROM genres
+                GROUP BY LOWER(name)
+            )
+           SELECT n.name,
+                  COALESCE(sc.songs, 0) AS songs,
+                  COALESCE(ac.albums, 0) AS albums
+           FROM names n
+           LEFT JOIN song_counts sc ON sc.key = n.key
+           LEFT JOIN album_counts ac ON ac.key = n.key
+           WHERE sc.songs IS NOT NULL OR ac.albums IS NOT NULL
+           ORDER BY COALESCE(sc.songs, 0) + COALESCE(ac.albums, 0) DESC, n.name"#,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn row_count(pool: &PgPool, item_type: &str) -> Result<i64, sqlx::Error> {
+    let sql = match item_type {
+        "song" => "SELECT COUNT(*) FROM songs WHERE deleted_at IS NULL",
+        "album" => "SELECT COUNT(*) FROM albums",
+        _ => "SELECT COUNT(*) FROM artists",
+    };
+    sqlx::query_scalar(sql).fetch_one(pool).await
+}
+
+/// Includes unavailable songs; callers filter on `Song::deleted_at` after hydration.
+/// `isrcs` must already be normalized with `normalize_isrc`.
 pub async fn song_ids_by_isrc(pool: &PgPool, isrcs: &[String]) -> Result<Vec<String>, sqlx::Error> {
     if isrcs.is_empty() {
         return Ok(Vec::new());
     }
-    let upper: Vec<String> = isrcs.iter().map(|s| s.to_uppercase()).collect();
-    let rows = sqlx::query("SELECT id FROM songs WHERE UPPER(isrc) = ANY($1) ORDER BY id")
-        .bind(&upper)
-        .fetch_all(pool)
-        .await?;
+    // The scrape data mixes dashed and undashed ISRCs; callers pass normalized values.
+    let rows = sqlx::query(
+        "SELECT id FROM songs WHERE REPLACE(UPPER(isrc), '-', '') = ANY($1) ORDER BY id",
+    )
+    .bind(isrcs)
+    .fetch_all(pool)
+    .await?;
     Ok(rows.into_iter().map(|r| r.get::<String, _>("id")).collect())
 }
 
@@ -42,15 +270,16 @@ pub async fn album_ids_by_upc(pool: &PgPool, upcs: &[String]) -> Result<Vec<Stri
     if upcs.is_empty() {
         return Ok(Vec::new());
     }
-    let rows = sqlx::query("SELECT id FROM albums WHERE upc = ANY($1) ORDER BY id")
-        .bind(upcs)
-        .fetch_all(pool)
-        .await?;
+    let rows =
+        sqlx::query("SELECT id FROM albums WHERE REPLACE(upc, '-', '') = ANY($1) ORDER BY id")
+            .bind(upcs)
+            .fetch_all(pool)
+            .await?;
     Ok(rows.into_iter().map(|r| r.get::<String, _>("id")).collect())
 }
 
 pub async fn get_song_by_id(pool: &PgPool, id: &str) -> Result<Option<Song>, sqlx::Error> {
-    let row = sqlx::query(
+    let row = sqlx::query_as::<_, SongRow>(
         r#"WITH song_genres_agg AS (
                 SELECT
                     sg.song_id,
@@ -77,9 +306,9 @@ pub async fn get_song_by_id(pool: &PgPool, id: &str) -> Result<Option<Song>, sql
                     json_agg(json_build_object(
                         'id', a.id,
                         'name', a.name,
-                        'image', a.image,
+                        'image', COALESCE(a.image, ''),
                         'genres', COALESCE(to_json(aga.genres), '[]'::json)
-                    ) ORDER BY a.name) AS artists_json
+                    ) ORDER BY sa.position NULLS LAST, sa.inserted_seq NULLS LAST, sa.artist_id) AS artists_json
                 FROM song_artists sa
                 JOIN artists a ON sa.artist_id = a.id
                 LEFT JOIN artist_genres_agg aga ON aga.artist_id = a.id
@@ -106,9 +335,9 @@ pub async fn get_song_by_id(pool: &PgPool, id: &str) -> Result<Option<Song>, sql
                     json_agg(json_build_object(
                         'id', a.id,
                         'name', a.name,
-                        'image', a.image,
+                        'image', COALESCE(a.image, ''),
                         'genres', COALESCE(to_json(aaga.genres), '[]'::json)
-                    ) ORDER BY a.name) AS artists_json
+                    ) ORDER BY aa.position NULLS LAST, aa.inserted_seq NULLS LAST, aa.artist_id) AS artists_json
                 FROM artist_albums aa
                 JOIN artists a ON aa.artist_id = a.id
                 LEFT JOIN album_artist_genres_agg aaga ON aaga.artist_id = a.id
@@ -136,12 +365,17 @@ pub async fn get_song_by_id(pool: &PgPool, id: &str) -> Result<Option<Song>, sql
                         'name', al.name,
                         'artist', COALESCE(ala.artists_json, '[]'::json),
                         'genres', COALESCE(to_json(alga.genres), '[]'::json),
-                        'image', al.image,
-                        'date', al.date,
-                        'track_count', al.track_count,
-                        'upc', al.upc,
-                        'label', al.label
-                    ) ORDER BY al.name) AS albums_json
+                        'image', COALESCE(al.image, ''),
+                        'date', COALESCE(al.date, ''),
+                        'track_count', COALESCE(al.track_count, 0),
+                        'available_track_count', (
+                            SELECT COUNT(*) FROM song_albums tsal
+                            JOIN songs ts ON ts.id = tsal.song_id
+                            WHERE tsal.album_id = al.id AND ts.deleted_at IS NULL
+                        ),
+                        'upc', COALESCE(al.upc, ''),
+                        'label', NULLIF(BTRIM(al.label), '')
+                    ) ORDER BY al.release_date NULLS LAST, al.id) AS albums_json
                 FROM song_albums sal
                 JOIN albums al ON sal.album_id = al.id
                 LEFT JOIN album_artists_agg ala ON ala.album_id = al.id
@@ -151,6 +385,8 @@ pub async fn get_song_by_id(pool: &PgPool, id: &str) -> Result<Option<Song>, sql
             )
            SELECT s.id, s.name, s.image, s.duration,
                   s.disc_number, s.track_number, s.isrc, s.date,
+                  s.created_at, s.updated_at, s.deleted_at,
+                  sp.score AS popularity, s.explicit, s.apple_music_id,
                   artist_agg.artists_json,
                   album_agg.albums_json,
                   COALESCE(song_genres_agg.genres, '{}') AS genres
@@ -158,6 +394,7 @@ pub async fn get_song_by_id(pool: &PgPool, id: &str) -> Result<Option<Song>, sql
            LEFT JOIN artist_agg ON artist_agg.song_id = s.id
            LEFT JOIN album_agg ON album_agg.song_id = s.id
            LEFT JOIN song_genres_agg ON song_genres_agg.song_id = s.id
+           LEFT JOIN song_popularity sp ON sp.song_id = s.id
            WHERE s.id = $1
         "#,
     )
@@ -165,62 +402,127 @@ pub async fn get_song_by_id(pool: &PgPool, id: &str) -> Result<Option<Song>, sql
     .fetch_optional(pool)
     .await?;
 
-    let Some(r) = row else { return Ok(None) };
+    Ok(row.map(Song::from))
+}
 
-    let artists_json: Option<serde_json::Value> = r.get("artists_json");
-    let albums_json: Option<serde_json::Value> = r.get("albums_json");
-    let artists: Vec<Artist> = match artists_json {
-        Some(v) => serde_json::from_value(v).unwrap_or_default(),
-        None => vec![],
-    };
-    let albums: Vec<Album> = match albums_json {
-        Some(v) => serde_json::from_value(v).unwrap_or_default(),
-        None => vec![],
-    };
+/// Available songs on an album in tracklist order.
+pub async fn album_track_ids(
+    pool: &PgPool,
+    album_id: &str,
+    limit: i64,
+) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"SELECT s.id
+           FROM song_albums sal
+           JOIN songs s ON s.id = sal.song_id
+           WHERE sal.album_id = $1 AND s.deleted_at IS NULL
+           ORDER BY s.disc_number NULLS LAST, s.track_number NULLS LAST, s.name, s.id
+           LIMIT $2"#,
+    )
+    .bind(album_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
 
-    if artists.is_empty() || albums.is_empty() {
-        return Ok(None);
-    }
+pub async fn song_album_ids(pool: &PgPool, song_id: &str) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"SELECT al.id
+           FROM song_albums sal
+           JOIN albums al ON al.id = sal.album_id
+           WHERE sal.song_id = $1
+           ORDER BY al.release_date NULLS LAST, al.id"#,
+    )
+    .bind(song_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// The artist's most recent available songs. The scrape database has no play data, so
+/// release date is the best popularity proxy available here.
+pub async fn artist_top_song_ids(
+    pool: &PgPool,
+    artist_id: &str,
+    limit: i64,
+) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"SELECT s.id
+           FROM song_artists sa
+           JOIN songs s ON s.id = sa.song_id
+           WHERE sa.artist_id = $1 AND s.deleted_at IS NULL
+           ORDER BY s.release_date DESC NULLS LAST, s.name, s.id
+           LIMIT $2"#,
+    )
+    .bind(artist_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
 
-    Ok(Some(Song {
-        id: r.get("id"),
-        name: r.get("name"),
-        artist: artists,
-        album: albums,
-        genres: r.get::<Vec<String>, _>("genres"),
-        image: r.get("image"),
-        disc_number: r.get::<i64, _>("disc_number") as i32,
-        track_number: r.get::<i64, _>("track_number") as i32,
-        duration: r.get::<i64, _>("duration") as i32,
-        isrc: r.get("isrc"),
-        date: r.get("date"),
-    }))
+/// A stable pseudo-random selection of complete, available entities for `seed`.
+pub async fn discover_ids(
+    pool: &PgPool,
+    item_type: &str,
+    seed: &str,
+    limit: i64,
+) -> Result<Vec<String>, sqlx::Error> {
+    let sql = match item_type {
+        "song" => {
+            r#"SELECT s.id FROM songs s
+               WHERE s.deleted_at IS NULL
+                 AND EXISTS (SELECT 1 FROM song_artists sa WHERE sa.song_id = s.id)
+                 AND EXISTS (SELECT 1 FROM song_albums sal WHERE sal.song_id = s.id)
+               ORDER BY md5(s.id || $1)
+               LIMIT $2"#
+        }
+        _ => {
+            r#"SELECT al.id FROM albums al
+               WHERE EXISTS (SELECT 1 FROM artist_albums aa WHERE aa.album_id = al.id)
+               ORDER BY md5(al.id || $1)
+               LIMIT $2"#
+        }
+    };
+    sqlx::query_scalar(sql)
+        .bind(seed)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
 }
 
 pub async fn get_artist_by_id(pool: &PgPool, id: &str) -> Result<Option<Artist>, sqlx::Error> {
-    let row = sqlx::query(
-        r#"SELECT a.id, a.name, a.image,
-                  COALESCE(array_agg(DISTINCT g.name) FILTER (WHERE g.name IS NOT NULL), '{}') AS genres
+    let sql = format!(
+        r#"SELECT a.id, a.name, a.image, fallback.image AS fallback_image,
+                  a.created_at, a.updated_at, a.apple_music_id,
+                  COALESCE(array_agg(DISTINCT g.name) FILTER (WHERE g.name IS NOT NULL), '{{}}') AS genres,
+                  counts.album_count, counts.song_count
            FROM artists a
            LEFT JOIN artist_genres ag ON ag.artist_id = a.id
            LEFT JOIN genres g ON g.id = ag.genre_id
+           LEFT JOIN LATERAL ({LATEST_ALBUM_IMAGE}) fallback ON COALESCE(a.image, '') = ''
+           CROSS JOIN LATERAL (
+               SELECT
+                   (SELECT COUNT(*) FROM artist_albums aa WHERE aa.artist_id = a.id) AS album_count,
+                   (
+                       SELECT COUNT(*) FROM song_artists sa
+                       JOIN songs s ON s.id = sa.song_id
+                       WHERE sa.artist_id = a.id AND s.deleted_at IS NULL
+                   ) AS song_count
+           ) counts
            WHERE a.id = $1
-           GROUP BY a.id, a.name, a.image"#,
-    )
-    .bind(id)
-    .fetch_optional(pool)
-    .await?;
+           GROUP BY a.id, a.name, a.image, fallback.image, a.created_at, a.updated_at,
+                    a.apple_music_id,
+                    counts.album_count, counts.song_count"#
+    );
+    let row = sqlx::query_as::<_, ArtistRow>(sqlx::AssertSqlSafe(sql))
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
 
-    Ok(row.map(|r| Artist {
-        id: r.get("id"),
-        name: r.get("name"),
-        image: r.get("image"),
-        genres: r.get::<Vec<String>, _>("genres"),
-    }))
+    Ok(row.map(Artist::from))
 }
 
 pub async fn get_album_by_id(pool: &PgPool, id: &str) -> Result<Option<Album>, sqlx::Error> {
-    let row = sqlx::query(
+    let row = sqlx::query_as::<_, AlbumRow>(
         r#"WITH artist_genres_agg AS (
                 SELECT
                     ag.artist_id,
@@ -231,51 +533,901 @@ pub async fn get_album_by_id(pool: &PgPool, id: &str) -> Result<Option<Album>, s
                     SELECT aa.artist_id FROM artist_albums aa WHERE aa.album_id = $1
                 )
                 GROUP BY ag.artist_id
+            ),
+            artist_agg AS (
+                SELECT
+                    aa.album_id,
+                    json_agg(json_build_object(
+                        'id', a.id,
+                        'name', a.name,
+                        'image', COALESCE(a.image, ''),
+                        'genres', COALESCE(to_json(aga.genres), '[]'::json)
+                    ) ORDER BY aa.position NULLS LAST, aa.inserted_seq NULLS LAST, aa.artist_id) AS artists_json
+                FROM artist_albums aa
+                JOIN artists a ON aa.artist_id = a.id
+                LEFT JOIN artist_genres_agg aga ON aga.artist_id = a.id
+                WHERE aa.album_id = $1
+                GROUP BY aa.album_id
+            ),
+            album_genres_agg AS (
+                SELECT
+                    alg.album_id,
+                    array_agg(g.name ORDER BY g.name) AS genres
+                FROM album_genres alg
+                JOIN genres g ON alg.genre_id = g.id
+                WHERE alg.album_id = $1
+                GROUP BY alg.album_id
             )
            SELECT al.id, al.name, al.image, al.date,
-                  al.track_count, al.upc, al.label,
-                  json_agg(json_build_object(
-                      'id', a.id,
-                      'name', a.name,
-                      'image', a.image,
-                      'genres', COALESCE(to_json(aga.genres), '[]'::json)
-                  ) ORDER BY a.name) as artists_json,
-                  COALESCE(array_agg(DISTINCT g2.name) FILTER (WHERE g2.name IS NOT NULL), '{}') AS genres
+                  al.track_count, al.upc, NULLIF(BTRIM(al.label), '') AS label,
+                  (
+                      SELECT COUNT(*) FROM song_albums sal
+                      JOIN songs s ON s.id = sal.song_id
+                      WHERE sal.album_id = al.id AND s.deleted_at IS NULL
+                  ) AS available_track_count,
+                  al.created_at, al.updated_at, al.apple_music_id,
+                  artist_agg.artists_json,
+                  COALESCE(album_genres_agg.genres, '{}') AS genres
            FROM albums al
-           LEFT JOIN artist_albums aa ON al.id = aa.album_id
-           LEFT JOIN artists a ON aa.artist_id = a.id
-           LEFT JOIN artist_genres_agg aga ON aga.artist_id = a.id
-           LEFT JOIN album_genres alg ON alg.album_id = al.id
-           LEFT JOIN genres g2 ON g2.id = alg.genre_id
-           WHERE al.id = $1
-           GROUP BY al.id, al.name, al.image, al.date,
-                    al.track_count, al.upc, al.label"#,
+           LEFT JOIN artist_agg ON artist_agg.album_id = al.id
+           LEFT JOIN album_genres_agg ON album_genres_agg.album_id = al.id
+           WHERE al.id = $1"#,
     )
     .bind(id)
     .fetch_optional(pool)
     .await?;
 
-    let Some(r) = row else { return Ok(None) };
+    Ok(row.map(Album::from))
+}
+
+/// Ids released between `from` and `to` (inclusive), ordered by release date, name and id,
+/// starting after the `after_id` row. Rows whose date doesn't parse are never returned.
+pub async fn ids_by_release_date(
+    pool: &PgPool,
+    item_type: &str,
+    from: Date,
+    to: Date,
+    after_id: Option<&str>,
+    limit: i64,
+) -> Result<Vec<String>, sqlx::Error> {
+    let (table, available) = match item_type {
+        "song" => ("songs", "t.deleted_at IS NULL"),
+        _ => ("albums", "TRUE"),
+    };
+    let sql = format!(
+        "SELECT t.id FROM {table} t
+         WHERE {available}
+           AND t.release_date BETWEEN $1 AND $2
+           AND ($3::text IS NULL OR (t.release_date, t.name, t.id) > (
+               SELECT c.release_date, c.name, c.id FROM {table} c WHERE c.id = $3
+           ))
+         ORDER BY t.release_date, t.name, t.id
+         LIMIT $4"
+    );
+    sqlx::query_scalar(sqlx::AssertSqlSafe(sql))
+        .bind(from)
+        .bind(to)
+        .bind(after_id)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
+
+/// Artists whose lower-cased name starts with `letter` (`a`–`z`), or with anything else
+/// for `#`, in (lower(name), id) order. Returns each id with its lower-cased name; `after`
+/// is that pair for the last artist already listed, so paging doesn't depend on it still
+/// existing.
+pub async fn artist_ids_by_letter(
+    pool: &PgPool,
+    letter: char,
+    after: Option<(&str, &str)>,
+    limit: i64,
+) -> Result<Vec<(String, String)>, sqlx::Error> {
+    let filter = if letter == '#' {
+        r#"lower(a.name) COLLATE "C" !~ '^[a-z]'"#.to_string()
+    } else {
+        format!(r#"lower(a.name) COLLATE "C" LIKE '{letter}%'"#)
+    };
+    let sql = format!(
+        r#"SELECT a.id, lower(a.name) FROM artists a
+           WHERE {filter}
+             AND ($1::text IS NULL OR (lower(a.name) COLLATE "C", a.id) > ($1 COLLATE "C", $2))
+           ORDER BY lower(a.name) COLLATE "C", a.id
+           LIMIT $3"#
+    );
+    sqlx::query_as(sqlx::AssertSqlSafe(sql))
+        .bind(after.map(|(name, _)| name))
+        .bind(after.map(|(_, id)| id))
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
 
-    let artists_json: Option<serde_json::Value> = r.get("artists_json");
-    let artists: Vec<Artist> = match artists_json {
-        Some(v) => serde_json::from_value(v).unwrap_or_default(),
-        None => vec![],
+/// Artist totals keyed by upper-case first letter, with `#` for everything else.
+pub async fn artist_letter_counts(
+    pool: &PgPool,
+) -> Result<std::collections::BTreeMap<String, i64>, sqlx::Error> {
+    let rows: Vec<(String, i64)> = sqlx::query_as(
+        r#"SELECT CASE WHEN lower(name) COLLATE "C" ~ '^[a-z]' THEN upper(left(name, 1))
+                       ELSE '#' END AS letter,
+                  COUNT(*)
+           FROM artists
+           GROUP BY 1"#,
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().collect())
+}
+
+/// Rows whose raw date starts with a year in `from_year..=to_year` but has no typed release
+/// date, either because it doesn't parse or because the backfill hasn't reached it yet.
+pub async fn unparseable_release_dates(
+    pool: &PgPool,
+    item_type: &str,
+    from_year: i32,
+    to_year: i32,
+) -> Result<i64, sqlx::Error> {
+    let sql = match item_type {
+        "song" => {
+            "SELECT COUNT(*) FROM songs
+             WHERE deleted_at IS NULL AND release_date IS NULL
+               AND CASE WHEN date ~ '^[0-9]{4}' THEN LEFT(date, 4)::int END BETWEEN $1 AND $2"
+        }
+        _ => {
+            "SELECT COUNT(*) FROM albums
+             WHERE release_date IS NULL
+               AND CASE WHEN date ~ '^[0-9]{4}' THEN LEFT(date, 4)::int END BETWEEN $1 AND $2"
+        }
     };
+    sqlx::query_scalar(sql)
+        .bind(from_year)
+        .bind(to_year)
+        .fetch_one(pool)
+        .await
+}
 
-    if artists.is_empty() {
+fn release_date_table(item_type: &str) -> &'static str {
+    match item_type {
+        "song" => "songs",
+        _ => "albums",
+    }
+}
+
+/// Up to `limit` rows whose typed release date hasn't been parsed from their current raw
+/// date, as `(id, raw date)`.
+pub async fn pending_release_dates(
+    pool: &PgPool,
+    item_type: &str,
+    limit: i64,
+) -> Result<Vec<(String, String)>, sqlx::Error> {
+    let table = release_date_table(item_type);
+    let sql = format!(
+        "SELECT id, COALESCE(date, '') FROM {table}
+         WHERE release_date_source IS DISTINCT FROM COALESCE(date, '')
+         ORDER BY id
+         LIMIT $1"
+    );
+    sqlx::query_as(sqlx::AssertSqlSafe(sql))
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
+
+/// The outcome of parsing one row's raw date; `date` and `precision` are `None` when it
+/// doesn't parse.
+#[derive(Debug)]
+pub struct ParsedReleaseDate {
+    pub id: String,
+    pub source: String,
+    pub date: Option<Date>,
+    pub precision: Option<&'static str>,
+}
+
+/// Stores parsed release dates. Rows whose raw date changed since it was read are left
+/// alone, so the next backfill picks up the new value. The `updated_at` triggers ignore
+/// these columns, so this doesn't mark the row as changed.
+pub async fn set_release_dates(
+    pool: &PgPool,
+    item_type: &str,
+    parsed: &[ParsedReleaseDate],
+) -> Result<u64, sqlx::Error> {
+    if parsed.is_empty() {
+        return Ok(0);
+    }
+    let table = release_date_table(item_type);
+    let ids: Vec<&str> = parsed.iter().map(|p| p.id.as_str()).collect();
+    let sources: Vec<&str> = parsed.iter().map(|p| p.source.as_str()).collect();
+    let dates: Vec<Option<Date>> = parsed.iter().map(|p| p.date).collect();
+    let precisions: Vec<Option<&str>> = parsed.iter().map(|p| p.precision).collect();
+    let sql = format!(
+        "UPDATE {table} t
+         SET release_date = u.release_date,
+             release_date_precision = u.precision,
+             release_date_source = u.source
+         FROM UNNEST($1::TEXT[], $2::TEXT[], $3::DATE[], $4::TEXT[])
+             AS u(id, source, release_date, precision)
+         WHERE t.id = u.id AND COALESCE(t.date, '') = u.source"
+    );
+    let result = sqlx::query(sqlx::AssertSqlSafe(sql))
+        .bind(ids)
+        .bind(sources)
+        .bind(dates)
+        .bind(precisions)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+#[derive(Debug, serde::Serialize, sqlx::FromRow)]
+pub struct UnparseableReleaseDate {
+    pub id: String,
+    pub name: String,
+    pub date: String,
+}
+
+/// Rows with a non-empty raw date the backfill couldn't parse, in id order after `after_id`.
+pub async fn unparseable_release_date_rows(
+    pool: &PgPool,
+    item_type: &str,
+    after_id: Option<&str>,
+    limit: i64,
+) -> Result<Vec<UnparseableReleaseDate>, sqlx::Error> {
+    let table = release_date_table(item_type);
+    let sql = format!(
+        "SELECT id, name, date FROM {table}
+         WHERE release_date IS NULL AND release_date_source = date AND date <> ''
+           AND ($1::text IS NULL OR id > $1)
+         ORDER BY id
+         LIMIT $2"
+    );
+    sqlx::query_as::<_, UnparseableReleaseDate>(sqlx::AssertSqlSafe(sql))
+        .bind(after_id)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
+
+#[derive(Debug, serde::Serialize, sqlx::FromRow)]
+pub struct TrackCountMismatch {
+    pub id: String,
+    pub name: String,
+    pub track_count: i64,
+    pub available_track_count: i64,
+}
+
+/// Albums whose scraped track count differs from their linked available songs by more than
+/// `threshold`, largest gap first.
+pub async fn track_count_mismatches(
+    pool: &PgPool,
+    threshold: i64,
+    limit: i64,
+) -> Result<Vec<TrackCountMismatch>, sqlx::Error> {
+    sqlx::query_as::<_, TrackCountMismatch>(
+        r#"SELECT * FROM (
+               SELECT al.id, al.name,
+                      COALESCE(al.track_count, 0)::bigint AS track_count,
+                      (
+                          SELECT COUNT(*) FROM song_albums sal
+                          JOIN songs s ON s.id = sal.song_id
+                          WHERE sal.album_id = al.id AND s.deleted_at IS NULL
+                      ) AS available_track_count
+               FROM albums al
+           ) counts
+           WHERE ABS(track_count - available_track_count) > $1
+           ORDER BY ABS(track_count - available_track_count) DESC, id
+           LIMIT $2"#,
+    )
+    .bind(threshold)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+/// Catalog problems for the data quality report, as `(name, query selecting offending ids)`.
+pub const QUALITY_CHECKS: [(&str, &str); 8] = [
+    (
+        "songsWithoutArtist",
+        "SELECT s.id FROM songs s WHERE s.deleted_at IS NULL
+         AND NOT EXISTS (SELECT 1 FROM song_artists sa WHERE sa.song_id = s.id)",
+    ),
+    (
+        "songsWithoutAlbum",
+        "SELECT s.id FROM songs s WHERE s.deleted_at IS NULL
+         AND NOT EXISTS (SELECT 1 FROM song_albums sal WHERE sal.song_id = s.id)",
+    ),
+    (
+        "songsWithoutIsrc",
+        "SELECT id FROM songs WHERE deleted_at IS NULL AND COALESCE(BTRIM(isrc), '') = ''",
+    ),
+    (
+        "albumsWithoutUpc",
+        "SELECT id FROM albums WHERE COALESCE(BTRIM(upc), '') = ''",
+    ),
+    (
+        "albumTrackCountMismatches",
+        "SELECT al.id FROM albums al
+         WHERE COALESCE(al.track_count, 0) <> (
+             SELECT COUNT(*) FROM song_albums sal
+             JOIN songs s ON s.id = sal.song_id
+             WHERE sal.album_id = al.id AND s.deleted_at IS NULL
+         )",
+    ),
+    (
+        "artistsWithoutImage",
+        "SELECT id FROM artists WHERE COALESCE(image, '') = ''",
+    ),
+    (
+        "songsWithUnparseableDate",
+        "SELECT id FROM songs
+         WHERE release_date IS NULL AND release_date_source = date AND date <> ''",
+    ),
+    (
+        "albumsWithUnparseableDate",
+        "SELECT id FROM albums
+         WHERE release_date IS NULL AND release_date_source = date AND date <> ''",
+    ),
+];
+
+#[derive(Debug, serde::Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct QualityCount {
+    pub count: i64,
+    /// The lowest offending ids, so a sample can be looked at right away.
+    pub sample_ids: Vec<String>,
+}
+
+/// Counts the ids one of [`QUALITY_CHECKS`] selects and samples `samples` of them, in a
+/// single scan.
+pub async fn quality_count(
+    pool: &PgPool,
+    select_ids: &str,
+    samples: i64,
+) -> Result<QualityCount, sqlx::Error> {
+    let sql = format!(
+        "WITH bad AS MATERIALIZED ({select_ids})
+         SELECT (SELECT COUNT(*) FROM bad) AS count,
+                ARRAY(SELECT id FROM bad ORDER BY id LIMIT $1) AS sample_ids"
+    );
+    sqlx::query_as::<_, QualityCount>(sqlx::AssertSqlSafe(sql))
+        .bind(samples)
+        .fetch_one(pool)
+        .await
+}
+
+fn table_for(item_type: &str) -> (&'static str, &'static str) {
+    match item_type {
+        "song" => ("songs", "deleted_at IS NULL"),
+        "album" => ("albums", "TRUE"),
+        _ => ("artists", "TRUE"),
+    }
+}
+
+/// Ids that should be searchable, in id order, starting after `after_id`.
+pub async fn indexable_ids(
+    pool: &PgPool,
+    item_type: &str,
+    after_id: Option<&str>,
+    limit: i64,
+) -> Result<Vec<String>, sqlx::Error> {
+    let (table, available) = table_for(item_type);
+    let sql = format!(
+        "SELECT id FROM {table}
+         WHERE {available} AND ($1::text IS NULL OR id > $1)
+         ORDER BY id
+         LIMIT $2"
+    );
+    sqlx::query_scalar(sqlx::AssertSqlSafe(sql))
+        .bind(after_id)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
+
+/// How many rows of `item_type` should be searchable; an exact count, so not for hot paths.
+pub async fn indexable_count(pool: &PgPool, item_type: &str) -> Result<i64, sqlx::Error> {
+    let (table, available) = table_for(item_type);
+    let sql = format!("SELECT COUNT(*) FROM {table} WHERE {available}");
+    sqlx::query_scalar(sqlx::AssertSqlSafe(sql))
+        .fetch_one(pool)
+        .await
+}
+
+/// Bumps `updated_at` so the next incremental sync reindexes these rows.
+pub async fn touch(pool: &PgPool, item_type: &str, ids: &[String]) -> Result<u64, sqlx::Error> {
+    let (table, _) = table_for(item_type);
+    let sql = format!("UPDATE {table} SET updated_at = NOW() WHERE id = ANY($1)");
+    Ok(sqlx::query(sqlx::AssertSqlSafe(sql))
+        .bind(ids)
+        .execute(pool)
+        .await?
+        .rows_affected())
+}
+
+#[derive(Debug, serde::Serialize, sqlx::FromRow)]
+pub struct DuplicateCluster {
+    /// What the members share, prefixed with the rule that matched (`name:`, `upc:`,
+    /// `isrc:`). Doubles as the page cursor.
+    pub key: String,
+    pub members: Json<Vec<serde_json::Value>>,
+}
+
+/// One duplicate rule: rows of `table` passing `filter` that share `expr`, which each rule
+/// has an index on.
+struct KeyRule {
+    prefix: &'static str,
+    table: &'static str,
+    expr: &'static str,
+    filter: &'static str,
+}
+
+const ARTIST_NAME: KeyRule = KeyRule {
+    prefix: "name:",
+    table: "artists",
+    expr: "normalized_name(name)",
+    filter: "TRUE",
+};
+const ALBUM_UPC: KeyRule = KeyRule {
+    prefix: "upc:",
+    table: "albums",
+    expr: "REPLACE(upc, '-', '')",
+    filter: "upc IS NOT NULL AND upc <> ''",
+};
+const SONG_ISRC: KeyRule = KeyRule {
+    prefix: "isrc:",
+    table: "songs",
+    expr: "REPLACE(UPPER(isrc), '-', '')",
+    filter: "isrc IS NOT NULL AND isrc <> ''",
+};
+
+/// Album names read per round while looking for name clusters.
+const ALBUM_NAME_BATCH: i64 = 500;
+
+/// The key prefixes `duplicate_clusters` reports for `item_type`, in page order.
+pub fn duplicate_key_prefixes(item_type: &str) -> &'static [&'static str] {
+    match item_type {
+        "artist" => &["name:"],
+        "album" => &["name:", "upc:"],
+        _ => &["isrc:"],
+    }
+}
+
+/// Groups of entities that look like the same thing: artists by accent- and
+/// case-insensitive name, albums by that name plus the same artist set or by UPC, songs by
+/// ISRC. Ordered by key, starting after `after`. Each rule is grouped on its own indexed
+/// expression, so a page only reads as far into the index as it needs.
+pub async fn duplicate_clusters(
+    pool: &PgPool,
+    item_type: &str,
+    after: Option<&str>,
+    limit: i64,
+) -> Result<Vec<DuplicateCluster>, sqlx::Error> {
+    let rule = match item_type {
+        "artist" => &ARTIST_NAME,
+        "album" => &ALBUM_UPC,
+        _ => &SONG_ISRC,
+    };
+    let mut clusters = Vec::new();
+    if item_type == "album" && after.is_none_or(|a| a.starts_with("name:")) {
+        let after = after.and_then(|a| a.strip_prefix("name:"));
+        clusters = album_name_clusters(pool, after, limit).await?;
+    }
+    let remaining = limit - clusters.len() as i64;
+    if remaining > 0 {
+        let after = after.and_then(|a| a.strip_prefix(rule.prefix));
+        clusters.extend(keyed_clusters(pool, rule, after, remaining).await?);
+    }
+    Ok(clusters)
+}
+
+async fn keyed_clusters(
+    pool: &PgPool,
+    rule: &KeyRule,
+    after: Option<&str>,
+    limit: i64,
+) -> Result<Vec<DuplicateCluster>, sqlx::Error> {
+    let KeyRule {
+        prefix,
+        table,
+        expr,
+        filter,
+    } = rule;
+    let sql = format!(
+        r#"SELECT '{prefix}' || {expr} AS key,
+                  jsonb_agg(jsonb_build_object('id', id, 'name', name) ORDER BY id) AS members
+           FROM {table}
+           WHERE {filter} AND ($1::text IS NULL OR {expr} > $1)
+           GROUP BY {expr}
+           HAVING COUNT(*) > 1
+           ORDER BY {expr}
+           LIMIT $2"#
+    );
+    sqlx::query_as::<_, DuplicateCluster>(sqlx::AssertSqlSafe(sql))
+        .bind(after)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
+
+/// Albums sharing a normalized name and artist set, keyed `name:<name>|<artist ids>`. Names
+/// shared by several albums come off the name index in batches; only those albums get
+/// their artist sets compared.
+async fn album_name_clusters(
+    pool: &PgPool,
+    after: Option<&str>,
+    limit: i64,
+) -> Result<Vec<DuplicateCluster>, sqlx::Error> {
+    // Artist ids never contain '|', so the last one splits the name from the artist set.
+    let mut cursor = after.map(|a| a.rsplit_once('|').unwrap_or((a, "")));
+    let mut from_name = cursor.map(|(name, _)| name.to_string());
+    let mut clusters = Vec::new();
+    loop {
+        // The cursor's own name can still have clusters after its artist set.
+        let op = if cursor.is_some() { ">=" } else { ">" };
+        let sql = format!(
+            "SELECT normalized_name(name) FROM albums
+             WHERE $1::text IS NULL OR normalized_name(name) {op} $1
+             GROUP BY 1
+             HAVING COUNT(*) > 1
+             ORDER BY 1
+             LIMIT $2"
+        );
+        let names: Vec<String> = sqlx::query_scalar(sqlx::AssertSqlSafe(sql))
+            .bind(&from_name)
+            .bind(ALBUM_NAME_BATCH)
+            .fetch_all(pool)
+            .await?;
+        let Some(last) = names.last().cloned() else {
+            break;
+        };
+        let page: Vec<DuplicateCluster> = sqlx::query_as(
+            r#"WITH candidates AS (
+                   SELECT al.id, al.name, normalized_name(al.name) AS name_key,
+                          COALESCE(string_agg(aa.artist_id, ',' ORDER BY aa.artist_id), '')
+                              AS artists
+                   FROM albums al
+                   LEFT JOIN artist_albums aa ON aa.album_id = al.id
+                   WHERE normalized_name(al.name) = ANY($1)
+                   GROUP BY al.id
+               )
+               SELECT 'name:' || name_key || '|' || artists AS key,
+                      jsonb_agg(jsonb_build_object('id', id, 'name', name) ORDER BY id)
+                          AS members
+               FROM candidates
+               WHERE $2::text IS NULL OR (name_key, artists) > ($2, $3)
+               GROUP BY name_key, artists
+               HAVING COUNT(*) > 1
+               ORDER BY name_key, artists
+               LIMIT $4"#,
+        )
+        .bind(&names)
+        .bind(cursor.map(|(name, _)| name))
+        .bind(cursor.map(|(_, artists)| artists))
+        .bind(limit - clusters.len() as i64)
+        .fetch_all(pool)
+        .await?;
+        clusters.extend(page);
+        if clusters.len() as i64 >= limit || (names.len() as i64) < ALBUM_NAME_BATCH {
+            break;
+        }
+        cursor = None;
+        from_name = Some(last);
+    }
+    Ok(clusters)
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeSummary {
+    pub repointed_song_links: u64,
+    pub repointed_album_links: u64,
+    pub repointed_genre_links: u64,
+    pub repointed_aliases: u64,
+    /// Links dropped because the kept artist already had the same one.
+    pub dropped_links: u64,
+    pub deleted_artists: u64,
+    pub touched_songs: u64,
+}
+
+/// Link tables keyed by artist, with the column that makes a link unique per artist.
+const ARTIST_LINKS: [(&str, &str); 4] = [
+    ("song_artists", "song_id"),
+    ("artist_albums", "album_id"),
+    ("artist_genres", "genre_id"),
+    ("artist_aliases", "alias"),
+];
+
+/// Folds `merge_ids` into `keep_id` in one transaction: links are repointed (or dropped
+/// when they would duplicate one the kept artist already has), the merged names become
+/// aliases, and the merged rows are deleted. Songs and albums that changed get a fresh
+/// `updated_at` so the next incremental sync reindexes them. Returns `None`, without
+/// changing anything, if any of the artists doesn't exist.
+pub async fn merge_artists(
+    pool: &PgPool,
+    keep_id: &str,
+    merge_ids: &[String],
+) -> Result<Option<MergeSummary>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let mut all_ids = merge_ids.to_vec();
+    all_ids.push(keep_id.to_string());
+    let found: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM artists WHERE id = ANY($1)")
+        .bind(&all_ids)
+        .fetch_one(&mut *tx)
+        .await?;
+    if found != all_ids.len() as i64 {
         return Ok(None);
     }
 
-    Ok(Some(Album {
-        id: r.get("id"),
-        name: r.get("name"),
-        artist: artists,
-        genres: r.get::<Vec<String>, _>("genres"),
-        image: r.get("image"),
-        date: r.get::<Option<String>, _>("date").unwrap_or_default(),
-        track_count: r.get::<i64, _>("track_count") as i32,
-        upc: r.get("upc"),
-        label: r.get::<Option<String>, _>("label"),
-    }))
+    let touched_songs = sqlx::query(
+        "UPDATE songs SET updated_at = NOW()
+         WHERE id IN (SELECT song_id FROM song_artists WHERE artist_id = ANY($1))",
+    )
+    .bind(merge_ids)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    sqlx::query(
+        "UPDATE albums SET updated_at = NOW()
+         WHERE id IN (SELECT album_id FROM artist_albums WHERE artist_id = ANY($1))",
+    )
+    .bind(merge_ids)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        "INSERT INTO artist_aliases (artist_id, alias)
+         SELECT $1, a.name FROM artists a
+         WHERE a.id = ANY($2)
+           AND lower(a.name) <> (SELECT lower(k.name) FROM artists k WHERE k.id = $1)
+         ON CONFLICT DO NOTHING",
+    )
+    .bind(keep_id)
+    .bind(merge_ids)
+    .execute(&mut *tx)
+    .await?;
+
+    let mut summary = MergeSummary {
+        touched_songs,
+        ..Default::default()
+    };
+    for (table, key) in ARTIST_LINKS {
+        // A link is redundant if the kept artist has it, or an earlier merged artist does
+        // and will be repointed onto the same row.
+        let dropped = sqlx::query(sqlx::AssertSqlSafe(format!(
+            "DELETE FROM {table} t
+             WHERE t.artist_id = ANY($2) AND EXISTS (
+                 SELECT 1 FROM {table} o
+                 WHERE o.{key} = t.{key}
+                   AND (o.artist_id = $1 OR (o.artist_id = ANY($2) AND o.artist_id < t.artist_id))
+             )"
+        )))
+        .bind(keep_id)
+        .bind(merge_ids)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        let repointed = sqlx::query(sqlx::AssertSqlSafe(format!(
+            "UPDATE {table} SET artist_id = $1 WHERE artist_id = ANY($2)"
+        )))
+        .bind(keep_id)
+        .bind(merge_ids)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        summary.dropped_links += dropped;
+        match table {
+            "song_artists" => summary.repointed_song_links = repointed,
+            "artist_albums" => summary.repointed_album_links = repointed,
+            "artist_genres" => summary.repointed_genre_links = repointed,
+            _ => summary.repointed_aliases = repointed,
+        }
+    }
+
+    summary.deleted_artists = sqlx::query("DELETE FROM artists WHERE id = ANY($1)")
+        .bind(merge_ids)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    sqlx::query("UPDATE artists SET updated_at = NOW() WHERE id = $1")
+        .bind(keep_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(Some(summary))
+}
+
+/// Unavailable songs are left out, like everywhere else songs are listed.
+fn export_sql(item_type: &str) -> Option<(&'static str, &'static str)> {
+    Some(match item_type {
+        "song" => (
+            "SELECT MAX(id) AS last_id, COUNT(*) AS n FROM (
+                 SELECT id FROM songs WHERE id > $1 AND deleted_at IS NULL ORDER BY id LIMIT $2
+             ) page",
+            r#"SELECT to_jsonb(s) || jsonb_build_object(
+                      'artist_ids', COALESCE((
+                          SELECT array_agg(sa.artist_id
+                              ORDER BY sa.position NULLS LAST, sa.inserted_seq NULLS LAST, sa.artist_id)
+                          FROM song_artists sa WHERE sa.song_id = s.id
+                      ), '{}'),
+                      'album_ids', COALESCE((
+                          SELECT array_agg(sal.album_id ORDER BY sal.album_id)
+                          FROM song_albums sal WHERE sal.song_id = s.id
+                      ), '{}')
+                  ) AS doc
+           FROM songs s
+           WHERE s.id > $1 AND s.id <= $2 AND s.deleted_at IS NULL
+           ORDER BY s.id"#,
+        ),
+        "album" => (
+            "SELECT MAX(id) AS last_id, COUNT(*) AS n FROM (
+                 SELECT id FROM albums WHERE id > $1 ORDER BY id LIMIT $2
+             ) page",
+            r#"SELECT to_jsonb(al) || jsonb_build_object(
+                      'artist_ids', COALESCE((
+                          SELECT array_agg(aa.artist_id
+                              ORDER BY aa.position NULLS LAST, aa.inserted_seq NULLS LAST, aa.artist_id)
+                          FROM artist_albums aa WHERE aa.album_id = al.id
+                      ), '{}')
+                  ) AS doc
+           FROM albums al
+           WHERE al.id > $1 AND al.id <= $2
+           ORDER BY al.id"#,
+        ),
+        "artist" => (
+            "SELECT MAX(id) AS last_id, COUNT(*) AS n FROM (
+                 SELECT id FROM artists WHERE id > $1 ORDER BY id LIMIT $2
+             ) page",
+            "SELECT to_jsonb(a) AS doc FROM artists a WHERE a.id > $1 AND a.id <= $2 ORDER BY a.id",
+        ),
+        _ => return None,
+    })
+}
+
+pub async fn export_page_end(
+    pool: &PgPool,
+    item_type: &str,
+    after_id: &str,
+    limit: i64,
+) -> Result<Option<(String, i64)>, sqlx::Error> {
+    let Some((page_sql, _)) = export_sql(item_type) else {
+        return Ok(None);
+    };
+    let row = sqlx::query(page_sql)
+        .bind(after_id)
+        .bind(limit)
+        .fetch_one(pool)
+        .await?;
+    Ok(row
+        .get::<Option<String>, _>("last_id")
+        .map(|last| (last, row.get::<i64, _>("n"))))
+}
+
+pub fn export_rows<'a>(
+    pool: &'a PgPool,
+    item_type: &str,
+    after_id: &'a str,
+    last_id: &'a str,
+) -> Option<impl Stream<Item = Result<serde_json::Value, sqlx::Error>> + 'a> {
+    let (_, rows_sql) = export_sql(item_type)?;
+    Some(
+        sqlx::query(rows_sql)
+            .bind(after_id)
+            .bind(last_id)
+            .fetch(pool)
+            .map_ok(|r| r.get::<serde_json::Value, _>("doc")),
+    )
+}
+
+pub async fn id_by_apple_music_id(
+    pool: &PgPool,
+    item_type: &str,
+    apple_music_id: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    // Unavailable songs still resolve, so callers get the same 410 as a direct lookup.
+    let (table, _) = table_for(item_type);
+    let sql = format!("SELECT id FROM {table} WHERE apple_music_id = $1 ORDER BY id LIMIT 1");
+    sqlx::query_scalar(sqlx::AssertSqlSafe(sql))
+        .bind(apple_music_id)
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn image_url(
+    pool: &PgPool,
+    item_type: &str,
+    id: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    let sql = match item_type {
+        "song" => "SELECT image FROM songs WHERE id = $1",
+        "album" => "SELECT image FROM albums WHERE id = $1",
+        "artist" => {
+            return sqlx::query_scalar(sqlx::AssertSqlSafe(format!(
+                "SELECT COALESCE(NULLIF(a.image, ''), fallback.image) FROM artists a
+                 LEFT JOIN LATERAL ({LATEST_ALBUM_IMAGE}) fallback ON COALESCE(a.image, '') = ''
+                 WHERE a.id = $1"
+            )))
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+            .map(|image: Option<Option<String>>| image.flatten().filter(|s| !s.is_empty()));
+        }
+        _ => return Ok(None),
+    };
+    let image: Option<Option<String>> = sqlx::query_scalar(sql)
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(image.flatten().filter(|s| !s.is_empty()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    async fn keys(pool: &PgPool, item_type: &str, after: Option<&str>, limit: i64) -> Vec<String> {
+        duplicate_clusters(pool, item_type, after, limit)
+            .await
+            .expect("duplicate clusters")
+            .into_iter()
+            .map(|c| c.key)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn duplicate_clusters_page_through_each_rule() {
+        let Some(pool) = test_support::scrape_db().await else {
+            return;
+        };
+        sqlx::raw_sql(
+            "INSERT INTO artists (id, name) VALUES
+                 ('beyonce000000001', 'Beyoncé'),
+                 ('beyonce000000002', 'BEYONCE'),
+                 ('strasse000000001', 'Straße'),
+                 ('strasse000000002', 'STRASSE'),
+                 ('istanbul00000001', 'İstanbul'),
+                 ('istanbul00000002', 'istanbul');
+             INSERT INTO albums (id, name, upc) VALUES
+                 ('discovery0000002', 'DISCOVERY', NULL),
+                 ('discovery0000003', 'Discovery', NULL),
+                 ('discovery0000004', 'Discovery (Reissue)', '7243-8496-0650');
+             INSERT INTO artist_albums (artist_id, album_id) VALUES
+                 ('daftpunk00000001', 'discovery0000002'),
+                 ('zedd000000000001', 'discovery0000003');
+             UPDATE songs SET isrc = 'usqx9-13-00108' WHERE id = 'doinitright00001';",
+        )
+        .execute(&pool)
+        .await
+        .expect("insert duplicates");
+
+        assert_eq!(
+            keys(&pool, "artist", None, 10).await,
+            ["name:beyonce", "name:istanbul", "name:strasse"]
+        );
+        assert_eq!(keys(&pool, "artist", None, 1).await, ["name:beyonce"]);
+        assert_eq!(
+            keys(&pool, "artist", Some("name:beyonce"), 1).await,
+            ["name:istanbul"]
+        );
+
+        // Same name by a different artist set isn't a duplicate; the UPC rule follows the
+        // name rule on the same pages.
+        assert_eq!(
+            keys(&pool, "album", None, 10).await,
+            ["name:discovery|daftpunk00000001", "upc:724384960650"]
+        );
+        assert_eq!(
+            keys(&pool, "album", Some("name:discovery|daftpunk00000001"), 10).await,
+            ["upc:724384960650"]
+        );
+        assert!(
+            keys(&pool, "album", Some("upc:724384960650"), 10)
+                .await
+                .is_empty()
+        );
+
+        assert_eq!(keys(&pool, "song", None, 10).await, ["isrc:USQX91300108"]);
+    }
 }
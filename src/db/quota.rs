@@ -0,0 +1,76 @@
+use sqlx::PgPool;
+
+/// Requests one client made in one hour, with `hour` counted in hours since the Unix epoch.
+#[derive(Debug, sqlx::FromRow)]
+pub struct HourlyCount {
+    pub group_name: String,
+    pub client_key: String,
+    pub hour: i64,
+    pub count: i32,
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn load_daily_counts(
+    pool: &PgPool,
+    after_hour: i64,
+) -> Result<Vec<HourlyCount>, sqlx::Error> {
+    sqlx::query_as::<_, HourlyCount>(
+        r#"
+        SELECT
+            group_name,
+            client_key,
+            (EXTRACT(EPOCH FROM hour) / 3600)::BIGINT AS hour,
+            count
+        FROM daily_quota_counters
+        WHERE hour > to_timestamp($1 * 3600)
+        "#,
+    )
+    .bind(after_hour)
+    .fetch_all(pool)
+    .await
+}
+
+/// Adds `counts` to the stored totals and returns the new totals.
+#[tracing::instrument(skip_all)]
+pub async fn save_daily_counts(
+    pool: &PgPool,
+    counts: &[HourlyCount],
+) -> Result<Vec<HourlyCount>, sqlx::Error> {
+    if counts.is_empty() {
+        return Ok(Vec::new());
+    }
+    let groups: Vec<&str> = counts.iter().map(|c| c.group_name.as_str()).collect();
+    let keys: Vec<&str> = counts.iter().map(|c| c.client_key.as_str()).collect();
+    let hours: Vec<i64> = counts.iter().map(|c| c.hour).collect();
+    let values: Vec<i32> = counts.iter().map(|c| c.count).collect();
+    sqlx::query_as::<_, HourlyCount>(
+        r#"
+        INSERT INTO daily_quota_counters (group_name, client_key, hour, count)
+        SELECT g, k, to_timestamp(h * 3600), c
+        FROM UNNEST($1::TEXT[], $2::TEXT[], $3::BIGINT[], $4::INTEGER[]) AS t(g, k, h, c)
+        ON CONFLICT (group_name, client_key, hour)
+            DO UPDATE SET count = daily_quota_counters.count + EXCLUDED.count
+        RETURNING
+            group_name,
+            client_key,
+            (EXTRACT(EPOCH FROM hour) / 3600)::BIGINT AS hour,
+            count
+        "#,
+    )
+    .bind(groups)
+    .bind(keys)
+    .bind(hours)
+    .bind(values)
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn delete_daily_counts_before(pool: &PgPool, hour: i64) -> Result<u64, sqlx::Error> {
+    let result =
+        sqlx::query("DELETE FROM daily_quota_counters WHERE hour <= to_timestamp($1 * 3600)")
+            .bind(hour)
+            .execute(pool)
+            .await?;
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,180 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use sqlx::migrate::Migrator;
+use std::collections::BTreeMap;
+use time::OffsetDateTime;
+
+/// Scrape database columns this binary reads, by table. The scraper owns that schema, so a
+/// rename there would otherwise only show up as failing hydration queries.
+pub const EXPECTED_SCRAPE_COLUMNS: &[(&str, &[&str])] = &[
+    (
+        "songs",
+        &[
+            "id",
+            "name",
+            "image",
+            "duration",
+            "disc_number",
+            "track_number",
+            "isrc",
+            "date",
+            "release_date",
+            "created_at",
+            "updated_at",
+            "deleted_at",
+            "explicit",
+            "apple_music_id",
+        ],
+    ),
+    (
+        "albums",
+        &[
+            "id",
+            "name",
+            "image",
+            "date",
+            "release_date",
+            "track_count",
+            "upc",
+            "label",
+            "created_at",
+            "updated_at",
+            "apple_music_id",
+        ],
+    ),
+    (
+        "artists",
+        &[
+            "id",
+            "name",
+            "image",
+            "created_at",
+            "updated_at",
+            "apple_music_id",
+        ],
+    ),
+    ("genres", &["id", "name"]),
+    ("song_artists", &["song_id", "artist_id", "position"]),
+    ("song_albums", &["song_id", "album_id"]),
+    ("artist_albums", &["artist_id", "album_id", "position"]),
+    ("song_genres", &["song_id", "genre_id"]),
+    ("album_genres", &["album_id", "genre_id"]),
+    ("artist_genres", &["artist_id", "genre_id"]),
+    ("artist_aliases", &["artist_id", "alias"]),
+];
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct AppliedMigration {
+    pub version: i64,
+    pub description: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub installed_on: OffsetDateTime,
+    pub success: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationStatus {
+    pub applied: Vec<AppliedMigration>,
+    /// Newest migration embedded in this binary.
+    pub latest_embedded: Option<i64>,
+    /// Embedded migrations not successfully applied.
+    pub pending: Vec<i64>,
+    pub up_to_date: bool,
+}
+
+/// Applied migrations of the database behind `pool` against the ones `migrator` embeds.
+pub async fn migration_status(
+    pool: &PgPool,
+    migrator: &Migrator,
+) -> Result<MigrationStatus, sqlx::Error> {
+    let applied = match sqlx::query_as::<_, AppliedMigration>(
+        "SELECT version, description, installed_on, success
+         FROM _sqlx_migrations ORDER BY version",
+    )
+    .fetch_all(pool)
+    .await
+    {
+        Ok(applied) => applied,
+        Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some("42P01") => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    let pending: Vec<i64> = migrator
+        .iter()
+        .filter(|m| m.migration_type.is_up_migration())
+        .map(|m| m.version)
+        .filter(|v| !applied.iter().any(|a| a.version == *v && a.success))
+        .collect();
+    Ok(MigrationStatus {
+        latest_embedded: migrator.iter().map(|m| m.version).max(),
+        up_to_date: pending.is_empty(),
+        pending,
+        applied,
+    })
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ColumnDrift {
+    /// SHA-256 over the expected columns that exist, as `table.column` lines.
+    pub checksum: String,
+    /// The same checksum with every expected column present.
+    pub expected_checksum: String,
+    pub matches: bool,
+    /// Expected columns the database lacks, as `table.column`.
+    pub missing: Vec<String>,
+}
+
+fn checksum<'a>(columns: impl Iterator<Item = &'a String>) -> String {
+    let mut hasher = Sha256::new();
+    for column in columns {
+        hasher.update(column.as_bytes());
+        hasher.update(b"\n");
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Compares the scrape tables against [`EXPECTED_SCRAPE_COLUMNS`]. Extra columns are
+/// ignored; only ones this binary reads can break it.
+pub async fn scrape_column_drift(pool: &PgPool) -> Result<ColumnDrift, sqlx::Error> {
+    let tables: Vec<&str> = EXPECTED_SCRAPE_COLUMNS.iter().map(|(t, _)| *t).collect();
+    let rows: Vec<(String, String)> = sqlx::query_as(
+        "SELECT table_name::text, column_name::text FROM information_schema.columns
+         WHERE table_schema = current_schema() AND table_name = ANY($1)",
+    )
+    .bind(&tables)
+    .fetch_all(pool)
+    .await?;
+    let mut actual: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (table, column) in rows {
+        actual.entry(table).or_default().push(column);
+    }
+
+    let mut expected = Vec::new();
+    let mut present = Vec::new();
+    let mut missing = Vec::new();
+    for (table, columns) in EXPECTED_SCRAPE_COLUMNS {
+        for column in *columns {
+            let name = format!("{table}.{column}");
+            if actual
+                .get(*table)
+                .is_some_and(|cols| cols.iter().any(|c| c == column))
+            {
+                present.push(name.clone());
+            } else {
+                missing.push(name.clone());
+            }
+            expected.push(name);
+        }
+    }
+    let found = checksum(present.iter());
+    let expected_checksum = checksum(expected.iter());
+    Ok(ColumnDrift {
+        matches: found == expected_checksum,
+        checksum: found,
+        expected_checksum,
+        missing,
+    })
+}
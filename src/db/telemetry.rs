@@ -1,28 +1,61 @@
+use futures::Stream;
 use sqlx::PgPool;
 use time::OffsetDateTime;
 use uuid::Uuid;
 
-use crate::models::telemetry::{DistributionPoint, TelemetrySubmission, TimeSeriesPoint};
+use crate::models::telemetry::{DistributionPoint, TelemetrySubmission, TimeSeriesPoint, TimeZone};
 
+/// Stores a submission and moves the user's `user_first_seen` entry back if this one is older.
+/// `recorded_at` backdates it; `None` stores it at the database's current time.
+#[tracing::instrument(skip_all)]
 pub async fn insert_submission(
     pool: &PgPool,
     payload: &TelemetrySubmission,
+    recorded_at: Option<OffsetDateTime>,
 ) -> Result<(), sqlx::Error> {
     sqlx::query(
         r#"
-        INSERT INTO telemetry (user_id, app_version, os, song_count, time)
-        VALUES ($1, $2, $3, $4, NOW())
+        WITH inserted AS (
+            INSERT INTO telemetry (user_id, app_version, os, song_count, time)
+            VALUES ($1, $2, $3, $4, COALESCE($5, NOW()))
+            RETURNING user_id, time
+        )
+        INSERT INTO user_first_seen (user_id, first_seen)
+        SELECT user_id, time FROM inserted
+        ON CONFLICT (user_id) DO UPDATE
+        SET first_seen = LEAST(user_first_seen.first_seen, EXCLUDED.first_seen)
         "#,
     )
     .bind(payload.user_id)
     .bind(&payload.app_version)
     .bind(payload.os.as_str())
     .bind(payload.song_count)
+    .bind(recorded_at)
     .execute(pool)
     .await?;
     Ok(())
 }
 
+/// Records an opt-out. Returns false when the user had already opted out.
+#[tracing::instrument(skip_all)]
+pub async fn opt_out(pool: &PgPool, user_id: Uuid) -> Result<bool, sqlx::Error> {
+    let result =
+        sqlx::query("INSERT INTO opted_out_users (user_id) VALUES ($1) ON CONFLICT DO NOTHING")
+            .bind(user_id)
+            .execute(pool)
+            .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn is_opted_out(pool: &PgPool, user_id: Uuid) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM opted_out_users WHERE user_id = $1)")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+}
+
+#[tracing::instrument(skip_all)]
 pub async fn daily_submission_count(pool: &PgPool, user_id: Uuid) -> Result<i64, sqlx::Error> {
     sqlx::query_scalar(
         "SELECT COUNT(*)::BIGINT FROM telemetry WHERE user_id = $1 AND time >= date_trunc('day', NOW())",
@@ -37,6 +70,7 @@ pub struct LastSubmission {
     pub os: String,
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn last_submission(
     pool: &PgPool,
     user_id: Uuid,
@@ -50,86 +84,166 @@ pub async fn last_submission(
     .await
 }
 
+/// What the ingest limits need to know about a user, and nothing else.
+#[derive(Debug, sqlx::FromRow)]
+pub struct SubmissionStatus {
+    pub last_submission_at: Option<OffsetDateTime>,
+    pub submissions_today: i64,
+    /// When the day [`daily_submission_count`] counts in ends.
+    pub day_ends_at: OffsetDateTime,
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn submission_status(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<SubmissionStatus, sqlx::Error> {
+    sqlx::query_as::<_, SubmissionStatus>(
+        r#"
+        SELECT
+            (SELECT MAX(time) FROM telemetry WHERE user_id = $1) AS last_submission_at,
+            (SELECT COUNT(*) FROM telemetry
+             WHERE user_id = $1 AND time >= date_trunc('day', NOW()))::BIGINT AS submissions_today,
+            date_trunc('day', NOW()) + INTERVAL '1 day' AS day_ends_at
+        "#,
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct RawSubmission {
+    pub user_id: Uuid,
+    pub app_version: String,
+    pub os: String,
+    pub song_count: i64,
+    pub time: OffsetDateTime,
+}
+
+/// Submissions in `[from, to)`, counted up to `cap` so oversized exports are refused cheaply.
+#[tracing::instrument(skip_all)]
+pub async fn count_submissions_capped(
+    pool: &PgPool,
+    from: OffsetDateTime,
+    to: OffsetDateTime,
+    cap: i64,
+) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT COUNT(*)::BIGINT FROM (
+            SELECT 1 FROM telemetry WHERE time >= $1 AND time < $2 LIMIT $3
+        ) capped",
+    )
+    .bind(from)
+    .bind(to)
+    .bind(cap)
+    .fetch_one(pool)
+    .await
+}
+
+/// Submissions in `[from, to)` by time, streamed from a server-side cursor.
+pub fn raw_submissions(
+    pool: &PgPool,
+    from: OffsetDateTime,
+    to: OffsetDateTime,
+) -> impl Stream<Item = Result<RawSubmission, sqlx::Error>> + '_ {
+    sqlx::query_as::<_, RawSubmission>(
+        "SELECT user_id, app_version, os, song_count, time
+         FROM telemetry
+         WHERE time >= $1 AND time < $2
+         ORDER BY time, user_id",
+    )
+    .bind(from)
+    .bind(to)
+    .fetch(pool)
+}
+
+#[tracing::instrument(skip_all)]
 pub async fn earliest_time(pool: &PgPool) -> Result<Option<OffsetDateTime>, sqlx::Error> {
     sqlx::query_scalar("SELECT MIN(time) FROM telemetry")
         .fetch_one(pool)
         .await
 }
 
+/// The `buckets (bucket, bucket_end)` CTE every time series is grouped by, covering `$1`
+/// to `$2` in steps of `$3` aligned to midnight in zone `$4`. Buckets are stepped in local
+/// wall-clock time, so a day stays midnight to midnight across DST changes, then converted
+/// back to instants.
+macro_rules! buckets_cte {
+    () => {
+        r#"
+        WITH local_buckets AS (
+            SELECT generate_series(
+                time_bucket($3::INTERVAL, $1::TIMESTAMPTZ AT TIME ZONE $4),
+                $2::TIMESTAMPTZ AT TIME ZONE $4,
+                $3::INTERVAL
+            ) as local_start
+        ),
+        buckets AS (
+            SELECT
+                local_start AT TIME ZONE $4 as bucket,
+                (local_start + $3::INTERVAL) AT TIME ZONE $4 as bucket_end
+            FROM local_buckets
+        )"#
+    };
+}
+
+#[tracing::instrument(skip_all)]
 pub async fn songs_over_time(
     pool: &PgPool,
     start: OffsetDateTime,
     end: OffsetDateTime,
     interval: String,
+    include_stale: bool,
+    churn_threshold: &str,
+    tz: TimeZone,
 ) -> Result<Vec<TimeSeriesPoint>, sqlx::Error> {
-    sqlx::query_as::<_, TimeSeriesPoint>(
-        r#"
-        WITH baseline AS (
-            -- Get the last known song count for each user before the time range
-            SELECT DISTINCT ON (user_id)
-                user_id,
-                song_count::FLOAT8 as last_val
-            FROM telemetry
-            WHERE time < $1
-            ORDER BY user_id, time DESC
-        ),
-        baseline_total AS (
-            SELECT COALESCE(SUM(last_val), 0)::FLOAT8 as total
-            FROM baseline
-        ),
-        -- Get all telemetry data in range ordered by time
-        ordered_telemetry AS (
+    sqlx::query_as::<_, TimeSeriesPoint>(concat!(
+        buckets_cte!(),
+        r#",
+        -- Every report that can be live at a bucket end, with the instant the same user's
+        -- next report replaced it, computed in one pass so each bucket only has to find the
+        -- reports live at its end. Without stale users nothing older than the churn
+        -- threshold before the first bucket end counts; with them, each user's history
+        -- before the range collapses to their latest report.
+        reports AS (
             SELECT
-                time,
-                user_id,
                 song_count::FLOAT8 as song_count,
-                time_bucket($3::INTERVAL, time) as bucket
-            FROM telemetry
-            WHERE time >= $1 AND time <= $2
-            ORDER BY user_id, time
-        ),
-        -- Calculate deltas from previous row or baseline
-        deltas AS (
-            SELECT
-                bucket,
-                user_id,
-                song_count,
-                song_count - COALESCE(
-                    LAG(song_count) OVER (PARTITION BY user_id ORDER BY time),
-                    (SELECT b.last_val FROM baseline b WHERE b.user_id = ordered_telemetry.user_id),
-                    0
-                ) as delta
-            FROM ordered_telemetry
-        ),
-        -- Sum all deltas per bucket (this gives us the change in total songs)
-        bucket_changes AS (
-            SELECT
-                bucket,
-                SUM(delta) as bucket_delta
-            FROM deltas
-            GROUP BY bucket
-        ),
-        -- Calculate cumulative total
-        cumulative AS (
-            SELECT
-                bucket,
-                (SELECT total FROM baseline_total) +
-                SUM(bucket_delta) OVER (ORDER BY bucket ROWS BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW) as value
-            FROM bucket_changes
+                time,
+                LEAD(time) OVER (PARTITION BY user_id ORDER BY time) as superseded_at
+            FROM (
+                SELECT user_id, song_count, time
+                FROM telemetry
+                WHERE time < (SELECT MAX(bucket_end) FROM buckets)
+                    AND time >= CASE
+                        WHEN $5 THEN (SELECT MIN(bucket) FROM buckets)
+                        ELSE (SELECT MIN(bucket_end) FROM buckets) - $6::INTERVAL
+                    END
+                UNION ALL
+                (
+                    SELECT DISTINCT ON (user_id) user_id, song_count, time
+                    FROM telemetry
+                    WHERE $5 AND time < (SELECT MIN(bucket) FROM buckets)
+                    ORDER BY user_id, time DESC
+                )
+            ) history
         ),
-        gapfilled AS (
-            SELECT
-                time_bucket_gapfill($3::INTERVAL, bucket, $1::TIMESTAMPTZ, $2::TIMESTAMPTZ) as gf_bucket,
-                interpolate(value) as gf_value
-            FROM cumulative
+        -- Each user's last known song count as of the end of every bucket (LOCF per user).
+        -- Users whose last report is older than the churn threshold are dropped unless
+        -- stale users are explicitly included.
+        carried AS (
+            SELECT b.bucket, r.song_count
+            FROM buckets b
+            JOIN reports r
+                ON r.time < b.bucket_end
+                AND (r.superseded_at IS NULL OR r.superseded_at >= b.bucket_end)
+            WHERE $5 OR r.time >= b.bucket_end - $6::INTERVAL
         ),
-        all_points AS (
-            SELECT
-                COALESCE(g.gf_bucket, c.bucket) as bucket,
-                COALESCE(g.gf_value, c.value, (SELECT total FROM baseline_total)) as value
-            FROM gapfilled g
-            FULL OUTER JOIN cumulative c ON g.gf_bucket = c.bucket
-            WHERE COALESCE(g.gf_bucket, c.bucket) IS NOT NULL
+        totals AS (
+            SELECT b.bucket, COALESCE(SUM(c.song_count), 0)::FLOAT8 as value
+            FROM buckets b
+            LEFT JOIN carried c ON c.bucket = b.bucket
+            GROUP BY b.bucket
         ),
         -- Only keep points where value changed from previous point
         changes_only AS (
@@ -137,81 +251,95 @@ pub async fn songs_over_time(
                 bucket,
                 value,
                 LAG(value) OVER (ORDER BY bucket) as prev_value
-            FROM all_points
+            FROM totals
         )
         SELECT bucket, value FROM changes_only
         WHERE prev_value IS NULL OR value != prev_value
         ORDER BY bucket ASC
-        "#,
-    )
+        "#
+    ))
     .bind(start)
     .bind(end)
     .bind(interval)
+    .bind(tz.as_str())
+    .bind(include_stale)
+    .bind(churn_threshold)
     .fetch_all(pool)
     .await
 }
 
+/// Cumulative users by the bucket of their first submission, read from `user_first_seen`.
+#[tracing::instrument(skip_all)]
 pub async fn users_over_time(
     pool: &PgPool,
     start: OffsetDateTime,
     end: OffsetDateTime,
     interval: String,
+    tz: TimeZone,
 ) -> Result<Vec<TimeSeriesPoint>, sqlx::Error> {
-    sqlx::query_as::<_, TimeSeriesPoint>(
-        r#"
-        WITH baseline AS (
-            -- Count users seen before the time range
-            SELECT COUNT(DISTINCT user_id)::FLOAT8 as initial_count
-            FROM telemetry
-            WHERE time < $1
-        ),
-        first_seen_per_user AS (
-            -- Find when each user first appeared
-            SELECT
-                user_id,
-                MIN(time) as first_seen
-            FROM telemetry
-            GROUP BY user_id
+    sqlx::query_as::<_, TimeSeriesPoint>(concat!(
+        buckets_cte!(),
+        r#",
+        baseline AS (
+            -- Users seen before the first bucket
+            SELECT COUNT(*)::FLOAT8 as initial_count
+            FROM user_first_seen
+            WHERE first_seen < (SELECT MIN(bucket) FROM buckets)
         ),
         bucketed_users AS (
-            -- Bucket users by their first seen time
-            SELECT
-                time_bucket($3::INTERVAL, first_seen) as bucket,
-                COUNT(*)::FLOAT8 as new_users
-            FROM first_seen_per_user
-            WHERE first_seen >= $1 AND first_seen <= $2
-            GROUP BY bucket
-        ),
-        cumulative AS (
-            -- Calculate running total using TimescaleDB's ordered aggregation
-            SELECT
-                bucket,
-                (SELECT initial_count FROM baseline) +
-                SUM(new_users) OVER (ORDER BY bucket ROWS BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW) as value
-            FROM bucketed_users
-        ),
-        gapfilled AS (
-            -- Gapfill for continuous time series
-            SELECT
-                time_bucket_gapfill($3::INTERVAL, bucket, $1::TIMESTAMPTZ, $2::TIMESTAMPTZ) as gf_bucket,
-                interpolate(value) as gf_value
-            FROM cumulative
+            -- Bucket users by their first seen time; empty buckets add nobody
+            SELECT b.bucket, COUNT(f.user_id)::FLOAT8 as new_users
+            FROM buckets b
+            LEFT JOIN user_first_seen f
+                ON f.first_seen >= b.bucket AND f.first_seen < b.bucket_end
+            GROUP BY b.bucket
         )
         SELECT
-            COALESCE(g.gf_bucket, c.bucket) as bucket,
-            COALESCE(g.gf_value, c.value, (SELECT initial_count FROM baseline)) as value
-        FROM gapfilled g
-        FULL OUTER JOIN cumulative c ON g.gf_bucket = c.bucket
+            bucket,
+            (SELECT initial_count FROM baseline) +
+            SUM(new_users) OVER (ORDER BY bucket ROWS BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW) as value
+        FROM bucketed_users
         ORDER BY bucket ASC
-        "#,
-    )
+        "#
+    ))
+    .bind(start)
+    .bind(end)
+    .bind(interval)
+    .bind(tz.as_str())
+    .fetch_all(pool)
+    .await
+}
+
+/// Raw submission counts per bucket; buckets without submissions are 0.
+#[tracing::instrument(skip_all)]
+pub async fn submissions_over_time(
+    pool: &PgPool,
+    start: OffsetDateTime,
+    end: OffsetDateTime,
+    interval: String,
+    tz: TimeZone,
+) -> Result<Vec<TimeSeriesPoint>, sqlx::Error> {
+    sqlx::query_as::<_, TimeSeriesPoint>(concat!(
+        buckets_cte!(),
+        r#"
+        SELECT b.bucket, COUNT(t.time)::FLOAT8 as value
+        FROM buckets b
+        LEFT JOIN telemetry t
+            ON t.time >= b.bucket AND t.time < b.bucket_end
+            AND t.time >= $1 AND t.time <= $2
+        GROUP BY b.bucket
+        ORDER BY b.bucket ASC
+        "#
+    ))
     .bind(start)
     .bind(end)
     .bind(interval)
+    .bind(tz.as_str())
     .fetch_all(pool)
     .await
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn os_distribution(pool: &PgPool) -> Result<Vec<DistributionPoint>, sqlx::Error> {
     sqlx::query_as::<_, DistributionPoint>(
         r#"
@@ -229,6 +357,7 @@ pub async fn os_distribution(pool: &PgPool) -> Result<Vec<DistributionPoint>, sq
     .await
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn version_distribution(pool: &PgPool) -> Result<Vec<DistributionPoint>, sqlx::Error> {
     sqlx::query_as::<_, DistributionPoint>(
         r#"
@@ -245,3 +374,35 @@ pub async fn version_distribution(pool: &PgPool) -> Result<Vec<DistributionPoint
     .fetch_all(pool)
     .await
 }
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct Kpis {
+    pub total_users: i64,
+    pub active_users_24h: i64,
+    pub submissions_1h: i64,
+    /// Sum of each user's latest reported library size.
+    pub library_songs: i64,
+    pub opted_out_users: i64,
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn kpis(pool: &PgPool) -> Result<Kpis, sqlx::Error> {
+    sqlx::query_as::<_, Kpis>(
+        r#"
+        SELECT
+            (SELECT COUNT(DISTINCT user_id) FROM telemetry)::BIGINT AS total_users,
+            (SELECT COUNT(DISTINCT user_id) FROM telemetry
+             WHERE time >= NOW() - INTERVAL '24 hours')::BIGINT AS active_users_24h,
+            (SELECT COUNT(*) FROM telemetry
+             WHERE time >= NOW() - INTERVAL '1 hour')::BIGINT AS submissions_1h,
+            (SELECT COALESCE(SUM(song_count), 0) FROM (
+                SELECT DISTINCT ON (user_id) song_count
+                FROM telemetry
+                ORDER BY user_id, time DESC
+            ) latest_states)::BIGINT AS library_songs,
+            (SELECT COUNT(*) FROM opted_out_users)::BIGINT AS opted_out_users
+        "#,
+    )
+    .fetch_one(pool)
+    .await
+}
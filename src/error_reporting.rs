@@ -0,0 +1,317 @@
+use axum::extract::{MatchedPath, Request};
+use axum::http::HeaderValue;
+use axum::middleware::Next;
+use axum::response::Response;
+use sentry::Scope;
+use sentry::protocol::{Event, IpAddress, Url};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::redaction::{self, Policy};
+
+const FILTERED: &str = "[Filtered]";
+/// Headers that carry client addresses when running behind a proxy.
+const IP_HEADERS: [&str; 3] = ["x-forwarded-for", "x-real-ip", "cf-connecting-ip"];
+/// Headers that carry credentials; filtered whatever the policy, like the cookie jar.
+const SECRET_HEADERS: [&str; 2] = ["authorization", "cookie"];
+const REQUEST_ID: &str = "x-request-id";
+/// Client-supplied request ids longer than this are replaced with a fresh one.
+const MAX_REQUEST_ID_LEN: usize = 64;
+
+/// Initializes Sentry when `SENTRY_DSN` is set. The returned guard flushes pending events on drop.
+pub fn init() -> Option<sentry::ClientInitGuard> {
+    let dsn = std::env::var("SENTRY_DSN")
+        .ok()
+        .filter(|v| !v.trim().is_empty())?;
+
+    let guard = sentry::init((
+        dsn,
+        sentry::ClientOptions {
+            release: Some(env!("CARGO_PKG_VERSION").into()),
+            environment: std::env::var("SENTRY_ENVIRONMENT").ok().map(Into::into),
+            send_default_pii: false,
+            before_send: Some(Arc::new(|event| Some(sanitize(event)))),
+            ..Default::default()
+        },
+    ));
+    sentry::configure_scope(build_tags);
+    Some(guard)
+}
+
+fn build_tags(scope: &mut Scope) {
+    scope.set_tag("git_sha", env!("VLEER_GIT_SHA"));
+    scope.set_tag("app_version", env!("CARGO_PKG_VERSION"));
+}
+
+/// Tags every later event with the search backend serving requests.
+pub fn set_search_backend(name: &str) {
+    sentry::configure_scope(|scope| scope.set_tag("search_backend", name));
+}
+
+/// Tags the request's events with its id and matched route, and echoes the id in
+/// `x-request-id` so a client's report can be matched to them. Keeps a well-formed id the
+/// client sent, otherwise generates one. Must sit inside the per-request hub that
+/// `NewSentryLayer` creates, and be added with `Router::layer` so the route is known.
+pub async fn request_context(req: Request, next: Next) -> Response {
+    let id = req
+        .headers()
+        .get(REQUEST_ID)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| {
+            !v.is_empty()
+                && v.len() <= MAX_REQUEST_ID_LEN
+                && v.bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        })
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(MatchedPath::as_str);
+    sentry::configure_scope(|scope| {
+        scope.set_tag("request_id", &id);
+        if let Some(route) = route {
+            scope.set_tag("route", route);
+        }
+    });
+    let mut response = next.run(req).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID, value);
+    }
+    response
+}
+
+pub fn enabled() -> bool {
+    sentry::Hub::current().client().is_some()
+}
+
+fn sanitize(mut event: Event<'static>) -> Event<'static> {
+    let strict = redaction::policy() == Policy::Strict;
+    if let Some(request) = event.request.as_mut() {
+        if strict {
+            if let Some(url) = request.url.as_mut() {
+                redact_query(url);
+            }
+            if let Some(query) = request.query_string.as_mut() {
+                *query = redacted_pairs(query);
+            }
+            for (name, value) in request.headers.iter_mut() {
+                if IP_HEADERS.iter().any(|h| name.eq_ignore_ascii_case(h)) {
+                    *value = redaction::ip_list(value);
+                }
+            }
+            request.env.remove("REMOTE_ADDR");
+        }
+        for (name, value) in request.headers.iter_mut() {
+            if SECRET_HEADERS.iter().any(|h| name.eq_ignore_ascii_case(h)) {
+                *value = FILTERED.to_string();
+            }
+        }
+        request.cookies = None;
+        request.data = None;
+    }
+    if strict {
+        if let Some(user) = event.user.as_mut() {
+            user.ip_address = match user.ip_address {
+                Some(IpAddress::Exact(ip)) => Some(IpAddress::Exact(redaction::truncate_ip(ip))),
+                _ => None,
+            };
+            user.id = user.id.as_deref().map(redaction::scrub);
+        }
+        if let Some(message) = event.message.as_mut() {
+            *message = redaction::scrub(message);
+        }
+        for exception in event.exception.values.iter_mut() {
+            exception.value = exception.value.as_deref().map(redaction::scrub);
+        }
+        for breadcrumb in event.breadcrumbs.values.iter_mut() {
+            breadcrumb.message = breadcrumb.message.as_deref().map(redaction::scrub);
+        }
+    }
+    event
+}
+
+fn redact_query(url: &mut Url) {
+    if let Some(query) = url.query().map(redacted_pairs) {
+        url.set_query(Some(&query).filter(|q| !q.is_empty()).map(|q| q.as_str()));
+    }
+}
+
+/// Filters every query value except a short enough `q`, which helps reproduce search errors.
+fn redacted_pairs(query: &str) -> String {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            match (key, redaction::query(value)) {
+                ("q", Some(q)) => format!("{key}={q}"),
+                _ => format!("{key}={FILTERED}"),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sentry::protocol::{Breadcrumb, Exception, Request, User};
+    use std::collections::BTreeMap;
+
+    const ID: &str = "3f2504e0-4f89-11d3-9a0c-0305e82c3301";
+    const IP: &str = "203.0.113.57";
+
+    fn sample_event() -> Event<'static> {
+        let mut event = Event {
+            message: Some(format!("telemetry insert failed for {ID}")),
+            user: Some(User {
+                id: Some(ID.to_string()),
+                ip_address: Some(IpAddress::Exact(IP.parse().unwrap())),
+                ..Default::default()
+            }),
+            request: Some(Request {
+                url: Some(
+                    format!("https://api.example/search?q=daft&user_id={ID}")
+                        .parse()
+                        .unwrap(),
+                ),
+                query_string: Some(format!("q=daft&user_id={ID}")),
+                headers: BTreeMap::from([
+                    ("X-Forwarded-For".to_string(), format!("{IP}, 10.0.0.1")),
+                    ("Cookie".to_string(), "session=abc".to_string()),
+                    ("Authorization".to_string(), "Bearer secret".to_string()),
+                ]),
+                env: BTreeMap::from([("REMOTE_ADDR".to_string(), IP.to_string())]),
+                cookies: Some("session=abc".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        event.exception.values.push(Exception {
+            ty: "Error".to_string(),
+            value: Some(format!("no row for {ID}")),
+            ..Default::default()
+        });
+        event.breadcrumbs.values.push(Breadcrumb {
+            message: Some(format!("loading {ID}")),
+            ..Default::default()
+        });
+        event
+    }
+
+    #[test]
+    fn sanitized_event_has_no_raw_uuid_or_full_ip() {
+        redaction::init_for_tests();
+        let event = sanitize(sample_event());
+        let serialized = serde_json::to_string(&event).unwrap();
+        assert!(!serialized.contains(ID), "{serialized}");
+        assert!(!serialized.contains(IP), "{serialized}");
+        assert!(!serialized.contains("session=abc"), "{serialized}");
+        assert!(!serialized.contains("secret"), "{serialized}");
+
+        let request = event.request.unwrap();
+        assert_eq!(
+            request.query_string.as_deref(),
+            Some("q=daft&user_id=[Filtered]")
+        );
+        assert_eq!(
+            request.url.unwrap().query(),
+            Some("q=daft&user_id=[Filtered]")
+        );
+        assert_eq!(
+            request.headers["X-Forwarded-For"],
+            "203.0.113.0/24, 10.0.0.0/24"
+        );
+        assert_eq!(
+            event.user.unwrap().ip_address,
+            Some(IpAddress::Exact("203.0.113.0".parse().unwrap()))
+        );
+    }
+
+    #[tokio::test]
+    async fn each_server_error_sends_one_tagged_event() {
+        use axum::{
+            Router, body::Body, extract::Request as HttpRequest, http::StatusCode, middleware,
+            routing::get,
+        };
+        use sentry::integrations::tower::{NewSentryLayer, SentryHttpLayer};
+        use sentry::test::TestTransport;
+        use sentry::{ClientOptions, Hub, SentryFutureExt};
+        use tower::ServiceExt;
+
+        use crate::api::error_response;
+
+        redaction::init_for_tests();
+        let transport = TestTransport::new();
+        let client = sentry::Client::from(ClientOptions {
+            dsn: Some("https://public@sentry.example/1".parse().unwrap()),
+            transport: Some(Arc::new(transport.clone())),
+            before_send: Some(Arc::new(|event| Some(sanitize(event)))),
+            ..Default::default()
+        });
+        let hub = Arc::new(Hub::new(Some(Arc::new(client)), Arc::new(Scope::default())));
+        hub.configure_scope(build_tags);
+
+        let app = Router::new()
+            .route(
+                "/fail/{id}",
+                get(|| async { error_response(StatusCode::INTERNAL_SERVER_ERROR, "boom") }),
+            )
+            .route(
+                "/busy",
+                get(|| async { error_response(StatusCode::SERVICE_UNAVAILABLE, "busy") }),
+            )
+            .route(
+                "/missing",
+                get(|| async { error_response(StatusCode::NOT_FOUND, "missing") }),
+            )
+            .layer(middleware::from_fn(request_context))
+            .layer(SentryHttpLayer::new())
+            .layer(NewSentryLayer::<HttpRequest>::new_from_top());
+
+        let mut request_ids = Vec::new();
+        for uri in [
+            format!("/fail/1?user_id={ID}"),
+            "/fail/2".to_string(),
+            "/busy".to_string(),
+            "/missing".to_string(),
+        ] {
+            let req = HttpRequest::get(uri)
+                .header("host", "api.example")
+                .body(Body::empty())
+                .unwrap();
+            let response = app
+                .clone()
+                .oneshot(req)
+                .bind_hub(hub.clone())
+                .await
+                .unwrap();
+            request_ids.push(response.headers()[REQUEST_ID].to_str().unwrap().to_string());
+        }
+
+        let events = transport.fetch_and_clear_events();
+        assert_eq!(events.len(), 2);
+        for (event, request_id) in events.iter().zip(&request_ids) {
+            assert_eq!(event.message.as_deref(), Some("boom"));
+            assert_eq!(event.tags["request_id"], *request_id);
+            assert_eq!(event.tags["route"], "/fail/{id}");
+            assert_eq!(event.tags["app_version"], env!("CARGO_PKG_VERSION"));
+        }
+        let url = events[0].request.as_ref().unwrap().url.as_ref().unwrap();
+        assert_eq!(url.query(), Some("user_id=[Filtered]"));
+        assert_ne!(request_ids[0], request_ids[1]);
+    }
+
+    #[test]
+    fn long_search_queries_are_filtered() {
+        redaction::init_for_tests();
+        let long = "a".repeat(200);
+        assert_eq!(
+            redacted_pairs(&format!("q={long}&page=2")),
+            "q=[Filtered]&page=[Filtered]"
+        );
+        assert_eq!(redacted_pairs("q=abba&&"), "q=abba");
+    }
+}
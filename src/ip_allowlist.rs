@@ -0,0 +1,276 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
+use crate::api::error_response;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                masked(u32::from(net) as u128, 32, self.prefix)
+                    == masked(u32::from(ip) as u128, 32, self.prefix)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                masked(u128::from(net), 128, self.prefix)
+                    == masked(u128::from(ip), 128, self.prefix)
+            }
+            (IpAddr::V4(_), IpAddr::V6(ip)) => ip
+                .to_ipv4_mapped()
+                .is_some_and(|ip| self.contains(IpAddr::V4(ip))),
+            (IpAddr::V6(_), IpAddr::V4(_)) => false,
+        }
+    }
+}
+
+fn masked(bits: u128, width: u8, prefix: u8) -> u128 {
+    if prefix == 0 {
+        0
+    } else {
+        bits >> (width - prefix)
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    /// Parses `addr/prefix`; a bare address is treated as a single-host range.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let network: IpAddr = addr
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid CIDR {s:?}: bad address"))?;
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(p) => p
+                .trim()
+                .parse::<u8>()
+                .ok()
+                .filter(|p| *p <= max)
+                .ok_or_else(|| format!("invalid CIDR {s:?}: bad prefix length"))?,
+            None => max,
+        };
+        Ok(Self { network, prefix })
+    }
+}
+
+/// Parses a comma-separated list of IPv4/IPv6 ranges.
+pub fn parse_cidrs(raw: &str) -> Result<Vec<Cidr>, String> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(Cidr::from_str)
+        .collect()
+}
+
+/// Proxies whose forwarding headers are believed by [`trusted_client_ip`].
+static TRUSTED_PROXIES: RwLock<Vec<Cidr>> = RwLock::new(Vec::new());
+
+/// Set once an ignored `X-Forwarded-For` has been logged, so a misconfigured deploy warns
+/// once rather than on every request.
+static WARNED_UNTRUSTED_FORWARDING: AtomicBool = AtomicBool::new(false);
+
+/// Loads `TRUSTED_PROXY_CIDRS`, the comma-separated ranges of the reverse proxies in front
+/// of the API. Unset means no proxy is trusted and every client is keyed by its peer
+/// address, so behind a proxy all traffic shares the proxy's allowlist entry and rate
+/// limits. Returns the number of ranges now trusted.
+pub fn load_trusted_proxies() -> Result<usize, String> {
+    let raw = std::env::var("TRUSTED_PROXY_CIDRS").unwrap_or_default();
+    let cidrs = parse_cidrs(&raw).map_err(|e| format!("invalid TRUSTED_PROXY_CIDRS: {e}"))?;
+    let count = cidrs.len();
+    *TRUSTED_PROXIES
+        .write()
+        .expect("trusted proxies lock poisoned") = cidrs;
+    Ok(count)
+}
+
+/// The client address for anything keyed or granted by IP: the allowlist, rate limits and
+/// quotas. The peer address is used as is unless it is a trusted proxy; only then is `X-Forwarded-For`
+/// walked from the right, past further trusted proxies, to the first untrusted hop. A
+/// client can prepend anything to that header, so the leftmost entry is never believed.
+/// `None` without connection info.
+pub fn trusted_client_ip(req: &Request) -> Option<IpAddr> {
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip())?;
+    let proxies = TRUSTED_PROXIES
+        .read()
+        .expect("trusted proxies lock poisoned");
+    warn_if_forwarding_ignored(peer, req.headers(), &proxies, &WARNED_UNTRUSTED_FORWARDING);
+    Some(forwarded_client(peer, req.headers(), &proxies))
+}
+
+fn warn_if_forwarding_ignored(
+    peer: IpAddr,
+    headers: &HeaderMap,
+    proxies: &[Cidr],
+    warned: &AtomicBool,
+) {
+    if headers.contains_key("x-forwarded-for")
+        && !proxies.iter().any(|cidr| cidr.contains(peer))
+        && !warned.swap(true, Ordering::Relaxed)
+    {
+        tracing::warn!(
+            peer = %crate::redaction::ip(peer),
+            "ignoring X-Forwarded-For from a peer outside TRUSTED_PROXY_CIDRS; \
+             set it if the API runs behind a reverse proxy"
+        );
+    }
+}
+
+fn forwarded_client(peer: IpAddr, headers: &HeaderMap, proxies: &[Cidr]) -> IpAddr {
+    let trusted = |ip: IpAddr| proxies.iter().any(|cidr| cidr.contains(ip));
+    if !trusted(peer) {
+        return peer;
+    }
+    let hops: Vec<Option<IpAddr>> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .flat_map(|v| v.to_str().unwrap_or_default().split(','))
+        .map(|hop| hop.trim().parse().ok())
+        .collect();
+    for hop in hops.into_iter().rev() {
+        match hop {
+            Some(ip) if trusted(ip) => continue,
+            Some(ip) => return ip,
+            // Nothing left of a malformed hop can be attributed to a trusted proxy.
+            None => break,
+        }
+    }
+    peer
+}
+
+/// Client networks allowed to reach admin and dashboard routes. Empty means unrestricted.
+#[derive(Debug, Clone, Default)]
+pub struct IpAllowlist(Arc<Vec<Cidr>>);
+
+impl IpAllowlist {
+    /// Parses `ADMIN_ALLOWED_CIDRS`, a comma-separated list of IPv4/IPv6 ranges.
+    pub fn from_env() -> Result<Self, String> {
+        let raw = std::env::var("ADMIN_ALLOWED_CIDRS").unwrap_or_default();
+        Ok(Self(Arc::new(parse_cidrs(&raw)?)))
+    }
+
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        self.0.is_empty() || self.0.iter().any(|cidr| cidr.contains(ip))
+    }
+}
+
+pub async fn require_allowed_ip(
+    State(allowlist): State<IpAllowlist>,
+    req: Request,
+    next: Next,
+) -> Response {
+    if allowlist.0.is_empty() {
+        return next.run(req).await;
+    }
+    // Forwarding headers only count when they come through a trusted proxy; otherwise
+    // anyone could claim an allowed address.
+    match trusted_client_ip(&req) {
+        Some(ip) if allowlist.allows(ip) => next.run(req).await,
+        _ => error_response(StatusCode::FORBIDDEN, "Client address not allowed").into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn v4_slash_28_boundaries() {
+        let cidr: Cidr = "192.0.2.16/28".parse().unwrap();
+        assert!(!cidr.contains(ip("192.0.2.15")));
+        assert!(cidr.contains(ip("192.0.2.16")));
+        assert!(cidr.contains(ip("192.0.2.31")));
+        assert!(!cidr.contains(ip("192.0.2.32")));
+        // IPv4-mapped IPv6 peers are matched against IPv4 ranges.
+        assert!(cidr.contains(ip("::ffff:192.0.2.20")));
+    }
+
+    #[test]
+    fn v6_slash_64_boundaries() {
+        let cidr: Cidr = "2001:db8:0:1::/64".parse().unwrap();
+        assert!(!cidr.contains(ip("2001:db8:0:0:ffff:ffff:ffff:ffff")));
+        assert!(cidr.contains(ip("2001:db8:0:1::")));
+        assert!(cidr.contains(ip("2001:db8:0:1:ffff:ffff:ffff:ffff")));
+        assert!(!cidr.contains(ip("2001:db8:0:2::")));
+        assert!(!cidr.contains(ip("192.0.2.1")));
+    }
+
+    #[test]
+    fn rejects_malformed_ranges() {
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("2001:db8::/129".parse::<Cidr>().is_err());
+        assert!("not-an-ip/8".parse::<Cidr>().is_err());
+        assert!(parse_cidrs("").unwrap().is_empty());
+    }
+
+    fn forwarded(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn forwarding_headers_from_untrusted_peers_are_ignored() {
+        let proxies = parse_cidrs("10.0.0.0/8").unwrap();
+        let headers = forwarded("192.0.2.1");
+        assert_eq!(
+            forwarded_client(ip("203.0.113.9"), &headers, &proxies),
+            ip("203.0.113.9")
+        );
+    }
+
+    #[test]
+    fn ignored_forwarding_headers_are_logged_once() {
+        let (logs, _subscriber) = crate::test_support::capture_logs();
+        let proxies = parse_cidrs("10.0.0.0/8").unwrap();
+        let warned = AtomicBool::new(false);
+        warn_if_forwarding_ignored(ip("10.0.0.1"), &forwarded("192.0.2.1"), &proxies, &warned);
+        warn_if_forwarding_ignored(ip("203.0.113.9"), &HeaderMap::new(), &proxies, &warned);
+        assert!(logs.contents().is_empty());
+        for _ in 0..2 {
+            warn_if_forwarding_ignored(
+                ip("203.0.113.9"),
+                &forwarded("192.0.2.1"),
+                &proxies,
+                &warned,
+            );
+        }
+        assert_eq!(logs.contents().matches("TRUSTED_PROXY_CIDRS").count(), 1);
+    }
+
+    #[test]
+    fn forwarding_headers_are_walked_from_the_right() {
+        let proxies = parse_cidrs("10.0.0.0/8").unwrap();
+        // The client prepended a spoofed hop; only the one the proxy appended counts.
+        let headers = forwarded("192.0.2.1, 203.0.113.9, 10.0.0.2");
+        assert_eq!(
+            forwarded_client(ip("10.0.0.1"), &headers, &proxies),
+            ip("203.0.113.9")
+        );
+    }
+}
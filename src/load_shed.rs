@@ -0,0 +1,34 @@
+use axum::{
+    BoxError, Router,
+    error_handling::HandleErrorLayer,
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use tower::ServiceBuilder;
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower::load_shed::LoadShedLayer;
+
+use crate::api::error_response;
+
+pub fn shed_load<S>(router: Router<S>, max_in_flight: usize) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    router.layer(
+        ServiceBuilder::new()
+            .layer(HandleErrorLayer::new(overloaded))
+            .layer(LoadShedLayer::new())
+            .layer(GlobalConcurrencyLimitLayer::new(max_in_flight)),
+    )
+}
+
+async fn overloaded(_: BoxError) -> Response {
+    (
+        [(header::RETRY_AFTER, "1")],
+        error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Server is busy, retry shortly",
+        ),
+    )
+        .into_response()
+}
@@ -1,68 +1,214 @@
 use anyhow::{Result, anyhow};
-use reqwest::Client;
+use reqwest::{Client, RequestBuilder};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use crate::models::metadata::{ExplicitFilter, ItemType};
+use crate::search::{
+    SchemaStatus, SearchBackendError, SearchBackendTimeout, SearchQuery, Suggestion,
+};
+use crate::synonyms::{self, Term};
 
 pub struct SearchClient {
     http: Client,
     url: String,
     index_name: String,
+    /// Extra attempts for requests that failed to connect.
+    retries: u32,
+}
+
+fn env_millis(name: &str, default: u64) -> Duration {
+    Duration::from_millis(
+        std::env::var(name)
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|v| *v > 0)
+            .unwrap_or(default),
+    )
+}
+
+/// Transport failures as typed errors, so handlers answer 504 or 502 rather than 500.
+fn transport_error(context: &str, e: reqwest::Error) -> anyhow::Error {
+    if e.is_timeout() {
+        SearchBackendTimeout(format!("{context} timed out: {e}")).into()
+    } else {
+        SearchBackendError(format!("{context} failed: {e}")).into()
+    }
+}
+
+/// The `error` field of a Manticore response, which is either a string or an object with
+/// a `reason`.
+fn error_message(response: &serde_json::Value) -> Option<String> {
+    match &response["error"] {
+        serde_json::Value::String(s) if !s.is_empty() => Some(s.clone()),
+        serde_json::Value::Object(obj) => Some(
+            obj.get("reason")
+                .and_then(|r| r.as_str())
+                .map(str::to_string)
+                .unwrap_or_else(|| response["error"].to_string()),
+        ),
+        _ => None,
+    }
+}
+
+/// Ids as a comma-separated list of SQL string literals, for `doc_id IN (...)`.
+fn quote_ids(doc_ids: &[String]) -> String {
+    doc_ids
+        .iter()
+        .map(|id| format!("'{}'", id.replace('\\', "\\\\").replace('\'', "\\'")))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Escapes Manticore query-language operators in a single term.
+fn escape_term(term: &str) -> String {
+    let mut escaped = String::with_capacity(term.len());
+    for c in term.chars() {
+        if "\\()|-!@~\"&/^$=<'".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
 }
 
+/// Plain `match` on `name`, or a `query_string` with synonym alternatives when any token
+/// of the query belongs to a synonym group.
+fn name_clause(name: &str) -> serde_json::Value {
+    let terms: Vec<Term> = synonyms::tokens(name)
+        .iter()
+        .map(|t| synonyms::expand(t))
+        .collect();
+    if terms.iter().all(|t| matches!(t, Term::Plain(_))) {
+        return serde_json::json!({ "match": { "name": name } });
+    }
+
+    let expr: Vec<String> = terms
+        .iter()
+        .filter_map(|term| match term {
+            Term::Plain(t) => Some(escape_term(t)),
+            Term::AnyOf(forms) => Some(format!(
+                "({})",
+                forms
+                    .iter()
+                    .map(|f| escape_term(f))
+                    .collect::<Vec<_>>()
+                    .join(" | ")
+            )),
+            Term::Optional => None,
+        })
+        .collect();
+    serde_json::json!({ "query_string": format!("@name {}", expr.join(" ")) })
+}
+
+/// Version of the document layout this binary writes. Bump it when documents gain fields
+/// that older syncs didn't fill, so indexes built before the change report as outdated
+/// until a full sync rebuilds them.
+pub const SCHEMA_VERSION: i64 = 4;
+
+const RANKER: &str =
+    "expr('sum((4*lcs+2*(min_hit_pos==1)+exact_hit)*user_weight)*1000+bm25+ln(1+popularity)*100')";
+
 impl SearchClient {
     pub fn new(manticore_url: &str) -> Result<Self> {
+        let retries = std::env::var("MANTICORE_RETRIES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(1);
+        Self::with_timeouts(
+            manticore_url,
+            env_millis("MANTICORE_TIMEOUT_MS", 10_000),
+            env_millis("MANTICORE_CONNECT_TIMEOUT_MS", 5_000),
+            retries,
+        )
+    }
+
+    pub fn with_timeouts(
+        manticore_url: &str,
+        timeout: Duration,
+        connect_timeout: Duration,
+        retries: u32,
+    ) -> Result<Self> {
         let http = Client::builder()
-            .timeout(std::time::Duration::from_secs(10))
-            .connect_timeout(std::time::Duration::from_secs(5))
-            .tcp_keepalive(std::time::Duration::from_secs(30))
-            .pool_idle_timeout(std::time::Duration::from_secs(90))
+            .timeout(timeout)
+            .connect_timeout(connect_timeout)
+            .tcp_keepalive(Duration::from_secs(30))
+            .pool_idle_timeout(Duration::from_secs(90))
             .build()
             .map_err(|e| anyhow!("failed to build http client: {e}"))?;
         Ok(Self {
             http,
             url: manticore_url.trim_end_matches('/').to_string(),
             index_name: "music".to_string(),
+            retries,
         })
     }
 
-    async fn sql(&self, query: &str) -> Result<serde_json::Value> {
-        let resp = self
-            .http
-            .post(format!("{}/sql", self.url))
-            .form(&[("query", query)])
-            .send()
-            .await
-            .map_err(|e| anyhow!("manticore request failed: {e}"))?;
-
+    /// Sends `request` and reads its body. Only connection failures are retried: the request
+    /// never reached Manticore, so sending it again can't apply it twice.
+    async fn send(&self, request: RequestBuilder) -> Result<(reqwest::StatusCode, String)> {
+        let mut attempt = 0;
+        let resp = loop {
+            let Some(req) = request.try_clone() else {
+                return Err(anyhow!("manticore request body can't be retried"));
+            };
+            match req.send().await {
+                Ok(resp) => break resp,
+                Err(e) if e.is_connect() && attempt < self.retries => {
+                    attempt += 1;
+                    metrics::counter!("search_retries_total").increment(1);
+                    tracing::warn!("manticore connect failed, retrying: {}", e);
+                }
+                Err(e) => return Err(transport_error("manticore request", e)),
+            }
+        };
         let status = resp.status();
         let body = resp
             .text()
             .await
-            .map_err(|e| anyhow!("failed to read manticore response: {e}"))?;
+            .map_err(|e| transport_error("reading manticore response", e))?;
+        Ok((status, body))
+    }
+
+    pub fn index_name(&self) -> &str {
+        &self.index_name
+    }
+
+    async fn sql(&self, query: &str) -> Result<serde_json::Value> {
+        let (status, body) = self
+            .send(
+                self.http
+                    .post(format!("{}/sql", self.url))
+                    .form(&[("query", query)]),
+            )
+            .await?;
 
         if !status.is_success() {
-            return Err(anyhow!("manticore error {status}: {body}"));
+            return Err(SearchBackendError(format!("manticore error {status}: {body}")).into());
+        }
+
+        let parsed: serde_json::Value = serde_json::from_str(&body)
+            .map_err(|e| anyhow!("failed to parse manticore response: {e}, body: {body}"))?;
+
+        // Errors can arrive with a 200 and no hits, which would otherwise read as empty.
+        if let Some(err) = error_message(&parsed) {
+            return Err(SearchBackendError(format!("manticore sql error: {err}")).into());
         }
 
-        serde_json::from_str(&body)
-            .map_err(|e| anyhow!("failed to parse manticore response: {e}, body: {body}"))
+        Ok(parsed)
     }
 
     async fn sql_raw(&self, query: &str) -> Result<serde_json::Value> {
-        let resp = self
-            .http
-            .post(format!("{}/sql?mode=raw", self.url))
-            .form(&[("query", query)])
-            .send()
-            .await
-            .map_err(|e| anyhow!("manticore request failed: {e}"))?;
-
-        let status = resp.status();
-        let body = resp
-            .text()
-            .await
-            .map_err(|e| anyhow!("failed to read manticore response: {e}"))?;
+        let (status, body) = self
+            .send(
+                self.http
+                    .post(format!("{}/sql?mode=raw", self.url))
+                    .form(&[("query", query)]),
+            )
+            .await?;
 
         if !status.is_success() {
-            return Err(anyhow!("manticore error {status}: {body}"));
+            return Err(SearchBackendError(format!("manticore error {status}: {body}")).into());
         }
 
         let parsed: serde_json::Value = serde_json::from_str(&body)
@@ -71,33 +217,29 @@ impl SearchClient {
         if let Some(err) = parsed[0]["error"].as_str()
             && !err.is_empty()
         {
-            return Err(anyhow!("manticore sql error: {err}"));
+            return Err(SearchBackendError(format!("manticore sql error: {err}")).into());
         }
 
         Ok(parsed)
     }
 
     async fn search_json(&self, body: serde_json::Value) -> Result<serde_json::Value> {
-        let resp = self
-            .http
-            .post(format!("{}/search", self.url))
-            .json(&body)
-            .send()
-            .await
-            .map_err(|e| anyhow!("manticore request failed: {e}"))?;
-
-        let status = resp.status();
-        let text = resp
-            .text()
-            .await
-            .map_err(|e| anyhow!("failed to read manticore response: {e}"))?;
+        let (status, text) = self
+            .send(self.http.post(format!("{}/search", self.url)).json(&body))
+            .await?;
 
         if !status.is_success() {
-            return Err(anyhow!("manticore error {status}: {text}"));
+            return Err(SearchBackendError(format!("manticore error {status}: {text}")).into());
+        }
+
+        let parsed: serde_json::Value = serde_json::from_str(&text)
+            .map_err(|e| anyhow!("failed to parse manticore response: {e}, body: {text}"))?;
+
+        if let Some(err) = error_message(&parsed) {
+            return Err(SearchBackendError(format!("manticore search error: {err}")).into());
         }
 
-        serde_json::from_str(&text)
-            .map_err(|e| anyhow!("failed to parse manticore response: {e}, body: {text}"))
+        Ok(parsed)
     }
 
     pub async fn create_index(&self) -> Result<()> {
@@ -109,36 +251,127 @@ impl SearchClient {
                 album_name text,
                 item_type string,
                 duration int,
-                date string
+                date string,
+                genres text,
+                popularity float,
+                label string,
+                explicit bool
             ) min_prefix_len='3'"#,
             self.index_name
         );
 
         let response = self.sql_raw(&create_sql).await?;
         tracing::info!("create table {} response: {}", self.index_name, response);
+        self.ensure_column("genres", "text").await?;
+        self.ensure_column("popularity", "float").await?;
+        self.ensure_column("label", "string").await?;
+        self.ensure_column("explicit", "bool").await?;
+
+        self.sql_raw(&format!(
+            "CREATE TABLE IF NOT EXISTS {} (note text, version int)",
+            self.meta_table()
+        ))
+        .await?;
+        // Only an empty index is known to match this binary's layout; one with documents
+        // and no version predates versioning and keeps reporting so until a full sync.
+        if self.schema_version().await?.is_none() && self.count().await? == 0 {
+            self.set_schema_version(SCHEMA_VERSION).await?;
+        }
+        Ok(())
+    }
+
+    fn meta_table(&self) -> String {
+        format!("{}_meta", self.index_name)
+    }
+
+    /// The schema version recorded by the last full sync, `None` for an unversioned index.
+    pub async fn schema_version(&self) -> Result<Option<i64>> {
+        let response = self
+            .sql(&format!(
+                "SELECT version FROM {} WHERE id = 1",
+                self.meta_table()
+            ))
+            .await?;
+        Ok(response["hits"]["hits"][0]["_source"]["version"].as_i64())
+    }
+
+    pub async fn set_schema_version(&self, version: i64) -> Result<()> {
+        self.sql_raw(&format!(
+            "REPLACE INTO {} (id, note, version) VALUES (1, 'schema', {version})",
+            self.meta_table()
+        ))
+        .await?;
+        Ok(())
+    }
+
+    /// Compares the index's schema version with the one this binary writes.
+    pub async fn healthcheck(&self) -> Result<SchemaStatus> {
+        Ok(SchemaStatus {
+            expected: SCHEMA_VERSION,
+            actual: self.schema_version().await?,
+        })
+    }
+
+    /// Adds a column introduced after the table was first created. Existing documents
+    /// keep an empty value until the next full sync.
+    async fn ensure_column(&self, column: &str, column_type: &str) -> Result<()> {
+        let described = self
+            .sql_raw(&format!("DESCRIBE {}", self.index_name))
+            .await?;
+        let empty_vec: Vec<serde_json::Value> = vec![];
+        let exists = described[0]["data"]
+            .as_array()
+            .unwrap_or(&empty_vec)
+            .iter()
+            .any(|row| row["Field"].as_str() == Some(column));
+        if !exists {
+            tracing::info!("adding column {} to {}", column, self.index_name);
+            self.sql_raw(&format!(
+                "ALTER TABLE {} ADD COLUMN {} {}",
+                self.index_name, column, column_type
+            ))
+            .await?;
+        }
         Ok(())
     }
 
     pub async fn search(
         &self,
-        item_type: &str,
-        name: Option<&str>,
-        artist: Option<&str>,
-        album: Option<&str>,
+        item_type: ItemType,
+        query: &SearchQuery<'_>,
         limit: i32,
         offset: i32,
     ) -> Result<Vec<(String, String, String, String)>> {
         let mut must: Vec<serde_json::Value> =
-            vec![serde_json::json!({ "equals": { "item_type": item_type } })];
-        if let Some(n) = name {
-            must.push(serde_json::json!({ "match": { "name": n } }));
+            vec![serde_json::json!({ "equals": { "item_type": item_type.as_str() } })];
+        if let Some(n) = query.name {
+            must.push(name_clause(n));
+        }
+        if !query.genres.is_empty() {
+            let any_genre: Vec<serde_json::Value> = query
+                .genres
+                .iter()
+                .map(|g| serde_json::json!({ "match_phrase": { "genres": g } }))
+                .collect();
+            must.push(serde_json::json!({ "bool": { "should": any_genre } }));
+        }
+        if let Some(label) = &query.label {
+            must.push(serde_json::json!({ "equals": { "label": label } }));
+        }
+        let explicit = match query.explicit {
+            ExplicitFilter::Include => None,
+            ExplicitFilter::Exclude => Some(0),
+            ExplicitFilter::Only => Some(1),
+        };
+        if let Some(explicit) = explicit {
+            must.push(serde_json::json!({ "equals": { "explicit": explicit } }));
         }
 
         let mut should: Vec<serde_json::Value> = vec![];
-        if let Some(a) = artist {
+        if let Some(a) = query.artist {
             should.push(serde_json::json!({ "match": { "artist_name": a } }));
         }
-        if let Some(a) = album {
+        if let Some(a) = query.album {
             should.push(serde_json::json!({ "match": { "album_name": a } }));
         }
 
@@ -148,12 +381,26 @@ impl SearchClient {
             serde_json::json!({ "bool": { "must": must, "should": should } })
         };
 
+        // The window is fetched from the top and cut here, after duplicate doc_ids are
+        // dropped, so pages line up with the memory backend's.
+        let (limit, offset) = (limit.max(0), offset.max(0));
+        let window = limit.saturating_add(offset);
         let body = serde_json::json!({
             "index": self.index_name,
             "query": query,
             "source": ["doc_id", "name", "artist_name", "album_name"],
-            "limit": limit,
-            "offset": offset,
+            "limit": window,
+            // Same tie-breaks as the memory backend: popularity, then doc_id.
+            "sort": [{ "_score": "desc" }, { "popularity": "desc" }, { "doc_id": "asc" }],
+            // The sph04 formula, which boosts hits at the start of a field and exact field
+            // matches on top of proximity/BM25 so "Hello" ranks the song literally titled
+            // "Hello" above fuzzy and infix matches, plus a popularity term that breaks ties
+            // between equally good text matches. Unscored documents have popularity 0.
+            "options": {
+                "ranker": RANKER,
+                "field_weights": { "name": 10, "artist_name": 3, "album_name": 2 },
+                "max_matches": window.max(1000),
+            },
         });
 
         let response = self.search_json(body).await?;
@@ -178,11 +425,49 @@ impl SearchClient {
                 Some((id, name, artist, album))
             })
             .filter(|(id, _, _, _)| seen.insert(id.clone()))
+            .skip(offset as usize)
+            .take(limit as usize)
             .collect();
 
         Ok(candidates)
     }
 
+    /// Runs `CALL QSUGGEST`, which corrects the last word of `text` against the index
+    /// dictionary.
+    pub async fn suggest(&self, text: &str) -> Result<Option<Suggestion>> {
+        let escaped = text.replace('\\', "\\\\").replace('\'', "\\'");
+        let response = self
+            .sql_raw(&format!(
+                "CALL QSUGGEST('{}', '{}')",
+                escaped, self.index_name
+            ))
+            .await?;
+        let empty_vec: Vec<serde_json::Value> = vec![];
+        let best = response[0]["data"]
+            .as_array()
+            .unwrap_or(&empty_vec)
+            .iter()
+            .filter(|row| row["distance"].as_i64().unwrap_or(0) > 0)
+            .max_by_key(|row| row["docs"].as_i64().unwrap_or(0));
+        let Some(best) = best else {
+            return Ok(None);
+        };
+        let Some(word) = best["suggest"].as_str() else {
+            return Ok(None);
+        };
+
+        // QSUGGEST only corrects the last word; keep the rest of the query as typed.
+        let prefix = text
+            .trim_end()
+            .rsplit_once(char::is_whitespace)
+            .map(|(head, _)| format!("{head} "))
+            .unwrap_or_default();
+        Ok(Some(Suggestion {
+            text: format!("{prefix}{word}"),
+            docs: best["docs"].as_i64().unwrap_or(0),
+        }))
+    }
+
     pub async fn ping(&self) -> Result<()> {
         let body = serde_json::json!({
             "index": self.index_name,
@@ -205,4 +490,114 @@ impl SearchClient {
 
         Ok(hits[0]["_source"]["cnt"].as_i64().unwrap_or(0))
     }
+
+    pub async fn count_by_type(&self) -> Result<HashMap<String, i64>> {
+        let sql = format!(
+            "SELECT item_type, COUNT(*) as cnt FROM {} GROUP BY item_type",
+            self.index_name
+        );
+        let response = self.sql(&sql).await?;
+        let empty_vec: Vec<serde_json::Value> = vec![];
+        let hits = response["hits"]["hits"].as_array().unwrap_or(&empty_vec);
+
+        Ok(hits
+            .iter()
+            .filter_map(|h| {
+                let item_type = h["_source"]["item_type"].as_str()?.to_string();
+                Some((item_type, h["_source"]["cnt"].as_i64().unwrap_or(0)))
+            })
+            .collect())
+    }
+
+    pub async fn truncate(&self) -> Result<()> {
+        self.sql_raw(&format!("TRUNCATE TABLE {}", self.index_name))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete_documents(&self, doc_ids: &[String]) -> Result<()> {
+        if doc_ids.is_empty() {
+            return Ok(());
+        }
+        self.sql_raw(&format!(
+            "DELETE FROM {} WHERE doc_id IN ({})",
+            self.index_name,
+            quote_ids(doc_ids)
+        ))
+        .await?;
+        Ok(())
+    }
+
+    /// Which of `doc_ids` have a document of `item_type` in the index.
+    pub async fn existing_ids(
+        &self,
+        item_type: &str,
+        doc_ids: &[String],
+    ) -> Result<HashSet<String>> {
+        if doc_ids.is_empty() {
+            return Ok(HashSet::new());
+        }
+        let sql = format!(
+            "SELECT doc_id FROM {} WHERE item_type = '{}' AND doc_id IN ({}) LIMIT {n} OPTION max_matches = {n}",
+            self.index_name,
+            item_type,
+            quote_ids(doc_ids),
+            n = doc_ids.len()
+        );
+        let response = self.sql(&sql).await?;
+        let empty_vec: Vec<serde_json::Value> = vec![];
+        let hits = response["hits"]["hits"].as_array().unwrap_or(&empty_vec);
+        Ok(hits
+            .iter()
+            .filter_map(|h| h["_source"]["doc_id"].as_str().map(str::to_string))
+            .collect())
+    }
+
+    pub async fn bulk_insert(&self, docs: &[serde_json::Value]) -> Result<()> {
+        let mut body = String::new();
+        for doc in docs {
+            let line = serde_json::json!({
+                "insert": {
+                    "table": self.index_name,
+                    "doc": {
+                        "doc_id": doc["doc_id"].as_str().unwrap_or(""),
+                        "name": doc["name"].as_str().unwrap_or(""),
+                        "artist_name": doc["artist_name"].as_str().unwrap_or(""),
+                        "album_name": doc["album_name"].as_str().unwrap_or(""),
+                        "item_type": doc["item_type"].as_str().unwrap_or(""),
+                        "duration": doc["duration"].as_i64().unwrap_or(0),
+                        "date": doc["date"].as_str().unwrap_or(""),
+                        "genres": doc["genres"].as_str().unwrap_or(""),
+                        "popularity": doc["popularity"].as_f64().unwrap_or(0.0),
+                        "label": doc["label"].as_str().unwrap_or(""),
+                        "explicit": doc["explicit"].as_bool().unwrap_or(false) as i32
+                    }
+                }
+            });
+            body.push_str(&line.to_string());
+            body.push('\n');
+        }
+
+        let (status, text) = self
+            .send(
+                self.http
+                    .post(format!("{}/bulk", self.url))
+                    .header("Content-Type", "application/x-ndjson")
+                    .body(body),
+            )
+            .await?;
+
+        if !status.is_success() {
+            return Err(anyhow!("manticore bulk error {status}: {text}"));
+        }
+
+        let parsed: serde_json::Value = serde_json::from_str(&text)
+            .map_err(|e| anyhow!("failed to parse bulk response: {e}, body: {text}"))?;
+
+        if parsed["errors"].as_bool().unwrap_or(false) {
+            return Err(anyhow!("manticore bulk returned errors: {text}"));
+        }
+
+        Ok(())
+    }
 }
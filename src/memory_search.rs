@@ -0,0 +1,169 @@
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+use crate::models::metadata::ItemType;
+use crate::search::{SearchQuery, Suggestion};
+use crate::synonyms;
+use crate::text;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MemoryDocument {
+    pub doc_id: String,
+    pub name: String,
+    #[serde(default)]
+    pub artist_name: String,
+    #[serde(default)]
+    pub album_name: String,
+    pub item_type: String,
+    #[serde(default)]
+    pub genres: Vec<String>,
+    #[serde(default)]
+    pub popularity: f64,
+    #[serde(default)]
+    pub label: String,
+    #[serde(default)]
+    pub explicit: bool,
+}
+
+pub struct MemorySearchClient {
+    documents: Vec<MemoryDocument>,
+}
+
+impl MemorySearchClient {
+    pub fn from_fixture(path: &str) -> Result<Self> {
+        let raw = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("failed to read search fixture {path}: {e}"))?;
+        let documents: Vec<MemoryDocument> = serde_json::from_str(&raw)
+            .map_err(|e| anyhow!("failed to parse search fixture {path}: {e}"))?;
+        Ok(Self { documents })
+    }
+
+    pub fn count(&self) -> i64 {
+        self.documents.len() as i64
+    }
+
+    pub fn count_by_type(&self) -> HashMap<String, i64> {
+        let mut counts = HashMap::new();
+        for doc in &self.documents {
+            *counts.entry(doc.item_type.clone()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    pub fn existing_ids(&self, item_type: ItemType, doc_ids: &[String]) -> HashSet<String> {
+        self.documents
+            .iter()
+            .filter(|d| d.item_type == item_type.as_str() && doc_ids.contains(&d.doc_id))
+            .map(|d| d.doc_id.clone())
+            .collect()
+    }
+
+    /// Closest document name by Jaro-Winkler similarity, if it is close but not equal.
+    pub fn suggest(&self, item_type: ItemType, name: &str) -> Option<Suggestion> {
+        let query = text::normalize(name.trim());
+        let (best, score) = self
+            .documents
+            .iter()
+            .filter(|d| d.item_type == item_type.as_str())
+            .map(|d| {
+                let score = strsim::jaro_winkler(&text::normalize(&d.name), &query);
+                (d, score)
+            })
+            .max_by(|(_, s1), (_, s2)| s1.total_cmp(s2))?;
+        let best_normalized = text::normalize(&best.name);
+        if best_normalized == query || score < 0.85 {
+            return None;
+        }
+        let docs = self
+            .documents
+            .iter()
+            .filter(|d| text::normalize(&d.name) == best_normalized)
+            .count() as i64;
+        Some(Suggestion {
+            text: best.name.to_lowercase(),
+            docs,
+        })
+    }
+
+    pub fn search(
+        &self,
+        item_type: ItemType,
+        query: &SearchQuery<'_>,
+        limit: i32,
+        offset: i32,
+    ) -> Vec<(String, String, String, String)> {
+        let name = query.name.map(synonyms::canonical);
+        let artist = query.artist.map(synonyms::canonical);
+        let album = query.album.map(synonyms::canonical);
+
+        let mut scored: Vec<(f64, &MemoryDocument)> = self
+            .documents
+            .iter()
+            .filter(|d| d.item_type == item_type.as_str())
+            .filter(|d| {
+                query.genres.is_empty()
+                    || d.genres
+                        .iter()
+                        .any(|g| query.genres.contains(&g.to_lowercase()))
+            })
+            .filter(|d| {
+                query
+                    .label
+                    .as_ref()
+                    .is_none_or(|l| d.label.trim().to_lowercase() == *l)
+            })
+            .filter(|d| query.explicit.allows(Some(d.explicit)))
+            .filter_map(|d| {
+                let mut score = 0.0;
+                if let Some(q) = &name {
+                    let n = synonyms::canonical(&d.name);
+                    score += if n == *q {
+                        3.0
+                    } else if n.starts_with(q.as_str()) {
+                        2.0
+                    } else if n.contains(q.as_str()) {
+                        1.0
+                    } else {
+                        return None;
+                    };
+                }
+                if artist
+                    .as_ref()
+                    .is_some_and(|q| synonyms::canonical(&d.artist_name).contains(q.as_str()))
+                {
+                    score += 0.5;
+                }
+                if album
+                    .as_ref()
+                    .is_some_and(|q| synonyms::canonical(&d.album_name).contains(q.as_str()))
+                {
+                    score += 0.5;
+                }
+                Some((score, d))
+            })
+            .collect();
+
+        scored.sort_by(|(s1, d1), (s2, d2)| {
+            s2.total_cmp(s1)
+                .then_with(|| d2.popularity.total_cmp(&d1.popularity))
+                .then_with(|| d1.doc_id.cmp(&d2.doc_id))
+        });
+
+        let mut seen = HashSet::new();
+        scored
+            .into_iter()
+            .filter(|(_, d)| seen.insert(d.doc_id.as_str()))
+            .skip(offset.max(0) as usize)
+            .take(limit.max(0) as usize)
+            .map(|(_, d)| {
+                (
+                    d.doc_id.clone(),
+                    d.name.clone(),
+                    d.artist_name.clone(),
+                    d.album_name.clone(),
+                )
+            })
+            .collect()
+    }
+}
@@ -1,4 +1,7 @@
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use time::{Date, Month, OffsetDateTime};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Artist {
@@ -6,6 +9,39 @@ pub struct Artist {
     pub name: String,
     pub image: String,
     pub genres: Vec<String>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub created_at: Option<OffsetDateTime>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub updated_at: Option<OffsetDateTime>,
+    /// Only computed for artist detail; zero when the artist is embedded in another entity.
+    #[serde(default)]
+    pub album_count: i64,
+    #[serde(default)]
+    pub song_count: i64,
+    /// Where `image` came from; only known for artist detail.
+    #[serde(default)]
+    pub image_source: Option<ImageSource>,
+    #[serde(default)]
+    pub apple_music_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageSource {
+    Artist,
+    /// The artist has no image of their own, so their most recent album's artwork is used.
+    AlbumFallback,
+    None,
+}
+
+impl ImageSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ImageSource::Artist => "artist",
+            ImageSource::AlbumFallback => "album_fallback",
+            ImageSource::None => "none",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -13,7 +49,10 @@ pub struct Song {
     pub id: String,
     pub name: String,
     pub artist: Vec<Artist>,
+    /// Primary album first: earliest release date, then lowest id.
     pub album: Vec<Album>,
+    #[serde(default)]
+    pub primary_album_id: Option<String>,
     pub genres: Vec<String>,
     pub image: String,
     #[serde(rename = "disc_number")]
@@ -23,6 +62,43 @@ pub struct Song {
     pub duration: i32,
     pub isrc: String,
     pub date: String,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub created_at: Option<OffsetDateTime>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub updated_at: Option<OffsetDateTime>,
+    /// Set when the scraper marks the track as removed or region-blocked.
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub deleted_at: Option<OffsetDateTime>,
+    /// Log-scaled 30-day play count; `None` until the song has been scored.
+    #[serde(default)]
+    pub popularity: Option<f64>,
+    /// `None` when the source gave no content rating; filters treat that as not explicit.
+    #[serde(default)]
+    pub explicit: Option<bool>,
+    #[serde(default)]
+    pub apple_music_id: Option<String>,
+}
+
+/// The `explicit` parameter: whether explicit songs are kept, dropped or the only ones kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExplicitFilter {
+    #[default]
+    Include,
+    Exclude,
+    Only,
+}
+
+impl ExplicitFilter {
+    /// Songs with an unknown rating count as not explicit.
+    pub fn allows(self, explicit: Option<bool>) -> bool {
+        let explicit = explicit.unwrap_or(false);
+        match self {
+            ExplicitFilter::Include => true,
+            ExplicitFilter::Exclude => !explicit,
+            ExplicitFilter::Only => explicit,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -35,6 +111,339 @@ pub struct Album {
     pub date: String,
     #[serde(rename = "track_count")]
     pub track_count: i32,
+    /// Available songs actually linked to the album, which can disagree with `track_count`.
+    #[serde(default)]
+    pub available_track_count: i32,
     pub upc: String,
+    /// Record label, trimmed; `None` when the scraper has none.
+    #[serde(default)]
     pub label: Option<String>,
+    #[serde(default)]
+    pub is_compilation: bool,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub created_at: Option<OffsetDateTime>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub updated_at: Option<OffsetDateTime>,
+    #[serde(default)]
+    pub apple_music_id: Option<String>,
+}
+
+pub const VARIOUS_ARTISTS: &str = "Various Artists";
+
+/// Compilations are the albums the catalog credits to "Various Artists". The number of
+/// credited artists says nothing on its own: soundtracks, orchestral recordings and
+/// collaboration albums credit dozens.
+pub fn is_compilation(artists: &[Artist]) -> bool {
+    artists
+        .iter()
+        .any(|a| a.name.eq_ignore_ascii_case(VARIOUS_ARTISTS))
+}
+
+/// The kinds of entity the API serves, as accepted in `type` parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ItemType {
+    Song,
+    Album,
+    Artist,
+}
+
+impl ItemType {
+    pub const VALID: &'static str = "song, album, artist";
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ItemType::Song => "song",
+            ItemType::Album => "album",
+            ItemType::Artist => "artist",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidItemType(pub String);
+
+impl FromStr for ItemType {
+    type Err = InvalidItemType;
+
+    /// Case-insensitive, and accepts plurals plus `track` for songs.
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        match raw.trim().to_lowercase().as_str() {
+            "song" | "songs" | "track" | "tracks" => Ok(ItemType::Song),
+            "album" | "albums" => Ok(ItemType::Album),
+            "artist" | "artists" => Ok(ItemType::Artist),
+            _ => Err(InvalidItemType(raw.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for ItemType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OmId {
+    pub item_type: String,
+    pub id: String,
+}
+
+#[derive(Debug)]
+pub struct InvalidOmId;
+
+impl FromStr for OmId {
+    type Err = InvalidOmId;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let raw = raw.trim().to_lowercase();
+        let parts: Vec<&str> = raw.splitn(3, ':').collect();
+        if parts.len() != 3 || parts[0] != "omm" {
+            return Err(InvalidOmId);
+        }
+        let item_type = parts[1];
+        let id = parts[2];
+        if !matches!(item_type, "song" | "album" | "artist") || !is_valid_omid(id) {
+            return Err(InvalidOmId);
+        }
+        Ok(OmId {
+            item_type: item_type.to_string(),
+            id: id.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for OmId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "omm:{}:{}", self.item_type, self.id)
+    }
+}
+
+/// Apple Music catalog ids are numeric.
+pub fn is_valid_apple_music_id(id: &str) -> bool {
+    (1..=20).contains(&id.len()) && id.bytes().all(|b| b.is_ascii_digit())
+}
+
+pub fn is_valid_omid(id: &str) -> bool {
+    id.len() == 16
+        && id
+            .chars()
+            .all(|c| c.is_ascii_digit() || c.is_ascii_lowercase())
+}
+
+/// Normalizes an ISRC (`CC-XXX-YY-NNNNN`, dashes optional) to its 12-character
+/// upper-case form, or returns `None` when it is malformed.
+pub fn normalize_isrc(raw: &str) -> Option<String> {
+    let isrc: String = raw.trim().replace('-', "").to_uppercase();
+    let b = isrc.as_bytes();
+    let valid = b.len() == 12
+        && b[..2].iter().all(u8::is_ascii_uppercase)
+        && b[2..5].iter().all(u8::is_ascii_alphanumeric)
+        && b[5..].iter().all(u8::is_ascii_digit);
+    valid.then_some(isrc)
+}
+
+/// Normalizes a UPC/EAN (12 to 14 digits, dashes optional), or returns `None` when it
+/// is malformed.
+pub fn normalize_upc(raw: &str) -> Option<String> {
+    let upc: String = raw.trim().replace('-', "");
+    ((12..=14).contains(&upc.len()) && upc.bytes().all(|c| c.is_ascii_digit())).then_some(upc)
+}
+
+/// How much of a parsed release date the raw value actually gave; the missing parts of the
+/// stored date are the first month or day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DatePrecision {
+    Year,
+    Month,
+    Day,
+}
+
+impl DatePrecision {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DatePrecision::Year => "year",
+            DatePrecision::Month => "month",
+            DatePrecision::Day => "day",
+        }
+    }
+}
+
+const MONTHS: [&str; 12] = [
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+
+/// Parses a scraped release date: `2019`, `2019-07`, `2019-07-12` (also with `/` or `.`
+/// separators or a trailing time), `July 2019`, `Jul. 2019`, `12 July 2019` and
+/// `July 12, 2019`. Ambiguous day-first numeric dates like `12/07/2019` are rejected.
+pub fn parse_release_date(raw: &str) -> Option<(Date, DatePrecision)> {
+    let raw = raw.trim();
+    let raw = match raw.get(..10) {
+        Some(date) if raw[10..].starts_with(['T', ' ']) => date,
+        _ => raw,
+    };
+    if raw.is_empty() {
+        return None;
+    }
+
+    if raw
+        .bytes()
+        .all(|b| b.is_ascii_digit() || matches!(b, b'-' | b'/' | b'.'))
+    {
+        let parts: Vec<&str> = raw.split(['-', '/', '.']).collect();
+        let year = year(parts[0])?;
+        let number = |s: &str| {
+            (1..=2).contains(&s.len()).then_some(())?;
+            s.parse::<u8>().ok()
+        };
+        return match parts[1..] {
+            [] => calendar_date(year, 1, 1, DatePrecision::Year),
+            [month] => calendar_date(year, number(month)?, 1, DatePrecision::Month),
+            [month, day] => calendar_date(year, number(month)?, number(day)?, DatePrecision::Day),
+            _ => None,
+        };
+    }
+
+    let tokens: Vec<&str> = raw
+        .split(|c: char| c.is_whitespace() || c == ',' || c == '.')
+        .filter(|t| !t.is_empty())
+        .collect();
+    let day = |s: &str| (s.len() <= 2).then(|| s.parse::<u8>().ok()).flatten();
+    match tokens[..] {
+        [month, y] => calendar_date(year(y)?, month_number(month)?, 1, DatePrecision::Month),
+        [d, month, y] if d.starts_with(|c: char| c.is_ascii_digit()) => {
+            calendar_date(year(y)?, month_number(month)?, day(d)?, DatePrecision::Day)
+        }
+        [month, d, y] => calendar_date(year(y)?, month_number(month)?, day(d)?, DatePrecision::Day),
+        _ => None,
+    }
+}
+
+fn year(s: &str) -> Option<i32> {
+    (s.len() == 4 && s.bytes().all(|b| b.is_ascii_digit()))
+        .then(|| s.parse().ok())
+        .flatten()
+        .filter(|y| *y >= 1000)
+}
+
+/// Full English month names and prefixes of at least three letters (`Jul`, `Sept`).
+fn month_number(s: &str) -> Option<u8> {
+    let s = s.to_ascii_lowercase();
+    if s.len() < 3 {
+        return None;
+    }
+    MONTHS
+        .iter()
+        .position(|m| m.starts_with(&s))
+        .map(|i| i as u8 + 1)
+}
+
+fn calendar_date(
+    year: i32,
+    month: u8,
+    day: u8,
+    precision: DatePrecision,
+) -> Option<(Date, DatePrecision)> {
+    let date = Date::from_calendar_date(year, Month::try_from(month).ok()?, day).ok()?;
+    Some((date, precision))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::assert_round_trip;
+
+    fn artist(name: &str) -> Artist {
+        Artist {
+            id: name.to_lowercase(),
+            name: name.to_string(),
+            image: String::new(),
+            genres: Vec::new(),
+            created_at: None,
+            updated_at: None,
+            album_count: 0,
+            song_count: 0,
+            image_source: None,
+            apple_music_id: None,
+        }
+    }
+
+    fn day(year: i32, month: Month, day: u8) -> Date {
+        Date::from_calendar_date(year, month, day).expect("valid date")
+    }
+
+    #[test]
+    fn parses_scraped_release_date_formats() {
+        use DatePrecision::{Day, Month as Mon, Year};
+        for (raw, expected) in [
+            ("2019", (day(2019, Month::January, 1), Year)),
+            ("2019-07", (day(2019, Month::July, 1), Mon)),
+            ("2019-07-12", (day(2019, Month::July, 12), Day)),
+            ("2019/07/12", (day(2019, Month::July, 12), Day)),
+            ("2019.7.2", (day(2019, Month::July, 2), Day)),
+            ("2019-07-12T00:00:00Z", (day(2019, Month::July, 12), Day)),
+            ("2019-07-12 00:00:00", (day(2019, Month::July, 12), Day)),
+            ("  2019-07-12  ", (day(2019, Month::July, 12), Day)),
+            ("July 2019", (day(2019, Month::July, 1), Mon)),
+            ("Jul. 2019", (day(2019, Month::July, 1), Mon)),
+            ("sept 2019", (day(2019, Month::September, 1), Mon)),
+            ("12 July 2019", (day(2019, Month::July, 12), Day)),
+            ("July 12, 2019", (day(2019, Month::July, 12), Day)),
+            ("2020-02-29", (day(2020, Month::February, 29), Day)),
+        ] {
+            assert_eq!(parse_release_date(raw), Some(expected), "{raw:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_ambiguous_and_invalid_release_dates() {
+        for raw in [
+            "",
+            "   ",
+            "12/07/2019",
+            "07-12-19",
+            "2019-07-00",
+            "2019-00-12",
+            "2019-02-30",
+            "2019-13",
+            "2019-07-12-01",
+            "0999",
+            "Ma 2019",
+            "Juliet 2019",
+            "32 July 2019",
+            "July 2019 extra words",
+            "2019-07-12é",
+            "2019-07-1é2",
+            "2019年7月12日",
+        ] {
+            assert_eq!(parse_release_date(raw), None, "{raw:?}");
+        }
+    }
+
+    #[test]
+    fn fixtures_round_trip() {
+        assert_round_trip::<Artist>("models/artist.json");
+        assert_round_trip::<Album>("models/album.json");
+        assert_round_trip::<Song>("models/song.json");
+    }
+
+    #[test]
+    fn compilations_are_credited_to_various_artists() {
+        assert!(is_compilation(&[artist("Various Artists")]));
+        assert!(is_compilation(&[artist("VARIOUS ARTISTS"), artist("Zedd")]));
+
+        let orchestra: Vec<Artist> = (0..30).map(|n| artist(&format!("Soloist {n}"))).collect();
+        assert!(!is_compilation(&orchestra));
+        assert!(!is_compilation(&[]));
+    }
 }
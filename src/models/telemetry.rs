@@ -5,6 +5,8 @@ use time::OffsetDateTime;
 use uuid::Uuid;
 use validator::{Validate, ValidationError};
 
+use crate::api::validation::KnownParams;
+
 static SEMVER_REGEX: OnceLock<Regex> = OnceLock::new();
 
 fn validate_semver(version: &str) -> Result<(), ValidationError> {
@@ -35,7 +37,7 @@ impl Os {
     }
 }
 
-#[derive(Deserialize, Validate)]
+#[derive(Deserialize, Serialize, Validate)]
 pub struct TelemetrySubmission {
     pub user_id: Uuid,
 
@@ -48,6 +50,20 @@ pub struct TelemetrySubmission {
     pub song_count: i64,
 }
 
+#[derive(Deserialize, Serialize, Validate)]
+pub struct OptOutRequest {
+    pub user_id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub struct SubmissionStatusQuery {
+    pub user_id: Uuid,
+}
+
+impl KnownParams for SubmissionStatusQuery {
+    const PARAMS: &'static [&'static str] = &["user_id"];
+}
+
 #[derive(Deserialize)]
 pub struct StatsQuery {
     #[serde(default)]
@@ -56,17 +72,173 @@ pub struct StatsQuery {
     #[serde(default)]
     #[serde(with = "time::serde::rfc3339::option")]
     pub to: Option<OffsetDateTime>,
+    pub include_stale: Option<bool>,
+    #[serde(default)]
+    pub format: SeriesFormat,
+    /// Emit buckets as Unix milliseconds instead of RFC 3339 strings.
+    #[serde(default)]
+    pub epoch: bool,
+    /// IANA zone whose midnight day and week buckets align to; UTC when absent.
+    pub tz: Option<String>,
+}
+
+impl KnownParams for StatsQuery {
+    const PARAMS: &'static [&'static str] =
+        &["from", "to", "include_stale", "format", "epoch", "tz"];
+}
+
+/// Zones dashboards may bucket in. Kept to a vetted list rather than whatever the database
+/// happens to know, so a typo is rejected instead of silently falling back.
+const TIME_ZONES: &[&str] = &[
+    "UTC",
+    "Europe/London",
+    "Europe/Dublin",
+    "Europe/Lisbon",
+    "Europe/Zurich",
+    "Europe/Berlin",
+    "Europe/Paris",
+    "Europe/Amsterdam",
+    "Europe/Brussels",
+    "Europe/Vienna",
+    "Europe/Rome",
+    "Europe/Madrid",
+    "Europe/Stockholm",
+    "Europe/Oslo",
+    "Europe/Copenhagen",
+    "Europe/Warsaw",
+    "Europe/Prague",
+    "Europe/Helsinki",
+    "Europe/Athens",
+    "Europe/Istanbul",
+    "Europe/Kyiv",
+    "Europe/Moscow",
+    "America/New_York",
+    "America/Chicago",
+    "America/Denver",
+    "America/Los_Angeles",
+    "America/Toronto",
+    "America/Mexico_City",
+    "America/Sao_Paulo",
+    "America/Buenos_Aires",
+    "Asia/Dubai",
+    "Asia/Kolkata",
+    "Asia/Singapore",
+    "Asia/Shanghai",
+    "Asia/Hong_Kong",
+    "Asia/Seoul",
+    "Asia/Tokyo",
+    "Australia/Perth",
+    "Australia/Sydney",
+    "Pacific/Auckland",
+    "Africa/Johannesburg",
+    "Africa/Lagos",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeZone(&'static str);
+
+#[derive(Debug)]
+pub struct InvalidTimeZone(pub String);
+
+impl TimeZone {
+    pub const UTC: TimeZone = TimeZone("UTC");
+
+    pub fn as_str(&self) -> &'static str {
+        self.0
+    }
+}
+
+impl std::str::FromStr for TimeZone {
+    type Err = InvalidTimeZone;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        TIME_ZONES
+            .iter()
+            .find(|tz| tz.eq_ignore_ascii_case(s))
+            .map(|tz| TimeZone(tz))
+            .ok_or_else(|| InvalidTimeZone(s.to_string()))
+    }
 }
 
-#[derive(Serialize, sqlx::FromRow)]
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SeriesFormat {
+    #[default]
+    Points,
+    /// Grafana JSON datasource: `[{"target": ..., "datapoints": [[value, epoch_ms], ...]}]`.
+    Grafana,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
 pub struct TimeSeriesPoint {
     #[serde(with = "time::serde::rfc3339")]
     pub bucket: OffsetDateTime,
     pub value: f64,
 }
 
-#[derive(Serialize, sqlx::FromRow)]
+impl TimeSeriesPoint {
+    pub fn bucket_millis(&self) -> i64 {
+        (self.bucket.unix_timestamp_nanos() / 1_000_000) as i64
+    }
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
 pub struct DistributionPoint {
     pub label: String,
     pub count: i64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{assert_round_trip, every_param, strict_query};
+    use axum::http::StatusCode;
+
+    #[test]
+    fn fixtures_round_trip() {
+        assert_round_trip::<TelemetrySubmission>("models/telemetry_submission.json");
+        assert_round_trip::<OptOutRequest>("models/optout_request.json");
+        assert_round_trip::<Vec<TimeSeriesPoint>>("models/time_series.json");
+        assert_round_trip::<Vec<DistributionPoint>>("models/distribution.json");
+    }
+
+    #[tokio::test]
+    async fn strict_stats_query_accepts_every_known_param() {
+        let query = every_param::<StatsQuery>(&[
+            ("from", "2026-01-01T00:00:00Z"),
+            ("to", "2026-02-01T00:00:00%2B01:00"),
+            ("include_stale", "false"),
+            ("format", "grafana"),
+            ("epoch", "true"),
+            ("tz", "Europe/Zurich"),
+        ]);
+        let parsed = strict_query::<StatsQuery>(&query)
+            .await
+            .expect("deserializes");
+        assert_eq!(parsed.include_stale, Some(false));
+        assert_eq!(parsed.format, SeriesFormat::Grafana);
+        assert!(parsed.epoch);
+        assert_eq!(parsed.tz.as_deref(), Some("Europe/Zurich"));
+        assert!(parsed.from.is_some() && parsed.to.is_some());
+
+        for bad in ["form=2026-01-01T00:00:00Z", "include_stale=false&limit=5"] {
+            let result = strict_query::<StatsQuery>(bad).await;
+            assert_eq!(result.err(), Some(StatusCode::BAD_REQUEST), "{bad}");
+        }
+    }
+
+    #[tokio::test]
+    async fn strict_status_query_accepts_every_known_param() {
+        let user_id = "8f5b2c1e-4d3a-4b6f-9e8d-7c6b5a493827";
+        let query = every_param::<SubmissionStatusQuery>(&[("user_id", user_id)]);
+        let parsed = strict_query::<SubmissionStatusQuery>(&query)
+            .await
+            .expect("deserializes");
+        assert_eq!(parsed.user_id.to_string(), user_id);
+
+        let typo = format!("userid={user_id}");
+        let result = strict_query::<SubmissionStatusQuery>(&typo).await;
+        assert_eq!(result.err(), Some(StatusCode::BAD_REQUEST));
+    }
+}
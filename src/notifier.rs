@@ -0,0 +1,91 @@
+use reqwest::Client;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use tokio::task::JoinHandle;
+
+const ATTEMPTS: u32 = 4;
+
+/// Posts ops events to `WEBHOOK_URL`. Does nothing when the URL is unset.
+#[derive(Clone, Default)]
+pub struct Notifier(Option<Arc<Inner>>);
+
+struct Inner {
+    http: Client,
+    url: String,
+    dedup_window: Duration,
+    last_sent: Mutex<HashMap<String, Instant>>,
+}
+
+impl Notifier {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let Some(url) = std::env::var("WEBHOOK_URL").ok().filter(|u| !u.is_empty()) else {
+            return Ok(Self(None));
+        };
+        let http = Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .map_err(|e| anyhow::anyhow!("failed to build webhook client: {e}"))?;
+        let dedup_window = Duration::from_secs(
+            std::env::var("WEBHOOK_DEDUP_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(600),
+        );
+        Ok(Self(Some(Arc::new(Inner {
+            http,
+            url,
+            dedup_window,
+            last_sent: Mutex::new(HashMap::new()),
+        }))))
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// Sends `event` in the background, retrying with backoff. An event with the same
+    /// name and `key` sent within `WEBHOOK_DEDUP_SECS` is dropped, so a flapping
+    /// dependency produces one notification per window. Callers that exit right after
+    /// can await the handle; everyone else drops it.
+    pub fn notify(&self, event: &'static str, key: &str, data: Value) -> Option<JoinHandle<()>> {
+        let inner = self.0.clone()?;
+        {
+            let mut last_sent = inner.last_sent.lock().expect("notifier lock poisoned");
+            let dedup_key = format!("{event}:{key}");
+            if last_sent
+                .get(&dedup_key)
+                .is_some_and(|at| at.elapsed() < inner.dedup_window)
+            {
+                return None;
+            }
+            last_sent.insert(dedup_key, Instant::now());
+        }
+
+        let body = json!({
+            "event": event,
+            "time": OffsetDateTime::now_utc().format(&Rfc3339).unwrap_or_default(),
+            "data": data,
+        });
+        Some(tokio::spawn(async move {
+            let mut backoff = Duration::from_secs(1);
+            for attempt in 1..=ATTEMPTS {
+                match inner.http.post(&inner.url).json(&body).send().await {
+                    Ok(resp) if resp.status().is_success() => return,
+                    Ok(resp) => {
+                        tracing::warn!("webhook {} attempt {}: {}", event, attempt, resp.status())
+                    }
+                    Err(e) => tracing::warn!("webhook {} attempt {}: {}", event, attempt, e),
+                }
+                if attempt < ATTEMPTS {
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+            }
+            tracing::error!("webhook {} dropped after {} attempts", event, ATTEMPTS);
+        }))
+    }
+}
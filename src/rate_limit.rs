@@ -1,24 +1,621 @@
-use axum::body::Body;
-use governor::middleware::NoOpMiddleware;
-use tower_governor::{
-    GovernorLayer, governor::GovernorConfigBuilder, key_extractor::SmartIpKeyExtractor,
-};
-
-pub type QuotaLayer = GovernorLayer<SmartIpKeyExtractor, NoOpMiddleware, Body>;
-
-pub fn rate_limit(requests: u32, duration_ms: u64) -> QuotaLayer {
-    let period = if requests > 0 {
-        duration_ms / (requests as u64)
-    } else {
-        duration_ms
+use axum::extract::Request;
+use axum::http::{self, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use futures::future::BoxFuture;
+use governor::clock::{Clock, DefaultClock};
+use governor::middleware::{StateInformationMiddleware, StateSnapshot};
+use governor::{DefaultDirectRateLimiter, DefaultKeyedRateLimiter, NotUntil, Quota, RateLimiter};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv6Addr};
+use std::num::NonZeroU32;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tower::{Layer, Service};
+
+use crate::api::error_response;
+use crate::auth::{ApiKeys, KeyQuota};
+use crate::ip_allowlist::{self, Cidr};
+
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+const ABUSE_WINDOW: Duration = Duration::from_secs(60);
+const DEFAULT_MAX_KEYS: usize = 100_000;
+
+/// API keys with this scope skip the limiter, so polling dashboards don't blank panels.
+const EXEMPT_SCOPE: &str = "dashboard";
+
+type KeyedLimiter = DefaultKeyedRateLimiter<ClientKey, StateInformationMiddleware>;
+type DirectLimiter = DefaultDirectRateLimiter<StateInformationMiddleware>;
+
+/// Networks given to limiters built after [`load_exemptions`].
+static EXEMPT_CIDRS: RwLock<Vec<Cidr>> = RwLock::new(Vec::new());
+
+/// Loads `RATE_LIMIT_EXEMPT_CIDRS`, networks whose requests skip the limiter. Call it before
+/// building the routers; each limiter keeps the ranges loaded when it was created. The client
+/// address is resolved with [`ip_allowlist::trusted_client_ip`], so a forged
+/// `X-Forwarded-For` only counts when it arrives through a trusted proxy. Returns the
+/// number of ranges now exempt.
+pub fn load_exemptions() -> Result<usize, String> {
+    let raw = std::env::var("RATE_LIMIT_EXEMPT_CIDRS").unwrap_or_default();
+    let cidrs = ip_allowlist::parse_cidrs(&raw)
+        .map_err(|e| format!("invalid RATE_LIMIT_EXEMPT_CIDRS: {e}"))?;
+    let count = cidrs.len();
+    *EXEMPT_CIDRS.write().expect("exemptions lock poisoned") = cidrs;
+    Ok(count)
+}
+
+/// Limits each client to `requests` per `duration_ms`. Requests with a valid API key are
+/// bucketed by key id (with any `API_KEY_QUOTAS` override), everything else by client IP.
+/// `group` labels rejection metrics.
+pub fn rate_limit(
+    group: &'static str,
+    requests: u32,
+    duration_ms: u64,
+    api_keys: &ApiKeys,
+) -> QuotaLayer {
+    rate_limit_if(group, requests, duration_ms, api_keys, |_| true)
+}
+
+/// Like [`rate_limit`], but only counts and limits requests for which `applies` holds;
+/// the rest pass straight through.
+pub fn rate_limit_if(
+    group: &'static str,
+    requests: u32,
+    duration_ms: u64,
+    api_keys: &ApiKeys,
+    applies: fn(&Request) -> bool,
+) -> QuotaLayer {
+    let requests = NonZeroU32::new(requests).unwrap_or(NonZeroU32::MIN);
+    let quota = quota(KeyQuota {
+        requests,
+        period: Duration::from_millis(duration_ms.max(1)),
+    });
+
+    let max_keys = std::env::var("RATE_LIMIT_MAX_KEYS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(DEFAULT_MAX_KEYS);
+    let seen = BoundedKeys::new(max_keys);
+
+    let limiter = Arc::new(RateLimiter::keyed(quota).with_middleware());
+    let overrides = api_keys
+        .quotas()
+        .map(|(id, q)| {
+            let limiter = RateLimiter::direct(self::quota(q)).with_middleware();
+            (id.to_string(), limiter)
+        })
+        .collect();
+
+    // A key is idle once its bucket has fully refilled; evict those so the store stays bounded.
+    let idle_after = Duration::from_millis(duration_ms.max(1));
+    let cleanup_limiter = limiter.clone();
+    let cleanup_seen = seen.clone();
+    let lowest_remaining = Arc::new(AtomicU32::new(requests.get()));
+    let capacity = Capacity {
+        group,
+        burst: requests.get(),
+        lowest_remaining: lowest_remaining.clone(),
     };
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+        loop {
+            interval.tick().await;
+            let tracked = cleanup_seen.evict_idle(idle_after);
+            cleanup_limiter.retain_recent();
+            cleanup_limiter.shrink_to_fit();
+            metrics::gauge!("rate_limit_tracked_keys", "group" => group).set(tracked as f64);
+            capacity.report();
+        }
+    });
+
+    QuotaLayer {
+        state: Arc::new(QuotaState {
+            group,
+            applies,
+            api_keys: api_keys.clone(),
+            limiter,
+            overrides,
+            seen,
+            lowest_remaining,
+            exempt_cidrs: EXEMPT_CIDRS
+                .read()
+                .expect("exemptions lock poisoned")
+                .clone(),
+            threshold: std::env::var("RATE_LIMIT_ABUSE_THRESHOLD")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(60),
+            abuse: Mutex::new(AbuseWindow::new()),
+        }),
+    }
+}
+
+/// The smallest burst any client of a group had left since the last report. Near zero
+/// means someone is about to be rejected; at the burst size, nobody came close.
+struct Capacity {
+    group: &'static str,
+    burst: u32,
+    lowest_remaining: Arc<AtomicU32>,
+}
+
+impl Capacity {
+    fn report(&self) {
+        let lowest = self.lowest_remaining.swap(self.burst, Ordering::Relaxed);
+        metrics::gauge!("rate_limit_remaining_burst", "group" => self.group).set(lowest as f64);
+    }
+}
+
+fn quota(q: KeyQuota) -> Quota {
+    let per_request = (q.period / q.requests.get()).max(Duration::from_nanos(1));
+    Quota::with_period(per_request)
+        .expect("quota period is non-zero")
+        .allow_burst(q.requests)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum ClientKey {
+    ApiKey(String),
+    Ip(IpAddr),
+    /// Shared bucket for clients seen while the key store is full.
+    Overflow,
+}
+
+/// Tracks which client IPs currently own a bucket (IPv6 by /64, so address rotation within
+/// one allocation shares a bucket) and caps how many are tracked at once.
+#[derive(Clone)]
+struct BoundedKeys {
+    max_keys: usize,
+    seen: Arc<Mutex<HashMap<IpAddr, Instant>>>,
+}
+
+impl BoundedKeys {
+    fn new(max_keys: usize) -> Self {
+        Self {
+            max_keys,
+            seen: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn key_for(&self, ip: IpAddr) -> ClientKey {
+        let ip = match ip {
+            IpAddr::V6(v6) => {
+                let mut segments = v6.segments();
+                segments[4..].fill(0);
+                IpAddr::V6(Ipv6Addr::from(segments))
+            }
+            v4 => v4,
+        };
+
+        let mut seen = self.seen.lock().expect("key store lock poisoned");
+        let now = Instant::now();
+        if let Some(last) = seen.get_mut(&ip) {
+            *last = now;
+        } else if seen.len() < self.max_keys {
+            seen.insert(ip, now);
+        } else {
+            metrics::counter!("rate_limit_overflow_total").increment(1);
+            return ClientKey::Overflow;
+        }
+        ClientKey::Ip(ip)
+    }
+
+    fn evict_idle(&self, idle_after: Duration) -> usize {
+        let mut seen = self.seen.lock().expect("key store lock poisoned");
+        seen.retain(|_, last| last.elapsed() < idle_after);
+        seen.shrink_to_fit();
+        seen.len()
+    }
+}
+
+struct QuotaState {
+    group: &'static str,
+    applies: fn(&Request) -> bool,
+    api_keys: ApiKeys,
+    limiter: Arc<KeyedLimiter>,
+    overrides: HashMap<String, DirectLimiter>,
+    seen: BoundedKeys,
+    /// See [`Capacity`]; rejections record zero.
+    lowest_remaining: Arc<AtomicU32>,
+    exempt_cidrs: Vec<Cidr>,
+    threshold: u32,
+    abuse: Mutex<AbuseWindow>,
+}
+
+enum Rejection {
+    TooManyRequests { wait: Duration, ip: Option<IpAddr> },
+    UnknownClient,
+}
+
+impl QuotaState {
+    /// Requests with a dashboard key or from an exempt network bypass the limiter.
+    fn is_exempt(&self, req: &Request) -> bool {
+        if let Some(key) = self.api_keys.authenticate(req.headers()) {
+            return key.has_scope(EXEMPT_SCOPE);
+        }
+        !self.exempt_cidrs.is_empty()
+            && ip_allowlist::trusted_client_ip(req)
+                .is_some_and(|ip| self.exempt_cidrs.iter().any(|cidr| cidr.contains(ip)))
+    }
+
+    fn check(&self, req: &Request) -> Result<(), Rejection> {
+        // Invalid keys fall through to IP limiting; the auth middleware rejects them later.
+        if let Some(key) = self.api_keys.authenticate(req.headers()) {
+            let result = match self.overrides.get(&key.id) {
+                Some(limiter) => limiter.check(),
+                None => self.limiter.check_key(&ClientKey::ApiKey(key.id.clone())),
+            };
+            return self.record_capacity(result, None);
+        }
+
+        // Keyed like the allowlist, so a client can't rotate its bucket by forging
+        // `X-Forwarded-For` unless the request came through a trusted proxy.
+        let ip = ip_allowlist::trusted_client_ip(req).ok_or(Rejection::UnknownClient)?;
+        let result = self.limiter.check_key(&self.seen.key_for(ip));
+        self.record_capacity(result, Some(ip))
+    }
+
+    fn record_capacity(
+        &self,
+        result: Result<StateSnapshot, NotUntil<<DefaultClock as Clock>::Instant>>,
+        ip: Option<IpAddr>,
+    ) -> Result<(), Rejection> {
+        let remaining = result
+            .as_ref()
+            .map_or(0, StateSnapshot::remaining_burst_capacity);
+        self.lowest_remaining
+            .fetch_min(remaining, Ordering::Relaxed);
+        result.map(drop).map_err(|n| Rejection::TooManyRequests {
+            wait: wait_time(&n),
+            ip,
+        })
+    }
+
+    fn reject(&self, wait: Duration, ip: Option<IpAddr>) -> Response {
+        metrics::counter!("rate_limit_rejections_total", "group" => self.group).increment(1);
+        if let Some(ip) = ip {
+            self.record_abuse(ip);
+        }
+
+        let mut headers = HeaderMap::new();
+        let wait_secs = HeaderValue::from(wait.as_secs());
+        headers.insert("x-ratelimit-after", wait_secs.clone());
+        headers.insert(http::header::RETRY_AFTER, wait_secs);
+        (
+            headers,
+            error_response(StatusCode::TOO_MANY_REQUESTS, "Too many requests"),
+        )
+            .into_response()
+    }
+
+    fn record_abuse(&self, ip: IpAddr) {
+        let prefix = network_prefix(ip);
+        let count = self
+            .abuse
+            .lock()
+            .expect("abuse window lock poisoned")
+            .record(&prefix);
+        if count == self.threshold + 1 {
+            tracing::warn!(
+                group = self.group,
+                prefix = %prefix,
+                "client network exceeded {} rate limit rejections in a minute",
+                self.threshold
+            );
+        }
+    }
+}
+
+fn wait_time(not_until: &NotUntil<<DefaultClock as Clock>::Instant>) -> Duration {
+    not_until.wait_time_from(DefaultClock::default().now())
+}
+
+#[derive(Clone)]
+pub struct QuotaLayer {
+    state: Arc<QuotaState>,
+}
+
+impl<S> Layer<S> for QuotaLayer {
+    type Service = QuotaService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        QuotaService {
+            inner,
+            state: self.state.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct QuotaService<S> {
+    inner: S,
+    state: Arc<QuotaState>,
+}
+
+impl<S> Service<Request> for QuotaService<S>
+where
+    S: Service<Request, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        if !(self.state.applies)(&req) {
+            return Box::pin(self.inner.call(req));
+        }
+        if self.state.is_exempt(&req) {
+            metrics::counter!("rate_limit_exempt_requests_total", "group" => self.state.group)
+                .increment(1);
+            return Box::pin(self.inner.call(req));
+        }
+        match self.state.check(&req) {
+            Ok(()) => Box::pin(self.inner.call(req)),
+            Err(Rejection::TooManyRequests { wait, ip }) => {
+                let response = self.state.reject(wait, ip);
+                Box::pin(async move { Ok(response) })
+            }
+            Err(Rejection::UnknownClient) => {
+                let response = error_response(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Unable to determine client address",
+                )
+                .into_response();
+                Box::pin(async move { Ok(response) })
+            }
+        }
+    }
+}
+
+/// Truncates to /24 for IPv4 and /48 for IPv6 so logs never carry a full client address.
+fn network_prefix(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, c, _] = v4.octets();
+            format!("{a}.{b}.{c}.0/24")
+        }
+        IpAddr::V6(v6) => {
+            let s = v6.segments();
+            format!("{:x}:{:x}:{:x}::/48", s[0], s[1], s[2])
+        }
+    }
+}
+
+struct AbuseWindow {
+    started: Instant,
+    counts: HashMap<String, u32>,
+}
+
+impl AbuseWindow {
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            counts: HashMap::new(),
+        }
+    }
+
+    fn record(&mut self, prefix: &str) -> u32 {
+        if self.started.elapsed() >= ABUSE_WINDOW {
+            self.started = Instant::now();
+            self.counts.clear();
+        }
+        let count = self.counts.entry(prefix.to_string()).or_insert(0);
+        *count += 1;
+        *count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn key_store_stays_bounded_under_100k_clients() {
+        let seen = BoundedKeys::new(1_000);
+        let limiter: DefaultKeyedRateLimiter<ClientKey> = RateLimiter::keyed(quota(KeyQuota {
+            requests: NonZeroU32::new(10).unwrap(),
+            period: Duration::from_secs(1),
+        }));
+
+        let mut overflowed = 0;
+        for n in 0..100_000u32 {
+            let key = seen.key_for(IpAddr::V4(Ipv4Addr::from(0x0a00_0000 + n)));
+            if key == ClientKey::Overflow {
+                overflowed += 1;
+            }
+            let _ = limiter.check_key(&key);
+        }
+
+        assert_eq!(seen.seen.lock().unwrap().len(), 1_000);
+        assert_eq!(overflowed, 99_000);
+        // 1000 tracked clients plus the shared overflow bucket.
+        assert_eq!(limiter.len(), 1_001);
+        // Clients tracked before the store filled keep their own bucket.
+        assert_eq!(
+            seen.key_for(IpAddr::V4(Ipv4Addr::from(0x0a00_0000))),
+            ClientKey::Ip(IpAddr::V4(Ipv4Addr::from(0x0a00_0000)))
+        );
+    }
+
+    #[test]
+    fn ipv6_clients_share_a_key_per_64() {
+        let seen = BoundedKeys::new(10);
+        let a = seen.key_for("2001:db8::1".parse().unwrap());
+        let b = seen.key_for("2001:db8::ffff:1".parse().unwrap());
+        let c = seen.key_for("2001:db8:0:1::1".parse().unwrap());
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert_eq!(seen.seen.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn idle_keys_are_evicted() {
+        let seen = BoundedKeys::new(10);
+        seen.key_for("192.0.2.1".parse().unwrap());
+        assert_eq!(seen.evict_idle(Duration::from_secs(60)), 1);
+        assert_eq!(seen.evict_idle(Duration::ZERO), 0);
+    }
+
+    #[tokio::test]
+    async fn rejection_carries_retry_headers() {
+        use crate::test_support::{get, send};
+        use axum::{Router, routing::get as route_get};
+
+        let app = Router::new()
+            .route("/", route_get(|| async { "ok" }))
+            .layer(rate_limit("test", 1, 60_000, &ApiKeys::default()));
+
+        let (status, _, _) = send(app.clone(), get("/")).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, headers, body) = send(app, get("/")).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = headers[http::header::RETRY_AFTER]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((1..=60).contains(&retry_after), "{retry_after}");
+        assert_eq!(
+            headers["x-ratelimit-after"],
+            headers[http::header::RETRY_AFTER]
+        );
+        assert_eq!(
+            body,
+            serde_json::json!({ "error": { "status": 429, "message": "Too many requests" } })
+        );
+    }
+
+    #[tokio::test]
+    async fn rejections_are_counted_and_abusive_networks_logged_by_prefix() {
+        use crate::test_support::{capture_logs, get, local_metrics, metric, send};
+        use axum::{Router, routing::get as route_get};
+
+        let (snapshotter, _recorder) = local_metrics();
+        let (logs, _subscriber) = capture_logs();
+        let mut layer = rate_limit("test", 1, 60_000, &ApiKeys::default());
+        Arc::get_mut(&mut layer.state)
+            .expect("layer not cloned yet")
+            .threshold = 2;
+        let lowest_remaining = layer.state.lowest_remaining.clone();
+        let app = Router::new()
+            .route("/", route_get(|| async { "ok" }))
+            .layer(layer);
+        let capacity = Capacity {
+            group: "test",
+            burst: 1,
+            lowest_remaining,
+        };
+
+        // Let the cleanup task take its immediate first tick, so only `capacity` reports.
+        tokio::task::yield_now().await;
+        let (status, _, _) = send(app.clone(), get("/")).await;
+        assert_eq!(status, StatusCode::OK);
+        capacity.report();
+        assert_eq!(
+            metric(
+                &snapshotter,
+                "rate_limit_remaining_burst",
+                &[("group", "test")]
+            ),
+            Some(0.0)
+        );
+
+        for _ in 0..4 {
+            let (status, _, _) = send(app.clone(), get("/")).await;
+            assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        }
+        let snapshot = |name| metric(&snapshotter, name, &[("group", "test")]);
+        assert_eq!(snapshot("rate_limit_rejections_total"), Some(4.0));
+
+        // Warned once, on the rejection past the threshold, and only with the /24.
+        let logs = logs.contents();
+        assert_eq!(
+            logs.matches("exceeded 2 rate limit rejections").count(),
+            1,
+            "{logs}"
+        );
+        assert!(logs.contains("prefix=192.0.2.0/24"), "{logs}");
+        assert!(!logs.contains("192.0.2.10"), "{logs}");
+
+        // A quiet interval reports the full burst again.
+        capacity.report();
+        capacity.report();
+        assert_eq!(snapshot("rate_limit_remaining_burst"), Some(1.0));
+    }
+
+    #[tokio::test]
+    async fn dashboard_keys_and_exempt_networks_skip_the_limiter() {
+        use crate::test_support::{local_metrics, metric, request, send};
+        use axum::body::Body;
+        use axum::extract::ConnectInfo;
+        use axum::{Router, routing::get as route_get};
+        use std::net::SocketAddr;
+
+        let (snapshotter, _recorder) = local_metrics();
+        let keys = ApiKeys::from_entries("grafana:dash-token:dashboard,ops:ops-token:admin");
+        let mut layer = rate_limit("exempt", 1, 60_000, &keys);
+        Arc::get_mut(&mut layer.state)
+            .expect("layer not cloned yet")
+            .exempt_cidrs = ip_allowlist::parse_cidrs("198.51.100.0/24").unwrap();
+        let app = Router::new()
+            .route("/", route_get(|| async { "ok" }))
+            .layer(layer);
+        let req = |token: Option<&str>, peer: Option<&str>, forwarded: Option<&str>| {
+            let mut builder = Request::get("/");
+            if let Some(token) = token {
+                builder = builder.header(http::header::AUTHORIZATION, format!("Bearer {token}"));
+            }
+            if let Some(forwarded) = forwarded {
+                builder = builder.header("x-forwarded-for", forwarded);
+            }
+            let mut req = request(builder.body(Body::empty()).unwrap());
+            if let Some(peer) = peer {
+                let peer: SocketAddr = peer.parse().unwrap();
+                req.extensions_mut().insert(ConnectInfo(peer));
+            }
+            req
+        };
+        let statuses = |token, peer, forwarded| {
+            let app = app.clone();
+            async move {
+                let mut statuses = Vec::new();
+                for _ in 0..3 {
+                    statuses.push(send(app.clone(), req(token, peer, forwarded)).await.0);
+                }
+                statuses
+            }
+        };
+        const LIMITED: [StatusCode; 3] = [
+            StatusCode::OK,
+            StatusCode::TOO_MANY_REQUESTS,
+            StatusCode::TOO_MANY_REQUESTS,
+        ];
 
-    let config = GovernorConfigBuilder::default()
-        .per_millisecond(period)
-        .burst_size(requests)
-        .key_extractor(SmartIpKeyExtractor)
-        .finish()
-        .expect("Failed to create rate limit config");
+        assert_eq!(
+            statuses(Some("dash-token"), None, None).await,
+            [StatusCode::OK; 3]
+        );
+        assert_eq!(
+            statuses(None, Some("198.51.100.7:40000"), None).await,
+            [StatusCode::OK; 3]
+        );
+        let exempt = metric(&snapshotter, "rate_limit_exempt_requests_total", &[]);
+        assert_eq!(exempt, Some(6.0));
 
-    GovernorLayer::new(config)
+        // Keys without the dashboard scope are limited like anyone else.
+        assert_eq!(statuses(Some("ops-token"), None, None).await, LIMITED);
+        // The peer isn't a trusted proxy, so its `X-Forwarded-For` is ignored and the
+        // request is limited as the peer's own.
+        assert_eq!(statuses(None, None, Some("198.51.100.7")).await, LIMITED);
+        let exempt = metric(&snapshotter, "rate_limit_exempt_requests_total", &[]);
+        assert_eq!(exempt, Some(0.0));
+    }
 }
@@ -0,0 +1,212 @@
+use hmac::{Hmac, KeyInit, Mac};
+use regex::Regex;
+use sha2::Sha256;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::OnceLock;
+use uuid::Uuid;
+
+/// `q` values longer than this are dropped from error reports.
+const DEFAULT_MAX_QUERY_CHARS: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    /// Hash user ids, truncate IPs and drop long search queries.
+    Strict,
+    /// Log request context as-is; meant for local development.
+    None,
+}
+
+struct Redactor {
+    policy: Policy,
+    salt: Vec<u8>,
+    max_query_chars: usize,
+}
+
+static REDACTOR: OnceLock<Redactor> = OnceLock::new();
+static UUID_PATTERN: OnceLock<Regex> = OnceLock::new();
+
+/// Reads the redaction policy from the environment:
+///
+/// - `LOG_REDACTION`: `strict` (default) or `none`
+/// - `LOG_REDACTION_SALT`: key for the user id hash. Without it a random key is used, so
+///   hashes only correlate within one process lifetime.
+/// - `LOG_REDACTION_MAX_QUERY_CHARS`: longest `q` kept in error reports (default 64)
+pub fn init() {
+    REDACTOR.get_or_init(|| {
+        let policy = match std::env::var("LOG_REDACTION")
+            .ok()
+            .as_deref()
+            .map(str::trim)
+        {
+            Some(v) if v.eq_ignore_ascii_case("none") => Policy::None,
+            Some(v) if !v.is_empty() && !v.eq_ignore_ascii_case("strict") => {
+                tracing::warn!("unknown LOG_REDACTION {:?}, using strict", v);
+                Policy::Strict
+            }
+            _ => Policy::Strict,
+        };
+        let salt = match std::env::var("LOG_REDACTION_SALT")
+            .ok()
+            .filter(|v| !v.is_empty())
+        {
+            Some(salt) => salt.into_bytes(),
+            None => {
+                if policy == Policy::Strict {
+                    tracing::warn!("LOG_REDACTION_SALT not set, user id hashes change on restart");
+                }
+                Uuid::new_v4().as_bytes().to_vec()
+            }
+        };
+        let max_query_chars = std::env::var("LOG_REDACTION_MAX_QUERY_CHARS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MAX_QUERY_CHARS);
+        Redactor {
+            policy,
+            salt,
+            max_query_chars,
+        }
+    });
+}
+
+fn redactor() -> &'static Redactor {
+    init();
+    REDACTOR.get().expect("redactor initialized")
+}
+
+pub fn policy() -> Policy {
+    redactor().policy
+}
+
+/// Keyed hash of a user id: stable for a given salt, so events can be correlated, but not
+/// reversible without it.
+pub fn user_id(id: &Uuid) -> String {
+    if redactor().policy == Policy::None {
+        return id.to_string();
+    }
+    hashed_user_id(id)
+}
+
+/// The hash [`user_id`] logs under the strict policy, regardless of the configured policy.
+/// For data that leaves the service, such as telemetry exports.
+pub fn hashed_user_id(id: &Uuid) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(&redactor().salt).expect("hmac accepts any key length");
+    mac.update(id.as_bytes());
+    hex::encode(&mac.finalize().into_bytes()[..8])
+}
+
+/// IPv4 addresses truncated to their /24, IPv6 to their /48.
+pub fn ip(ip: IpAddr) -> String {
+    match (redactor().policy, truncate_ip(ip)) {
+        (Policy::None, _) => ip.to_string(),
+        (Policy::Strict, IpAddr::V4(v4)) => format!("{v4}/24"),
+        (Policy::Strict, IpAddr::V6(v6)) => format!("{v6}/48"),
+    }
+}
+
+/// The network address of `ip` with the host bits cleared, for fields that must hold an address.
+pub fn truncate_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, c, _] = v4.octets();
+            IpAddr::V4(Ipv4Addr::new(a, b, c, 0))
+        }
+        IpAddr::V6(v6) => {
+            let s = v6.segments();
+            IpAddr::V6(Ipv6Addr::new(s[0], s[1], s[2], 0, 0, 0, 0, 0))
+        }
+    }
+}
+
+/// Truncates every address in an `X-Forwarded-For`-style list; unparseable entries are dropped.
+pub fn ip_list(value: &str) -> String {
+    value
+        .split(',')
+        .filter_map(|v| v.trim().parse::<IpAddr>().ok())
+        .map(ip)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The search query when it is short enough to be kept in an error report.
+pub fn query(q: &str) -> Option<&str> {
+    let r = redactor();
+    (r.policy == Policy::None || q.chars().count() <= r.max_query_chars).then_some(q)
+}
+
+/// Replaces every UUID in free text (error messages, breadcrumbs) with its keyed hash.
+pub fn scrub(text: &str) -> String {
+    if redactor().policy == Policy::None {
+        return text.to_string();
+    }
+    let pattern = UUID_PATTERN.get_or_init(|| {
+        Regex::new(r"(?i)\b[0-9a-f]{8}-?[0-9a-f]{4}-?[0-9a-f]{4}-?[0-9a-f]{4}-?[0-9a-f]{12}\b")
+            .expect("valid uuid pattern")
+    });
+    pattern
+        .replace_all(text, |caps: &regex::Captures| {
+            match Uuid::parse_str(&caps[0]) {
+                Ok(id) => user_id(&id),
+                Err(_) => caps[0].to_string(),
+            }
+        })
+        .into_owned()
+}
+
+/// Initializes the strict policy with a fixed salt, unless something initialized it first.
+#[cfg(test)]
+pub fn init_for_tests() {
+    REDACTOR.get_or_init(|| Redactor {
+        policy: Policy::Strict,
+        salt: b"test salt".to_vec(),
+        max_query_chars: DEFAULT_MAX_QUERY_CHARS,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "3f2504e0-4f89-11d3-9a0c-0305e82c3301";
+
+    #[test]
+    fn user_ids_hash_stably() {
+        init_for_tests();
+        let id = Uuid::parse_str(ID).unwrap();
+        let hashed = user_id(&id);
+        assert_eq!(hashed.len(), 16);
+        assert_eq!(hashed, hashed_user_id(&id));
+        assert!(!hashed.contains("3f2504e0"));
+    }
+
+    #[test]
+    fn ips_are_truncated() {
+        init_for_tests();
+        assert_eq!(ip("203.0.113.57".parse().unwrap()), "203.0.113.0/24");
+        assert_eq!(ip("2001:db8:1:2:3::9".parse().unwrap()), "2001:db8:1::/48");
+        assert_eq!(
+            ip_list("203.0.113.57, bogus, 198.51.100.4"),
+            "203.0.113.0/24, 198.51.100.0/24"
+        );
+    }
+
+    #[test]
+    fn scrub_replaces_uuids_in_free_text() {
+        init_for_tests();
+        let id = Uuid::parse_str(ID).unwrap();
+        let simple = id.simple().to_string();
+        let text = format!("user {ID} failed; retry for {simple}");
+        let scrubbed = scrub(&text);
+        assert!(!scrubbed.contains(ID));
+        assert!(!scrubbed.contains(&simple));
+        assert_eq!(scrubbed.matches(&user_id(&id)).count(), 2);
+    }
+
+    #[test]
+    fn long_queries_are_dropped() {
+        init_for_tests();
+        assert_eq!(query("daft punk"), Some("daft punk"));
+        assert_eq!(query(&"a".repeat(DEFAULT_MAX_QUERY_CHARS + 1)), None);
+    }
+}
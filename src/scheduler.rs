@@ -0,0 +1,182 @@
+use serde::Serialize;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use time::OffsetDateTime;
+use tokio::sync::{Notify, watch};
+use tokio::task::JoinHandle;
+
+/// How long shutdown waits for a job that is mid-run.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobStatus {
+    pub name: &'static str,
+    pub interval_secs: u64,
+    pub running: bool,
+    pub runs: u64,
+    pub failures: u64,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub last_started: Option<OffsetDateTime>,
+    pub last_duration_ms: Option<u128>,
+    pub last_error: Option<String>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub next_run: Option<OffsetDateTime>,
+}
+
+struct Job {
+    status: Arc<Mutex<JobStatus>>,
+    trigger: Arc<Notify>,
+    task: JoinHandle<()>,
+}
+
+pub enum Trigger {
+    Queued,
+    AlreadyRunning,
+    UnknownJob,
+}
+
+/// Runs named periodic jobs: one at a time per job, each run in its own task so a panic
+/// only fails that run, with up to 10% jitter on the interval.
+#[derive(Clone)]
+pub struct Scheduler(Arc<Inner>);
+
+struct Inner {
+    jobs: Mutex<Vec<Job>>,
+    shutdown: watch::Sender<bool>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self(Arc::new(Inner {
+            jobs: Mutex::new(Vec::new()),
+            shutdown: watch::Sender::new(false),
+        }))
+    }
+
+    /// Starts `job` now and then every `interval`.
+    pub fn register<F, Fut>(&self, name: &'static str, interval: Duration, job: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        let status = Arc::new(Mutex::new(JobStatus {
+            name,
+            interval_secs: interval.as_secs(),
+            running: false,
+            runs: 0,
+            failures: 0,
+            last_started: None,
+            last_duration_ms: None,
+            last_error: None,
+            next_run: Some(OffsetDateTime::now_utc()),
+        }));
+        let trigger = Arc::new(Notify::new());
+        let mut shutdown = self.0.shutdown.subscribe();
+
+        let task = tokio::spawn({
+            let status = status.clone();
+            let trigger = trigger.clone();
+            async move {
+                let mut next = tokio::time::Instant::now();
+                loop {
+                    tokio::select! {
+                        biased;
+                        _ = shutdown.changed() => break,
+                        _ = trigger.notified() => {}
+                        _ = tokio::time::sleep_until(next) => {}
+                    }
+
+                    let started = OffsetDateTime::now_utc();
+                    {
+                        let mut s = status.lock().expect("job status lock poisoned");
+                        s.running = true;
+                        s.last_started = Some(started);
+                        s.next_run = None;
+                    }
+                    let clock = std::time::Instant::now();
+                    let error = match tokio::spawn(job()).await {
+                        Ok(Ok(())) => None,
+                        Ok(Err(e)) => Some(e.to_string()),
+                        Err(e) if e.is_panic() => Some("job panicked".to_string()),
+                        Err(e) => Some(e.to_string()),
+                    };
+                    if let Some(e) = &error {
+                        tracing::warn!("job {} failed: {}", name, e);
+                    }
+
+                    let delay = interval + jitter(interval);
+                    next = tokio::time::Instant::now() + delay;
+                    let mut s = status.lock().expect("job status lock poisoned");
+                    s.running = false;
+                    s.runs += 1;
+                    s.failures += error.is_some() as u64;
+                    s.last_duration_ms = Some(clock.elapsed().as_millis());
+                    s.last_error = error;
+                    s.next_run = Some(OffsetDateTime::now_utc() + delay);
+                }
+            }
+        });
+
+        self.0.jobs.lock().expect("jobs lock poisoned").push(Job {
+            status,
+            trigger,
+            task,
+        });
+    }
+
+    pub fn statuses(&self) -> Vec<JobStatus> {
+        self.0
+            .jobs
+            .lock()
+            .expect("jobs lock poisoned")
+            .iter()
+            .map(|job| job.status.lock().expect("job status lock poisoned").clone())
+            .collect()
+    }
+
+    /// Runs the job as soon as it is idle.
+    pub fn trigger(&self, name: &str) -> Trigger {
+        let jobs = self.0.jobs.lock().expect("jobs lock poisoned");
+        let Some(job) = jobs
+            .iter()
+            .find(|job| job.status.lock().expect("job status lock poisoned").name == name)
+        else {
+            return Trigger::UnknownJob;
+        };
+        if job.status.lock().expect("job status lock poisoned").running {
+            return Trigger::AlreadyRunning;
+        }
+        job.trigger.notify_one();
+        Trigger::Queued
+    }
+
+    /// Stops scheduling new runs and waits briefly for runs in progress to finish.
+    pub async fn shutdown(&self) {
+        self.0.shutdown.send_replace(true);
+        let tasks: Vec<JoinHandle<()>> = self
+            .0
+            .jobs
+            .lock()
+            .expect("jobs lock poisoned")
+            .drain(..)
+            .map(|job| job.task)
+            .collect();
+        if tokio::time::timeout(SHUTDOWN_GRACE, futures::future::join_all(tasks))
+            .await
+            .is_err()
+        {
+            tracing::warn!("scheduled jobs still running after shutdown grace period");
+        }
+    }
+}
+
+/// Up to 10% of `interval`, so jobs registered together don't stay in lockstep.
+fn jitter(interval: Duration) -> Duration {
+    let max_ms = interval.as_millis() as u64 / 10;
+    if max_ms == 0 {
+        return Duration::ZERO;
+    }
+    Duration::from_millis((uuid::Uuid::new_v4().as_u128() % max_ms as u128) as u64)
+}
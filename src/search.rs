@@ -0,0 +1,215 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+use crate::manticore::SearchClient;
+use crate::memory_search::MemorySearchClient;
+use crate::models::metadata::{ExplicitFilter, ItemType};
+
+#[derive(Debug, Default)]
+pub struct SearchQuery<'a> {
+    pub name: Option<&'a str>,
+    pub artist: Option<&'a str>,
+    pub album: Option<&'a str>,
+    /// Lowercased genre names; a document matches if it carries any of them.
+    pub genres: Vec<String>,
+    /// Lowercased record label; songs match on their primary album's label.
+    pub label: Option<String>,
+    /// Only narrows songs; other types are never explicit.
+    pub explicit: ExplicitFilter,
+}
+
+/// A spelling correction and how many indexed documents contain it.
+#[derive(Debug, Clone)]
+pub struct Suggestion {
+    pub text: String,
+    pub docs: i64,
+}
+
+/// The search backend answered with an error or couldn't be reached, as opposed to a bug on
+/// our side. Handlers map it to 502.
+#[derive(Debug)]
+pub struct SearchBackendError(pub String);
+
+impl std::fmt::Display for SearchBackendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for SearchBackendError {}
+
+/// The search backend didn't answer within the configured timeout. Handlers map it to 504.
+#[derive(Debug)]
+pub struct SearchBackendTimeout(pub String);
+
+impl std::fmt::Display for SearchBackendTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for SearchBackendTimeout {}
+
+/// Optional features a backend implements, so handlers can reject parameters it would
+/// otherwise silently ignore.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Capabilities {
+    pub supports_facets: bool,
+    pub supports_suggest: bool,
+    pub supports_cursor: bool,
+    pub supports_genre_filter: bool,
+    pub supports_label_filter: bool,
+    pub supports_explicit_filter: bool,
+}
+
+/// The index's document layout against the one this binary writes.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct SchemaStatus {
+    #[serde(rename = "expectedSchemaVersion")]
+    pub expected: i64,
+    /// `None` for an index built before versioning.
+    #[serde(rename = "schemaVersion")]
+    pub actual: Option<i64>,
+}
+
+impl SchemaStatus {
+    pub fn is_current(&self) -> bool {
+        self.actual == Some(self.expected)
+    }
+}
+
+pub enum SearchBackend {
+    Manticore(SearchClient),
+    Memory(MemorySearchClient),
+}
+
+impl SearchBackend {
+    /// Selects the backend from `SEARCH_BACKEND` (`manticore` unless set to `memory`).
+    pub fn from_env() -> Result<Self> {
+        let backend = std::env::var("SEARCH_BACKEND").unwrap_or_else(|_| "manticore".to_string());
+        if backend == "memory" {
+            let fixture = std::env::var("SEARCH_FIXTURE")
+                .unwrap_or_else(|_| "fixtures/search.json".to_string());
+            return Ok(SearchBackend::Memory(MemorySearchClient::from_fixture(
+                &fixture,
+            )?));
+        }
+        let url =
+            std::env::var("MANTICORE_URL").unwrap_or_else(|_| "http://localhost:9308".to_string());
+        Ok(SearchBackend::Manticore(SearchClient::new(&url)?))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SearchBackend::Manticore(_) => "manticore",
+            SearchBackend::Memory(_) => "memory",
+        }
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        match self {
+            SearchBackend::Manticore(_) => Capabilities {
+                supports_facets: false,
+                supports_suggest: true,
+                supports_cursor: false,
+                supports_genre_filter: true,
+                supports_label_filter: true,
+                supports_explicit_filter: true,
+            },
+            SearchBackend::Memory(_) => Capabilities {
+                supports_facets: false,
+                supports_suggest: true,
+                supports_cursor: false,
+                supports_genre_filter: true,
+                supports_label_filter: true,
+                supports_explicit_filter: true,
+            },
+        }
+    }
+
+    pub fn index_name(&self) -> &str {
+        match self {
+            SearchBackend::Manticore(client) => client.index_name(),
+            SearchBackend::Memory(_) => "memory",
+        }
+    }
+
+    #[tracing::instrument(name = "search.index", skip_all, fields(backend = self.name(), item_type = item_type.as_str()))]
+    pub async fn search(
+        &self,
+        item_type: ItemType,
+        query: &SearchQuery<'_>,
+        limit: i32,
+        offset: i32,
+    ) -> Result<Vec<(String, String, String, String)>> {
+        let result = match self {
+            SearchBackend::Manticore(client) => {
+                client.search(item_type, query, limit, offset).await
+            }
+            SearchBackend::Memory(client) => Ok(client.search(item_type, query, limit, offset)),
+        };
+        metrics::counter!("search_requests_total", "backend" => self.name()).increment(1);
+        if result.is_err() {
+            metrics::counter!("search_errors_total", "backend" => self.name()).increment(1);
+        }
+        result
+    }
+
+    /// Suggests a correction for a query that matched nothing; `None` when the query
+    /// already looks right or nothing is close enough.
+    pub async fn suggest(&self, item_type: ItemType, text: &str) -> Result<Option<Suggestion>> {
+        match self {
+            SearchBackend::Manticore(client) => client.suggest(text).await,
+            SearchBackend::Memory(client) => Ok(client.suggest(item_type, text)),
+        }
+    }
+
+    /// The memory backend serves a fixture in whatever shape it has, so it is always current.
+    pub async fn schema_status(&self) -> Result<SchemaStatus> {
+        match self {
+            SearchBackend::Manticore(client) => client.healthcheck().await,
+            SearchBackend::Memory(_) => Ok(SchemaStatus {
+                expected: crate::manticore::SCHEMA_VERSION,
+                actual: Some(crate::manticore::SCHEMA_VERSION),
+            }),
+        }
+    }
+
+    pub async fn ping(&self) -> Result<()> {
+        match self {
+            SearchBackend::Manticore(client) => client.ping().await,
+            SearchBackend::Memory(_) => Ok(()),
+        }
+    }
+
+    /// Removes documents by id. The memory backend serves a read-only fixture, so this is a
+    /// no-op there.
+    pub async fn delete_documents(&self, doc_ids: &[String]) -> Result<()> {
+        match self {
+            SearchBackend::Manticore(client) => client.delete_documents(doc_ids).await,
+            SearchBackend::Memory(_) => Ok(()),
+        }
+    }
+
+    /// Which of `doc_ids` are indexed as `item_type`, checked in one round trip.
+    pub async fn existing_ids(
+        &self,
+        item_type: ItemType,
+        doc_ids: &[String],
+    ) -> Result<HashSet<String>> {
+        match self {
+            SearchBackend::Manticore(client) => {
+                client.existing_ids(item_type.as_str(), doc_ids).await
+            }
+            SearchBackend::Memory(client) => Ok(client.existing_ids(item_type, doc_ids)),
+        }
+    }
+
+    pub async fn count_by_type(&self) -> Result<HashMap<String, i64>> {
+        match self {
+            SearchBackend::Manticore(client) => client.count_by_type().await,
+            SearchBackend::Memory(client) => Ok(client.count_by_type()),
+        }
+    }
+}
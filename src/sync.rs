@@ -0,0 +1,498 @@
+use anyhow::Result;
+use futures::TryStreamExt;
+use indicatif::{ProgressBar, ProgressStyle};
+use serde_json::{Value, json};
+use sqlx::postgres::{PgArguments, PgRow};
+use sqlx::query::Query;
+use sqlx::{PgPool, Postgres, Row};
+use time::OffsetDateTime;
+
+use crate::db::metadata::{self, ParsedReleaseDate};
+use crate::manticore::{SCHEMA_VERSION, SearchClient};
+use crate::models::metadata::parse_release_date;
+
+const BATCH_SIZE: usize = 5000;
+// Tokenized as whitespace by Manticore, but lets the API split names back apart.
+const NAME_SEPARATOR: &str = "\u{1f}";
+pub const ITEM_TYPES: [&str; 3] = ["song", "artist", "album"];
+const RELEASE_DATE_BATCH: i64 = 1000;
+
+/// Rows parsed and rows whose raw date didn't parse, for one type.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BackfillCounts {
+    pub parsed: u64,
+    pub unparseable: u64,
+}
+
+/// Fills `release_date` and `release_date_precision` from the raw `date` of songs and albums
+/// that are new or whose raw date changed. Values that don't parse are stored as `NULL` and
+/// show up in the admin release date report.
+pub async fn backfill_release_dates(pool: &PgPool) -> Result<Vec<(&'static str, BackfillCounts)>> {
+    let mut report = Vec::with_capacity(2);
+    for item_type in ["song", "album"] {
+        let mut counts = BackfillCounts::default();
+        loop {
+            let pending =
+                metadata::pending_release_dates(pool, item_type, RELEASE_DATE_BATCH).await?;
+            if pending.is_empty() {
+                break;
+            }
+            let parsed: Vec<ParsedReleaseDate> = pending
+                .into_iter()
+                .map(|(id, source)| {
+                    let date = parse_release_date(&source);
+                    match date {
+                        Some(_) => counts.parsed += 1,
+                        None if !source.is_empty() => counts.unparseable += 1,
+                        None => {}
+                    }
+                    ParsedReleaseDate {
+                        id,
+                        source,
+                        date: date.map(|(d, _)| d),
+                        precision: date.map(|(_, p)| p.as_str()),
+                    }
+                })
+                .collect();
+            // Nothing stored means every row changed under us; the next run retries them.
+            if metadata::set_release_dates(pool, item_type, &parsed).await? == 0 {
+                break;
+            }
+        }
+        if counts.parsed + counts.unparseable > 0 {
+            tracing::info!(
+                item_type,
+                parsed = counts.parsed,
+                unparseable = counts.unparseable,
+                "release dates backfilled"
+            );
+        }
+        report.push((item_type, counts));
+    }
+    Ok(report)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Scope<'a> {
+    All,
+    UpdatedSince(OffsetDateTime),
+    Id(&'a str),
+}
+
+pub struct SyncRunner {
+    pool: PgPool,
+    client: SearchClient,
+    progress: bool,
+}
+
+impl SyncRunner {
+    pub fn new(pool: PgPool, client: SearchClient, progress: bool) -> Self {
+        Self {
+            pool,
+            client,
+            progress,
+        }
+    }
+
+    /// Rebuilds the index from scratch. Returns the documents indexed per type.
+    pub async fn full(&self) -> Result<Vec<(&'static str, u64)>> {
+        backfill_release_dates(&self.pool).await?;
+        self.client.create_index().await?;
+        tracing::info!(
+            "truncating {} to prevent duplicates",
+            self.client.index_name()
+        );
+        self.client.truncate().await?;
+
+        let mut synced = Vec::with_capacity(ITEM_TYPES.len());
+        for item_type in ITEM_TYPES {
+            synced.push((item_type, self.sync_type(item_type, Scope::All).await?));
+        }
+        // Every document now has the current layout.
+        self.client.set_schema_version(SCHEMA_VERSION).await?;
+        Ok(synced)
+    }
+
+    /// Re-indexes rows updated after `since`, replacing their existing documents.
+    pub async fn incremental(&self, since: OffsetDateTime) -> Result<Vec<(&'static str, u64)>> {
+        backfill_release_dates(&self.pool).await?;
+        self.client.create_index().await?;
+
+        let mut synced = Vec::with_capacity(ITEM_TYPES.len());
+        for item_type in ITEM_TYPES {
+            let count = self
+                .sync_type(item_type, Scope::UpdatedSince(since))
+                .await?;
+            synced.push((item_type, count));
+        }
+        Ok(synced)
+    }
+
+    /// Upserts a single document. Returns false when the row does not exist in Postgres.
+    pub async fn reindex(&self, item_type: &str, id: &str) -> Result<bool> {
+        Ok(self.sync_type(item_type, Scope::Id(id)).await? > 0)
+    }
+
+    async fn sync_type(&self, item_type: &str, scope: Scope<'_>) -> Result<u64> {
+        let replace = !matches!(scope, Scope::All);
+        let (from, select) = documents_sql(item_type);
+        let filter = match scope {
+            Scope::All if item_type == "song" => " WHERE t.deleted_at IS NULL",
+            Scope::All => "",
+            Scope::UpdatedSince(_) => " WHERE t.updated_at > $1",
+            Scope::Id(_) => " WHERE t.id = $1",
+        };
+
+        let count_sql = format!("SELECT COUNT(*) FROM {from}{filter}");
+        let total: i64 = bind_scope(sqlx::query(sqlx::AssertSqlSafe(count_sql)), scope)
+            .fetch_one(&self.pool)
+            .await?
+            .get(0);
+        if total == 0 {
+            return Ok(0);
+        }
+
+        let pb = if self.progress {
+            let pb = ProgressBar::new(total as u64);
+            pb.set_style(
+                ProgressStyle::default_bar()
+                    .template(&format!(
+                        "{item_type:<7} {{spinner:.green}} [{{bar:40.cyan/blue}}] {{pos}}/{{len}} ({{percent}}%) {{eta}}"
+                    ))?
+                    .progress_chars("=>-"),
+            );
+            pb
+        } else {
+            ProgressBar::hidden()
+        };
+
+        let sql = format!("{select} FROM {from}{filter}");
+        let mut stream = bind_scope(sqlx::query(sqlx::AssertSqlSafe(sql)), scope).fetch(&self.pool);
+
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        let mut synced = 0u64;
+        let start = std::time::Instant::now();
+
+        while let Some(row) = stream.try_next().await? {
+            batch.push(to_document(item_type, &row));
+
+            if batch.len() >= BATCH_SIZE {
+                self.send_batch(&batch, replace).await?;
+                synced += batch.len() as u64;
+                pb.set_position(synced);
+                batch.clear();
+            }
+        }
+
+        if !batch.is_empty() {
+            self.send_batch(&batch, replace).await?;
+            synced += batch.len() as u64;
+            pb.set_position(synced);
+        }
+
+        pb.finish_and_clear();
+        let elapsed = start.elapsed();
+        let rate = if elapsed.as_secs() > 0 {
+            synced / elapsed.as_secs()
+        } else {
+            synced
+        };
+        tracing::info!("{}s: {} synced at {} docs/sec", item_type, synced, rate);
+        Ok(synced)
+    }
+
+    async fn send_batch(&self, batch: &[Value], replace: bool) -> Result<()> {
+        if replace {
+            let ids: Vec<String> = batch
+                .iter()
+                .filter_map(|doc| doc["doc_id"].as_str().map(str::to_string))
+                .collect();
+            self.client.delete_documents(&ids).await?;
+        }
+        // Unavailable songs only appear in replace scopes, so their stale documents are
+        // removed above without being reinserted.
+        let available: Vec<Value> = batch
+            .iter()
+            .filter(|doc| !doc["deleted"].as_bool().unwrap_or(false))
+            .cloned()
+            .collect();
+        if available.is_empty() {
+            return Ok(());
+        }
+        self.client.bulk_insert(&available).await
+    }
+}
+
+fn bind_scope<'q>(
+    query: Query<'q, Postgres, PgArguments>,
+    scope: Scope<'q>,
+) -> Query<'q, Postgres, PgArguments> {
+    match scope {
+        Scope::All => query,
+        Scope::UpdatedSince(since) => query.bind(since),
+        Scope::Id(id) => query.bind(id),
+    }
+}
+
+fn documents_sql(item_type: &str) -> (&'static str, &'static str) {
+    match item_type {
+        "song" => (
+            "songs t",
+            "SELECT t.id, t.name, t.duration, t.deleted_at IS NOT NULL AS deleted,
+                    COALESCE(t.explicit, false) AS explicit,
+                    COALESCE((
+                        SELECT array_agg(a.name
+                            ORDER BY sa.position NULLS LAST, sa.inserted_seq NULLS LAST, sa.artist_id)
+                        FROM song_artists sa
+                        JOIN artists a ON sa.artist_id = a.id
+                        WHERE sa.song_id = t.id
+                    ), ARRAY[]::text[]) as artist_names,
+                    COALESCE((
+                        SELECT array_agg(DISTINCT aka.alias)
+                        FROM song_artists sa
+                        JOIN artist_aliases aka ON aka.artist_id = sa.artist_id
+                        WHERE sa.song_id = t.id
+                    ), ARRAY[]::text[]) as artist_aliases,
+                    COALESCE((
+                        SELECT al.name
+                        FROM song_albums sal
+                        JOIN albums al ON sal.album_id = al.id
+                        WHERE sal.song_id = t.id
+                        ORDER BY al.release_date NULLS LAST, al.id
+                        LIMIT 1
+                    ), '') as primary_album_name,
+                    COALESCE((
+                        SELECT LOWER(BTRIM(al.label))
+                        FROM song_albums sal
+                        JOIN albums al ON sal.album_id = al.id
+                        WHERE sal.song_id = t.id
+                        ORDER BY al.release_date NULLS LAST, al.id
+                        LIMIT 1
+                    ), '') as primary_album_label,
+                    COALESCE((
+                        SELECT array_agg(g.name ORDER BY g.name)
+                        FROM song_genres sg
+                        JOIN genres g ON sg.genre_id = g.id
+                        WHERE sg.song_id = t.id
+                    ), ARRAY[]::text[]) as genres,
+                    COALESCE((
+                        SELECT sp.score FROM song_popularity sp WHERE sp.song_id = t.id
+                    ), 0) as popularity",
+        ),
+        "album" => (
+            "albums t",
+            "SELECT t.id, t.name, t.date,
+                    COALESCE(LOWER(BTRIM(t.label)), '') as label,
+                    COALESCE((
+                        SELECT array_agg(g.name ORDER BY g.name)
+                        FROM album_genres alg
+                        JOIN genres g ON alg.genre_id = g.id
+                        WHERE alg.album_id = t.id
+                    ), ARRAY[]::text[]) as genres",
+        ),
+        _ => ("artists t", "SELECT t.id, t.name"),
+    }
+}
+
+fn to_document(item_type: &str, row: &PgRow) -> Value {
+    let id = row.get::<String, _>("id");
+    let name = row.get::<String, _>("name");
+    match item_type {
+        "song" => {
+            let mut artist_names: Vec<String> = row.get("artist_names");
+            // Aliases go after the credited names so they only widen what matches.
+            artist_names.extend(row.get::<Vec<String>, _>("artist_aliases"));
+            let genres: Vec<String> = row.get("genres");
+            json!({
+                "doc_id": id,
+                "name": name,
+                "duration": row.get::<Option<i64>, _>("duration").unwrap_or(0),
+                "artist_name": artist_names.join(NAME_SEPARATOR),
+                "album_name": row.get::<String, _>("primary_album_name"),
+                "genres": genres.join(NAME_SEPARATOR),
+                "popularity": row.get::<f64, _>("popularity"),
+                "label": row.get::<String, _>("primary_album_label"),
+                "explicit": row.get::<bool, _>("explicit"),
+                "item_type": "song",
+                "deleted": row.get::<bool, _>("deleted")
+            })
+        }
+        "album" => {
+            let genres: Vec<String> = row.get("genres");
+            json!({
+                "doc_id": id,
+                "name": name,
+                "date": row.get::<Option<String>, _>("date").unwrap_or_default(),
+                "genres": genres.join(NAME_SEPARATOR),
+                "label": row.get::<String, _>("label"),
+                "item_type": "album"
+            })
+        }
+        _ => json!({
+            "doc_id": id,
+            "name": name,
+            "item_type": "artist"
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, get, send};
+
+    /// The document a sync would write for one song.
+    async fn song_document(pool: &PgPool, id: &str) -> Value {
+        let (from, select) = documents_sql("song");
+        let sql = format!("{select} FROM {from} WHERE t.id = $1");
+        let row = sqlx::query(sqlx::AssertSqlSafe(sql))
+            .bind(id)
+            .fetch_one(pool)
+            .await
+            .expect("song row");
+        to_document("song", &row)
+    }
+
+    #[tokio::test]
+    async fn song_on_two_albums_syncs_the_same_primary_album_every_time() {
+        let Some(pool) = test_support::scrape_db().await else {
+            return;
+        };
+        // Both of Get Lucky's albums on one day, so only the id breaks the tie.
+        sqlx::query("UPDATE albums SET date = '2013-05-17' WHERE id = 'dancehits0000001'")
+            .execute(&pool)
+            .await
+            .expect("update album date");
+        backfill_release_dates(&pool).await.expect("backfill");
+        let app = test_support::app(
+            Some(test_support::search_state(pool.clone())),
+            test_support::unreachable_pool(),
+        );
+        let synced = || async {
+            let document = song_document(&pool, "getlucky00000001").await;
+            let (_, _, song) = send(
+                app.clone(),
+                get("/metadata/v1/lookup/omm:song:getlucky00000001"),
+            )
+            .await;
+            let (_, _, albums) = send(
+                app.clone(),
+                get("/metadata/v1/lookup/omm:song:getlucky00000001/albums"),
+            )
+            .await;
+            (
+                document,
+                song["data"]["attributes"]["primaryAlbumId"].clone(),
+                albums["data"][0]["id"].clone(),
+            )
+        };
+
+        let first = synced().await;
+        // Relink in the opposite order, so nothing depends on how the rows were stored.
+        sqlx::raw_sql(
+            "DELETE FROM song_albums WHERE song_id = 'getlucky00000001';
+             INSERT INTO song_albums (song_id, album_id) VALUES
+                 ('getlucky00000001', 'dancehits0000001');
+             INSERT INTO song_albums (song_id, album_id) VALUES
+                 ('getlucky00000001', 'ram0000000000001');",
+        )
+        .execute(&pool)
+        .await
+        .expect("relink albums");
+        let second = synced().await;
+
+        assert_eq!(first, second);
+        let (document, primary, first_album) = first;
+        assert_eq!(document["album_name"], "Dance Hits 2013");
+        assert_eq!(primary, "omm:album:dancehits0000001");
+        assert_eq!(first_album, primary);
+    }
+
+    #[tokio::test]
+    async fn release_date_backfill_leaves_updated_at_alone() {
+        let Some(pool) = test_support::scrape_db().await else {
+            return;
+        };
+        let updated_at = || async {
+            sqlx::query_scalar::<_, time::OffsetDateTime>(
+                "SELECT updated_at FROM songs WHERE id = 'getlucky00000001'",
+            )
+            .fetch_one(&pool)
+            .await
+            .expect("updated_at")
+        };
+        sqlx::query(
+            "UPDATE songs SET release_date = NULL, release_date_source = NULL,
+                 updated_at = '2026-01-01T00:00:00Z'
+             WHERE id = 'getlucky00000001'",
+        )
+        .execute(&pool)
+        .await
+        .expect("reset release date");
+        let before = updated_at().await;
+
+        backfill_release_dates(&pool).await.expect("backfill");
+        let (source, parsed): (Option<String>, Option<time::Date>) = sqlx::query_as(
+            "SELECT release_date_source, release_date FROM songs WHERE id = 'getlucky00000001'",
+        )
+        .fetch_one(&pool)
+        .await
+        .expect("release date");
+        assert!(source.is_some() && parsed.is_some());
+        assert_eq!(updated_at().await, before);
+
+        // Any other change still counts.
+        sqlx::query(
+            "UPDATE songs SET name = 'Get Lucky (Radio Edit)' WHERE id = 'getlucky00000001'",
+        )
+        .execute(&pool)
+        .await
+        .expect("rename");
+        assert!(updated_at().await > before);
+    }
+
+    #[tokio::test]
+    async fn links_from_before_inserted_seq_keep_their_insertion_order() {
+        let Some(pool) = test_support::scrape_db().await else {
+            return;
+        };
+        // Written before the link columns existed: no position, no inserted_seq, and the
+        // credited artist first although its id sorts last.
+        sqlx::raw_sql(
+            "INSERT INTO artists (id, name) VALUES
+                 ('jayz000000000001', 'Jay-Z'),
+                 ('aliciakeys000001', 'Alicia Keys'),
+                 ('arrangements0001', 'Mr. Arranger');
+             INSERT INTO songs (id, name) VALUES ('empirestate00001', 'Empire State of Mind');
+             INSERT INTO song_artists (song_id, artist_id, inserted_seq) VALUES
+                 ('empirestate00001', 'jayz000000000001', NULL);
+             INSERT INTO song_artists (song_id, artist_id, inserted_seq) VALUES
+                 ('empirestate00001', 'aliciakeys000001', NULL);",
+        )
+        .execute(&pool)
+        .await
+        .expect("insert pre-migration links");
+        sqlx::raw_sql(include_str!(
+            "../scrape_migrations/20261017100000_backfill_link_inserted_seq.sql"
+        ))
+        .execute(&pool)
+        .await
+        .expect("backfill inserted_seq");
+        // Linked after the migration, so it takes the next sequence value.
+        sqlx::query(
+            "INSERT INTO song_artists (song_id, artist_id)
+             VALUES ('empirestate00001', 'arrangements0001')",
+        )
+        .execute(&pool)
+        .await
+        .expect("insert new link");
+
+        let document = song_document(&pool, "empirestate00001").await;
+        let names: Vec<&str> = document["artist_name"]
+            .as_str()
+            .unwrap()
+            .split(NAME_SEPARATOR)
+            .collect();
+        assert_eq!(names, ["Jay-Z", "Alicia Keys", "Mr. Arranger"]);
+    }
+}
@@ -0,0 +1,85 @@
+use std::sync::RwLock;
+
+use crate::text;
+
+/// Built-in groups, always loaded before `SYNONYMS_FILE`.
+const STARTER: &[&[&str]] = &[&["and", "&"], &["feat", "ft", "featuring"]];
+
+static GROUPS: RwLock<Vec<Vec<String>>> = RwLock::new(Vec::new());
+
+/// Loads the starter list plus `SYNONYMS_FILE` (one comma-separated group per line, `#` for
+/// comments) and swaps it in. Returns the number of groups now active.
+pub fn load() -> Result<usize, String> {
+    let mut groups: Vec<Vec<String>> = STARTER
+        .iter()
+        .map(|g| g.iter().map(|t| text::normalize(t)).collect())
+        .collect();
+
+    if let Ok(path) = std::env::var("SYNONYMS_FILE") {
+        let raw = std::fs::read_to_string(&path)
+            .map_err(|e| format!("failed to read synonyms file {path}: {e}"))?;
+        for line in raw.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let group: Vec<String> = line
+                .split(',')
+                .map(|t| text::normalize(t.trim()))
+                .filter(|t| !t.is_empty())
+                .collect();
+            if group.len() > 1 {
+                groups.push(group);
+            }
+        }
+    }
+
+    let count = groups.len();
+    *GROUPS.write().expect("synonyms lock poisoned") = groups;
+    Ok(count)
+}
+
+fn is_word(term: &str) -> bool {
+    term.chars().any(char::is_alphanumeric)
+}
+
+pub fn tokens(raw: &str) -> Vec<String> {
+    raw.split_whitespace().map(text::normalize).collect()
+}
+
+/// How a query token should be matched once synonyms are applied.
+pub enum Term {
+    Plain(String),
+    /// Any of these forms.
+    AnyOf(Vec<String>),
+    /// The group has a form the index tokenizer drops (e.g. `&`), so documents may contain
+    /// none of the word forms; the token can't be required.
+    Optional,
+}
+
+pub fn expand(token: &str) -> Term {
+    let groups = GROUPS.read().expect("synonyms lock poisoned");
+    let Some(group) = groups.iter().find(|g| g.iter().any(|t| t == token)) else {
+        return Term::Plain(token.to_string());
+    };
+    if !group.iter().all(|t| is_word(t)) {
+        return Term::Optional;
+    }
+    Term::AnyOf(group.clone())
+}
+
+/// Rewrites every token to the first form of its group, for backends that compare text
+/// directly instead of through a query language.
+pub fn canonical(raw: &str) -> String {
+    let groups = GROUPS.read().expect("synonyms lock poisoned");
+    tokens(raw)
+        .into_iter()
+        .map(|token| {
+            groups
+                .iter()
+                .find(|g| g.contains(&token))
+                .map(|g| g[0].clone())
+                .unwrap_or(token)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
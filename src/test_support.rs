@@ -0,0 +1,350 @@
+//! Setup shared by the `#[cfg(test)]` modules: the app router over the in-memory search
+//! backend, throwaway Postgres databases and the files under `fixtures/`.
+//!
+//! Tests that need Postgres read `TEST_SCRAPE_DATABASE_URL` (plain Postgres) or
+//! `TEST_DATABASE_URL` (TimescaleDB, for telemetry) and skip themselves when it is unset.
+//! Every call creates a fresh database on that server, so point them at a disposable one.
+
+use axum::Router;
+use axum::body::Body;
+use axum::extract::{ConnectInfo, FromRequestParts, Request};
+use axum::http::{HeaderMap, StatusCode, header};
+use metrics_util::debugging::{DebugValue, DebuggingRecorder, Snapshotter};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::{ConnectOptions, Executor, PgPool};
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tower::ServiceExt;
+
+use crate::api::validation::{KnownParams, StrictQuery};
+use crate::api::{self, metadata::v1::metadata::SearchState};
+use crate::auth::ApiKeys;
+use crate::db::{self, DbPools};
+use crate::ip_allowlist::IpAllowlist;
+use crate::memory_search::MemorySearchClient;
+use crate::search::SearchBackend;
+
+/// The peer address requests are sent from.
+pub const CLIENT: &str = "192.0.2.10:40000";
+
+const SCRAPE_SCHEMA: &str = include_str!("../fixtures/scrape_schema.sql");
+const SCRAPE_SEED: &str = include_str!("../fixtures/scrape_seed.sql");
+
+pub fn fixture_path(name: &str) -> String {
+    format!("{}/fixtures/{name}", env!("CARGO_MANIFEST_DIR"))
+}
+
+pub fn fixture(name: &str) -> String {
+    let path = fixture_path(name);
+    std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("reading {path}: {e}"))
+}
+
+/// Checks that the fixture `name` deserializes into `T` and serializes back to the same
+/// bytes, so a renamed, dropped or reformatted field shows up as a fixture diff.
+pub fn assert_round_trip<T: DeserializeOwned + Serialize>(name: &str) {
+    let raw = fixture(name);
+    let parsed: T = serde_json::from_str(&raw).unwrap_or_else(|e| panic!("parsing {name}: {e}"));
+    let serialized = serde_json::to_string_pretty(&parsed).expect("serializable");
+    assert_eq!(serialized, raw.trim_end(), "{name} does not round-trip");
+}
+
+/// The in-memory backend over `fixtures/search.json`.
+pub fn memory_search() -> Arc<SearchBackend> {
+    let client = MemorySearchClient::from_fixture(&fixture_path("search.json"))
+        .expect("search fixture loads");
+    Arc::new(SearchBackend::Memory(client))
+}
+
+/// A pool whose connections always fail, for routes that must answer without the database
+/// and for checking how handlers report an unreachable one.
+pub fn unreachable_pool() -> PgPool {
+    PgPoolOptions::new()
+        .acquire_timeout(Duration::from_millis(500))
+        .connect_lazy("postgres://vleer@127.0.0.1:1/unreachable")
+        .expect("valid url")
+}
+
+/// A pool whose server accepts connections and never answers, so every query hangs until
+/// the caller gives up. For budgets, timeouts and pool exhaustion.
+pub async fn stalled_pool() -> PgPool {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind stalled server");
+    let addr = listener.local_addr().expect("local address");
+    tokio::spawn(async move {
+        let mut held = Vec::new();
+        while let Ok((socket, _)) = listener.accept().await {
+            held.push(socket);
+        }
+    });
+    PgPoolOptions::new()
+        .acquire_timeout(Duration::from_secs(30))
+        .connect_lazy(&format!("postgres://vleer@{addr}/stalled"))
+        .expect("valid url")
+}
+
+/// A listener whose accept queue is full, so connections to it hang in the handshake like
+/// ones to an unroutable host. Lives as long as the value.
+pub struct Blackhole {
+    pub addr: SocketAddr,
+    _listener: tokio::net::TcpListener,
+    _queued: Vec<std::net::TcpStream>,
+}
+
+pub fn blackhole() -> Blackhole {
+    let socket = tokio::net::TcpSocket::new_v4().expect("create socket");
+    socket
+        .bind("127.0.0.1:0".parse().expect("valid address"))
+        .expect("bind blackhole");
+    let listener = socket.listen(1).expect("listen");
+    let addr = listener.local_addr().expect("local address");
+    let mut queued = Vec::new();
+    while let Ok(stream) = std::net::TcpStream::connect_timeout(&addr, Duration::from_millis(100)) {
+        queued.push(stream);
+        assert!(queued.len() < 16, "accept queue never filled");
+    }
+    Blackhole {
+        addr,
+        _listener: listener,
+        _queued: queued,
+    }
+}
+
+/// A new, empty UTF-8 database on the server `var` points at, or `None` when it is unset.
+async fn fresh_database(var: &str) -> Option<PgConnectOptions> {
+    let Ok(url) = std::env::var(var) else {
+        eprintln!("{var} not set, skipping");
+        return None;
+    };
+    let opts = PgConnectOptions::from_str(&url).expect("valid test database url");
+    let name = format!("vleer_test_{}", uuid::Uuid::new_v4().simple());
+    let mut admin = opts
+        .clone()
+        .database("postgres")
+        .connect()
+        .await
+        .expect("test database server reachable");
+    admin
+        .execute(sqlx::AssertSqlSafe(format!(
+            "CREATE DATABASE {name} ENCODING 'UTF8' TEMPLATE template0"
+        )))
+        .await
+        .expect("create test database");
+    Some(opts.database(&name))
+}
+
+/// A scrape database with the scraper's base tables, every scrape migration and the
+/// catalog in `fixtures/scrape_seed.sql`.
+pub async fn scrape_db() -> Option<PgPool> {
+    let opts = fresh_database("TEST_SCRAPE_DATABASE_URL").await?;
+    let pool = PgPoolOptions::new()
+        .max_connections(5)
+        .connect_with(opts)
+        .await
+        .expect("connect to scrape test database");
+    sqlx::raw_sql(SCRAPE_SCHEMA)
+        .execute(&pool)
+        .await
+        .expect("create scraper tables");
+    db::SCRAPE_MIGRATOR
+        .run(&pool)
+        .await
+        .expect("run scrape migrations");
+    sqlx::raw_sql(SCRAPE_SEED)
+        .execute(&pool)
+        .await
+        .expect("seed scrape database");
+    Some(pool)
+}
+
+/// A migrated telemetry database. Needs the TimescaleDB extension on the server.
+pub async fn telemetry_db() -> Option<PgPool> {
+    let opts = fresh_database("TEST_DATABASE_URL").await?;
+    let pool = PgPoolOptions::new()
+        .max_connections(5)
+        .connect_with(opts)
+        .await
+        .expect("connect to telemetry test database");
+    db::MIGRATOR
+        .run(&pool)
+        .await
+        .expect("run telemetry migrations");
+    Some(pool)
+}
+
+pub fn search_state(scrape_pool: PgPool) -> SearchState {
+    SearchState::new(memory_search(), scrape_pool, ApiKeys::default())
+}
+
+/// The token of the `admin`-scoped key in [`admin_search_state`].
+pub const ADMIN_TOKEN: &str = "admin-token";
+
+/// One key, `ops`, holding the `admin` scope.
+pub fn admin_keys() -> ApiKeys {
+    ApiKeys::from_entries(&format!("ops:{ADMIN_TOKEN}:admin"))
+}
+
+/// [`search_state`] with [`admin_keys`].
+pub fn admin_search_state(scrape_pool: PgPool) -> SearchState {
+    SearchState::new(memory_search(), scrape_pool, admin_keys())
+}
+
+/// The public routes as `main` mounts them, without the global limits.
+pub fn app(search_state: Option<SearchState>, telemetry_pool: PgPool) -> Router {
+    api::app_router(
+        search_state,
+        DbPools::new(telemetry_pool, None),
+        ApiKeys::default(),
+        IpAllowlist::default(),
+        64,
+    )
+}
+
+pub fn get(uri: &str) -> Request {
+    request(
+        Request::get(uri)
+            .body(Body::empty())
+            .expect("valid request"),
+    )
+}
+
+pub fn post_json(uri: &str, body: &Value) -> Request {
+    request(
+        Request::post(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .expect("valid request"),
+    )
+}
+
+/// Authenticates `req` with [`ADMIN_TOKEN`].
+pub fn as_admin(mut req: Request) -> Request {
+    let value = format!("Bearer {ADMIN_TOKEN}")
+        .parse()
+        .expect("valid header");
+    req.headers_mut().insert(header::AUTHORIZATION, value);
+    req
+}
+
+/// Adds the peer address `axum::serve` would, so IP-keyed middleware sees a client.
+pub fn request(mut req: Request) -> Request {
+    let addr: SocketAddr = CLIENT.parse().expect("valid address");
+    req.extensions_mut().insert(ConnectInfo(addr));
+    req
+}
+
+/// Sends one request through `router`. The body is parsed as JSON, `Null` when empty and a
+/// string when it is something else.
+pub async fn send(router: Router, req: Request) -> (StatusCode, HeaderMap, Value) {
+    let response = router.oneshot(req).await.expect("router is infallible");
+    let (parts, body) = response.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .expect("read body");
+    let body = if bytes.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_slice(&bytes)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into_owned()))
+    };
+    (parts.status, parts.headers, body)
+}
+
+/// `name=value&...` for every parameter `T` knows, with values from `samples`. Panics on a
+/// parameter without a sample, so a new one can't go untested.
+pub fn every_param<T: KnownParams>(samples: &[(&str, &str)]) -> String {
+    T::PARAMS
+        .iter()
+        .map(|param| {
+            let (_, value) = samples
+                .iter()
+                .find(|(name, _)| name == param)
+                .unwrap_or_else(|| panic!("no sample value for {param}"));
+            format!("{param}={value}")
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Extracts `T` from `query` in strict mode, as a handler taking `StrictQuery<T>` would.
+pub async fn strict_query<T: DeserializeOwned + KnownParams>(query: &str) -> Result<T, StatusCode> {
+    let (mut parts, ()) = Request::get(format!("/?{query}"))
+        .header("x-strict", "true")
+        .body(())
+        .expect("valid request")
+        .into_parts();
+    StrictQuery::<T>::from_request_parts(&mut parts, &())
+        .await
+        .map(|StrictQuery(value)| value)
+        .map_err(|rejection| rejection.status())
+}
+
+/// Records the metrics emitted on this thread until the guard drops. A `#[tokio::test]`
+/// runs on one thread, so that is everything the code under test emits.
+pub fn local_metrics() -> (Snapshotter, metrics::LocalRecorderGuard<'static>) {
+    let recorder: &'static DebuggingRecorder = Box::leak(Box::new(DebuggingRecorder::new()));
+    (
+        recorder.snapshotter(),
+        metrics::set_default_local_recorder(recorder),
+    )
+}
+
+/// The metric `name` with at least `labels` recorded since the last call. Counters and
+/// gauges read as their value, so reading resets them.
+pub fn metric(snapshotter: &Snapshotter, name: &str, labels: &[(&str, &str)]) -> Option<f64> {
+    snapshotter
+        .snapshot()
+        .into_vec()
+        .into_iter()
+        .find_map(|(key, _, _, value)| {
+            let key = key.key();
+            let matches = key.name() == name
+                && labels
+                    .iter()
+                    .all(|(k, v)| key.labels().any(|l| l.key() == *k && l.value() == *v));
+            match value {
+                _ if !matches => None,
+                DebugValue::Counter(n) => Some(n as f64),
+                DebugValue::Gauge(v) => Some(v.into_inner()),
+                DebugValue::Histogram(_) => None,
+            }
+        })
+}
+
+/// Log lines written on this thread until the guard drops, formatted without colours.
+#[derive(Clone, Default)]
+pub struct Logs(Arc<std::sync::Mutex<Vec<u8>>>);
+
+impl Logs {
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().expect("log buffer lock poisoned")).into_owned()
+    }
+}
+
+impl std::io::Write for Logs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0
+            .lock()
+            .expect("log buffer lock poisoned")
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+pub fn capture_logs() -> (Logs, tracing::subscriber::DefaultGuard) {
+    let logs = Logs::default();
+    let writer = logs.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_ansi(false)
+        .with_writer(move || writer.clone())
+        .finish();
+    (logs, tracing::subscriber::set_default(subscriber))
+}
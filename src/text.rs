@@ -0,0 +1,53 @@
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+
+/// Folds case and strips diacritics so "Beyoncé", "BEYONCE" and "beyonce" compare equal.
+/// Every layer that compares names (query tokens, synonyms, in-process scoring) goes
+/// through this, so they agree with each other and with the index's charset folding.
+pub fn normalize(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    for c in text.nfkd().filter(|c| !is_combining_mark(*c)) {
+        match c {
+            // Lowercasing keeps these, full case folding does not.
+            'ß' | 'ẞ' => folded.push_str("ss"),
+            'ς' => folded.push('σ'),
+            // Turkish dotless i; the dotted capital İ already decomposes to I + a mark.
+            'ı' => folded.push('i'),
+            c => folded.extend(c.to_lowercase()),
+        }
+    }
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::normalize;
+
+    #[test]
+    fn folds_case_and_diacritics() {
+        assert_eq!(normalize("Beyoncé"), "beyonce");
+        assert_eq!(normalize("BEYONCE"), "beyonce");
+    }
+
+    #[test]
+    fn turkish_i_variants_fold_to_i() {
+        assert_eq!(normalize("ı"), "i");
+        assert_eq!(normalize("İ"), "i");
+        assert_eq!(normalize("Işık"), normalize("ISIK"));
+        assert_eq!(normalize("İstanbul"), "istanbul");
+    }
+
+    #[test]
+    fn sharp_s_folds_to_ss() {
+        assert_eq!(normalize("Straße"), "strasse");
+        assert_eq!(normalize("STRAẞE"), "strasse");
+        assert_eq!(normalize("Straße"), normalize("STRASSE"));
+    }
+
+    #[test]
+    fn final_sigma_matches_medial_and_capital() {
+        assert_eq!(normalize("Οδυσσευς"), normalize("ΟΔΥΣΣΕΥΣ"));
+        assert_eq!(normalize("ς"), normalize("Σ"));
+        assert_eq!(normalize("ς"), "σ");
+    }
+}